@@ -1,6 +1,13 @@
-use crate::{locals::Locals, model::FuncType, stack::Stack, value::Value};
+use crate::{
+    locals::Locals,
+    model::FuncType,
+    stack::Stack,
+    value::{DisplayMode, Value, ValueFormat},
+};
 use anyhow::{anyhow, Result};
+use std::fmt::{self, Display};
 
+#[derive(Clone)]
 pub struct CallStack {
     func_stacks: Vec<FuncStack>,
 }
@@ -16,7 +23,10 @@ impl CallStack {
         self.get_func_stack().unwrap().commit();
     }
 
+    /// Rolls back the stack of the line, dropping the frames of the
+    /// calls an error left behind.
     pub fn rollback(&mut self) {
+        self.func_stacks.truncate(1);
         self.get_func_stack().unwrap().rollback();
     }
 
@@ -29,16 +39,35 @@ impl CallStack {
     pub fn add_func_stack(&mut self, ty: &FuncType) -> Result<()> {
         let mut func_state = FuncStack::new();
         let func_stack = self.get_func_stack()?;
+        let mut values = vec![];
         for param in ty.params.iter().rev() {
             let val = func_stack.pop()?;
             val.is_same_type(&param.val_type)?;
-            func_state.locals.grow(param.id.clone(), val)?;
+            values.push(val);
+        }
+
+        for param in ty.params.iter() {
+            func_state
+                .locals
+                .grow(param.id.clone(), values.pop().unwrap())?;
         }
         self.func_stacks.push(func_state);
 
         Ok(())
     }
 
+    /// Replaces the frame on top with the one of a function taking its
+    /// params from it, as for a tail call.
+    pub fn replace_func_stack(&mut self, ty: &FuncType) -> Result<()> {
+        if self.func_stacks.len() < 2 {
+            return Err(anyhow!("No function in stack"));
+        }
+        self.add_func_stack(ty)?;
+        let replaced = self.func_stacks.len() - 2;
+        self.func_stacks.remove(replaced);
+        Ok(())
+    }
+
     pub fn remove_func_stack(&mut self, ty: &FuncType, requires_empty: bool) -> Result<()> {
         let mut func_stack = self
             .func_stacks
@@ -72,15 +101,47 @@ impl CallStack {
             .remove_block_stack(ty, requires_empty)
     }
 
-    pub fn to_string(&self) -> String {
-        self.func_stacks.last().unwrap().to_string()
+    pub fn unwind_block_stack(&mut self) -> Result<()> {
+        self.get_func_stack()?.unwind_block_stack()
+    }
+
+    pub fn restart_block_stack(&mut self, ty: &FuncType, requires_empty: bool) -> Result<()> {
+        self.get_func_stack()?
+            .restart_block_stack(ty, requires_empty)
     }
 
     pub fn len(&self) -> usize {
         self.func_stacks.len()
     }
+
+    pub fn stack_len(&self) -> usize {
+        self.func_stacks.last().unwrap().len()
+    }
+
+    pub fn values(&self) -> &[Value] {
+        self.func_stacks.last().unwrap().values()
+    }
+
+    pub fn locals(&self) -> &Locals {
+        &self.func_stacks.last().unwrap().locals
+    }
+
+    pub fn format(&self, format: ValueFormat) -> String {
+        self.func_stacks.last().unwrap().format(format)
+    }
+
+    pub fn to_typed_string(&self, mode: DisplayMode) -> String {
+        self.func_stacks.last().unwrap().to_typed_string(mode)
+    }
 }
 
+impl Display for CallStack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.func_stacks.last().unwrap())
+    }
+}
+
+#[derive(Clone)]
 pub struct FuncStack {
     block_stacks: Vec<Stack>,
     pub locals: Locals,
@@ -99,7 +160,9 @@ impl FuncStack {
         self.locals.commit();
     }
 
+    /// Also drops the blocks an error left behind.
     fn rollback(&mut self) {
+        self.block_stacks.truncate(1);
         self.block_stacks.last_mut().unwrap().rollback();
         self.locals.rollback();
     }
@@ -114,6 +177,10 @@ impl FuncStack {
         self.get_latest_block()?.pop()
     }
 
+    pub fn len(&self) -> usize {
+        self.block_stacks.last().unwrap().len()
+    }
+
     fn is_empty(&mut self) -> Result<bool> {
         Ok(self.get_latest_block()?.is_empty())
     }
@@ -132,8 +199,8 @@ impl FuncStack {
             values.push(val);
         }
 
-        while !values.is_empty() {
-            block_state.push(values.pop().unwrap());
+        while let Some(value) = values.pop() {
+            block_state.push(value);
         }
         self.block_stacks.push(block_state);
 
@@ -163,17 +230,76 @@ impl FuncStack {
         Ok(())
     }
 
+    /// Removes the latest block, moving all of its values to the enclosing
+    /// block, when a branch or return leaves it early.
+    fn unwind_block_stack(&mut self) -> Result<()> {
+        let mut block_stack = self
+            .block_stacks
+            .pop()
+            .ok_or(anyhow!("No block in stack"))?;
+        let mut values = vec![];
+        while !block_stack.is_empty() {
+            values.push(block_stack.pop()?);
+        }
+
+        while let Some(value) = values.pop() {
+            self.push(value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts the latest block over with the params of `ty` taken off its
+    /// top, when a branch goes back to a loop.
+    fn restart_block_stack(&mut self, ty: &FuncType, requires_empty: bool) -> Result<()> {
+        let mut block_stack = self
+            .block_stacks
+            .pop()
+            .ok_or(anyhow!("No block in stack"))?;
+        let mut values = vec![];
+        for param in ty.params.iter().rev() {
+            let value = block_stack.pop()?;
+            value.is_same_type(&param.val_type)?;
+            values.push(value);
+        }
+
+        if requires_empty && !block_stack.is_empty() {
+            return Err(anyhow!("Too many returns"));
+        }
+
+        let mut block_stack = Stack::new();
+        while let Some(value) = values.pop() {
+            block_stack.push(value);
+        }
+        self.block_stacks.push(block_stack);
+
+        Ok(())
+    }
+
     pub fn peek(&mut self) -> Result<Value> {
         self.get_latest_block()?.peek()
     }
 
-    pub fn to_string(&self) -> String {
-        self.block_stacks.last().unwrap().to_string()
+    pub fn values(&self) -> &[Value] {
+        self.block_stacks.last().unwrap().values()
+    }
+
+    pub fn format(&self, format: ValueFormat) -> String {
+        self.block_stacks.last().unwrap().format(format)
+    }
+
+    pub fn to_soft_string(&self, format: ValueFormat) -> Result<String> {
+        self.block_stacks.last().unwrap().to_soft_string(format)
+    }
+
+    pub fn to_typed_string(&self, mode: DisplayMode) -> String {
+        self.block_stacks.last().unwrap().to_typed_string(mode)
     }
+}
 
-    #[allow(unused)]
-    pub fn to_soft_string(&self) -> Result<String> {
-        self.block_stacks.last().unwrap().to_soft_string()
+impl Display for FuncStack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.block_stacks.last().unwrap())
     }
 }
 
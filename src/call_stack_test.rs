@@ -2,7 +2,7 @@ use crate::{
     call_stack::{CallStack, FuncStack},
     model::{FuncType, Index, Local, ValType},
     test_utils::{test_func_type, test_local, test_local_id},
-    value::Value,
+    value::{DisplayMode, Value, ValueFormat},
 };
 
 #[test]
@@ -68,6 +68,24 @@ fn test_func_add_local_id_already_exists() {
     assert!(call_stack.add_func_stack(&func_type).is_err());
 }
 
+#[test]
+fn test_func_add_params_order() {
+    let mut call_stack = CallStack::new();
+    let func_type = test_func_type!(
+        (test_local!(ValType::I32), test_local!(ValType::I64)),
+        ()
+    );
+
+    let func_stack = call_stack.get_func_stack().unwrap();
+    func_stack.push(Value::I32(1)).unwrap();
+    func_stack.push(Value::I64(2)).unwrap();
+    call_stack.add_func_stack(&func_type).unwrap();
+
+    let locals = &call_stack.get_func_stack().unwrap().locals;
+    assert_eq!(locals.get(&Index::Num(0)).unwrap().clone(), Value::I32(1));
+    assert_eq!(locals.get(&Index::Num(1)).unwrap().clone(), Value::I64(2));
+}
+
 #[test]
 fn test_func_remove_not_enough_outputs_error() {
     let mut call_stack = CallStack::new();
@@ -151,6 +169,48 @@ fn test_block_commit_rollback() {
     assert!(func_stack.pop().is_err());
 }
 
+#[test]
+fn test_func_rollback() {
+    let mut call_stack = CallStack::new();
+    let func_type = test_func_type!((test_local!(ValType::I32)), ());
+
+    call_stack
+        .get_func_stack()
+        .unwrap()
+        .push(Value::I32(1))
+        .unwrap();
+    call_stack.commit();
+    call_stack.add_func_stack(&func_type).unwrap();
+    assert_eq!(call_stack.len(), 2);
+
+    call_stack.rollback();
+    assert_eq!(call_stack.len(), 1);
+    let func_stack = call_stack.get_func_stack().unwrap();
+    assert_eq!(func_stack.pop().unwrap(), Value::I32(1));
+}
+
+#[test]
+fn test_block_rollback() {
+    let mut call_stack = CallStack::new();
+    call_stack
+        .get_func_stack()
+        .unwrap()
+        .push(Value::I32(1))
+        .unwrap();
+    call_stack.commit();
+    call_stack.add_block_stack(&test_func_type!()).unwrap();
+    call_stack
+        .get_func_stack()
+        .unwrap()
+        .push(Value::I32(2))
+        .unwrap();
+
+    call_stack.rollback();
+    assert_eq!(call_stack.stack_len(), 1);
+    let func_stack = call_stack.get_func_stack().unwrap();
+    assert_eq!(func_stack.pop().unwrap(), Value::I32(1));
+}
+
 #[test]
 fn test_locals_commit_rollback() {
     let mut call_stack = CallStack::new();
@@ -208,6 +268,20 @@ fn test_block_add_invalid_inputs_error() {
     assert!(func_stack.add_block_stack(&func_type).is_err());
 }
 
+#[test]
+fn test_block_add_invalid_inputs_error_names_types() {
+    let mut func_stack = FuncStack::new();
+    let func_type = test_func_type!(
+        (test_local!(ValType::I32), test_local!(ValType::I32)),
+        ()
+    );
+
+    func_stack.push(Value::I32(1)).unwrap();
+    func_stack.push(Value::F32(2.0)).unwrap();
+    let err = func_stack.add_block_stack(&func_type).unwrap_err();
+    assert_eq!(err.to_string(), "type mismatch: expected i32, found f32");
+}
+
 #[test]
 fn test_block_remove_not_enough_outputs_error() {
     let mut func_stack = FuncStack::new();
@@ -225,7 +299,8 @@ fn test_block_remove_incorrect_type() {
     func_stack.add_block_stack(&func_type).unwrap();
 
     func_stack.push(Value::I64(2)).unwrap();
-    assert!(func_stack.remove_block_stack(&func_type, true).is_err());
+    let err = func_stack.remove_block_stack(&func_type, true).unwrap_err();
+    assert_eq!(err.to_string(), "type mismatch: expected i32, found i64");
 }
 
 #[test]
@@ -264,6 +339,16 @@ fn test_to_string() {
     assert_eq!(call_stack.to_string(), "[1, 2]");
 }
 
+#[test]
+fn test_to_typed_string() {
+    let mut call_stack = CallStack::new();
+    let func_stack = call_stack.get_func_stack().unwrap();
+    func_stack.push(Value::I64(1)).unwrap();
+    func_stack.push(Value::I32(2)).unwrap();
+    call_stack.commit();
+    assert_eq!(call_stack.to_typed_string(DisplayMode::Decimal), "[1: i64, 2: i32]");
+}
+
 #[test]
 fn test_len() {
     let mut call_stack = CallStack::new();
@@ -289,7 +374,7 @@ fn test_soft_string() {
     let func_stack = call_stack.get_func_stack().unwrap();
     func_stack.push(Value::I64(1)).unwrap();
     func_stack.push(Value::I32(2)).unwrap();
-    assert_eq!(func_stack.to_soft_string().unwrap(), "[1, 2]");
+    assert_eq!(func_stack.to_soft_string(ValueFormat::new()).unwrap(), "[1, 2]");
 }
 
 #[test]
@@ -1,9 +1,21 @@
 use anyhow::{Error, Result};
 use std::collections::HashMap;
 
+#[cfg(test)]
+thread_local! {
+    static LOOKUPS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// How many keys have been looked up on this thread, so that tests can
+/// check what resolving ids up front saves.
+#[cfg(test)]
+pub fn lookups() -> usize {
+    LOOKUPS.with(|lookups| lookups.get())
+}
+
 /// This dict is essentially a HashMap on which the changes can be
 /// commited or rolled back.
-
+#[derive(Clone)]
 pub struct Dict<T: Copy> {
     values: HashMap<String, T>,
     soft_values: HashMap<String, T>,
@@ -22,6 +34,8 @@ impl<T: Copy> Dict<T> {
     }
 
     pub fn get(&self, key: &str) -> Result<T> {
+        #[cfg(test)]
+        LOOKUPS.with(|lookups| lookups.set(lookups.get() + 1));
         match self.soft_values.get(key) {
             Some(value) => Ok(*value),
             None => match self.values.get(key) {
@@ -31,6 +45,11 @@ impl<T: Copy> Dict<T> {
         }
     }
 
+    /// The committed keys and values, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
     pub fn commit(&mut self) {
         self.soft_values.drain().for_each(|(k, v)| {
             self.values.insert(k, v);
@@ -71,6 +90,15 @@ mod tests {
         assert_eq!(dict.get("b").unwrap(), 2);
     }
 
+    #[test]
+    fn test_iter() {
+        let mut dict = Dict::new();
+        dict.set(String::from("a"), 1);
+        dict.commit();
+        dict.set(String::from("b"), 2);
+        assert_eq!(dict.iter().collect::<Vec<_>>(), vec![("a", &1)]);
+    }
+
     #[test]
     fn test_rollback() {
         let mut dict = Dict::new();
@@ -1,6 +1,8 @@
 use crate::{dict::Dict, list::List, model::Index};
 use anyhow::Result;
+use std::collections::HashMap;
 
+#[derive(Clone)]
 pub struct Elements<T> {
     values: List<T>,
     ids: Dict<usize>,
@@ -25,6 +27,26 @@ impl<T> Elements<T> {
         Ok(index)
     }
 
+    /// Replaces the element with the given id, keeping its index, or adds
+    /// it if there is none yet.
+    pub fn redefine(&mut self, id: String, value: T) -> Result<usize> {
+        match self.index_of(&id) {
+            Some(index) => {
+                self.values.set(index, value)?;
+                Ok(index)
+            }
+            None => self.grow(Some(id), value),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn index_of(&self, id: &str) -> Option<usize> {
+        self.ids.get(id).ok()
+    }
+
     fn set_by_num(&mut self, index: usize, value: T) -> Result<()> {
         self.values.set(index, value)
     }
@@ -57,6 +79,29 @@ impl<T> Elements<T> {
         }
     }
 
+    pub fn get_mut(&mut self, index: &Index) -> Result<&mut T> {
+        let index = match index {
+            Index::Id(id) => self.ids.get(id)?,
+            Index::Num(index) => *index as usize,
+        };
+        self.values.get_mut(index)
+    }
+
+    /// The committed elements, in the order of their index.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.values.iter()
+    }
+
+    /// The committed elements with their index and id, in the order of
+    /// their index.
+    pub fn entries(&self) -> impl Iterator<Item = (usize, Option<&str>, &T)> {
+        let ids: HashMap<usize, &str> = self.ids.iter().map(|(id, i)| (*i, id)).collect();
+        self.values
+            .iter()
+            .enumerate()
+            .map(move |(i, value)| (i, ids.get(&i).copied(), value))
+    }
+
     pub fn commit(&mut self) {
         self.values.commit();
         self.ids.commit();
@@ -88,6 +133,53 @@ mod tests {
         assert!(elements.grow(Some(String::from("a")), 0).is_err());
     }
 
+    #[test]
+    fn test_elements_len_index_of() {
+        let mut elements = Elements::new();
+        elements.grow(None, 0).unwrap();
+        elements.grow(Some(String::from("a")), 0).unwrap();
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements.index_of("a"), Some(1));
+        assert_eq!(elements.index_of("b"), None);
+    }
+
+    #[test]
+    fn test_elements_redefine() {
+        let mut elements = Elements::new();
+        elements.grow(None, 0).unwrap();
+        assert_eq!(elements.redefine(String::from("a"), 1).unwrap(), 1);
+        elements.commit();
+
+        assert_eq!(elements.redefine(String::from("a"), 2).unwrap(), 1);
+        assert_eq!(elements.get(&test_index("a")).unwrap().clone(), 2);
+        assert_eq!(elements.len(), 2);
+        elements.rollback();
+        assert_eq!(elements.get(&test_index("a")).unwrap().clone(), 1);
+    }
+
+    #[test]
+    fn test_elements_iter() {
+        let mut elements = Elements::new();
+        elements.grow(None, 1).unwrap();
+        elements.grow(Some(String::from("a")), 2).unwrap();
+        elements.commit();
+        elements.grow(None, 3).unwrap();
+        assert_eq!(elements.iter().collect::<Vec<_>>(), vec![&1, &2]);
+    }
+
+    #[test]
+    fn test_elements_entries() {
+        let mut elements = Elements::new();
+        elements.grow(None, 1).unwrap();
+        elements.grow(Some(String::from("a")), 2).unwrap();
+        elements.commit();
+        elements.grow(Some(String::from("b")), 3).unwrap();
+        assert_eq!(
+            elements.entries().collect::<Vec<_>>(),
+            vec![(0, None, &1), (1, Some("a"), &2)]
+        );
+    }
+
     #[test]
     fn test_elements_set_get_by_id() {
         let mut elements = Elements::new();
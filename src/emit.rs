@@ -0,0 +1,349 @@
+// Turns the model back into the text format, so that the definitions made
+// at the REPL can be saved and loaded again.
+//
+use crate::model::{
+    BlockType, Expression, Func, FuncType, Global, Index, Instruction, Local, MemArg,
+};
+use crate::value::Value;
+
+impl Func {
+    /// Renders the function as a `(func ...)` definition, one instruction
+    /// per line, with `block`, `loop` and `if` closed by `end`.
+    pub fn to_wat(&self) -> String {
+        let mut head = vec![String::from("func")];
+        head.extend(self.id.as_ref().map(|id| format!("${}", id)));
+        head.extend(params_wat(&self.ty.params));
+        head.extend(results_wat(&self.ty));
+        for local in self.line_expression.locals.iter() {
+            head.push(local_wat("local", local));
+        }
+
+        let mut lines = vec![format!("({}", head.join(" "))];
+        self.line_expression.expr.wat_lines(1, &mut lines);
+        let last = lines.len() - 1;
+        lines[last].push(')');
+        lines.join("\n")
+    }
+}
+
+impl Global {
+    /// Renders the global as a `(global ...)` definition, with its init
+    /// expression on the same line.
+    pub fn to_wat(&self) -> String {
+        let mut head = vec![String::from("global")];
+        head.extend(self.id.as_ref().map(|id| format!("${}", id)));
+        if self.mutable {
+            head.push(format!("(mut {})", self.ty));
+        } else {
+            head.push(self.ty.to_string());
+        }
+        let init: Vec<String> = self.init.instrs.iter().map(Instruction::to_wat).collect();
+        format!("({} {})", head.join(" "), init.join(" "))
+    }
+}
+
+impl Expression {
+    /// Renders the instructions one per line.
+    pub fn to_wat(&self) -> String {
+        let mut lines = Vec::new();
+        self.wat_lines(0, &mut lines);
+        lines.join("\n")
+    }
+
+    fn wat_lines(&self, depth: usize, lines: &mut Vec<String>) {
+        let indent = "  ".repeat(depth);
+        for instr in self.instrs.iter() {
+            match instr {
+                Instruction::If(bt, if_block, else_block) => {
+                    lines.push(format!("{}if{}", indent, block_type_wat(bt)));
+                    if let Some(block) = if_block {
+                        block.wat_lines(depth + 1, lines);
+                    }
+                    if let Some(block) = else_block {
+                        lines.push(format!("{}else", indent));
+                        block.wat_lines(depth + 1, lines);
+                    }
+                    lines.push(format!("{}end", indent));
+                }
+                Instruction::Block(bt, block) | Instruction::Loop(bt, block) => {
+                    lines.push(format!(
+                        "{}{}{}",
+                        indent,
+                        instr.mnemonic(),
+                        block_type_wat(bt)
+                    ));
+                    if let Some(block) = block {
+                        block.wat_lines(depth + 1, lines);
+                    }
+                    lines.push(format!("{}end", indent));
+                }
+                instr => lines.push(format!("{}{}", indent, instr.to_wat())),
+            }
+        }
+    }
+}
+
+impl Instruction {
+    /// Renders the instruction with its immediates, like `i32.const 1`.
+    /// The body of a `block`, `loop` or `if` is left out.
+    pub fn to_wat(&self) -> String {
+        let args = match self {
+            Instruction::I32Const(n) => Value::from(*n).to_literal(),
+            Instruction::I64Const(n) => Value::from(*n).to_literal(),
+            Instruction::F32Const(n) => Value::from(*n).to_literal(),
+            Instruction::F64Const(n) => Value::from(*n).to_literal(),
+            Instruction::LocalGet(index)
+            | Instruction::LocalSet(index)
+            | Instruction::LocalTee(index)
+            | Instruction::GlobalGet(index)
+            | Instruction::GlobalSet(index)
+            | Instruction::Call(index)
+            | Instruction::ReturnCall(index)
+            | Instruction::MemoryInit(index)
+            | Instruction::DataDrop(index)
+            | Instruction::ElemDrop(index)
+            | Instruction::TableGet(index)
+            | Instruction::TableSet(index)
+            | Instruction::TableSize(index)
+            | Instruction::TableGrow(index)
+            | Instruction::RefFunc(index)
+            | Instruction::Br(index)
+            | Instruction::BrIf(index) => index_wat(index),
+            Instruction::I32Load(arg) | Instruction::I32Store(arg) => mem_arg_wat(arg),
+            Instruction::RefNull => String::from("func"),
+            Instruction::TableInit(table, elem) => {
+                format!("{} {}", index_wat(table), index_wat(elem))
+            }
+            Instruction::CallIndirect(table, ty) | Instruction::ReturnCallIndirect(table, ty) => {
+                let mut parts = vec![index_wat(table)];
+                parts.extend(params_wat(&ty.params));
+                parts.extend(results_wat(ty));
+                parts.join(" ")
+            }
+            Instruction::BrTable(labels, default) => {
+                let labels = labels.iter().chain([default]).map(index_wat);
+                labels.collect::<Vec<_>>().join(" ")
+            }
+            Instruction::If(bt, ..) | Instruction::Block(bt, _) | Instruction::Loop(bt, _) => {
+                return format!("{}{}", self.mnemonic(), block_type_wat(bt));
+            }
+            _ => String::new(),
+        };
+
+        if args.is_empty() {
+            self.mnemonic().to_string()
+        } else {
+            format!("{} {}", self.mnemonic(), args)
+        }
+    }
+}
+
+fn index_wat(index: &Index) -> String {
+    match index {
+        Index::Id(id) => format!("${}", id),
+        Index::Num(num) => num.to_string(),
+    }
+}
+
+/// Leaves out the offset and alignment when they are the defaults of a
+/// 4 byte access.
+fn mem_arg_wat(arg: &MemArg) -> String {
+    let mut parts = Vec::new();
+    if arg.offset != 0 {
+        parts.push(format!("offset={}", arg.offset));
+    }
+    if arg.align != 4 {
+        parts.push(format!("align={}", arg.align));
+    }
+    parts.join(" ")
+}
+
+fn block_type_wat(bt: &BlockType) -> String {
+    let mut parts = vec![String::new()];
+    parts.extend(bt.label.as_ref().map(|label| format!("${}", label)));
+    parts.extend(params_wat(&bt.ty.params));
+    parts.extend(results_wat(&bt.ty));
+    parts.join(" ")
+}
+
+/// Params with an id each get a `(param $id ty)` of their own.
+fn params_wat(params: &[Local]) -> Vec<String> {
+    if params.iter().any(|param| param.id.is_some()) {
+        params
+            .iter()
+            .map(|param| local_wat("param", param))
+            .collect()
+    } else {
+        let ty = FuncType {
+            params: params.to_vec(),
+            results: vec![],
+        };
+        Some(ty.to_string())
+            .filter(|ty| !ty.is_empty())
+            .into_iter()
+            .collect()
+    }
+}
+
+fn results_wat(ty: &FuncType) -> Option<String> {
+    let ty = FuncType {
+        params: vec![],
+        results: ty.results.clone(),
+    };
+    Some(ty.to_string()).filter(|ty| !ty.is_empty())
+}
+
+fn local_wat(kind: &str, local: &Local) -> String {
+    match &local.id {
+        Some(id) => format!("({} ${} {})", kind, id, local.val_type),
+        None => format!("({} {})", kind, local.val_type),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::executor::Executor;
+    use crate::model::{Expression, Func, Global, Instruction, Line, ValType};
+    use crate::parser::parse_line;
+    use wast::parser::ParseBuffer;
+
+    fn parse_func(src: &str) -> Func {
+        let buf = ParseBuffer::new(src).unwrap();
+        match Line::try_from(&parse_line(&buf).unwrap()).unwrap() {
+            Line::Func(func) => func,
+            _ => panic!("Expected Line::Func"),
+        }
+    }
+
+    /// Defines the function, saves it and parses the saved text back.
+    fn round_trip(src: &str) -> String {
+        let mut executor = Executor::new();
+        executor.eval(src).unwrap();
+        let func = executor.funcs().next().unwrap().clone();
+        let wat = func.to_wat();
+        let saved = parse_func(&wat);
+        assert_eq!(saved.id, func.id);
+        assert_eq!(saved.ty, func.ty);
+        assert_eq!(saved.line_expression.locals, func.line_expression.locals);
+        assert!(saved
+            .line_expression
+            .expr
+            .structural_eq(&func.line_expression.expr, false));
+        wat
+    }
+
+    #[test]
+    fn test_func_to_wat() {
+        let wat = round_trip(
+            "(func $add (param $a i32) (param $b i32) (result i32) (local i64) \
+            local.get $a local.get $b i32.add)",
+        );
+        assert_eq!(
+            wat,
+            "(func $add (param $a i32) (param $b i32) (result i32) (local i64)\n  \
+            local.get 0\n  \
+            local.get 1\n  \
+            i32.add)"
+        );
+    }
+
+    #[test]
+    fn test_empty_func_to_wat() {
+        let wat = round_trip("(func)");
+        assert_eq!(wat, "(func)");
+    }
+
+    #[test]
+    fn test_blocks_to_wat() {
+        let wat = round_trip(
+            "(func $f (param i32) (result i32) \
+            (block $outer (result i32) \
+              (local.get 0) \
+              (loop $inner (param i32) (result i32) \
+                (br_if $inner (i32.eqz (local.get 0))))) \
+            (if (result i32) (then (i32.const 1)) (else (i32.const 2))))",
+        );
+        assert_eq!(
+            wat,
+            "(func $f (param i32) (result i32)\n  \
+            block $outer (result i32)\n    \
+              local.get 0\n    \
+              loop $inner (param i32) (result i32)\n      \
+                local.get 0\n      \
+                i32.eqz\n      \
+                br_if $inner\n    \
+              end\n  \
+            end\n  \
+            if (result i32)\n    \
+              i32.const 1\n  \
+            else\n    \
+              i32.const 2\n  \
+            end)"
+        );
+    }
+
+    #[test]
+    fn test_instructions_to_wat() {
+        round_trip(
+            "(func $g (param i32) (result f64) \
+            (drop (i64.const -1)) (drop (f32.const -1.5)) \
+            (if (local.get 0) (then (nop))) \
+            (block (br_table 0 0 (local.get 0))) \
+            (memory.size) (i32.load offset=4 align=2) (i32.store (i32.const 0)) \
+            (f64.const -0))",
+        );
+    }
+
+    #[test]
+    fn test_float_const_to_wat() {
+        let instr = Instruction::F32Const(f32::from_bits(0x7fa00000));
+        assert_eq!(instr.to_wat(), "f32.const nan:0x200000");
+        let instr = Instruction::F64Const(f64::NEG_INFINITY);
+        assert_eq!(instr.to_wat(), "f64.const -inf");
+    }
+
+    #[test]
+    fn test_calls_to_wat() {
+        let mut executor = Executor::new();
+        executor
+            .eval("(func $f (param i32) (result i32) (local.get 0))")
+            .unwrap();
+        let src = "(func $g (result i32 i32) (call $f (i32.const 1)) \
+            (call_indirect (param i32) (result i32) (i32.const 2) (i32.const 0)))";
+        executor.eval(src).unwrap();
+        let func = executor.funcs().nth(1).unwrap();
+        assert_eq!(
+            func.to_wat(),
+            "(func $g (result i32 i32)\n  \
+            i32.const 1\n  \
+            call 0\n  \
+            i32.const 2\n  \
+            i32.const 0\n  \
+            call_indirect 0 (param i32) (result i32))"
+        );
+
+        let mut func = func.clone();
+        func.name_func_ids(|index| (index == 0).then(|| String::from("f")));
+        assert!(func.to_wat().contains("\n  call $f\n"));
+    }
+
+    #[test]
+    fn test_global_to_wat() {
+        let global = Global {
+            id: Some(String::from("g")),
+            ty: ValType::I64,
+            mutable: true,
+            init: Expression {
+                instrs: vec![Instruction::I64Const(-1)],
+            },
+        };
+        assert_eq!(global.to_wat(), "(global $g (mut i64) i64.const -1)");
+
+        let global = Global {
+            id: None,
+            mutable: false,
+            ..global
+        };
+        assert_eq!(global.to_wat(), "(global i64 i64.const -1)");
+    }
+}
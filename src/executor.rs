@@ -1,18 +1,148 @@
 use anyhow::{anyhow, Result};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
 
 use crate::call_stack::CallStack;
 use crate::elements::Elements;
+use crate::globals::Globals;
 use crate::handler::Handler;
-use crate::model::{BlockType, Expression, Func, Index, Instruction, Local, ValType};
-use crate::model::{Line, LineExpression};
+use crate::memory::{Memory, MAX_PAGES};
+use crate::model::ValType;
+use crate::model::{BlockType, Data, Elem, Expression, Func, FuncType, Global, Index, Instruction};
+use crate::model::{Line, LineExpression, Local, MemArg, Memory as MemoryDef, Module, Table};
+use crate::parser::{const_error, locate_error, parse_line, rewrite_binary_literals};
 use crate::response::{Control, Response};
-use crate::value::Value;
+use crate::tables::Tables;
+use crate::validate::{validate_func, Context};
+use crate::value::{DisplayMode, Value, ValueFormat};
+use wast::parser::ParseBuffer;
 
-const MAX_STACK_SIZE: i32 = 100;
+const MAX_DEPTH: usize = 100;
+const MAX_LOCALS: usize = 50_000;
+
+// Built-in function printing the operand stack of the calling function,
+// unless a function with the same id is defined.
+const DEBUG_FUNC: &str = "debug";
 
 pub struct Executor {
     call_stack: CallStack,
     funcs: Elements<Func>,
+    globals: Globals,
+    memory: Memory,
+    memory_declared: bool,
+    memory_id: Option<String>,
+    memory_import: Option<(String, String)>,
+    memory_exports: Vec<String>,
+    host_memories: HashMap<(String, String), Vec<u8>>,
+    datas: Elements<Vec<u8>>,
+    elems: Elements<Vec<u32>>,
+    tables: Tables,
+    format: ValueFormat,
+    warn_residue: bool,
+    canonicalize_nan: bool,
+    checked: bool,
+    max_locals: usize,
+    max_depth: usize,
+    quiet: bool,
+    output: Vec<String>,
+}
+
+/// The kinds of definition the REPL can hold.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum DefinitionKind {
+    Func,
+    Global,
+    Table,
+    Memory,
+    Data,
+    Elem,
+}
+
+/// A definition, with the index and id it can be referred to by.
+#[derive(PartialEq, Debug)]
+pub struct Definition {
+    pub kind: DefinitionKind,
+    pub index: u32,
+    pub id: Option<String>,
+}
+
+/// An error raised by an instruction, with its position in the sequence
+/// it was executed from. Only the innermost failing instruction is
+/// recorded, enclosing blocks pass the error on unchanged.
+#[derive(Debug)]
+pub struct InstrError {
+    pub position: usize,
+    pub mnemonic: &'static str,
+    pub source: anyhow::Error,
+}
+
+impl Display for InstrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}, while executing instruction {} ({})",
+            self.source, self.position, self.mnemonic
+        )
+    }
+}
+
+impl std::error::Error for InstrError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// A line of instructions being executed one instruction at a time, see
+/// `Executor::step_line`.
+pub struct Stepper {
+    frames: Vec<Frame>,
+}
+
+/// An expression being stepped through, with the next instruction to run.
+struct Frame {
+    expr: Expression,
+    ip: usize,
+    kind: FrameKind,
+}
+
+enum FrameKind {
+    Line,
+    Block(BlockType),
+    Loop(BlockType),
+}
+
+impl Stepper {
+    /// Whether the line has run to its end.
+    pub fn is_done(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The instruction the next step runs.
+    pub fn next_instr(&self) -> Option<&Instruction> {
+        let frame = self.frames.last()?;
+        frame.expr.instrs.get(frame.ip)
+    }
+}
+
+/// Committed state of an `Executor`, which can be restored later.
+pub struct Snapshot {
+    call_stack: CallStack,
+    funcs: Elements<Func>,
+    globals: Globals,
+    memory: Memory,
+    memory_declared: bool,
+    memory_id: Option<String>,
+    memory_import: Option<(String, String)>,
+    memory_exports: Vec<String>,
+    datas: Elements<Vec<u8>>,
+    elems: Elements<Vec<u32>>,
+    tables: Tables,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Executor::new()
+    }
 }
 
 impl Executor {
@@ -20,62 +150,997 @@ impl Executor {
         Executor {
             call_stack: CallStack::new(),
             funcs: Elements::new(),
+            globals: Globals::new(),
+            memory: Memory::new(),
+            memory_declared: false,
+            memory_id: None,
+            memory_import: None,
+            memory_exports: Vec::new(),
+            host_memories: HashMap::new(),
+            datas: Elements::new(),
+            elems: Elements::new(),
+            tables: Tables::new(),
+            format: ValueFormat::new(),
+            warn_residue: false,
+            canonicalize_nan: false,
+            checked: false,
+            max_locals: MAX_LOCALS,
+            max_depth: MAX_DEPTH,
+            quiet: false,
+            output: Vec::new(),
         }
     }
 
+    /// Drops every definition and value, keeping the settings, like the
+    /// display mode, the depth limit and whether the output is quiet.
+    pub fn reset(&mut self) {
+        *self = Executor {
+            format: self.format,
+            warn_residue: self.warn_residue,
+            canonicalize_nan: self.canonicalize_nan,
+            checked: self.checked,
+            max_locals: self.max_locals,
+            max_depth: self.max_depth,
+            quiet: self.quiet,
+            ..Executor::new()
+        };
+    }
+
+    pub fn set_display_mode(&mut self, mode: DisplayMode) {
+        self.format.mode = mode;
+    }
+
+    /// Shows the type of each value on the stack, as in `[i32:42, i64:1]`.
+    pub fn set_typed(&mut self, typed: bool) {
+        self.format.typed = typed;
+    }
+
+    pub fn set_warn_residue(&mut self, warn_residue: bool) {
+        self.warn_residue = warn_residue;
+    }
+
+    /// Makes NaN results of float arithmetic canonical, so sessions are
+    /// reproducible across platforms.
+    pub fn set_canonicalize_nan(&mut self, canonicalize_nan: bool) {
+        self.canonicalize_nan = canonicalize_nan;
+    }
+
+    /// Makes i32 and i64 add, sub and mul error with `integer overflow`,
+    /// rather than wrapping around as wasm does.
+    pub fn set_checked(&mut self, checked: bool) {
+        self.checked = checked;
+    }
+
+    /// Limits the number of params and locals a single frame can have.
+    pub fn set_max_locals(&mut self, max_locals: usize) {
+        self.max_locals = max_locals;
+    }
+
+    /// Limits how deep calls can nest before a stack overflow.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Leaves out the `func ;0;` and `local ;0;` lines echoed for
+    /// definitions, so only stack results are shown.
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    /// Parses and executes `src` as a line typed at the REPL, returning the
+    /// resulting stack or the definition made.
+    ///
+    /// ```
+    /// use wasmrepl::executor::Executor;
+    ///
+    /// let mut executor = Executor::new();
+    /// assert_eq!(executor.eval("(i32.const 0b101)").unwrap(), "[5]");
+    /// let func = "(func $inc (param i32) (result i32) (local.get 0) (i32.const 1) (i32.add))";
+    /// assert_eq!(executor.eval(func).unwrap(), "func ;0; inc");
+    /// assert_eq!(executor.eval("(call $inc)").unwrap(), "[6]");
+    /// ```
+    pub fn eval(&mut self, src: &str) -> Result<String> {
+        let rewrite = rewrite_binary_literals(src);
+        let buf = ParseBuffer::new(&rewrite.src)
+            .map_err(|err| rewrite.original_error(src, err.into()))?;
+        let wast_line = parse_line(&buf).map_err(|err| {
+            let err = rewrite.original_error(src, err);
+            match const_error(src, &err) {
+                Some(message) => anyhow!(message),
+                None => err,
+            }
+        })?;
+        let line = Line::try_from(&wast_line).map_err(|err| locate_error(src, err))?;
+        Ok(self.execute_line(line)?.message())
+    }
+
     pub fn execute_line(&mut self, line: Line) -> Result<Response> {
         match line {
             Line::Expression(line) => self.execute_repl_line(line),
             Line::Func(func) => self.execute_add_func(func),
+            Line::Global(global) => self.execute_add_global(global),
+            Line::Table(table) => self.execute_add_table(table),
+            Line::Memory(memory) => self.execute_add_memory(memory),
+            Line::Elem(elem) => self.execute_add_elem(elem),
+            Line::Data(data) => self.execute_add_data(data),
+            Line::AssertTrap(expr, message) => self.execute_assert_trap(expr, message),
+            Line::Module(module) => self.execute_add_module(module),
+        }
+    }
+
+    /// Starts executing a line of instructions one at a time. Locals are
+    /// added right away, the instructions run on each call to `step`.
+    pub fn step_line(&mut self, line: Line) -> Result<Stepper> {
+        let line = match line {
+            Line::Expression(line) => line,
+            _ => return Err(anyhow!("Only a line of instructions can be stepped")),
+        };
+        let mut stepper = Stepper {
+            frames: vec![Frame {
+                expr: line.expr,
+                ip: 0,
+                kind: FrameKind::Line,
+            }],
+        };
+        let result = line
+            .locals
+            .iter()
+            .try_for_each(|lc| self.execute_local(lc).map(|_| ()))
+            .and_then(|_| self.end_frames(&mut stepper, Control::None, true));
+        if let Err(err) = result {
+            self.rollback();
+            return Err(err);
+        }
+        Ok(stepper)
+    }
+
+    /// Executes the next instruction of a stepped line, and responds with
+    /// the stack of the innermost block after it. Blocks and loops are
+    /// stepped into, while a call runs the whole function in one step.
+    /// The line is committed after its last instruction, or rolled back
+    /// on an error.
+    pub fn step(&mut self, stepper: &mut Stepper) -> Result<Response> {
+        match self.step_instr(stepper) {
+            Ok(()) => {
+                let mut response = Response::new();
+                for message in self.output.drain(..) {
+                    response.add_message(message);
+                }
+                let func_stack = self.call_stack.get_func_stack()?;
+                response.add_message(func_stack.to_soft_string(self.format)?);
+                Ok(response)
+            }
+            Err(err) => {
+                stepper.frames.clear();
+                self.rollback();
+                Err(err)
+            }
+        }
+    }
+
+    /// Drops a stepped line which has not run to its end, along with the
+    /// changes made so far.
+    pub fn stop_line(&mut self, stepper: Stepper) {
+        if !stepper.is_done() {
+            self.rollback();
+        }
+    }
+
+    fn step_instr(&mut self, stepper: &mut Stepper) -> Result<()> {
+        let frame = match stepper.frames.last_mut() {
+            Some(frame) => frame,
+            None => return Err(anyhow!("Nothing left to step")),
+        };
+        let (position, instr) = (frame.ip + 1, frame.expr.instrs[frame.ip].clone());
+        frame.ip += 1;
+
+        let response = self
+            .execute_single(instr.clone())
+            .map_err(|err| at_instr(err, position, &instr))?;
+        let (expr, kind) = match response.control {
+            Control::ExecBlock(block_type, expr) => (expr, FrameKind::Block(block_type)),
+            Control::ExecLoop(block_type, expr) => (expr, FrameKind::Loop(block_type)),
+            control => return self.end_frames(stepper, control, response.requires_empty),
+        };
+        if let FrameKind::Block(block_type) | FrameKind::Loop(block_type) = &kind {
+            self.call_stack.add_block_stack(&block_type.ty)?;
         }
+        stepper.frames.push(Frame { expr, ip: 0, kind });
+        self.end_frames(stepper, Control::None, true)
+    }
+
+    /// Leaves the frames which have run to their end or are escaped by a
+    /// branch or return, the same way `execute_block` and `execute_loop`
+    /// do, and commits the line once it is left.
+    fn end_frames(
+        &mut self,
+        stepper: &mut Stepper,
+        mut control: Control,
+        mut requires_empty: bool,
+    ) -> Result<()> {
+        while let Some(frame) = stepper.frames.last_mut() {
+            if !is_escaping(&control) && frame.ip < frame.expr.instrs.len() {
+                return Ok(());
+            }
+            match &frame.kind {
+                FrameKind::Loop(block_type) if is_target(&control, &block_type.label) => {
+                    self.call_stack
+                        .restart_block_stack(&block_type.ty, requires_empty)?;
+                    frame.ip = 0;
+                    return Ok(());
+                }
+                FrameKind::Block(block_type) | FrameKind::Loop(block_type) => {
+                    if !is_target(&control, &block_type.label) && is_escaping(&control) {
+                        self.call_stack.unwind_block_stack()?;
+                        control = outer_control(control);
+                    } else {
+                        self.call_stack
+                            .remove_block_stack(&block_type.ty, requires_empty)?;
+                        control = Control::None;
+                    }
+                    requires_empty = true;
+                }
+                FrameKind::Line => {
+                    verify_repl_result(Ok(Response::new_ctrl(control)))?;
+                    self.commit();
+                    stepper.frames.clear();
+                    return Ok(());
+                }
+            }
+            stepper.frames.pop();
+        }
+        Ok(())
+    }
+
+    /// Pops the top value off the stack, outside of any line.
+    pub fn pop_value(&mut self) -> Result<Value> {
+        if self.call_stack.stack_len() == 0 {
+            return Err(anyhow!("Stack is empty"));
+        }
+
+        let value = self.call_stack.get_func_stack()?.pop()?;
+        self.call_stack.commit();
+        Ok(value)
+    }
+
+    /// The value on top of the stack as of the last line, without
+    /// popping it.
+    pub fn peek_top(&self) -> Option<Value> {
+        self.call_stack.values().last().cloned()
+    }
+
+    /// Drops every value on the stack, keeping definitions and locals.
+    pub fn clear_stack(&mut self) -> Result<()> {
+        let func_stack = self.call_stack.get_func_stack()?;
+        for _ in 0..func_stack.len() {
+            func_stack.pop()?;
+        }
+        self.call_stack.commit();
+        Ok(())
+    }
+
+    /// Everything defined so far, in the order of kind and index. The
+    /// single memory is listed once it has pages, whether it was defined
+    /// or grown into.
+    pub fn definitions(&self) -> Vec<Definition> {
+        let funcs = (0..self.funcs.len() as u32).map(|index| Definition {
+            kind: DefinitionKind::Func,
+            index,
+            id: self.funcs.get(&Index::Num(index)).unwrap().id.clone(),
+        });
+        let globals = (0..self.globals.len() as u32).map(|index| Definition {
+            kind: DefinitionKind::Global,
+            index,
+            id: self.globals.id(&Index::Num(index)).unwrap(),
+        });
+        let tables = (0..self.tables.len() as u32).map(|index| Definition {
+            kind: DefinitionKind::Table,
+            index,
+            id: self.tables.id(&Index::Num(index)).unwrap(),
+        });
+        let has_memory = self.memory.size() > 0 || self.memory_import.is_some();
+        let memory = has_memory.then(|| Definition {
+            kind: DefinitionKind::Memory,
+            index: 0,
+            id: self.memory_id.clone(),
+        });
+        let datas = self.datas.entries().map(|(index, id, _)| Definition {
+            kind: DefinitionKind::Data,
+            index: index as u32,
+            id: id.map(String::from),
+        });
+        let elems = self.elems.entries().map(|(index, id, _)| Definition {
+            kind: DefinitionKind::Elem,
+            index: index as u32,
+            id: id.map(String::from),
+        });
+        funcs
+            .chain(globals)
+            .chain(tables)
+            .chain(memory)
+            .chain(datas)
+            .chain(elems)
+            .collect()
+    }
+
+    /// Index, id and type of each defined function.
+    pub fn list_funcs(&self) -> Vec<(usize, Option<String>, FuncType)> {
+        self.funcs
+            .iter()
+            .enumerate()
+            .map(|(i, func)| (i, func.id.clone(), func.ty.clone()))
+            .collect()
+    }
+
+    /// Index, id and value of each local, as of the last line.
+    pub fn list_locals(&self) -> Vec<(usize, Option<String>, Value)> {
+        self.call_stack
+            .locals()
+            .iter()
+            .map(|(i, id, value)| (i, id.map(String::from), value.clone()))
+            .collect()
+    }
+
+    /// The value of a global, and whether it is mutable.
+    pub fn global(&self, index: &Index) -> Result<(Value, bool)> {
+        let value = self.globals.get(index).map_err(|_| unknown_global(index))?;
+        let (_, mutable) = self.globals.ty(index)?;
+        Ok((value.clone(), mutable))
+    }
+
+    /// Sets a mutable global, outside of any line.
+    pub fn set_global(&mut self, index: &Index, value: Value) -> Result<()> {
+        self.globals.get(index).map_err(|_| unknown_global(index))?;
+        match self.globals.set(index, value) {
+            Ok(()) => {
+                self.globals.commit();
+                Ok(())
+            }
+            Err(err) => {
+                self.globals.rollback();
+                Err(err)
+            }
+        }
+    }
+
+    /// Provides the bytes of a memory for a `(memory (import "module"
+    /// "name") ...)` definition to take.
+    pub fn define_host_memory(&mut self, module: &str, name: &str, bytes: Vec<u8>) {
+        let key = (String::from(module), String::from(name));
+        self.host_memories.insert(key, bytes);
+    }
+
+    /// The committed bytes of a host memory, as changed by the lines run
+    /// since it was imported.
+    pub fn host_memory(&self, module: &str, name: &str) -> Option<&[u8]> {
+        let key = (String::from(module), String::from(name));
+        if self.memory_import.as_ref() == Some(&key) {
+            return Some(self.memory.bytes());
+        }
+        self.host_memories.get(&key).map(Vec::as_slice)
+    }
+
+    /// The committed bytes of the memory, if it is exported by the name.
+    pub fn exported_memory(&self, name: &str) -> Option<&[u8]> {
+        let exported = self.memory_exports.iter().any(|export| export == name);
+        exported.then(|| self.memory.bytes())
+    }
+
+    /// The defined functions, in the order of their indices.
+    pub fn funcs(&self) -> impl Iterator<Item = &Func> {
+        self.funcs.iter()
+    }
+
+    /// Lists who calls whom among the defined functions, one function per
+    /// line, like `$a -> $b, $c`.
+    pub fn call_graph(&self) -> String {
+        let mut lines = Vec::new();
+        let definitions = self.definitions();
+        let funcs = definitions
+            .iter()
+            .filter(|def| def.kind == DefinitionKind::Func);
+        for def in funcs {
+            let index = Index::Num(def.index);
+            let func = self.funcs.get(&index).unwrap();
+            let mut callees: Vec<String> = Vec::new();
+            for call in func.line_expression.expr.calls() {
+                let callee = self.func_name(call);
+                if !callees.contains(&callee) {
+                    callees.push(callee);
+                }
+            }
+
+            let name = self.func_name(&index);
+            if callees.is_empty() {
+                lines.push(name);
+            } else {
+                lines.push(format!("{} -> {}", name, callees.join(", ")));
+            }
+        }
+        lines.join("\n")
+    }
+
+    fn func_name(&self, index: &Index) -> String {
+        match (index, self.funcs.get(index)) {
+            (_, Ok(Func { id: Some(id), .. })) | (Index::Id(id), _) => format!("${}", id),
+            (Index::Num(num), _) => num.to_string(),
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        // Lines are either committed or rolled back as a whole, so in
+        // between lines there is no soft state to leave out.
+        Snapshot {
+            call_stack: self.call_stack.clone(),
+            funcs: self.funcs.clone(),
+            globals: self.globals.clone(),
+            memory: self.memory.clone(),
+            memory_declared: self.memory_declared,
+            memory_id: self.memory_id.clone(),
+            memory_import: self.memory_import.clone(),
+            memory_exports: self.memory_exports.clone(),
+            datas: self.datas.clone(),
+            elems: self.elems.clone(),
+            tables: self.tables.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.call_stack = snapshot.call_stack.clone();
+        self.funcs = snapshot.funcs.clone();
+        self.globals = snapshot.globals.clone();
+        self.memory = snapshot.memory.clone();
+        self.memory_declared = snapshot.memory_declared;
+        self.memory_id = snapshot.memory_id.clone();
+        self.memory_import = snapshot.memory_import.clone();
+        self.memory_exports = snapshot.memory_exports.clone();
+        self.datas = snapshot.datas.clone();
+        self.elems = snapshot.elems.clone();
+        self.tables = snapshot.tables.clone();
+    }
+
+    /// The committed stack, with the type of each value.
+    pub fn stack_string(&self) -> String {
+        self.call_stack.to_typed_string(self.format.mode)
     }
 
     fn to_state(&self) -> String {
-        self.call_stack.to_string()
+        self.call_stack.format(self.format)
     }
 
     fn execute_add_func(&mut self, func: Func) -> Result<Response> {
         let id = func.id.clone();
-        self.funcs
-            .grow(func.id.clone(), func)
-            .map(|i| Response::new_index("func", i, id))
+        match self.add_func(func) {
+            Ok(i) => {
+                self.funcs.commit();
+                Ok(self.new_index("func", i, id))
+            }
+            Err(err) => {
+                self.funcs.rollback();
+                Err(err)
+            }
+        }
+    }
+
+    fn execute_add_global(&mut self, global: Global) -> Result<Response> {
+        let id = global.id.clone();
+        match self.add_global(global) {
+            Ok(i) => {
+                self.globals.commit();
+                Ok(self.new_index("global", i, id))
+            }
+            Err(err) => {
+                self.globals.rollback();
+                Err(err)
+            }
+        }
+    }
+
+    fn add_global(&mut self, global: Global) -> Result<usize> {
+        let value = self.eval_const(&global.init)?;
+        value.is_same_type(&global.ty)?;
+        self.globals.grow(global.id, value, global.mutable)
+    }
+
+    fn execute_add_table(&mut self, table: Table) -> Result<Response> {
+        let id = table.id.clone();
+        match self.add_table(table) {
+            Ok(i) => {
+                self.tables.commit();
+                Ok(self.new_index("table", i, id))
+            }
+            Err(err) => {
+                self.tables.rollback();
+                Err(err)
+            }
+        }
+    }
+
+    fn execute_add_memory(&mut self, memory: MemoryDef) -> Result<Response> {
+        // A memory grown into by `memory.grow` counts as well.
+        if self.memory_declared || self.memory.size() > 0 {
+            return Err(anyhow!("only one memory is supported"));
+        }
+        if memory.max.is_some_and(|max| max < memory.min) {
+            return Err(anyhow!("size minimum must not be greater than maximum"));
+        }
+
+        match &memory.import {
+            Some((module, name)) => {
+                let key = (module.clone(), name.clone());
+                let bytes = self
+                    .host_memories
+                    .get(&key)
+                    .ok_or_else(|| anyhow!("unknown import {}.{}", module, name))?;
+                let mut host = Memory::from_bytes(bytes.clone())?;
+                if host.size() < memory.min || memory.max.is_some_and(|max| host.size() > max) {
+                    return Err(anyhow!("incompatible import type"));
+                }
+                host.set_max(memory.max);
+                self.host_memories.remove(&key);
+                self.memory = host;
+            }
+            None => {
+                if memory.min > MAX_PAGES {
+                    return Err(anyhow!("memory size must be at most {} pages", MAX_PAGES));
+                }
+                self.memory.set_max(memory.max);
+                self.memory.grow(memory.min);
+                self.memory.commit();
+            }
+        }
+
+        self.memory_declared = true;
+        self.memory_id = memory.id.clone();
+        self.memory_import = memory.import;
+        self.memory_exports = memory.exports;
+        Ok(self.new_index("memory", 0, memory.id))
+    }
+
+    fn add_table(&mut self, table: Table) -> Result<usize> {
+        if table.max.is_some_and(|max| max < table.min) {
+            return Err(anyhow!("size minimum must not be greater than maximum"));
+        }
+        self.tables.grow(table.id, table.min, table.max)
+    }
+
+    fn execute_add_elem(&mut self, elem: Elem) -> Result<Response> {
+        let id = elem.id.clone();
+        match self.add_elem(elem) {
+            Ok(i) => {
+                self.tables.commit();
+                self.elems.commit();
+                Ok(self.new_index("elem", i, id))
+            }
+            Err(err) => {
+                self.tables.rollback();
+                self.elems.rollback();
+                Err(err)
+            }
+        }
+    }
+
+    /// Puts an active segment into its table, after which it is dropped,
+    /// as when a module is instantiated.
+    fn add_elem(&mut self, elem: Elem) -> Result<usize> {
+        let funcs = elem
+            .funcs
+            .iter()
+            .map(|index| self.func_index(index))
+            .collect::<Result<Vec<_>>>()?;
+
+        let funcs = match &elem.active {
+            Some((table, offset)) => {
+                let offset: i32 = self.eval_const(offset)?.try_into()?;
+                self.tables.init(table, offset as u32, &funcs)?;
+                Vec::new()
+            }
+            None => funcs,
+        };
+        self.elems.grow(elem.id, funcs)
+    }
+
+    fn func_index(&self, index: &Index) -> Result<u32> {
+        match index {
+            Index::Num(num) => self.funcs.get(index).map(|_| *num),
+            Index::Id(id) => match self.funcs.index_of(id) {
+                Some(num) => Ok(num as u32),
+                None => Err(anyhow!("unknown function ${}", id)),
+            },
+        }
+    }
+
+    /// Adds a data segment, writing it into memory if it is active. A
+    /// segment which does not fit leaves memory as it was.
+    fn execute_add_data(&mut self, data: Data) -> Result<Response> {
+        let id = data.id.clone();
+        match self.add_data(data) {
+            Ok(i) => {
+                self.memory.commit();
+                self.datas.commit();
+                Ok(self.new_index("data", i, id))
+            }
+            Err(err) => {
+                self.memory.rollback();
+                self.datas.rollback();
+                Err(err)
+            }
+        }
+    }
+
+    /// Writes an active segment into memory, after which it is dropped,
+    /// as when a module is instantiated.
+    fn add_data(&mut self, data: Data) -> Result<usize> {
+        let bytes = match &data.offset {
+            Some(offset) => {
+                let offset: i32 = self.eval_const(offset)?.try_into()?;
+                self.memory.store(offset as u32 as u64, &data.bytes)?;
+                Vec::new()
+            }
+            None => data.bytes,
+        };
+        self.datas.grow(data.id, bytes)
+    }
+
+    /// Evaluates a constant expression, a single `const` or a `global.get`.
+    fn eval_const(&self, expr: &Expression) -> Result<Value> {
+        match expr.instrs.as_slice() {
+            [Instruction::I32Const(value)] => Ok((*value).into()),
+            [Instruction::I64Const(value)] => Ok((*value).into()),
+            [Instruction::F32Const(value)] => Ok((*value).into()),
+            [Instruction::F64Const(value)] => Ok((*value).into()),
+            [Instruction::GlobalGet(index)] => Ok(self.globals.get(index)?.clone()),
+            _ => Err(anyhow!("Expected a constant expression")),
+        }
+    }
+
+    /// Adds all the definitions of a module, or none of them if any fails.
+    /// Tables and globals are added first, so that the functions can refer
+    /// to them, and the segments last, once the functions are there.
+    fn execute_add_module(&mut self, module: Module) -> Result<Response> {
+        let mut ids = HashSet::new();
+        for id in module.funcs.iter().filter_map(|func| func.id.as_ref()) {
+            if !ids.insert(id) {
+                return Err(anyhow!("duplicate function id ${} in module", id));
+            }
+        }
+
+        match self.add_module(module) {
+            Ok(response) => {
+                self.funcs.commit();
+                self.globals.commit();
+                self.tables.commit();
+                self.memory.commit();
+                self.datas.commit();
+                self.elems.commit();
+                Ok(response)
+            }
+            Err(err) => {
+                self.funcs.rollback();
+                self.globals.rollback();
+                self.tables.rollback();
+                self.memory.rollback();
+                self.datas.rollback();
+                self.elems.rollback();
+                Err(err)
+            }
+        }
+    }
+
+    fn add_module(&mut self, module: Module) -> Result<Response> {
+        let mut response = Response::new();
+        for table in module.tables {
+            let id = table.id.clone();
+            let i = self.add_table(table)?;
+            response.extend(self.new_index("table", i, id));
+        }
+        for global in module.globals {
+            let id = global.id.clone();
+            let i = self.add_global(global)?;
+            response.extend(self.new_index("global", i, id));
+        }
+        for func in module.funcs {
+            let id = func.id.clone();
+            let i = self.add_func(func)?;
+            response.extend(self.new_index("func", i, id));
+        }
+        for elem in module.elems {
+            let id = elem.id.clone();
+            let i = self.add_elem(elem)?;
+            response.extend(self.new_index("elem", i, id));
+        }
+        for data in module.datas {
+            let id = data.id.clone();
+            let i = self.add_data(data)?;
+            response.extend(self.new_index("data", i, id));
+        }
+        Ok(response)
+    }
+
+    /// Adds a function, or replaces the one with the same id in place so
+    /// that calls to it run the new body.
+    fn add_func(&mut self, mut func: Func) -> Result<usize> {
+        let id = func.id.clone();
+        let existing = id.as_ref().and_then(|id| self.funcs.index_of(id));
+        let index = existing.unwrap_or(self.funcs.len());
+        func.resolve_ids(|func_id| match id {
+            Some(ref id) if id == func_id => Some(index),
+            _ => self.funcs.index_of(func_id),
+        });
+        self.validate(&func, index)?;
+        func.recursive = self.is_recursive(&func, index);
+        match (id, existing) {
+            (Some(id), Some(_)) => {
+                let retyped = self.funcs.get(&Index::Num(index as u32))?.ty != func.ty;
+                self.funcs.redefine(id.clone(), func)?;
+                if retyped {
+                    self.validate_callers(&id, index)?;
+                }
+                self.update_recursive()?;
+                Ok(index)
+            }
+            (id, _) => self.funcs.grow(id, func),
+        }
+    }
+
+    /// Type checks the body of `func`, being defined at `index`, against
+    /// the functions and globals defined so far.
+    fn validate(&self, func: &Func, index: usize) -> Result<()> {
+        let func_type = |call: &Index| match call {
+            Index::Num(num) if *num as usize == index => Some(func.ty.clone()),
+            Index::Id(id) if id == DEBUG_FUNC => Some(FuncType {
+                params: vec![],
+                results: vec![],
+            }),
+            _ => self.funcs.get(call).ok().map(|func| func.ty.clone()),
+        };
+        let global_type = |global: &Index| self.globals.ty(global).ok();
+        let context = Context {
+            func_type: &func_type,
+            global_type: &global_type,
+        };
+        validate_func(func, &context)
+    }
+
+    /// Type checks again the functions calling `$id`, at `index`, which
+    /// has been redefined with a different type.
+    fn validate_callers(&self, id: &str, index: usize) -> Result<()> {
+        let callee = [Index::Num(index as u32), Index::Id(id.to_string())];
+        for num in 0..self.funcs.len() {
+            let caller = self.funcs.get(&Index::Num(num as u32))?;
+            let calls = caller.line_expression.expr.calls();
+            if num == index || !calls.iter().any(|call| callee.contains(call)) {
+                continue;
+            }
+            self.validate(caller, num).map_err(|err| {
+                let name = match &caller.id {
+                    Some(caller_id) => format!("${}", caller_id),
+                    None => num.to_string(),
+                };
+                anyhow!("${} is called by {} with its old type: {}", id, name, err)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Works out again which functions may re-enter themselves, as
+    /// replacing a function can close a cycle of calls through it.
+    fn update_recursive(&mut self) -> Result<()> {
+        for num in 0..self.funcs.len() as u32 {
+            let index = Index::Num(num);
+            let func = self.funcs.get(&index)?;
+            let recursive = self.calls_back(num);
+            if func.recursive != recursive {
+                let func = Func {
+                    recursive,
+                    ..func.clone()
+                };
+                self.funcs.set(&index, func)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the function at `index` could end up calling itself.
+    fn calls_back(&self, index: u32) -> bool {
+        let mut seen = HashSet::new();
+        let mut next = vec![index];
+        while let Some(num) = next.pop() {
+            let func = match self.funcs.get(&Index::Num(num)) {
+                Ok(func) => func,
+                Err(_) => return true,
+            };
+            if func.line_expression.expr.calls_indirect() {
+                return true;
+            }
+            for call in func.line_expression.expr.calls() {
+                match call {
+                    Index::Num(call) if *call == index => return true,
+                    Index::Num(call) => {
+                        if seen.insert(*call) {
+                            next.push(*call);
+                        }
+                    }
+                    Index::Id(_) => return true,
+                }
+            }
+        }
+        false
+    }
+
+    /// Whether a call to `func`, being defined at `index`, could end up
+    /// calling it again. Calls by an id that is not defined yet could
+    /// lead anywhere, so those are taken as recursive.
+    fn is_recursive(&self, func: &Func, index: usize) -> bool {
+        // Which function an indirect call ends up in is only known when
+        // it runs.
+        if func.line_expression.expr.calls_indirect() {
+            return true;
+        }
+
+        let calls = func.line_expression.expr.calls();
+        calls.into_iter().any(|call| match call {
+            Index::Num(num) => {
+                *num as usize == index || self.funcs.get(call).map_or(true, |f| f.recursive)
+            }
+            Index::Id(_) => true,
+        })
     }
 
     fn execute_repl_line(&mut self, line: LineExpression) -> Result<Response> {
-        let result = self.execute_line_expression(line);
+        let stack_len = self.call_stack.stack_len();
+        let result = self.execute_line_expression(&line);
 
         match verify_repl_result(result) {
             Ok(mut response) => {
-                self.call_stack.commit();
+                self.commit();
+                for message in self.output.drain(..) {
+                    response.add_message(message);
+                }
                 response.add_message(self.to_state());
+                response.set_stack_snapshot(self.call_stack.values().to_vec());
+                if self.warn_residue {
+                    if let Some(note) = self.residue_note(stack_len) {
+                        response.add_message(note);
+                    }
+                }
                 Ok(response)
             }
             Err(err) => {
-                self.call_stack.rollback();
+                self.rollback();
                 Err(err)
             }
         }
     }
 
+    fn commit(&mut self) {
+        self.call_stack.commit();
+        self.globals.commit();
+        self.memory.commit();
+        self.datas.commit();
+        self.elems.commit();
+        self.tables.commit();
+    }
+
+    fn rollback(&mut self) {
+        self.call_stack.rollback();
+        self.globals.rollback();
+        self.memory.rollback();
+        self.datas.rollback();
+        self.elems.rollback();
+        self.tables.rollback();
+        self.output.clear();
+    }
+
+    fn execute_assert_trap(
+        &mut self,
+        expr: Expression,
+        message: Option<String>,
+    ) -> Result<Response> {
+        let line = LineExpression {
+            locals: vec![],
+            expr,
+        };
+        let result = verify_repl_result(self.execute_line_expression(&line));
+
+        // The assertion never changes the state, whether it traps or not.
+        self.rollback();
+
+        match (result, message) {
+            (Ok(_), _) => Err(anyhow!("Expected a trap")),
+            (Err(err), Some(message)) if !err.to_string().contains(&message) => Err(anyhow!(
+                "Expected a trap with \"{}\", got \"{}\"",
+                message,
+                err
+            )),
+            (Err(err), _) => Ok(Response::new_message(format!("trap: {}", err))),
+        }
+    }
+
+    fn residue_note(&self, prev_len: usize) -> Option<String> {
+        // Growing by one value is what a line usually intends to do,
+        // anything more is worth pointing out.
+        let len = self.call_stack.stack_len();
+        if len > prev_len + 1 {
+            Some(format!("note: {} values now on the stack", len))
+        } else {
+            None
+        }
+    }
+
     fn execute_func(&mut self, index: &Index) -> Result<Response> {
-        if self.call_stack.len() > MAX_STACK_SIZE as usize {
+        if self.call_stack.len() > self.max_depth {
             return Err(anyhow!("Stack overflow"));
         }
 
-        let func = self.funcs.get(index)?.clone();
-        self.call_stack.add_func_stack(&func.ty)?;
-        let response = self.execute_line_expression(func.line_expression)?;
+        if *index == Index::Id(DEBUG_FUNC.to_string()) && self.funcs.get(index).is_err() {
+            return self.execute_debug();
+        }
 
-        verify_func_response(&response)?;
+        let func = self.funcs.get(index)?;
+        if func.ty.params.len() > self.max_locals {
+            return Err(self.too_many_locals());
+        }
+        let ty = func.ty.clone();
+        self.call_stack.add_func_stack(&ty)?;
 
-        self.call_stack
-            .remove_func_stack(&func.ty, response.requires_empty)?;
+        // A tail call takes the place of the function making it, so the
+        // call stack does not grow with it.
+        let (mut index, mut callee_ty) = (index.clone(), ty.clone());
+        loop {
+            let response = self.execute_func_body(&index)?;
+            let next = match &response.control {
+                Control::ReturnCall(next) => next.clone(),
+                _ => {
+                    verify_func_response(&response)?;
+                    self.call_stack
+                        .remove_func_stack(&callee_ty, response.requires_empty)?;
+                    return Ok(Response::new());
+                }
+            };
+
+            let func = self.funcs.get(&next)?;
+            if func.ty.results != ty.results {
+                return Err(anyhow!("return_call to a function with different results"));
+            }
+            if func.ty.params.len() > self.max_locals {
+                return Err(self.too_many_locals());
+            }
+            callee_ty = func.ty.clone();
+            self.call_stack.replace_func_stack(&callee_ty)?;
+            index = next;
+        }
+    }
+
+    /// Runs the body of a function whose frame is already on the stack.
+    fn execute_func_body(&mut self, index: &Index) -> Result<Response> {
+        let func = self.funcs.get(index)?;
+        if func.recursive {
+            let line_expression = func.line_expression.clone();
+            self.execute_line_expression(&line_expression)
+        } else {
+            // Nothing can call back into the function while it runs, so the
+            // body is moved out for the call rather than cloned.
+            let func = self.funcs.get_mut(index)?;
+            let line_expression = std::mem::take(&mut func.line_expression);
+            let result = self.execute_line_expression(&line_expression);
+            self.funcs.get_mut(index)?.line_expression = line_expression;
+            result
+        }
+    }
+
+    fn execute_debug(&mut self) -> Result<Response> {
+        let stack = self
+            .call_stack
+            .get_func_stack()?
+            .to_soft_string(self.format)?;
+        self.output.push(stack);
         Ok(Response::new())
     }
 
-    fn execute_line_expression(&mut self, line: LineExpression) -> Result<Response> {
+    fn execute_line_expression(&mut self, line: &LineExpression) -> Result<Response> {
         let mut response = Response::new();
-        for lc in line.locals.into_iter() {
+        for lc in line.locals.iter() {
             match self.execute_local(lc) {
                 Ok(resp) => response.extend(resp),
                 Err(err) => {
@@ -84,18 +1149,21 @@ impl Executor {
             }
         }
 
-        response.extend(self.execute_expr(line.expr)?);
+        response.extend(self.execute_expr(&line.expr)?);
         Ok(response)
     }
 
-    fn execute_expr(&mut self, expr: Expression) -> Result<Response> {
-        for instr in expr.instrs {
-            let response = self.execute_instr(instr)?;
+    fn execute_expr(&mut self, expr: &Expression) -> Result<Response> {
+        for (i, instr) in expr.instrs.iter().enumerate() {
+            let response = self
+                .execute_instr(instr.clone())
+                .map_err(|err| at_instr(err, i + 1, instr))?;
             // Break all recursive blocks
             // returning to calling block
             match response.control {
                 Control::Return => return Ok(response),
                 Control::Branch(_) => return Ok(response),
+                Control::ReturnCall(_) => return Ok(response),
                 _ => {}
             }
         }
@@ -103,67 +1171,353 @@ impl Executor {
     }
 
     fn execute_instr(&mut self, instr: Instruction) -> Result<Response> {
+        let response = self.execute_single(instr)?;
+        match response.control {
+            Control::ExecBlock(block_type, block) => self.execute_block(block_type, block),
+            Control::ExecLoop(block_type, block) => self.execute_loop(block_type, block),
+            _ => Ok(response),
+        }
+    }
+
+    /// Executes an instruction, leaving the body of a block or loop for the
+    /// caller to run.
+    fn execute_single(&mut self, instr: Instruction) -> Result<Response> {
+        match instr {
+            Instruction::GlobalGet(index) => return self.execute_global_get(&index),
+            Instruction::GlobalSet(index) => return self.execute_global_set(&index),
+            Instruction::I32Load(arg) => return self.execute_i32_load(&arg),
+            Instruction::I32Store(arg) => return self.execute_i32_store(&arg),
+            Instruction::MemorySize => return self.execute_memory_size(),
+            Instruction::MemoryGrow => return self.execute_memory_grow(),
+            Instruction::MemoryInit(index) => return self.execute_memory_init(&index),
+            Instruction::DataDrop(index) => return self.execute_data_drop(&index),
+            Instruction::TableInit(table, elem) => return self.execute_table_init(&table, &elem),
+            Instruction::ElemDrop(index) => return self.execute_elem_drop(&index),
+            Instruction::TableGet(index) => return self.execute_table_get(&index),
+            Instruction::TableSet(index) => return self.execute_table_set(&index),
+            Instruction::TableSize(index) => return self.execute_table_size(&index),
+            Instruction::TableGrow(index) => return self.execute_table_grow(&index),
+            Instruction::RefFunc(index) => return self.execute_ref_func(&index),
+            Instruction::CallIndirect(table, ty) => return self.execute_call_indirect(&table, &ty),
+            Instruction::ReturnCallIndirect(table, ty) => {
+                return self.execute_return_call_indirect(&table, &ty)
+            }
+            _ => {}
+        }
+
         let mut handler = Handler::new(self.call_stack.get_func_stack()?);
+        handler.set_canonicalize_nan(self.canonicalize_nan);
+        handler.set_checked(self.checked);
         let response = handler.handle(instr)?;
 
         match response.control {
             Control::ExecFunc(index) => self.execute_func(&index),
-            Control::ExecBlock(block_type, block) => self.execute_block(block_type, block),
-            Control::ExecLoop(block_type, block) => self.execute_loop(block_type, block),
             _ => Ok(response),
         }
     }
 
+    fn execute_call_indirect(&mut self, table: &Index, ty: &FuncType) -> Result<Response> {
+        let index = self.indirect_callee(table, ty)?;
+        self.execute_func(&index)
+    }
+
+    /// Leaves the call to `execute_func`, which runs the callee in place
+    /// of the calling function.
+    fn execute_return_call_indirect(&mut self, table: &Index, ty: &FuncType) -> Result<Response> {
+        let index = self.indirect_callee(table, ty)?;
+        Ok(Response::new_ctrl(Control::ReturnCall(index)))
+    }
+
+    /// Pops the element of the table an indirect call goes to, and checks
+    /// that the function there has the type the call expects.
+    fn indirect_callee(&mut self, table: &Index, ty: &FuncType) -> Result<Index> {
+        let i: i32 = self.call_stack.get_func_stack()?.pop()?.try_into()?;
+        let index = Index::Num(self.tables.get(table, i as u32)?);
+        if !is_same_signature(&self.funcs.get(&index)?.ty, ty) {
+            return Err(anyhow!("indirect call type mismatch"));
+        }
+        Ok(index)
+    }
+
+    fn execute_global_get(&mut self, index: &Index) -> Result<Response> {
+        let value = self.globals.get(index)?.clone();
+        self.call_stack.get_func_stack()?.push(value)?;
+        Ok(Response::new())
+    }
+
+    fn execute_global_set(&mut self, index: &Index) -> Result<Response> {
+        let value = self.call_stack.get_func_stack()?.pop()?;
+        self.globals.set(index, value)?;
+        Ok(Response::new())
+    }
+
+    fn execute_i32_load(&mut self, arg: &MemArg) -> Result<Response> {
+        let func_stack = self.call_stack.get_func_stack()?;
+        let addr = effective_addr(func_stack.pop()?, arg)?;
+        let value = i32::from_le_bytes(self.memory.load(addr)?);
+        func_stack.push(value.into())?;
+        Ok(Response::new())
+    }
+
+    fn execute_i32_store(&mut self, arg: &MemArg) -> Result<Response> {
+        let func_stack = self.call_stack.get_func_stack()?;
+        let value: i32 = func_stack.pop()?.try_into()?;
+        let addr = effective_addr(func_stack.pop()?, arg)?;
+        self.memory.store(addr, &value.to_le_bytes())?;
+        Ok(Response::new())
+    }
+
+    fn execute_memory_size(&mut self) -> Result<Response> {
+        let size = self.memory.size() as i32;
+        self.call_stack.get_func_stack()?.push(size.into())?;
+        Ok(Response::new())
+    }
+
+    /// Pushes the previous size in pages, or -1 if the memory can not grow.
+    fn execute_memory_grow(&mut self) -> Result<Response> {
+        let func_stack = self.call_stack.get_func_stack()?;
+        let delta: i32 = func_stack.pop()?.try_into()?;
+        let prev = match self.memory.grow(delta as u32) {
+            Some(pages) => pages as i32,
+            None => -1,
+        };
+        func_stack.push(prev.into())?;
+        Ok(Response::new())
+    }
+
+    /// Copies bytes of a data segment into memory, taking the address, the
+    /// offset in the segment and the length off the stack.
+    fn execute_memory_init(&mut self, index: &Index) -> Result<Response> {
+        let func_stack = self.call_stack.get_func_stack()?;
+        let len: i32 = func_stack.pop()?.try_into()?;
+        let offset: i32 = func_stack.pop()?.try_into()?;
+        let addr: i32 = func_stack.pop()?.try_into()?;
+
+        let bytes = self.datas.get(index)?;
+        let (offset, len) = (offset as u32 as usize, len as u32 as usize);
+        let bytes = offset
+            .checked_add(len)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or(anyhow!("out of bounds memory access"))?;
+        self.memory.store(addr as u32 as u64, bytes)?;
+        Ok(Response::new())
+    }
+
+    /// A dropped segment is left empty, so only copies of no bytes from it
+    /// go through.
+    fn execute_data_drop(&mut self, index: &Index) -> Result<Response> {
+        self.datas.set(index, Vec::new())?;
+        Ok(Response::new())
+    }
+
+    fn execute_table_init(&mut self, table: &Index, elem: &Index) -> Result<Response> {
+        let func_stack = self.call_stack.get_func_stack()?;
+        let len: i32 = func_stack.pop()?.try_into()?;
+        let offset: i32 = func_stack.pop()?.try_into()?;
+        let i: i32 = func_stack.pop()?.try_into()?;
+
+        let funcs = self.elems.get(elem)?;
+        let (offset, len) = (offset as u32 as usize, len as u32 as usize);
+        let funcs = offset
+            .checked_add(len)
+            .and_then(|end| funcs.get(offset..end))
+            .ok_or(anyhow!("out of bounds table access"))?;
+        self.tables.init(table, i as u32, funcs)?;
+        Ok(Response::new())
+    }
+
+    /// A dropped segment is left empty, as with `data.drop`.
+    fn execute_elem_drop(&mut self, index: &Index) -> Result<Response> {
+        self.elems.set(index, Vec::new())?;
+        Ok(Response::new())
+    }
+
+    fn execute_table_get(&mut self, index: &Index) -> Result<Response> {
+        let func_stack = self.call_stack.get_func_stack()?;
+        let i: i32 = func_stack.pop()?.try_into()?;
+        let func = self.tables.get_elem(index, i as u32)?;
+        func_stack.push(Value::FuncRef(func))?;
+        Ok(Response::new())
+    }
+
+    fn execute_table_set(&mut self, index: &Index) -> Result<Response> {
+        let func_stack = self.call_stack.get_func_stack()?;
+        let func = func_ref(func_stack.pop()?)?;
+        let i: i32 = func_stack.pop()?.try_into()?;
+        self.tables.set_elem(index, i as u32, func)?;
+        Ok(Response::new())
+    }
+
+    fn execute_table_size(&mut self, index: &Index) -> Result<Response> {
+        let size = self.tables.size(index)? as i32;
+        self.call_stack.get_func_stack()?.push(size.into())?;
+        Ok(Response::new())
+    }
+
+    /// Pushes the previous size, or -1 if the table can not grow, as
+    /// `memory.grow` does.
+    fn execute_table_grow(&mut self, index: &Index) -> Result<Response> {
+        let func_stack = self.call_stack.get_func_stack()?;
+        let delta: i32 = func_stack.pop()?.try_into()?;
+        let init = func_ref(func_stack.pop()?)?;
+        let prev = match self.tables.grow_elems(index, delta as u32, init)? {
+            Some(size) => size as i32,
+            None => -1,
+        };
+        func_stack.push(prev.into())?;
+        Ok(Response::new())
+    }
+
+    fn execute_ref_func(&mut self, index: &Index) -> Result<Response> {
+        let func = self.func_index(index)?;
+        self.call_stack
+            .get_func_stack()?
+            .push(Value::FuncRef(Some(func)))?;
+        Ok(Response::new())
+    }
+
     fn execute_block(&mut self, block_type: BlockType, expr: Expression) -> Result<Response> {
         self.call_stack.add_block_stack(&block_type.ty)?;
-        let mut response = self.execute_expr(expr)?;
-        self.call_stack
-            .remove_block_stack(&block_type.ty, response.requires_empty)?;
+        let mut response = self.execute_expr(&expr)?;
 
-        response.control = match response.control {
-            Control::Branch(Index::Num(0)) => Control::None,
-            Control::Branch(Index::Num(num)) => Control::Branch(Index::Num(num - 1)),
-            Control::Branch(Index::Id(ref id)) => match block_type.label {
-                Some(ref block_id) if id == block_id => Control::None,
-                _ => response.control,
-            },
-            _ => response.control,
-        };
+        if !is_target(&response.control, &block_type.label) && is_escaping(&response.control) {
+            // The branch or return is for an enclosing frame, which picks
+            // its own results off the values we pass through.
+            self.call_stack.unwind_block_stack()?;
+            response.control = outer_control(response.control);
+            response.requires_empty = true;
+            return Ok(response);
+        }
 
+        self.call_stack
+            .remove_block_stack(&block_type.ty, response.requires_empty)?;
+        response.control = Control::None;
         response.requires_empty = true;
         Ok(response)
     }
 
     fn execute_loop(&mut self, block_type: BlockType, expr: Expression) -> Result<Response> {
+        self.call_stack.add_block_stack(&block_type.ty)?;
         loop {
-            self.call_stack.add_block_stack(&block_type.ty)?;
-            let mut response = self.execute_expr(expr.clone())?;
+            let mut response = self.execute_expr(&expr)?;
+
+            if is_target(&response.control, &block_type.label) {
+                // Branching back takes the params for the next iteration,
+                // not the results.
+                self.call_stack
+                    .restart_block_stack(&block_type.ty, response.requires_empty)?;
+                continue;
+            }
+
+            if is_escaping(&response.control) {
+                self.call_stack.unwind_block_stack()?;
+                response.control = outer_control(response.control);
+                response.requires_empty = true;
+                break Ok(response);
+            }
+
             self.call_stack
                 .remove_block_stack(&block_type.ty, response.requires_empty)?;
-
-            response.control = match response.control {
-                Control::Branch(Index::Num(0)) => continue,
-                Control::Branch(Index::Num(num)) => Control::Branch(Index::Num(num - 1)),
-                Control::Branch(Index::Id(ref id)) => match block_type.label {
-                    Some(ref block_id) if id == block_id => continue,
-                    _ => response.control,
-                },
-                _ => response.control,
-            };
             response.requires_empty = true;
             break Ok(response);
         }
     }
 
-    fn execute_local(&mut self, lc: Local) -> Result<Response> {
+    fn execute_local(&mut self, lc: &Local) -> Result<Response> {
         let func_stack = self.call_stack.get_func_stack()?;
-        let (id, val_type) = (lc.id, lc.val_type);
-        let print_id = id.clone();
-        func_stack
+        if func_stack.locals.len() >= self.max_locals {
+            return Err(self.too_many_locals());
+        }
+        let (id, val_type) = (lc.id.clone(), &lc.val_type);
+        let i = func_stack
             .locals
-            .grow(id, default_value(&val_type)?)
-            .map(|i| Response::new_index("local", i, print_id))
+            .grow(id.clone(), default_value(val_type)?)?;
+        Ok(self.new_index("local", i, id))
+    }
+
+    /// Echoes a definition, unless quiet.
+    fn new_index(&self, ty: &str, i: usize, id: Option<String>) -> Response {
+        if self.quiet {
+            Response::new()
+        } else {
+            Response::new_index(ty, i, id)
+        }
     }
+
+    fn too_many_locals(&self) -> anyhow::Error {
+        anyhow!("too many locals (limit {})", self.max_locals)
+    }
+}
+
+/// Whether a branch targets the block with the given label. Labels are
+/// matched from the innermost block outwards, so an inner label shadows
+/// an outer one with the same name.
+fn is_target(control: &Control, label: &Option<String>) -> bool {
+    match control {
+        Control::Branch(Index::Num(0)) => true,
+        Control::Branch(Index::Id(id)) => label.as_ref() == Some(id),
+        _ => false,
+    }
+}
+
+fn is_escaping(control: &Control) -> bool {
+    matches!(
+        control,
+        Control::Branch(_) | Control::Return | Control::ReturnCall(_)
+    )
+}
+
+fn outer_control(control: Control) -> Control {
+    match control {
+        Control::Branch(Index::Num(num)) => Control::Branch(Index::Num(num - 1)),
+        control => control,
+    }
+}
+
+fn at_instr(err: anyhow::Error, position: usize, instr: &Instruction) -> anyhow::Error {
+    if err.is::<InstrError>() {
+        return err;
+    }
+    InstrError {
+        position,
+        mnemonic: instr.mnemonic(),
+        source: err,
+    }
+    .into()
+}
+
+/// Whether two function types have the same params and results, whatever
+/// the ids of the params.
+fn is_same_signature(a: &FuncType, b: &FuncType) -> bool {
+    a.results == b.results
+        && a.params.len() == b.params.len()
+        && a.params
+            .iter()
+            .zip(&b.params)
+            .all(|(a, b)| a.val_type == b.val_type)
+}
+
+fn unknown_global(index: &Index) -> anyhow::Error {
+    match index {
+        Index::Id(id) => anyhow!("unknown global ${}", id),
+        Index::Num(num) => anyhow!("unknown global {}", num),
+    }
+}
+
+fn func_ref(value: Value) -> Result<Option<u32>> {
+    match value {
+        Value::FuncRef(func) => Ok(func),
+        value => Err(anyhow!(
+            "type mismatch: expected funcref, found {}",
+            value.val_type()
+        )),
+    }
+}
+
+/// Address of a memory access, from the base address on the stack.
+fn effective_addr(base: Value, arg: &MemArg) -> Result<u64> {
+    let base: i32 = base.try_into()?;
+    Ok(base as u32 as u64 + arg.offset)
 }
 
 fn verify_func_response(response: &Response) -> Result<()> {
@@ -179,6 +1533,7 @@ fn verify_repl_result(result: Result<Response>) -> Result<Response> {
     match result {
         Ok(response) => match response.control {
             Control::Return => Err(anyhow!("return is allowed only in func")),
+            Control::ReturnCall(_) => Err(anyhow!("return_call is allowed only in func")),
             Control::Branch(_) => Err(anyhow!("br leaking out")),
             _ => Ok(response),
         },
@@ -192,9 +1547,14 @@ fn default_value(val_type: &ValType) -> Result<Value> {
         ValType::I64 => Ok(Value::default_i64()),
         ValType::F32 => Ok(Value::default_f32()),
         ValType::F64 => Ok(Value::default_f64()),
+        ValType::FuncRef => Ok(Value::default_func_ref()),
     }
 }
 
 #[cfg(test)]
 #[path = "./executor_test.rs"]
 mod executor_test;
+
+#[cfg(test)]
+#[path = "./spec_test.rs"]
+mod spec_test;
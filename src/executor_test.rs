@@ -1,12 +1,18 @@
 use crate::model::{
-    Expression, Func, FuncType, Index, Instruction, Line, LineExpression, Local, ValType,
+    Elem, Expression, Func, FuncType, Global, Index, Instruction, Line, LineExpression, Local,
+    MemArg, Module, Table, ValType,
 };
 
-use crate::executor::Executor;
+use crate::dict;
+use crate::executor::{Definition, DefinitionKind, Executor};
+use crate::memory::MAX_PAGES;
+use crate::parser::parse_line;
+use crate::value::{Value, ValueFormat};
 use crate::test_utils::{
     test_block, test_block_type, test_func_type, test_if, test_index, test_local, test_local_id,
     test_loop,
 };
+use wast::parser::ParseBuffer;
 
 macro_rules! test_line {
     (($( $y:expr ),*), ($( $x:expr ),*)) => {
@@ -32,6 +38,7 @@ macro_rules! test_func {
                 locals: vec![],
                 expr:  Expression { instrs: vec![$( $instr ),*] }
             },
+            recursive: false,
         })
     };
 }
@@ -48,6 +55,27 @@ fn test_add() {
     assert_eq!(response.message(), "[100]");
 }
 
+#[test]
+fn test_add_stack_snapshot() {
+    let mut executor = Executor::new();
+    let line = test_line![(), (Instruction::I64Const(7))];
+    executor.execute_line(line).unwrap();
+    let line = test_line![(), (
+        Instruction::I32Const(42),
+        Instruction::I32Const(58),
+        Instruction::I32Add
+    )];
+    let response = executor.execute_line(line).unwrap();
+    let values = response.stack_snapshot().unwrap();
+    assert_eq!(values, &[Value::I64(7), Value::I32(100)]);
+    let strs: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    assert_eq!(response.message(), format!("[{}]", strs.join(", ")));
+
+    let func = test_func!("f", (), (), ());
+    let response = executor.execute_line(func).unwrap();
+    assert_eq!(response.stack_snapshot(), None);
+}
+
 #[test]
 fn test_error_rollback() {
     let mut executor = Executor::new();
@@ -62,1263 +90,3683 @@ fn test_error_rollback() {
             .call_stack
             .get_func_stack()
             .unwrap()
-            .to_soft_string()
+            .to_soft_string(ValueFormat::new())
             .unwrap(),
         "[55]"
     );
 }
 
 #[test]
-fn test_local_set_get() {
+fn test_error_instruction_position() {
     let mut executor = Executor::new();
-    let line = test_line![(test_local!(ValType::I32)), (
-        Instruction::I32Const(42),
-        Instruction::LocalSet(Index::Num(0)),
-        Instruction::LocalGet(Index::Num(0))
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        Instruction::I32Const(0),
+        Instruction::I32DivS,
+        Instruction::Drop
     )];
+    let err = executor.execute_line(line).err().unwrap();
     assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "local ;0;\n[42]"
+        err.to_string(),
+        "integer divide by zero, while executing instruction 3 (i32.div_s)"
     );
 }
 
 #[test]
-fn test_local_set_commit() {
+fn test_error_nested_instruction_position() {
     let mut executor = Executor::new();
-    let line = test_line![(test_local!(ValType::I32)), (
-        Instruction::I32Const(42),
-        Instruction::LocalSet(Index::Num(0)),
-        Instruction::LocalGet(Index::Num(0))
+    let block_type = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_if!(
+            block_type,
+            (Instruction::I32Const(2), Instruction::I32Const(0), Instruction::I32RemU),
+            (Instruction::I32Const(3))
+        )
     )];
+    let err = executor.execute_line(line).err().unwrap();
     assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "local ;0;\n[42]"
+        err.to_string(),
+        "integer divide by zero, while executing instruction 3 (i32.rem_u)"
     );
-
-    let line = test_line![(), (
-        Instruction::Drop,
-        Instruction::I32Const(55),
-        Instruction::LocalSet(Index::Num(0)),
-        Instruction::LocalGet(Index::Num(0))
-    )];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[55]");
 }
 
 #[test]
-fn test_local_set_local_value_rollback() {
+fn test_error_rollback_all_state() {
     let mut executor = Executor::new();
     let line = test_line![(test_local!(ValType::I32)), (
-        Instruction::I32Const(42),
-        Instruction::LocalSet(Index::Num(0))
+        Instruction::I32Const(10),
+        Instruction::LocalSet(Index::Num(0)),
+        Instruction::I32Const(1)
     )];
     executor.execute_line(line).unwrap();
+    let global = test_global(None, ValType::I32, true, Instruction::I32Const(20));
+    executor.execute_line(global).unwrap();
 
-    let line = test_line![(), (
-        Instruction::I32Const(43),
-        Instruction::I32Const(55),
+    // Touches the stack, an existing local, a new local, a global and the
+    // memory before trapping.
+    let line = test_line![(test_local!(ValType::I64)), (
+        Instruction::I32Const(1),
+        Instruction::MemoryGrow,
+        Instruction::Drop,
+        Instruction::I32Const(21),
+        Instruction::GlobalSet(Index::Num(0)),
+        Instruction::Drop,
+        Instruction::I32Const(2),
+        Instruction::I32Const(99),
         Instruction::LocalSet(Index::Num(0)),
-        // Failing instruction
-        Instruction::F32Neg
+        Instruction::I64Const(7),
+        Instruction::LocalSet(Index::Num(1)),
+        Instruction::I32Const(0),
+        Instruction::I32DivS
     )];
     assert!(executor.execute_line(line).is_err());
 
-    let line = test_line![(test_local!(ValType::I32)), (Instruction::LocalGet(
-        Index::Num(0)
-    ))];
+    let line = test_line![(), ()];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1]");
+    let line = test_line![(), (Instruction::LocalGet(Index::Num(0)))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1, 10]");
+    let line = test_line![(), (Instruction::LocalGet(Index::Num(1)))];
+    assert!(executor.execute_line(line).is_err());
+    let line = test_line![(), (Instruction::Drop, Instruction::MemorySize)];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1, 0]");
+    let line = test_line![(), (Instruction::Drop, Instruction::GlobalGet(Index::Num(0)))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1, 20]");
+}
+
+#[test]
+fn test_memory_grow() {
+    let mut executor = Executor::new();
+    let line = test_line![(), (Instruction::MemorySize)];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[0]");
+
+    let line = test_line![(), (
+        Instruction::I32Const(2),
+        Instruction::MemoryGrow,
+        Instruction::I32Const(1),
+        Instruction::MemoryGrow,
+        Instruction::MemorySize
+    )];
     assert_eq!(
         executor.execute_line(line).unwrap().message(),
-        "local ;1;\n[42]"
+        "[0, 0, 2, 3]"
     );
 }
 
 #[test]
-fn test_local_rollback() {
+fn test_memory_grow_failure() {
     let mut executor = Executor::new();
-    let line = test_line![(test_local_id!("num", ValType::I32)), ()];
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        Instruction::MemoryGrow,
+        Instruction::I32Const(-1),
+        Instruction::MemoryGrow,
+        Instruction::I32Const(MAX_PAGES as i32),
+        Instruction::MemoryGrow,
+        Instruction::MemorySize
+    )];
     assert_eq!(
         executor.execute_line(line).unwrap().message(),
-        "local ;0; num\n[]"
+        "[0, -1, -1, 1]"
     );
-    let line = test_line![(test_local_id!("num", ValType::I32)), ()];
+}
+
+#[test]
+fn test_memory_grow_error() {
+    let mut executor = Executor::new();
+    let line = test_line![(), (Instruction::MemoryGrow)];
+    assert!(executor.execute_line(line).is_err());
+    let line = test_line![(), (Instruction::I64Const(1), Instruction::MemoryGrow)];
     assert!(executor.execute_line(line).is_err());
+}
 
-    let line = test_line![(test_local_id!("num2", ValType::I32)), ()];
+fn test_global(id: Option<&str>, ty: ValType, mutable: bool, init: Instruction) -> Line {
+    Line::Global(Global {
+        id: id.map(String::from),
+        ty,
+        mutable,
+        init: Expression {
+            instrs: vec![init],
+        },
+    })
+}
+
+#[test]
+fn test_global_get_set() {
+    let mut executor = Executor::new();
+    let global = test_global(Some("g"), ValType::I32, true, Instruction::I32Const(5));
     assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "local ;1; num2\n[]"
+        executor.execute_line(global).unwrap().message(),
+        "global ;0; g"
     );
+
+    let line = test_line![(), (
+        Instruction::GlobalGet(test_index("g")),
+        Instruction::I32Const(2),
+        Instruction::I32Add,
+        Instruction::GlobalSet(Index::Num(0)),
+        Instruction::GlobalGet(test_index("g"))
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[7]");
 }
 
 #[test]
-fn test_local_by_id() {
+fn test_global_init_global_get() {
     let mut executor = Executor::new();
+    let global = test_global(Some("a"), ValType::F64, false, Instruction::F64Const(1.5));
+    executor.execute_line(global).unwrap();
+    let init = Instruction::GlobalGet(test_index("a"));
+    let global = test_global(None, ValType::F64, true, init);
+    executor.execute_line(global).unwrap();
 
-    let local = test_local_id!("num", ValType::I32);
+    let line = test_line![(), (Instruction::GlobalGet(Index::Num(1)))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1.5]");
+}
 
-    let set_index = test_index("num");
-    let get_index = test_index("num");
+#[test]
+fn test_global_init_error() {
+    let mut executor = Executor::new();
+    let global = test_global(None, ValType::I64, false, Instruction::I32Const(1));
+    assert!(executor.execute_line(global).is_err());
+    let global = test_global(None, ValType::I32, false, Instruction::I32Clz);
+    assert!(executor.execute_line(global).is_err());
+    let init = Instruction::GlobalGet(Index::Num(0));
+    let global = test_global(None, ValType::I32, false, init);
+    assert!(executor.execute_line(global).is_err());
+    assert!(executor.definitions().is_empty());
+}
 
-    let line = test_line![(local), (
-        Instruction::I32Const(42),
-        Instruction::LocalSet(set_index),
-        Instruction::LocalGet(get_index)
-    )];
-    assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "local ;0; num\n[42]"
-    );
+#[test]
+fn test_global_set_immutable_error() {
+    let mut executor = Executor::new();
+    let global = test_global(None, ValType::I32, false, Instruction::I32Const(5));
+    executor.execute_line(global).unwrap();
+
+    let line = test_line![(), (Instruction::I32Const(6), Instruction::GlobalSet(Index::Num(0)))];
+    let err = executor.execute_line(line).err().unwrap();
+    assert!(err.to_string().starts_with("global is immutable"));
 }
 
 #[test]
-fn test_local_by_id_mix() {
+fn test_global_set_rollback() {
     let mut executor = Executor::new();
-    let local = test_local_id!("num", ValType::I32);
-    let index = test_index("num");
+    let global = test_global(None, ValType::I32, true, Instruction::I32Const(5));
+    executor.execute_line(global).unwrap();
 
-    let line = test_line![(test_local!(ValType::I32), local), (
-        Instruction::I32Const(42),
-        Instruction::LocalSet(index),
-        Instruction::LocalGet(Index::Num(1))
+    let line = test_line![(), (
+        Instruction::I32Const(6),
+        Instruction::GlobalSet(Index::Num(0)),
+        Instruction::Drop
     )];
-    assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "local ;0;\nlocal ;1; num\n[42]"
-    );
+    assert!(executor.execute_line(line).is_err());
+
+    let line = test_line![(), (Instruction::GlobalGet(Index::Num(0)))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[5]");
 }
 
 #[test]
-fn test_local_set_get_i64() {
+fn test_global_set_in_func_rollback() {
     let mut executor = Executor::new();
-    let line = test_line![(test_local!(ValType::I64)), (
-        Instruction::I64Const(42),
-        Instruction::LocalSet(Index::Num(0)),
-        Instruction::LocalGet(Index::Num(0))
+    executor.eval("(global $g (mut i32) (i32.const 5))").unwrap();
+    executor
+        .eval("(func $set (param i32) (global.set $g (local.get 0)))")
+        .unwrap();
+
+    let err = executor
+        .eval("(call $set (i32.const 6)) (i32.div_s (i32.const 1) (i32.const 0))")
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("integer divide by zero"));
+    assert_eq!(executor.eval("(global.get $g)").unwrap(), "[5]");
+
+    executor.eval("(call $set (i32.const 7))").unwrap();
+    assert_eq!(executor.eval("(global.get $g)").unwrap(), "[5, 7]");
+}
+
+#[test]
+fn test_global_set_step_rollback() {
+    let mut executor = Executor::new();
+    let global = test_global(None, ValType::I32, true, Instruction::I32Const(5));
+    executor.execute_line(global).unwrap();
+
+    let line = test_line![(), (
+        Instruction::I32Const(6),
+        Instruction::GlobalSet(Index::Num(0)),
+        Instruction::I32Const(1)
     )];
-    assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "local ;0;\n[42]"
-    );
+    let mut stepper = executor.step_line(line).unwrap();
+    executor.step(&mut stepper).unwrap();
+    executor.step(&mut stepper).unwrap();
+    executor.stop_line(stepper);
+
+    let line = test_line![(), (Instruction::GlobalGet(Index::Num(0)))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[5]");
+}
+
+fn mem_arg(offset: u64, align: u32) -> MemArg {
+    MemArg { offset, align }
 }
 
 #[test]
-fn test_local_set_get_f32() {
+fn test_i32_store_load() {
     let mut executor = Executor::new();
-    let local = test_local!(ValType::F32);
-    let line = test_line![(local), (
-        Instruction::F32Const(3.14),
-        Instruction::LocalSet(Index::Num(0)),
-        Instruction::LocalGet(Index::Num(0))
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        Instruction::MemoryGrow,
+        Instruction::Drop,
+        Instruction::I32Const(8),
+        Instruction::I32Const(-2),
+        Instruction::I32Store(mem_arg(4, 4)),
+        Instruction::I32Const(12),
+        Instruction::I32Load(mem_arg(0, 4)),
+        Instruction::I32Const(4),
+        Instruction::I32Load(mem_arg(8, 4))
     )];
-    assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "local ;0;\n[3.14]"
-    );
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[-2, -2]");
 }
 
 #[test]
-fn test_local_set_get_f64() {
+fn test_i32_load_align() {
     let mut executor = Executor::new();
-    let local = Local {
-        id: None,
-        val_type: ValType::F64,
-    };
-    let line = test_line![(local), (
-        Instruction::F64Const(3.14f64),
-        Instruction::LocalSet(Index::Num(0)),
-        Instruction::LocalGet(Index::Num(0))
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        Instruction::MemoryGrow,
+        Instruction::Drop,
+        Instruction::I32Const(1),
+        Instruction::I32Const(0x04030201),
+        Instruction::I32Store(mem_arg(0, 1))
+    )];
+    executor.execute_line(line).unwrap();
+
+    // Unaligned access with any alignment hint reads the same bytes.
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        Instruction::I32Load(mem_arg(0, 1)),
+        Instruction::I32Const(1),
+        Instruction::I32Load(mem_arg(0, 4))
     )];
     assert_eq!(
         executor.execute_line(line).unwrap().message(),
-        "local ;0;\n[3.14]"
+        "[67305985, 67305985]"
     );
 }
 
 #[test]
-fn test_local_set_get_type_error() {
+fn test_i32_store_out_of_bounds_error() {
     let mut executor = Executor::new();
-    let line = test_line![(test_local!(ValType::I32)), (
-        Instruction::I64Const(55),
-        Instruction::LocalSet(Index::Num(0))
+    let line = test_line![(), (Instruction::I32Const(1), Instruction::MemoryGrow)];
+    executor.execute_line(line).unwrap();
+
+    let line = test_line![(), (
+        Instruction::I32Const(0),
+        Instruction::I32Const(7),
+        Instruction::I32Store(mem_arg(0, 4)),
+        Instruction::I32Const(65533),
+        Instruction::I32Const(7),
+        Instruction::I32Store(mem_arg(0, 4))
+    )];
+    let err = executor.execute_line(line).err().unwrap();
+    assert!(err.to_string().starts_with("out of bounds memory access"));
+
+    // The store before the failing one is rolled back too.
+    let line = test_line![(), (
+        Instruction::I32Const(0),
+        Instruction::I32Load(mem_arg(0, 4))
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[0, 0]");
+
+    let line = test_line![(), (
+        Instruction::I32Const(-1),
+        Instruction::I32Load(mem_arg(0, 4))
     )];
     assert!(executor.execute_line(line).is_err());
 }
 
 #[test]
-fn test_func() {
+fn test_memory_grow_rollback() {
     let mut executor = Executor::new();
-    let func = test_func!(
-        "subtract",
-        (
-            test_local_id!("first", ValType::I32),
-            test_local_id!("second", ValType::I32)
-        ),
-        (ValType::I32, ValType::I32),
-        (
-            Instruction::LocalGet(test_index("first")),
-            Instruction::LocalGet(test_index("first")),
-            Instruction::LocalGet(test_index("second")),
-            Instruction::I32Sub
-        )
-    );
-    let response = executor.execute_line(func).unwrap();
-    assert_eq!(response.message(), "func ;0; subtract");
+    let line = test_line![(), (Instruction::I32Const(1), Instruction::MemoryGrow)];
+    executor.execute_line(line).unwrap();
 
-    let call_sub = test_line![(), (
-        Instruction::I32Const(7),
-        Instruction::I32Const(2),
-        Instruction::Call(test_index("subtract"))
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        Instruction::MemoryGrow,
+        Instruction::Drop,
+        Instruction::Drop,
+        Instruction::Drop
     )];
-    assert_eq!(executor.execute_line(call_sub).unwrap().message(), "[7, 5]");
+    assert!(executor.execute_line(line).is_err());
+
+    let line = test_line![(), (Instruction::MemorySize)];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[0, 1]");
 }
 
 #[test]
-fn test_func_error_less_number_of_inputs() {
+fn test_active_data() {
     let mut executor = Executor::new();
-    let func = test_func!("fun", (test_local!(ValType::I32)), (), ());
-    executor.execute_line(func).unwrap();
+    executor.eval("(memory.grow (i32.const 1)) (drop)").unwrap();
+    let data = "(data (i32.const 8) \"\\01\\02\" \"\\03\\04\")";
+    assert_eq!(executor.eval(data).unwrap(), "data ;0;");
+    let line = "(i32.load (i32.const 8))";
+    assert_eq!(executor.eval(line).unwrap(), "[67305985]");
 
-    let call_fun = test_line![(), (Instruction::Call(test_index("fun")))];
-    assert!(executor.execute_line(call_fun).is_err());
+    // Active segments are dropped once written.
+    let line = "(memory.init 0 (i32.const 0) (i32.const 0) (i32.const 1))";
+    let err = executor.eval(line).err().unwrap();
+    assert!(err.to_string().contains("out of bounds memory access"));
 }
 
 #[test]
-fn test_func_error_less_number_of_outputs() {
+fn test_active_data_out_of_bounds_error() {
     let mut executor = Executor::new();
-    let func = test_func!("fun", (), (ValType::I32), ());
-    executor.execute_line(func).unwrap();
-
-    let call = test_line![(), (Instruction::Call(test_index("fun")))];
-    // We expect one output but will get none hence an error
-    assert!(executor.execute_line(call).is_err());
+    executor.eval("(memory.grow (i32.const 1)) (drop)").unwrap();
+    let err = executor
+        .eval("(data $d (i32.const 65535) \"ab\")")
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("out of bounds memory access"));
+    assert!(executor.eval("(data.drop $d)").is_err());
+    assert_eq!(executor.eval("(i32.load (i32.const 65532))").unwrap(), "[0]");
 }
 
 #[test]
-fn test_func_error_more_number_of_outputs() {
+fn test_memory_init() {
     let mut executor = Executor::new();
-    let func = test_func!("fun", (), (), (Instruction::I32Const(5)));
-    executor.execute_line(func).unwrap();
+    executor.eval("(memory.grow (i32.const 1)) (drop)").unwrap();
+    let data = "(data $d \"\\01\\02\\03\\04\\05\")";
+    assert_eq!(executor.eval(data).unwrap(), "data ;0; d");
+    let line = "(memory.init $d (i32.const 16) (i32.const 1) (i32.const 4)) \
+        (i32.load (i32.const 16))";
+    assert_eq!(executor.eval(line).unwrap(), "[84148994]");
 
-    let call = test_line![(), (Instruction::Call(test_index("fun")))];
-    // We expect no output but will get one hence an error
-    assert!(executor.execute_line(call).is_err());
+    let line = "(memory.init $d (i32.const 0) (i32.const 2) (i32.const 4))";
+    let err = executor.eval(line).err().unwrap();
+    assert!(err.to_string().contains("out of bounds memory access"));
+    let line = "(memory.init $d (i32.const 65535) (i32.const 0) (i32.const 2))";
+    let err = executor.eval(line).err().unwrap();
+    assert!(err.to_string().contains("out of bounds memory access"));
 }
 
 #[test]
-fn test_func_input_type() {
+fn test_data_drop() {
     let mut executor = Executor::new();
-    let func = test_func!(
-        "fun",
-        (test_local!(ValType::I32), test_local!(ValType::I64)),
-        (),
-        ()
-    );
-    executor.execute_line(func).unwrap();
+    executor.eval("(memory.grow (i32.const 1)) (drop)").unwrap();
+    executor.eval("(data $d \"abcd\")").unwrap();
 
-    let call_fun = test_line![(), (
-        Instruction::I32Const(5),
-        Instruction::I64Const(10),
-        Instruction::Call(test_index("fun"))
-    )];
-    executor.execute_line(call_fun).unwrap();
+    // A drop on a line that fails is rolled back.
+    let line = "(data.drop $d) (i32.add (i32.const 1) (i64.const 1))";
+    assert!(executor.eval(line).is_err());
+    let line = "(memory.init $d (i32.const 0) (i32.const 0) (i32.const 4))";
+    assert_eq!(executor.eval(line).unwrap(), "[]");
+
+    executor.eval("(data.drop $d)").unwrap();
+    let line = "(memory.init $d (i32.const 0) (i32.const 0) (i32.const 1))";
+    let err = executor.eval(line).err().unwrap();
+    assert!(err.to_string().contains("out of bounds memory access"));
+    let line = "(memory.init $d (i32.const 0) (i32.const 0) (i32.const 0))";
+    assert_eq!(executor.eval(line).unwrap(), "[]");
 }
 
 #[test]
-fn test_func_error_input_type() {
+fn test_memory_definition() {
     let mut executor = Executor::new();
-    let func = test_func!(
-        "fun",
-        (test_local!(ValType::I32), test_local!(ValType::I64)),
-        (),
-        ()
-    );
-    executor.execute_line(func).unwrap();
+    let line = "(memory $m (export \"mem\") 1 2)";
+    assert_eq!(executor.eval(line).unwrap(), "memory ;0; m");
+    assert_eq!(executor.eval("(memory.size)").unwrap(), "[1]");
+    assert_eq!(executor.eval("(memory.grow (i32.const 2))").unwrap(), "[1, -1]");
 
-    let call_fun = test_line![(), (
-        Instruction::I64Const(5),
-        Instruction::I32Const(10),
-        Instruction::Call(test_index("fun"))
-    )];
-    assert!(executor.execute_line(call_fun).is_err());
+    executor.eval("(i32.store (i32.const 0) (i32.const 7))").unwrap();
+    assert_eq!(executor.exported_memory("mem").unwrap()[..4], [7, 0, 0, 0]);
+    assert!(executor.exported_memory("other").is_none());
+
+    let err = executor.eval("(memory 1)").err().unwrap();
+    assert_eq!(err.to_string(), "only one memory is supported");
 }
 
 #[test]
-fn test_func_output_type() {
+fn test_memory_definition_error() {
     let mut executor = Executor::new();
-    let func = test_func!(
-        "fun",
-        (),
-        (ValType::I32, ValType::I64),
-        (Instruction::I32Const(5), Instruction::I64Const(10))
-    );
-    executor.execute_line(func).unwrap();
+    let err = executor.eval("(memory 2 1)").err().unwrap();
+    assert_eq!(err.to_string(), "size minimum must not be greater than maximum");
+    let err = executor.eval("(memory 257)").err().unwrap();
+    assert_eq!(err.to_string(), "memory size must be at most 256 pages");
+    assert_eq!(executor.eval("(memory.size)").unwrap(), "[0]");
+}
 
-    let call_fun = test_line![(), (Instruction::Call(test_index("fun")))];
-    executor.execute_line(call_fun).unwrap();
+#[test]
+fn test_memory_definition_empty() {
+    let mut executor = Executor::new();
+    assert_eq!(executor.eval("(memory $m 0 2)").unwrap(), "memory ;0; m");
+    let err = executor.eval("(memory 1)").err().unwrap();
+    assert_eq!(err.to_string(), "only one memory is supported");
+
+    // The maximum of the first declaration still holds.
+    let line = "(memory.grow (i32.const 3))";
+    assert_eq!(executor.eval(line).unwrap(), "[-1]");
 }
 
 #[test]
-fn test_func_output_type_error() {
+fn test_host_memory() {
     let mut executor = Executor::new();
-    let func = test_func!(
-        "fun",
-        (),
-        (ValType::I32, ValType::I64),
-        (Instruction::I64Const(10), Instruction::I32Const(5))
-    );
-    executor.execute_line(func).unwrap();
+    executor.define_host_memory("env", "mem", vec![1, 2, 3, 4]);
+    let line = "(memory (import \"env\" \"mem\") 1)";
+    assert_eq!(executor.eval(line).unwrap(), "memory ;0;");
+    assert_eq!(executor.eval("(i32.load (i32.const 0))").unwrap(), "[67305985]");
 
-    let call_fun = test_line![(), (Instruction::Call(test_index("fun")))];
-    assert!(executor.execute_line(call_fun).is_err());
+    executor
+        .eval("(i32.store (i32.const 2) (i32.const 0x0a0b0c0d))")
+        .unwrap();
+    let bytes = executor.host_memory("env", "mem").unwrap();
+    assert_eq!(bytes[..6], [1, 2, 0x0d, 0x0c, 0x0b, 0x0a]);
+
+    // A store on a line that fails does not reach the host memory.
+    let line = "(i32.store (i32.const 0) (i32.const 0)) (i32.div_s (i32.const 1) (i32.const 0))";
+    assert!(executor.eval(line).is_err());
+    assert_eq!(executor.host_memory("env", "mem").unwrap()[0], 1);
 }
 
 #[test]
-fn test_func_no_id() {
+fn test_host_memory_import_error() {
     let mut executor = Executor::new();
-    let func = Line::Func(Func {
+    let err = executor
+        .eval("(memory (import \"env\" \"mem\") 1)")
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "unknown import env.mem");
+
+    executor.define_host_memory("env", "mem", vec![0; 10]);
+    let err = executor
+        .eval("(memory (import \"env\" \"mem\") 2)")
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "incompatible import type");
+    assert_eq!(executor.host_memory("env", "mem").unwrap().len(), 10);
+}
+
+fn test_table(size: u32) -> Line {
+    Line::Table(Table {
         id: None,
-        ty: FuncType {
-            params: vec![test_local!(ValType::I32)],
-            results: vec![ValType::I32],
-        },
-        line_expression: LineExpression {
-            locals: vec![],
-            expr: Expression {
-                instrs: vec![Instruction::LocalGet(Index::Num(0))],
-            },
-        },
-    });
-    let response = executor.execute_line(func).unwrap();
-    assert_eq!(response.message(), "func ;0;");
+        min: size,
+        max: None,
+    })
+}
 
-    let call_fun = test_line![(), (
-        Instruction::I32Const(2),
-        Instruction::Call(Index::Num(0))
-    )];
-    let response = executor.execute_line(call_fun).unwrap();
-    assert_eq!(response.message(), "[2]");
+fn test_elem(offset: i32, funcs: Vec<Index>) -> Line {
+    Line::Elem(Elem {
+        id: None,
+        active: Some((
+            Index::Num(0),
+            Expression {
+                instrs: vec![Instruction::I32Const(offset)],
+            },
+        )),
+        funcs,
+    })
 }
 
-#[test]
-fn test_func_return() {
+/// A table of 3, with `$double` at 0, `$zero` at 1 and nothing at 2.
+fn test_call_indirect_executor() -> Executor {
     let mut executor = Executor::new();
-    let func = test_func!(
-        "fun",
-        (),
+    let double = test_func!(
+        "double",
+        (test_local!(ValType::I32)),
         (ValType::I32),
         (
-            Instruction::I32Const(5),
-            Instruction::Return,
-            Instruction::Drop,
-            Instruction::I32Const(6)
+            Instruction::LocalGet(Index::Num(0)),
+            Instruction::LocalGet(Index::Num(0)),
+            Instruction::I32Add
         )
     );
-    executor.execute_line(func).unwrap();
+    executor.execute_line(double).unwrap();
+    let zero = test_func!("zero", (), (ValType::I32), (Instruction::I32Const(0)));
+    executor.execute_line(zero).unwrap();
 
-    let call_fun = test_line![(), (Instruction::Call(test_index("fun")))];
-    let response = executor.execute_line(call_fun).unwrap();
-    assert_eq!(response.message(), "[5]");
+    let table = executor.execute_line(test_table(3)).unwrap();
+    assert_eq!(table.message(), "table ;0;");
+    let elem = test_elem(0, vec![test_index("double"), Index::Num(1)]);
+    executor.execute_line(elem).unwrap();
+    executor
+}
+
+fn call_indirect_i32_i32() -> Instruction {
+    Instruction::CallIndirect(
+        Index::Num(0),
+        test_func_type!((test_local!(ValType::I32)), (ValType::I32)),
+    )
 }
 
 #[test]
-fn test_func_return_too_many() {
-    let mut executor = Executor::new();
-    let func = test_func!(
-        "fun",
-        (),
-        (ValType::I32, ValType::I64),
+fn test_call_indirect() {
+    let mut executor = test_call_indirect_executor();
+    let line = test_line![(), (
+        Instruction::I32Const(21),
+        Instruction::I32Const(0),
+        call_indirect_i32_i32()
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[42]");
+}
+
+#[test]
+fn test_call_indirect_undefined_element_error() {
+    let mut executor = test_call_indirect_executor();
+    for (i, message) in [(3, "undefined element 3"), (2, "uninitialized element 2")] {
+        let line = test_line![(), (
+            Instruction::I32Const(21),
+            Instruction::I32Const(i),
+            call_indirect_i32_i32()
+        )];
+        let err = executor.execute_line(line).err().unwrap();
+        assert!(err.to_string().starts_with(message));
+    }
+}
+
+#[test]
+fn test_call_indirect_type_mismatch_error() {
+    let mut executor = test_call_indirect_executor();
+    let line = test_line![(), (
+        Instruction::I32Const(21),
+        Instruction::I32Const(1),
+        call_indirect_i32_i32()
+    )];
+    let err = executor.execute_line(line).err().unwrap();
+    assert!(err.to_string().starts_with("indirect call type mismatch"));
+}
+
+#[test]
+fn test_call_indirect_recursive() {
+    let mut executor = test_call_indirect_executor();
+    // Counts down to 0 calling itself, at element 2, through the table.
+    let countdown = test_func!(
+        "countdown",
+        (test_local!(ValType::I32)),
+        (ValType::I32),
         (
-            Instruction::I32Const(10),
-            Instruction::I32Const(20),
-            Instruction::I64Const(30),
-            Instruction::Return
+            Instruction::LocalGet(Index::Num(0)),
+            Instruction::If(
+                test_block_type!((), (ValType::I32)),
+                Some(Expression {
+                    instrs: vec![
+                        Instruction::LocalGet(Index::Num(0)),
+                        Instruction::I32Const(1),
+                        Instruction::I32Sub,
+                        Instruction::I32Const(2),
+                        call_indirect_i32_i32()
+                    ]
+                }),
+                Some(Expression {
+                    instrs: vec![Instruction::I32Const(7)]
+                })
+            )
         )
     );
-    executor.execute_line(func).unwrap();
+    executor.execute_line(countdown).unwrap();
+    executor
+        .execute_line(test_elem(2, vec![test_index("countdown")]))
+        .unwrap();
 
-    let call_fun = test_line![(), (Instruction::Call(test_index("fun")))];
-    let response = executor.execute_line(call_fun).unwrap();
-    assert_eq!(response.message(), "[20, 30]");
+    let line = test_line![(), (Instruction::I32Const(3), Instruction::Call(test_index("countdown")))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[7]");
 }
 
 #[test]
-fn test_func_stack_overflow_error() {
-    let mut executor = Executor::new();
-    let func = test_func!(
-        "fun",
-        (),
-        (),
-        (Instruction::Call(Index::Id(String::from("fun"))))
-    );
-    executor.execute_line(func).unwrap();
+fn test_elem_error() {
+    let mut executor = test_call_indirect_executor();
+    let elem = test_elem(2, vec![Index::Num(0), Index::Num(1)]);
+    assert!(executor.execute_line(elem).is_err());
+    let elem = test_elem(2, vec![test_index("unknown")]);
+    assert!(executor.execute_line(elem).is_err());
 
-    let call_fun = test_line![(), (Instruction::Call(test_index("fun")))];
-    assert!(executor.execute_line(call_fun).is_err());
+    // Neither of the failed lines set element 2.
+    let line = test_line![(), (
+        Instruction::I32Const(21),
+        Instruction::I32Const(2),
+        call_indirect_i32_i32()
+    )];
+    assert!(executor.execute_line(line).is_err());
 }
 
 #[test]
-fn test_return_line() {
-    let mut executor = Executor::new();
-    let line = test_line![(), (Instruction::I32Const(5), Instruction::Return)];
-    assert!(executor.execute_line(line).is_err());
+fn test_table_get_set() {
+    let mut executor = test_call_indirect_executor();
+    let line = "(table.get (i32.const 0)) (table.get (i32.const 2))";
+    assert_eq!(executor.eval(line).unwrap(), "[ref.func 0, ref.null func]");
 
-    // Ensure rollback
+    // Copies $double to element 2, to call it from there.
+    let line = "(table.set (i32.const 2) (table.get (i32.const 0))) \
+        (call_indirect (param i32) (result i32) (i32.const 21) (i32.const 2))";
+    let stack = "[ref.func 0, ref.null func, 42]";
+    assert_eq!(executor.eval(line).unwrap(), stack);
+    let line = "(table.set (i32.const 0) (ref.func $zero)) (table.get (i32.const 0))";
+    assert_eq!(executor.eval(line).unwrap(), "[ref.func 0, ref.null func, 42, ref.func 1]");
+
+    let line = "(table.set (i32.const 1) (ref.null func)) (table.get (i32.const 3))";
+    let err = executor.eval(line).err().unwrap();
     assert_eq!(
-        executor
-            .call_stack
-            .get_func_stack()
-            .unwrap()
-            .to_soft_string()
-            .unwrap(),
-        "[]"
+        err.to_string(),
+        "out of bounds table access, while executing instruction 5 (table.get)"
     );
+    // The failed line did not clear element 1.
+    let line = "(drop) (drop) (drop) (drop) (ref.is_null (table.get (i32.const 1)))";
+    assert_eq!(executor.eval(line).unwrap(), "[0]");
 }
 
 #[test]
-fn test_if() {
+fn test_table_size_grow() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!(
-        (test_local!(ValType::I32), test_local!(ValType::I32)),
-        (ValType::I32)
-    );
-    let line = test_line![(), (
-        Instruction::I32Const(12),
-        Instruction::I32Const(3),
-        Instruction::I32Const(1),
-        test_if!(block_type, (Instruction::I32Add), (Instruction::I32Sub)),
-        Instruction::I32Const(4)
-    )];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[15, 4]");
+    executor.eval("(table $t 1 3 funcref)").unwrap();
+    executor.eval("(func $f (result i32) (i32.const 5))").unwrap();
+    assert_eq!(executor.eval("(table.size $t)").unwrap(), "[1]");
+
+    let line = "(table.grow $t (ref.func $f) (i32.const 2)) (table.size $t)";
+    assert_eq!(executor.eval(line).unwrap(), "[1, 1, 3]");
+    let line = "(call_indirect $t (result i32) (i32.const 2))";
+    assert_eq!(executor.eval(line).unwrap(), "[1, 1, 3, 5]");
+
+    // Beyond the maximum of the table.
+    let line = "(table.grow $t (ref.null func) (i32.const 1))";
+    assert_eq!(executor.eval(line).unwrap(), "[1, 1, 3, 5, -1]");
+    let line = "(table.grow $t (ref.null func) (i32.const 0))";
+    assert_eq!(executor.eval(line).unwrap(), "[1, 1, 3, 5, -1, 3]");
 }
 
 #[test]
-fn test_if_execution_error() {
+fn test_table_grow_rollback() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!(
-        (test_local!(ValType::I32), test_local!(ValType::I32)),
-        (ValType::I32)
-    );
-    let line = test_line![(), (
-        Instruction::I32Const(1),
-        test_if!(block_type, (Instruction::I32Add), (Instruction::I32Sub)),
-        Instruction::I32Const(4)
-    )];
-    assert!(executor.execute_line(line).is_err());
+    executor.eval("(table $t 0 funcref)").unwrap();
+    let line = "(table.grow $t (ref.null func) (i32.const 4)) \
+        (i32.div_s (i32.const 1) (i32.const 0))";
+    assert!(executor.eval(line).is_err());
+    assert_eq!(executor.eval("(table.size $t)").unwrap(), "[0]");
 }
 
 #[test]
-fn test_if_param_error() {
+fn test_table_init() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let line = test_line![(), (
-        Instruction::I32Const(1),
-        test_if!(block_type),
-        Instruction::I32Const(4)
-    )];
-    assert!(executor.execute_line(line).is_err());
-    assert_eq!(
-        executor
-            .call_stack
-            .get_func_stack()
-            .unwrap()
-            .to_soft_string()
-            .unwrap(),
-        "[]"
-    );
+    executor.eval("(table 4 funcref)").unwrap();
+    executor.eval("(func $one (result i32) (i32.const 1))").unwrap();
+    executor.eval("(func $two (result i32) (i32.const 2))").unwrap();
+    let elem = "(elem $e func $one $two)";
+    assert_eq!(executor.eval(elem).unwrap(), "elem ;0; e");
+
+    let line = "(table.init $e (i32.const 2) (i32.const 0) (i32.const 2)) \
+        (call_indirect (result i32) (i32.const 3))";
+    assert_eq!(executor.eval(line).unwrap(), "[2]");
+
+    let line = "(table.init $e (i32.const 0) (i32.const 1) (i32.const 2))";
+    let err = executor.eval(line).err().unwrap();
+    assert!(err.to_string().contains("out of bounds table access"));
+    let line = "(table.init $e (i32.const 3) (i32.const 0) (i32.const 2))";
+    let err = executor.eval(line).err().unwrap();
+    assert!(err.to_string().contains("out of bounds table access"));
+
+    // Active segments are dropped once put into the table.
+    executor.eval("(elem (i32.const 0) $one)").unwrap();
+    let line = "(table.init 1 (i32.const 0) (i32.const 0) (i32.const 1))";
+    let err = executor.eval(line).err().unwrap();
+    assert!(err.to_string().contains("out of bounds table access"));
 }
 
 #[test]
-fn test_if_param_type_error() {
+fn test_elem_drop() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let line = test_line![(), (
-        Instruction::F32Const(1.0),
-        Instruction::I32Const(1),
-        test_if!(block_type),
-        Instruction::I32Const(4)
-    )];
-    assert!(executor.execute_line(line).is_err());
-    assert_eq!(
-        executor
-            .call_stack
-            .get_func_stack()
-            .unwrap()
-            .to_soft_string()
-            .unwrap(),
-        "[]"
-    );
+    executor.eval("(table 1 funcref)").unwrap();
+    executor.eval("(func $one (result i32) (i32.const 1))").unwrap();
+    executor.eval("(elem $e func $one)").unwrap();
+
+    // A drop on a line that fails is rolled back.
+    let line = "(elem.drop $e) (i32.div_s (i32.const 1) (i32.const 0))";
+    assert!(executor.eval(line).is_err());
+    let line = "(table.init $e (i32.const 0) (i32.const 0) (i32.const 1))";
+    assert_eq!(executor.eval(line).unwrap(), "[]");
+
+    executor.eval("(elem.drop $e)").unwrap();
+    let line = "(table.init $e (i32.const 0) (i32.const 0) (i32.const 1))";
+    let err = executor.eval(line).err().unwrap();
+    assert!(err.to_string().contains("out of bounds table access"));
+    let line = "(table.init $e (i32.const 0) (i32.const 0) (i32.const 0))";
+    assert_eq!(executor.eval(line).unwrap(), "[]");
 }
 
 #[test]
-fn test_if_result_error() {
+fn test_drop() {
     let mut executor = Executor::new();
+    let line = test_line![(), (Instruction::I32Const(5), Instruction::Drop)];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[]");
+
     let line = test_line![(), (
-        Instruction::I32Const(1),
-        test_if!(test_block_type!((), (ValType::I32))),
-        Instruction::I32Const(4)
+        Instruction::F64Const(1.5),
+        Instruction::I64Const(2),
+        Instruction::Drop
     )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1.5]");
+    let line = test_line![(), (Instruction::Drop)];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[]");
+}
+
+#[test]
+fn test_drop_empty_error() {
+    let mut executor = Executor::new();
+    let line = test_line![(), (Instruction::Drop)];
     assert!(executor.execute_line(line).is_err());
-    assert_eq!(
-        executor
-            .call_stack
-            .get_func_stack()
-            .unwrap()
-            .to_soft_string()
-            .unwrap(),
-        "[]"
-    );
+
+    let line = test_line![(), ()];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[]");
 }
 
 #[test]
-fn test_if_result_type_error() {
+fn test_local_set_get() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32));
-    let line = test_line![(), (
-        Instruction::I32Const(1),
-        test_if!(
-            block_type,
-            (Instruction::F64Const(1.0)),
-            (Instruction::F64Const(2.0))
-        ),
-        Instruction::I32Const(4)
+    let line = test_line![(test_local!(ValType::I32)), (
+        Instruction::I32Const(42),
+        Instruction::LocalSet(Index::Num(0)),
+        Instruction::LocalGet(Index::Num(0))
     )];
-    assert!(executor.execute_line(line).is_err());
     assert_eq!(
-        executor
-            .call_stack
-            .get_func_stack()
-            .unwrap()
-            .to_soft_string()
-            .unwrap(),
-        "[]"
+        executor.execute_line(line).unwrap().message(),
+        "local ;0;\n[42]"
+    );
+}
+
+#[test]
+fn test_local_default_value() {
+    let defaults = [
+        (ValType::I32, Value::I32(0)),
+        (ValType::I64, Value::I64(0)),
+        (ValType::F32, Value::F32(0.0)),
+        (ValType::F64, Value::F64(0.0)),
+    ];
+    for (val_type, default) in defaults {
+        let mut executor = Executor::new();
+        let line = test_line![(test_local!(val_type)), (Instruction::LocalGet(Index::Num(0)))];
+        assert_eq!(executor.execute_line(line).unwrap().message(), "local ;0;\n[0]");
+
+        let value = executor.call_stack.get_func_stack().unwrap().pop().unwrap();
+        assert_eq!(value, default);
+        // Positive zero, not -0.0
+        assert!(!matches!(value, Value::F32(n) if n.is_sign_negative()));
+        assert!(!matches!(value, Value::F64(n) if n.is_sign_negative()));
+    }
+}
+
+#[test]
+fn test_local_set_commit() {
+    let mut executor = Executor::new();
+    let line = test_line![(test_local!(ValType::I32)), (
+        Instruction::I32Const(42),
+        Instruction::LocalSet(Index::Num(0)),
+        Instruction::LocalGet(Index::Num(0))
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0;\n[42]"
+    );
+
+    let line = test_line![(), (
+        Instruction::Drop,
+        Instruction::I32Const(55),
+        Instruction::LocalSet(Index::Num(0)),
+        Instruction::LocalGet(Index::Num(0))
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[55]");
+}
+
+#[test]
+fn test_local_set_local_value_rollback() {
+    let mut executor = Executor::new();
+    let line = test_line![(test_local!(ValType::I32)), (
+        Instruction::I32Const(42),
+        Instruction::LocalSet(Index::Num(0))
+    )];
+    executor.execute_line(line).unwrap();
+
+    let line = test_line![(), (
+        Instruction::I32Const(43),
+        Instruction::I32Const(55),
+        Instruction::LocalSet(Index::Num(0)),
+        // Failing instruction
+        Instruction::F32Neg
+    )];
+    assert!(executor.execute_line(line).is_err());
+
+    let line = test_line![(test_local!(ValType::I32)), (Instruction::LocalGet(
+        Index::Num(0)
+    ))];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;1;\n[42]"
+    );
+}
+
+#[test]
+fn test_local_rollback() {
+    let mut executor = Executor::new();
+    let line = test_line![(test_local_id!("num", ValType::I32)), ()];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0; num\n[]"
+    );
+    let line = test_line![(test_local_id!("num", ValType::I32)), ()];
+    assert!(executor.execute_line(line).is_err());
+
+    let line = test_line![(test_local_id!("num2", ValType::I32)), ()];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;1; num2\n[]"
+    );
+}
+
+#[test]
+fn test_local_by_id() {
+    let mut executor = Executor::new();
+
+    let local = test_local_id!("num", ValType::I32);
+
+    let set_index = test_index("num");
+    let get_index = test_index("num");
+
+    let line = test_line![(local), (
+        Instruction::I32Const(42),
+        Instruction::LocalSet(set_index),
+        Instruction::LocalGet(get_index)
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0; num\n[42]"
+    );
+}
+
+#[test]
+fn test_local_by_id_mix() {
+    let mut executor = Executor::new();
+    let local = test_local_id!("num", ValType::I32);
+    let index = test_index("num");
+
+    let line = test_line![(test_local!(ValType::I32), local), (
+        Instruction::I32Const(42),
+        Instruction::LocalSet(index),
+        Instruction::LocalGet(Index::Num(1))
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0;\nlocal ;1; num\n[42]"
+    );
+}
+
+#[test]
+fn test_local_set_get_i64() {
+    let mut executor = Executor::new();
+    let line = test_line![(test_local!(ValType::I64)), (
+        Instruction::I64Const(42),
+        Instruction::LocalSet(Index::Num(0)),
+        Instruction::LocalGet(Index::Num(0))
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0;\n[42]"
+    );
+}
+
+#[test]
+#[allow(clippy::approx_constant)]
+fn test_local_set_get_f32() {
+    let mut executor = Executor::new();
+    let local = test_local!(ValType::F32);
+    let line = test_line![(local), (
+        Instruction::F32Const(3.14),
+        Instruction::LocalSet(Index::Num(0)),
+        Instruction::LocalGet(Index::Num(0))
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0;\n[3.14]"
+    );
+}
+
+#[test]
+#[allow(clippy::approx_constant)]
+fn test_local_set_get_f64() {
+    let mut executor = Executor::new();
+    let local = Local {
+        id: None,
+        val_type: ValType::F64,
+    };
+    let line = test_line![(local), (
+        Instruction::F64Const(3.14f64),
+        Instruction::LocalSet(Index::Num(0)),
+        Instruction::LocalGet(Index::Num(0))
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0;\n[3.14]"
+    );
+}
+
+#[test]
+fn test_local_set_get_type_error() {
+    let mut executor = Executor::new();
+    let line = test_line![(test_local!(ValType::I32)), (
+        Instruction::I64Const(55),
+        Instruction::LocalSet(Index::Num(0))
+    )];
+    assert!(executor.execute_line(line).is_err());
+}
+
+#[test]
+fn test_func() {
+    let mut executor = Executor::new();
+    let func = test_func!(
+        "subtract",
+        (
+            test_local_id!("first", ValType::I32),
+            test_local_id!("second", ValType::I32)
+        ),
+        (ValType::I32, ValType::I32),
+        (
+            Instruction::LocalGet(test_index("first")),
+            Instruction::LocalGet(test_index("first")),
+            Instruction::LocalGet(test_index("second")),
+            Instruction::I32Sub
+        )
+    );
+    let response = executor.execute_line(func).unwrap();
+    assert_eq!(response.message(), "func ;0; subtract");
+
+    let call_sub = test_line![(), (
+        Instruction::I32Const(7),
+        Instruction::I32Const(2),
+        Instruction::Call(test_index("subtract"))
+    )];
+    assert_eq!(executor.execute_line(call_sub).unwrap().message(), "[7, 5]");
+}
+
+#[test]
+fn test_func_error_less_number_of_inputs() {
+    let mut executor = Executor::new();
+    let func = test_func!("fun", (test_local!(ValType::I32)), (), ());
+    executor.execute_line(func).unwrap();
+
+    let call_fun = test_line![(), (Instruction::Call(test_index("fun")))];
+    assert!(executor.execute_line(call_fun).is_err());
+}
+
+#[test]
+fn test_func_error_less_number_of_outputs() {
+    let mut executor = Executor::new();
+    let func = test_func!("fun", (), (ValType::I32), ());
+    let err = executor.execute_line(func).err().unwrap();
+    assert!(err.to_string().contains("expected i32, found none at the end of func"));
+}
+
+#[test]
+fn test_func_error_more_number_of_outputs() {
+    let mut executor = Executor::new();
+    let func = test_func!("fun", (), (), (Instruction::I32Const(5)));
+    let err = executor.execute_line(func).err().unwrap();
+    assert!(err.to_string().contains("too many values at the end of func"));
+}
+
+#[test]
+fn test_func_input_type() {
+    let mut executor = Executor::new();
+    let func = test_func!(
+        "fun",
+        (test_local!(ValType::I32), test_local!(ValType::I64)),
+        (),
+        ()
+    );
+    executor.execute_line(func).unwrap();
+
+    let call_fun = test_line![(), (
+        Instruction::I32Const(5),
+        Instruction::I64Const(10),
+        Instruction::Call(test_index("fun"))
+    )];
+    executor.execute_line(call_fun).unwrap();
+}
+
+#[test]
+fn test_func_error_input_type() {
+    let mut executor = Executor::new();
+    let func = test_func!(
+        "fun",
+        (test_local!(ValType::I32), test_local!(ValType::I64)),
+        (),
+        ()
+    );
+    executor.execute_line(func).unwrap();
+
+    let call_fun = test_line![(), (
+        Instruction::I64Const(5),
+        Instruction::I32Const(10),
+        Instruction::Call(test_index("fun"))
+    )];
+    assert!(executor.execute_line(call_fun).is_err());
+}
+
+#[test]
+fn test_func_output_type() {
+    let mut executor = Executor::new();
+    let func = test_func!(
+        "fun",
+        (),
+        (ValType::I32, ValType::I64),
+        (Instruction::I32Const(5), Instruction::I64Const(10))
+    );
+    executor.execute_line(func).unwrap();
+
+    let call_fun = test_line![(), (Instruction::Call(test_index("fun")))];
+    executor.execute_line(call_fun).unwrap();
+}
+
+#[test]
+fn test_func_output_type_error() {
+    let mut executor = Executor::new();
+    let func = test_func!(
+        "fun",
+        (),
+        (ValType::I32, ValType::I64),
+        (Instruction::I64Const(10), Instruction::I32Const(5))
+    );
+    let err = executor.execute_line(func).err().unwrap();
+    assert!(err.to_string().contains("expected i64, found i32 at the end of func"));
+}
+
+#[test]
+fn test_func_no_id() {
+    let mut executor = Executor::new();
+    let func = Line::Func(Func {
+        id: None,
+        ty: FuncType {
+            params: vec![test_local!(ValType::I32)],
+            results: vec![ValType::I32],
+        },
+        line_expression: LineExpression {
+            locals: vec![],
+            expr: Expression {
+                instrs: vec![Instruction::LocalGet(Index::Num(0))],
+            },
+        },
+        recursive: false,
+    });
+    let response = executor.execute_line(func).unwrap();
+    assert_eq!(response.message(), "func ;0;");
+
+    let call_fun = test_line![(), (
+        Instruction::I32Const(2),
+        Instruction::Call(Index::Num(0))
+    )];
+    let response = executor.execute_line(call_fun).unwrap();
+    assert_eq!(response.message(), "[2]");
+}
+
+#[test]
+fn test_func_return() {
+    let mut executor = Executor::new();
+    let func = test_func!(
+        "fun",
+        (),
+        (ValType::I32),
+        (
+            Instruction::I32Const(5),
+            Instruction::Return,
+            Instruction::Drop,
+            Instruction::I32Const(6)
+        )
+    );
+    executor.execute_line(func).unwrap();
+
+    let call_fun = test_line![(), (Instruction::Call(test_index("fun")))];
+    let response = executor.execute_line(call_fun).unwrap();
+    assert_eq!(response.message(), "[5]");
+}
+
+#[test]
+fn test_func_return_too_many() {
+    let mut executor = Executor::new();
+    let func = test_func!(
+        "fun",
+        (),
+        (ValType::I32, ValType::I64),
+        (
+            Instruction::I32Const(10),
+            Instruction::I32Const(20),
+            Instruction::I64Const(30),
+            Instruction::Return
+        )
+    );
+    executor.execute_line(func).unwrap();
+
+    let call_fun = test_line![(), (Instruction::Call(test_index("fun")))];
+    let response = executor.execute_line(call_fun).unwrap();
+    assert_eq!(response.message(), "[20, 30]");
+}
+
+#[test]
+fn test_func_stack_overflow_error() {
+    let mut executor = Executor::new();
+    let func = test_func!(
+        "fun",
+        (),
+        (),
+        (Instruction::Call(Index::Id(String::from("fun"))))
+    );
+    executor.execute_line(func).unwrap();
+
+    let call_fun = test_line![(), (Instruction::Call(test_index("fun")))];
+    assert!(executor.execute_line(call_fun).is_err());
+}
+
+/// Defines `$down`, which calls itself until its param is 0.
+fn define_down(executor: &mut Executor) {
+    let func = "(func $down (param i32) (if (local.get 0) (then \
+        (call $down (i32.sub (local.get 0) (i32.const 1))))))";
+    executor.eval(func).unwrap();
+}
+
+#[test]
+fn test_max_depth_raised() {
+    let mut executor = Executor::new();
+    define_down(&mut executor);
+    let err = executor.eval("(call $down (i32.const 110))").err().unwrap();
+    assert!(err.to_string().contains("Stack overflow"));
+
+    executor.set_max_depth(120);
+    assert_eq!(executor.eval("(call $down (i32.const 110))").unwrap(), "[]");
+}
+
+#[test]
+fn test_max_depth_lowered() {
+    let mut executor = Executor::new();
+    define_down(&mut executor);
+    assert_eq!(executor.eval("(call $down (i32.const 50))").unwrap(), "[]");
+
+    executor.set_max_depth(20);
+    let err = executor.eval("(call $down (i32.const 50))").err().unwrap();
+    assert!(err.to_string().contains("Stack overflow"));
+    assert_eq!(executor.eval("(call $down (i32.const 10))").unwrap(), "[]");
+}
+
+#[test]
+fn test_return_call_countdown() {
+    let mut executor = Executor::new();
+    let func = "(func $tail (param i32) (if (local.get 0) (then \
+        (return_call $tail (i32.sub (local.get 0) (i32.const 1))))))";
+    executor.eval(func).unwrap();
+    assert_eq!(executor.eval("(call $tail (i32.const 1000))").unwrap(), "[]");
+
+    define_down(&mut executor);
+    let err = executor.eval("(call $down (i32.const 1000))").err().unwrap();
+    assert!(err.to_string().contains("Stack overflow"));
+}
+
+#[test]
+fn test_return_call_result() {
+    let mut executor = Executor::new();
+    executor
+        .eval("(func $double (param i32) (result i32) (i32.mul (local.get 0) (i32.const 2)))")
+        .unwrap();
+    let func = "(func $sum (param i32 i32) (result i32) \
+        (if (result i32) (local.get 0) \
+          (then (return_call $sum \
+            (i32.sub (local.get 0) (i32.const 1)) (i32.add (local.get 0) (local.get 1)))) \
+          (else (return_call $double (local.get 1)))))";
+    executor.eval(func).unwrap();
+    let response = executor.eval("(i32.const 7) (call $sum (i32.const 500) (i32.const 0))");
+    assert_eq!(response.unwrap(), "[7, 250500]");
+}
+
+#[test]
+fn test_return_call_callee_retype_error() {
+    let mut executor = Executor::new();
+    executor.eval("(func $none (result i32) (i32.const 0))").unwrap();
+    executor.eval("(func $f (result i32) (return_call $none))").unwrap();
+    let err = executor.eval("(func $none)").err().unwrap();
+    assert!(err
+        .to_string()
+        .starts_with("$none is called by $f with its old type: "));
+    assert_eq!(executor.eval("(call $f)").unwrap(), "[0]");
+}
+
+#[test]
+fn test_redefine_retype_callers() {
+    let mut executor = Executor::new();
+    executor.eval("(func $g (result i32) (i32.const 1))").unwrap();
+    executor.eval("(func $f (result i32) (call $g))").unwrap();
+
+    let err = executor.eval("(func $g (result i64) (i64.const 2))").err().unwrap();
+    assert!(err.to_string().starts_with("$g is called by $f with its old type: "));
+    assert_eq!(executor.eval("(call $f)").unwrap(), "[1]");
+
+    // Once no caller is left, the type can change.
+    executor.eval("(func $f (result i32) (i32.const 3))").unwrap();
+    executor.eval("(func $g (result i64) (i64.const 2))").unwrap();
+    assert_eq!(executor.eval("(call $g)").unwrap(), "[1, 2]");
+}
+
+/// `$ping` and `$pong` at 0 and 1 of a table, each counting down and
+/// handing over to the other, until `$ping` ends on 0 or `$pong` on 1.
+fn define_ping_pong(executor: &mut Executor) {
+    executor.eval("(table 2 funcref)").unwrap();
+    for (name, last, next) in [("ping", 0, 1), ("pong", 1, 0)] {
+        let func = format!(
+            "(func ${} (param i32) (result i32) \
+              (if (result i32) (i32.eqz (local.get 0)) \
+                (then (i32.const {})) \
+                (else (return_call_indirect (param i32) (result i32) \
+                  (i32.sub (local.get 0) (i32.const 1)) (i32.const {})))))",
+            name, last, next
+        );
+        executor.eval(&func).unwrap();
+    }
+    executor.eval("(elem (i32.const 0) $ping $pong)").unwrap();
+}
+
+#[test]
+fn test_return_call_indirect_state_machine() {
+    let mut executor = Executor::new();
+    define_ping_pong(&mut executor);
+    executor.set_max_depth(5);
+    assert_eq!(executor.eval("(call $ping (i32.const 1001))").unwrap(), "[1]");
+    assert_eq!(executor.eval("(call $pong (i32.const 1000))").unwrap(), "[1, 1]");
+}
+
+#[test]
+fn test_return_call_indirect_type_mismatch_error() {
+    let mut executor = Executor::new();
+    define_ping_pong(&mut executor);
+    let func = "(func $f (result i32) \
+        (return_call_indirect (result i32) (i32.const 0)))";
+    executor.eval(func).unwrap();
+    let err = executor.eval("(call $f)").err().unwrap();
+    assert!(err.to_string().starts_with("indirect call type mismatch"));
+    assert_eq!(executor.eval("(i32.const 1)").unwrap(), "[1]");
+}
+
+#[test]
+fn test_return_call_line() {
+    let mut executor = Executor::new();
+    executor.eval("(func $none)").unwrap();
+    let err = executor.eval("(return_call $none)").err().unwrap();
+    assert_eq!(err.to_string(), "return_call is allowed only in func");
+}
+
+#[test]
+fn test_quiet() {
+    let line = "(local i32) (local $x f32) (i32.const 1)";
+    let mut executor = Executor::new();
+    assert_eq!(executor.eval(line).unwrap(), "local ;0;\nlocal ;1; x\n[1]");
+
+    let mut executor = Executor::new();
+    executor.set_quiet(true);
+    assert_eq!(executor.eval(line).unwrap(), "[1]");
+    let module = "(module (func $f) (func $g))";
+    assert_eq!(executor.eval(module).unwrap(), "");
+    assert_eq!(executor.eval("(global $c i32 (i32.const 0))").unwrap(), "");
+}
+
+#[test]
+fn test_return_line() {
+    let mut executor = Executor::new();
+    let line = test_line![(), (Instruction::I32Const(5), Instruction::Return)];
+    assert!(executor.execute_line(line).is_err());
+
+    // Ensure rollback
+    assert_eq!(
+        executor
+            .call_stack
+            .get_func_stack()
+            .unwrap()
+            .to_soft_string(ValueFormat::new())
+            .unwrap(),
+        "[]"
+    );
+}
+
+#[test]
+fn test_if() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!(
+        (test_local!(ValType::I32), test_local!(ValType::I32)),
+        (ValType::I32)
+    );
+    let line = test_line![(), (
+        Instruction::I32Const(12),
+        Instruction::I32Const(3),
+        Instruction::I32Const(1),
+        test_if!(block_type, (Instruction::I32Add), (Instruction::I32Sub)),
+        Instruction::I32Const(4)
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[15, 4]");
+}
+
+#[test]
+fn test_if_execution_error() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!(
+        (test_local!(ValType::I32), test_local!(ValType::I32)),
+        (ValType::I32)
+    );
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_if!(block_type, (Instruction::I32Add), (Instruction::I32Sub)),
+        Instruction::I32Const(4)
+    )];
+    assert!(executor.execute_line(line).is_err());
+}
+
+#[test]
+fn test_if_param_error() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_if!(block_type),
+        Instruction::I32Const(4)
+    )];
+    assert!(executor.execute_line(line).is_err());
+    assert_eq!(
+        executor
+            .call_stack
+            .get_func_stack()
+            .unwrap()
+            .to_soft_string(ValueFormat::new())
+            .unwrap(),
+        "[]"
+    );
+}
+
+#[test]
+fn test_if_param_type_error() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::F32Const(1.0),
+        Instruction::I32Const(1),
+        test_if!(block_type),
+        Instruction::I32Const(4)
+    )];
+    assert!(executor.execute_line(line).is_err());
+    assert_eq!(
+        executor
+            .call_stack
+            .get_func_stack()
+            .unwrap()
+            .to_soft_string(ValueFormat::new())
+            .unwrap(),
+        "[]"
+    );
+}
+
+#[test]
+fn test_if_condition_type_error() {
+    let mut executor = Executor::new();
+    executor.eval("(i32.const 7)").unwrap();
+    let err = executor
+        .eval("(if (f32.const 1.0) (then (i32.const 1) (drop)))")
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("if expected i32 but found f32"));
+    let err = executor
+        .eval("(i32.const 2) (if (i64.const 0) (then (drop)))")
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("if expected i32 but found i64"));
+    assert_eq!(executor.eval("(nop)").unwrap(), "[7]");
+}
+
+#[test]
+fn test_br_if_condition_type_error() {
+    let mut executor = Executor::new();
+    executor.eval("(i32.const 7)").unwrap();
+    let err = executor
+        .eval("(block (i32.const 1) (br_if 0 (f32.const 1.0)) (drop))")
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("br_if expected i32 but found f32"));
+    assert_eq!(executor.eval("(nop)").unwrap(), "[7]");
+
+    let func = "(func (if (i64.const 1) (then (nop))))";
+    let err = executor.eval(func).err().unwrap();
+    assert!(err.to_string().contains("expected i32, found i64 in if"));
+}
+
+#[test]
+fn test_if_result_error() {
+    let mut executor = Executor::new();
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_if!(test_block_type!((), (ValType::I32))),
+        Instruction::I32Const(4)
+    )];
+    assert!(executor.execute_line(line).is_err());
+    assert_eq!(
+        executor
+            .call_stack
+            .get_func_stack()
+            .unwrap()
+            .to_soft_string(ValueFormat::new())
+            .unwrap(),
+        "[]"
+    );
+}
+
+#[test]
+fn test_if_result_type_error() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_if!(
+            block_type,
+            (Instruction::F64Const(1.0)),
+            (Instruction::F64Const(2.0))
+        ),
+        Instruction::I32Const(4)
+    )];
+    assert!(executor.execute_line(line).is_err());
+    assert_eq!(
+        executor
+            .call_stack
+            .get_func_stack()
+            .unwrap()
+            .to_soft_string(ValueFormat::new())
+            .unwrap(),
+        "[]"
     );
 }
 
 #[test]
 fn test_if_result_too_many() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32));
+    let block_type = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_if!(
+            block_type,
+            (Instruction::I32Const(1), Instruction::I32Const(3)),
+            (Instruction::I32Const(2), Instruction::I32Const(4))
+        ),
+        Instruction::I32Const(4)
+    )];
+    assert!(executor.execute_line(line).is_err());
+    assert_eq!(
+        executor
+            .call_stack
+            .get_func_stack()
+            .unwrap()
+            .to_soft_string(ValueFormat::new())
+            .unwrap(),
+        "[]"
+    );
+}
+
+#[test]
+fn test_else() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!(
+        (test_local!(ValType::I32), test_local!(ValType::I32)),
+        (ValType::I32)
+    );
+    let line = test_line![(), (
+        Instruction::I32Const(12),
+        Instruction::I32Const(3),
+        Instruction::I32Const(0),
+        test_if!(block_type, (Instruction::I32Add), (Instruction::I32Sub)),
+        Instruction::I32Const(4)
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[9, 4]");
+}
+
+#[test]
+fn test_nested_if() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((), (ValType::I32));
+    let block_type_inner = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_if!(
+            block_type,
+            (
+                Instruction::I32Const(2),
+                test_if!(
+                    block_type_inner,
+                    (Instruction::I32Const(3)),
+                    (Instruction::I32Const(5))
+                )
+            ),
+            (Instruction::I32Const(4))
+        ),
+        Instruction::I32Const(6)
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[3, 6]");
+}
+
+#[test]
+fn test_skip_nested_if() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((), (ValType::I32));
+    let block_type_inner = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(0),
+        test_if!(
+            block_type,
+            (
+                Instruction::I32Const(2),
+                test_if!(
+                    block_type_inner,
+                    (Instruction::I32Const(3)),
+                    (Instruction::I32Const(5))
+                )
+            ),
+            (Instruction::I32Const(4))
+        ),
+        Instruction::I32Const(6)
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[4, 6]");
+}
+
+#[test]
+fn test_no_if() {
+    let mut executor = Executor::new();
+    let line = test_line![(), (
+        Instruction::I32Const(3),
+        test_if!(
+            test_block_type!(),
+            (),
+            (Instruction::I32Const(5), Instruction::Drop)
+        ),
+        Instruction::I32Const(2)
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[2]");
+}
+
+#[test]
+fn test_no_else() {
+    let mut executor = Executor::new();
+    let line = test_line![(), (
+        Instruction::I32Const(-2),
+        test_if!(
+            test_block_type!(),
+            (Instruction::I32Const(5), Instruction::Drop),
+            ()
+        ),
+        Instruction::I32Const(2)
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[2]");
+}
+
+#[test]
+fn test_func_nested_return() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((), (ValType::I32));
+    let func = test_func!(
+        "fn",
+        (test_local!(ValType::I32)),
+        (ValType::I32),
+        (
+            Instruction::LocalGet(Index::Num(0)),
+            test_if!(
+                block_type,
+                (
+                    Instruction::I32Const(2),
+                    Instruction::Return,
+                    Instruction::I32Const(3)
+                ),
+                (Instruction::I32Const(4))
+            ),
+            Instruction::I32Const(5),
+            Instruction::Return
+        )
+    );
+    executor.execute_line(func).unwrap();
+
+    let call_sub = test_line![(), (
+        Instruction::I32Const(1),
+        Instruction::Call(test_index("fn"))
+    )];
+    assert_eq!(executor.execute_line(call_sub).unwrap().message(), "[2]");
+
+    let call_sub = test_line![(), (
+        Instruction::Drop,
+        Instruction::I32Const(0),
+        Instruction::Call(test_index("fn"))
+    )];
+    assert_eq!(executor.execute_line(call_sub).unwrap().message(), "[5]");
+}
+
+#[test]
+fn test_func_nested_too_many() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((), (ValType::I32));
+    let func = test_func!(
+        "fn",
+        (),
+        (ValType::I32),
+        (
+            Instruction::I32Const(1),
+            test_block!(
+                block_type,
+                (
+                    Instruction::I32Const(2),
+                    Instruction::Return,
+                    Instruction::I32Const(3)
+                )
+            )
+        )
+    );
+    let err = executor.execute_line(func).err().unwrap();
+    assert!(err.to_string().contains("too many values at the end of func"));
+}
+
+#[test]
+fn test_block() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        Instruction::I32Const(2),
+        test_block!(block_type, (Instruction::I32Const(3), Instruction::I32Add)),
+        Instruction::I32Const(4)
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1, 5, 4]");
+}
+
+#[test]
+fn test_block_identity() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let line = test_line![(), (Instruction::I32Const(7), test_block!(block_type))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[7]");
+}
+
+#[test]
+fn test_if_identity() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(7),
+        Instruction::I32Const(1),
+        test_if!(block_type, (), (Instruction::Drop, Instruction::I32Const(8)))
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[7]");
+}
+
+#[test]
+fn test_block_param_not_local() {
+    // Block params are taken from the stack, they are not locals.
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(7),
+        test_block!(block_type, (Instruction::LocalGet(Index::Num(0))))
+    )];
+    assert!(executor.execute_line(line).is_err());
+}
+
+#[test]
+fn test_nested_block() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((), (ValType::I32, ValType::I32));
+    let block_type_inner = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_block!(
+            block_type,
+            (
+                Instruction::I32Const(2),
+                test_block!(block_type_inner, (Instruction::I32Const(3)))
+            )
+        ),
+        Instruction::I32Const(4)
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "[1, 2, 3, 4]"
+    );
+}
+
+#[test]
+fn test_block_branch() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_block!(
+            block_type,
+            (
+                Instruction::I32Const(2),
+                Instruction::Br(Index::Num(0)),
+                Instruction::I32Const(3)
+            )
+        ),
+        Instruction::I32Const(4)
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1, 2, 4]");
+}
+
+#[test]
+fn test_nested_branch_inner_block() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((), (ValType::I32, ValType::I32, ValType::I32));
+    let block_type_inner = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_block!(
+            block_type,
+            (
+                Instruction::I32Const(2),
+                test_block!(
+                    block_type_inner,
+                    (
+                        Instruction::I32Const(3),
+                        Instruction::Br(Index::Num(0)),
+                        Instruction::I32Const(4)
+                    )
+                ),
+                Instruction::I32Const(5)
+            )
+        ),
+        Instruction::I32Const(6)
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "[1, 2, 3, 5, 6]"
+    );
+}
+
+#[test]
+fn test_nested_branch_outer_block() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((), (ValType::I32, ValType::I32));
+    let block_type_inner = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_block!(
+            block_type,
+            (
+                Instruction::I32Const(2),
+                test_block!(
+                    block_type_inner,
+                    (
+                        Instruction::I32Const(4),
+                        Instruction::Br(Index::Num(1)),
+                        Instruction::I32Const(5)
+                    )
+                ),
+                Instruction::I32Const(6)
+            )
+        ),
+        Instruction::I32Const(7)
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "[1, 2, 4, 7]"
+    );
+}
+
+#[test]
+fn test_branch_too_many() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_block!(
+            block_type,
+            (
+                Instruction::I32Const(2),
+                Instruction::I32Const(3),
+                Instruction::Br(Index::Num(0)),
+                Instruction::I32Const(4)
+            )
+        ),
+        Instruction::I32Const(7)
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1, 3, 7]");
+}
+
+#[test]
+fn test_branch_nested_too_many_error() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((), (ValType::I32));
+    let block_type_inner = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_block!(
+            block_type,
+            (
+                Instruction::I32Const(2),
+                test_block!(
+                    block_type_inner,
+                    (
+                        Instruction::I32Const(3),
+                        Instruction::Br(Index::Num(1)),
+                        Instruction::I32Const(4)
+                    )
+                )
+            )
+        )
+    )];
+    assert!(executor.execute_line(line).is_err());
+}
+
+#[test]
+fn test_branch_not_deep_enough_error() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((), (ValType::I32));
+    let block_type_inner = test_block_type!((), (ValType::I32));
+
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_block!(
+            block_type,
+            (test_block!(
+                block_type_inner,
+                (
+                    Instruction::I32Const(3),
+                    Instruction::Br(Index::Num(2)),
+                    Instruction::I32Const(4)
+                )
+            ))
+        )
+    )];
+    assert!(executor.execute_line(line).is_err());
+}
+
+#[test]
+fn test_func_branch() {
+    let mut executor = Executor::new();
+    let func = test_func!(
+        "fn",
+        (),
+        (ValType::I32),
+        (
+            Instruction::I32Const(1),
+            Instruction::Br(Index::Num(0)),
+            Instruction::I32Const(2)
+        )
+    );
+    executor.execute_line(func).unwrap();
+
+    let call_func = test_line![(), (
+        Instruction::Call(test_index("fn")),
+        Instruction::I32Const(3)
+    )];
+    assert_eq!(
+        executor.execute_line(call_func).unwrap().message(),
+        "[1, 3]"
+    );
+}
+
+#[test]
+fn test_func_branch_not_deep_enough_error() {
+    let mut executor = Executor::new();
+    let func = test_func!(
+        "fn",
+        (),
+        (ValType::I32),
+        (
+            Instruction::I32Const(1),
+            Instruction::Br(Index::Num(1)),
+            Instruction::I32Const(2)
+        )
+    );
+    let err = executor.execute_line(func).err().unwrap();
+    assert!(err.to_string().contains("unknown label 1 in br"));
+}
+
+#[test]
+fn test_block_branch_by_id() {
+    let mut executor = Executor::new();
+    let mut block_type = test_block_type!((), (ValType::I32));
+    block_type.label = Some("block_id".to_string());
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_block!(
+            block_type,
+            (
+                Instruction::I32Const(2),
+                Instruction::Br(Index::Id("block_id".to_string())),
+                Instruction::I32Const(3)
+            )
+        ),
+        Instruction::I32Const(4)
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1, 2, 4]");
+}
+
+#[test]
+fn test_block_branch_by_unknown_id_error() {
+    let mut executor = Executor::new();
+    let mut block_type = test_block_type!((), (ValType::I32));
+    block_type.label = Some("block_id".to_string());
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_block!(
+            block_type,
+            (
+                Instruction::I32Const(2),
+                Instruction::Br(Index::Id("block_id_uk".to_string())),
+                Instruction::I32Const(3)
+            )
+        ),
+        Instruction::I32Const(4)
+    )];
+    assert!(executor.execute_line(line).is_err());
+}
+
+#[test]
+fn test_func_branch_too_many() {
+    let mut executor = Executor::new();
+    let func = test_func!(
+        "fn",
+        (),
+        (ValType::I32),
+        (
+            Instruction::I32Const(0),
+            Instruction::I32Const(1),
+            Instruction::Br(Index::Num(0)),
+            Instruction::I32Const(2)
+        )
+    );
+    executor.execute_line(func).unwrap();
+
+    let call_func = test_line![(), (
+        Instruction::Call(test_index("fn")),
+        Instruction::I32Const(3)
+    )];
+    assert_eq!(
+        executor.execute_line(call_func).unwrap().message(),
+        "[1, 3]"
+    );
+}
+
+#[test]
+fn test_func_branch_id_error() {
+    let mut executor = Executor::new();
+    let func = test_func!(
+        "fname",
+        (),
+        (ValType::I32),
+        (
+            Instruction::I32Const(0),
+            Instruction::I32Const(1),
+            Instruction::Br(Index::Id("fname".to_string())),
+            Instruction::I32Const(2)
+        )
+    );
+    let err = executor.execute_line(func).err().unwrap();
+    assert!(err.to_string().contains("unknown label $fname in br"));
+}
+
+#[test]
+fn test_if_branch() {
+    let mut executor = Executor::new();
+    let block_type = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        test_if!(
+            block_type,
+            (
+                Instruction::I32Const(2),
+                Instruction::I32Const(3),
+                Instruction::Br(Index::Num(0)),
+                Instruction::I32Const(4)
+            ),
+            (Instruction::I32Const(5))
+        ),
+        Instruction::I32Const(6)
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[3, 6]");
+}
+
+#[test]
+fn test_loop() {
+    let mut executor = Executor::new();
+    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let if_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let line = test_line![(test_local!(ValType::I32)), (
+        Instruction::I32Const(10),
+        test_loop!(
+            loop_block_type,
+            (
+                Instruction::I32Const(1),
+                Instruction::I32Sub,
+                Instruction::LocalTee(Index::Num(0)),
+                Instruction::LocalGet(Index::Num(0)),
+                test_if!(if_block_type, (Instruction::Br(Index::Num(1))), ())
+            )
+        )
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0;\n[0]"
+    );
+}
+
+#[test]
+fn test_loop_br_if() {
+    let mut executor = Executor::new();
+    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let line = test_line![(test_local!(ValType::I32), test_local!(ValType::I32)), (
+        Instruction::I32Const(10),
+        test_loop!(
+            loop_block_type,
+            (
+                Instruction::LocalGet(Index::Num(1)),
+                Instruction::I32Const(1),
+                Instruction::I32Add,
+                Instruction::LocalSet(Index::Num(1)),
+                Instruction::I32Const(1),
+                Instruction::I32Sub,
+                Instruction::LocalTee(Index::Num(0)),
+                Instruction::LocalGet(Index::Num(0)),
+                Instruction::BrIf(Index::Num(0))
+            )
+        ),
+        Instruction::LocalGet(Index::Num(1))
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0;\nlocal ;1;\n[0, 10]"
+    );
+}
+
+#[test]
+fn test_block_br_if_out() {
+    let mut executor = Executor::new();
+    let outer_block_type = test_block_type!((), (ValType::I32));
+    let inner_block_type = test_block_type!((), ());
+    let line = test_line![(), (test_block!(
+        outer_block_type,
+        (
+            test_block!(
+                inner_block_type,
+                (
+                    Instruction::I32Const(2),
+                    Instruction::I32Const(1),
+                    Instruction::BrIf(Index::Num(1)),
+                    Instruction::Drop
+                )
+            ),
+            Instruction::I32Const(3)
+        )
+    ))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[2]");
+}
+
+#[test]
+fn test_block_br_table() {
+    for (n, expected) in [(0, "[111]"), (1, "[110]"), (2, "[10]"), (9, "[10]")] {
+        let mut executor = Executor::new();
+        let block_type = test_block_type!((), (ValType::I32));
+        let line = test_line![(), (test_block!(
+            block_type.clone(),
+            (
+                test_block!(
+                    block_type.clone(),
+                    (
+                        test_block!(
+                            block_type.clone(),
+                            (
+                                Instruction::I32Const(10),
+                                Instruction::I32Const(n),
+                                Instruction::BrTable(
+                                    vec![Index::Num(0), Index::Num(1)],
+                                    Index::Num(2)
+                                )
+                            )
+                        ),
+                        Instruction::I32Const(1),
+                        Instruction::I32Add
+                    )
+                ),
+                Instruction::I32Const(100),
+                Instruction::I32Add
+            )
+        ))];
+        assert_eq!(executor.execute_line(line).unwrap().message(), expected);
+    }
+}
+
+#[test]
+fn test_loop_by_id() {
+    let mut executor = Executor::new();
+    let mut loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    loop_block_type.label = Some("lname".to_string());
+    let if_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let line = test_line![(test_local!(ValType::I32)), (
+        Instruction::I32Const(10),
+        test_loop!(
+            loop_block_type,
+            (
+                Instruction::I32Const(1),
+                Instruction::I32Sub,
+                Instruction::LocalTee(Index::Num(0)),
+                Instruction::LocalGet(Index::Num(0)),
+                test_if!(
+                    if_block_type,
+                    (Instruction::Br(Index::Id("lname".to_string()))),
+                    ()
+                )
+            )
+        )
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0;\n[0]"
+    );
+}
+
+#[test]
+fn test_loop_param_br() {
+    let mut executor = Executor::new();
+    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let if_block_type = test_block_type!();
+    // Adds up 5 + 4 + 3 + 2 + 1, carrying the sum as the loop param.
+    let line = test_line![(test_local!(ValType::I32)), (
+        Instruction::I32Const(5),
+        Instruction::LocalSet(Index::Num(0)),
+        Instruction::I32Const(0),
+        test_loop!(
+            loop_block_type,
+            (
+                Instruction::LocalGet(Index::Num(0)),
+                Instruction::I32Add,
+                Instruction::LocalGet(Index::Num(0)),
+                Instruction::I32Const(1),
+                Instruction::I32Sub,
+                Instruction::LocalTee(Index::Num(0)),
+                test_if!(if_block_type, (Instruction::Br(Index::Num(1))), ())
+            )
+        )
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0;\n[15]"
+    );
+}
+
+#[test]
+fn test_loop_params_differ_from_results() {
+    let mut executor = Executor::new();
+    let loop_block_type = test_block_type!(
+        (test_local!(ValType::I32), test_local!(ValType::I64)),
+        (ValType::I32)
+    );
+    // Doubles the i32 as many times as the i64 counts down. The branch
+    // back takes both params, while falling out of the loop leaves only
+    // the i32 result.
+    let line = test_line![(test_local!(ValType::I64)), (
+        Instruction::I32Const(1),
+        Instruction::I64Const(3),
+        test_loop!(
+            loop_block_type,
+            (
+                Instruction::LocalSet(Index::Num(0)),
+                Instruction::I32Const(2),
+                Instruction::I32Mul,
+                Instruction::LocalGet(Index::Num(0)),
+                Instruction::I64Const(1),
+                Instruction::I64Sub,
+                Instruction::LocalTee(Index::Num(0)),
+                Instruction::LocalGet(Index::Num(0)),
+                Instruction::I32WrapI64,
+                Instruction::BrIf(Index::Num(0)),
+                Instruction::Drop
+            )
+        )
+    )];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0;\n[8]"
+    );
+}
+
+#[test]
+fn test_loop_br_param_type_error() {
+    let mut executor = Executor::new();
+    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I64));
     let line = test_line![(), (
         Instruction::I32Const(1),
-        test_if!(
-            block_type,
-            (Instruction::I32Const(1), Instruction::I32Const(3)),
-            (Instruction::I32Const(2), Instruction::I32Const(4))
-        ),
-        Instruction::I32Const(4)
+        test_loop!(
+            loop_block_type,
+            (Instruction::I64Const(2), Instruction::Br(Index::Num(0)))
+        )
     )];
-    assert!(executor.execute_line(line).is_err());
+    let err = executor.execute_line(line).err().unwrap();
     assert_eq!(
-        executor
-            .call_stack
-            .get_func_stack()
-            .unwrap()
-            .to_soft_string()
-            .unwrap(),
-        "[]"
+        err.root_cause().to_string(),
+        "type mismatch: expected i32, found i64"
     );
 }
 
 #[test]
-fn test_else() {
+fn test_loop_not_enough_inputs_error() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!(
-        (test_local!(ValType::I32), test_local!(ValType::I32)),
-        (ValType::I32)
+    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let line = test_line![(), (test_loop!(
+        loop_block_type,
+        (Instruction::I32Const(1), Instruction::I32Sub)
+    ))];
+    assert!(executor.execute_line(line).is_err(),);
+}
+
+#[test]
+fn test_loop_execution_error() {
+    let mut executor = Executor::new();
+    let loop_block_type = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (test_loop!(loop_block_type, (Instruction::I32Add)))];
+    assert!(executor.execute_line(line).is_err(),);
+}
+
+#[test]
+fn test_loop_output_type_error() {
+    let mut executor = Executor::new();
+    let loop_block_type = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (test_loop!(loop_block_type, (Instruction::I64Const(2))))];
+    assert!(executor.execute_line(line).is_err(),);
+}
+
+#[test]
+fn test_loop_branch_out() {
+    let mut executor = Executor::new();
+    let outer_block_type = test_block_type!((), (ValType::I32));
+    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let if_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let line = test_line![(test_local!(ValType::I32)), (test_block!(
+        outer_block_type,
+        (
+            Instruction::I32Const(10),
+            test_loop!(
+                loop_block_type,
+                (
+                    Instruction::I32Const(1),
+                    Instruction::I32Sub,
+                    Instruction::LocalTee(Index::Num(0)),
+                    Instruction::LocalGet(Index::Num(0)),
+                    test_if!(if_block_type, (Instruction::Br(Index::Num(2))), ())
+                )
+            )
+        )
+    ))];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0;\n[9]"
+    );
+}
+
+#[test]
+fn test_loop_branch_out_by_id() {
+    let mut executor = Executor::new();
+    let mut outer_block_type = test_block_type!((), (ValType::I32));
+    outer_block_type.label = Some("outer".to_string());
+    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let if_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let line = test_line![(test_local!(ValType::I32)), (test_block!(
+        outer_block_type,
+        (
+            Instruction::I32Const(10),
+            test_loop!(
+                loop_block_type,
+                (
+                    Instruction::I32Const(1),
+                    Instruction::I32Sub,
+                    Instruction::LocalTee(Index::Num(0)),
+                    Instruction::LocalGet(Index::Num(0)),
+                    test_if!(
+                        if_block_type,
+                        (Instruction::Br(Index::Id("outer".to_string()))),
+                        ()
+                    )
+                )
+            )
+        )
+    ))];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;0;\n[9]"
+    );
+}
+
+#[test]
+fn test_loop_func_return() {
+    let mut executor = Executor::new();
+    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let if_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let test_loop = test_loop!(
+        loop_block_type,
+        (
+            Instruction::I32Const(1),
+            Instruction::I32Sub,
+            Instruction::LocalTee(Index::Num(0)),
+            Instruction::LocalGet(Index::Num(0)),
+            test_if!(if_block_type, (Instruction::Return), ())
+        )
+    );
+
+    let func = test_func!(
+        "fname",
+        (test_local!(ValType::I32)),
+        (ValType::I32),
+        (Instruction::LocalGet(Index::Num(0)), test_loop)
     );
+    let response = executor.execute_line(func).unwrap();
+    assert_eq!(response.message(), "func ;0; fname");
+
     let line = test_line![(), (
-        Instruction::I32Const(12),
-        Instruction::I32Const(3),
-        Instruction::I32Const(0),
-        test_if!(block_type, (Instruction::I32Add), (Instruction::I32Sub)),
-        Instruction::I32Const(4)
+        Instruction::I32Const(10),
+        Instruction::Call(test_index("fname"))
     )];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[9, 4]");
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[9]");
 }
 
 #[test]
-fn test_nested_if() {
+fn test_loop_too_may_returns_error() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32));
-    let block_type_inner = test_block_type!((), (ValType::I32));
+    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let if_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let test_loop = test_loop!(
+        loop_block_type,
+        (
+            Instruction::I32Const(2),
+            Instruction::I32Const(1),
+            Instruction::I32Sub,
+            Instruction::LocalTee(Index::Num(0)),
+            Instruction::LocalGet(Index::Num(0)),
+            test_if!(
+                if_block_type,
+                (Instruction::I32Const(5), Instruction::Br(Index::Num(1))),
+                ()
+            )
+        )
+    );
+    let line = test_line![(test_local!(ValType::I32)), (
+        Instruction::I32Const(10),
+        test_loop
+    )];
+    assert!(executor.execute_line(line).is_err());
+}
+
+#[test]
+fn test_loop_as_normal_block() {
+    let mut executor = Executor::new();
+    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let test_loop = test_loop!(
+        loop_block_type,
+        (Instruction::I32Const(1), Instruction::I32Sub)
+    );
+    let line = test_line![(), (Instruction::I32Const(10), test_loop)];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[9]");
+}
+
+#[test]
+fn test_snapshot_restore() {
+    let mut executor = Executor::new();
+    let func = test_func!("first", (), (ValType::I32), (Instruction::I32Const(1)));
+    executor.execute_line(func).unwrap();
+    let line = test_line![(test_local!(ValType::I32)), (
+        Instruction::I32Const(42),
+        Instruction::LocalSet(Index::Num(0)),
+        Instruction::I32Const(5)
+    )];
+    executor.execute_line(line).unwrap();
+
+    let snapshot = executor.snapshot();
+
+    let func = test_func!("second", (), (ValType::I32), (Instruction::I32Const(2)));
+    assert_eq!(
+        executor.execute_line(func).unwrap().message(),
+        "func ;1; second"
+    );
+    let line = test_line![(test_local!(ValType::I32)), (
+        Instruction::I32Const(43),
+        Instruction::LocalSet(Index::Num(0)),
+        Instruction::Drop
+    )];
+    executor.execute_line(line).unwrap();
+
+    executor.restore(&snapshot);
+
     let line = test_line![(), (
-        Instruction::I32Const(1),
-        test_if!(
-            block_type,
-            (
-                Instruction::I32Const(2),
-                test_if!(
-                    block_type_inner,
-                    (Instruction::I32Const(3)),
-                    (Instruction::I32Const(5))
-                )
-            ),
-            (Instruction::I32Const(4))
-        ),
-        Instruction::I32Const(6)
+        Instruction::LocalGet(Index::Num(0)),
+        Instruction::Call(test_index("first"))
     )];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[3, 6]");
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[5, 42, 1]");
+
+    let line = test_line![(), (Instruction::Call(test_index("second")))];
+    assert!(executor.execute_line(line).is_err());
+
+    let line = test_line![(test_local!(ValType::I32)), ()];
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "local ;1;\n[5, 42, 1]"
+    );
 }
 
 #[test]
-fn test_skip_nested_if() {
+fn test_func_redefine() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32));
-    let block_type_inner = test_block_type!((), (ValType::I32));
+    let func = test_func!("fname", (), (ValType::I32), (Instruction::I32Const(1)));
+    executor.execute_line(func).unwrap();
+    let func = test_func!("other", (), (ValType::I32), (Instruction::Call(test_index("fname"))));
+    executor.execute_line(func).unwrap();
+
+    let func = test_func!("fname", (), (ValType::I32), (Instruction::I32Const(2)));
+    assert_eq!(
+        executor.execute_line(func).unwrap().message(),
+        "func ;0; fname"
+    );
     let line = test_line![(), (
-        Instruction::I32Const(0),
+        Instruction::Call(test_index("fname")),
+        Instruction::Call(test_index("other"))
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[2, 2]");
+
+    // Functions without an id are always added.
+    let mut func = module_func(test_func!("anon", (), (), ()));
+    func.id = None;
+    let response = executor.execute_line(Line::Func(func)).unwrap();
+    assert_eq!(response.message(), "func ;2;");
+}
+
+#[test]
+fn test_func_redefine_recursive() {
+    let mut executor = Executor::new();
+    let func = test_func!("f", (test_local!(ValType::I32)), (ValType::I32), (
+        Instruction::LocalGet(Index::Num(0))
+    ));
+    executor.execute_line(func).unwrap();
+    let func = test_func!("g", (test_local!(ValType::I32)), (ValType::I32), (
+        Instruction::LocalGet(Index::Num(0)),
+        Instruction::Call(test_index("f"))
+    ));
+    executor.execute_line(func).unwrap();
+
+    // $g only calls back into itself once $f is replaced.
+    let if_block_type = test_block_type!((), (ValType::I32));
+    let func = test_func!("f", (test_local!(ValType::I32)), (ValType::I32), (
+        Instruction::LocalGet(Index::Num(0)),
         test_if!(
-            block_type,
+            if_block_type,
             (
-                Instruction::I32Const(2),
-                test_if!(
-                    block_type_inner,
-                    (Instruction::I32Const(3)),
-                    (Instruction::I32Const(5))
-                )
+                Instruction::LocalGet(Index::Num(0)),
+                Instruction::I32Const(1),
+                Instruction::I32Sub,
+                Instruction::Call(test_index("g"))
             ),
-            (Instruction::I32Const(4))
-        ),
-        Instruction::I32Const(6)
-    )];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[4, 6]");
+            (Instruction::I32Const(42))
+        )
+    ));
+    executor.execute_line(func).unwrap();
+    assert!(executor.funcs.get(&test_index("g")).unwrap().recursive);
+
+    let line = test_line![(), (Instruction::I32Const(3), Instruction::Call(test_index("g")))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[42]");
 }
 
 #[test]
-fn test_no_if() {
+fn test_warn_residue() {
     let mut executor = Executor::new();
+    executor.set_warn_residue(true);
+    let line = test_line![(), (Instruction::I32Const(1))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1]");
+
     let line = test_line![(), (
+        Instruction::I32Const(2),
         Instruction::I32Const(3),
-        test_if!(
-            test_block_type!(),
-            (),
-            (Instruction::I32Const(5), Instruction::Drop)
-        ),
-        Instruction::I32Const(2)
+        Instruction::I32Const(4)
     )];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[2]");
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "[1, 2, 3, 4]\nnote: 4 values now on the stack"
+    );
 }
 
 #[test]
-fn test_no_else() {
+fn test_warn_residue_off() {
     let mut executor = Executor::new();
-    let line = test_line![(), (
-        Instruction::I32Const(-2),
-        test_if!(
-            test_block_type!(),
-            (Instruction::I32Const(5), Instruction::Drop),
-            ()
-        ),
-        Instruction::I32Const(2)
-    )];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[2]");
+    let line = test_line![(), (Instruction::I32Const(2), Instruction::I32Const(3))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[2, 3]");
 }
 
 #[test]
-fn test_func_nested_return() {
+fn test_f64_comparison_result_type() {
+    let comparisons = [
+        (Instruction::F64Eq, 0),
+        (Instruction::F64Ne, 1),
+        (Instruction::F64Lt, 1),
+        (Instruction::F64Gt, 0),
+        (Instruction::F64Le, 1),
+        (Instruction::F64Ge, 0),
+    ];
+
+    for (instr, expected) in comparisons {
+        let mut executor = Executor::new();
+        let line = test_line![(), (
+            Instruction::F64Const(1.0),
+            Instruction::F64Const(2.0),
+            instr
+        )];
+        executor.execute_line(line).unwrap();
+        let func_stack = executor.call_stack.get_func_stack().unwrap();
+        assert_eq!(func_stack.pop().unwrap(), Value::I32(expected));
+    }
+}
+
+#[test]
+fn test_debug_func() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32));
     let func = test_func!(
-        "fn",
+        "inc",
         (test_local!(ValType::I32)),
         (ValType::I32),
         (
             Instruction::LocalGet(Index::Num(0)),
-            test_if!(
-                block_type,
-                (
-                    Instruction::I32Const(2),
-                    Instruction::Return,
-                    Instruction::I32Const(3)
-                ),
-                (Instruction::I32Const(4))
-            ),
-            Instruction::I32Const(5),
-            Instruction::Return
+            Instruction::I32Const(1),
+            Instruction::Call(test_index("debug")),
+            Instruction::I32Add
         )
     );
     executor.execute_line(func).unwrap();
 
-    let call_sub = test_line![(), (
-        Instruction::I32Const(1),
-        Instruction::Call(test_index("fn"))
+    let line = test_line![(), (
+        Instruction::I32Const(7),
+        Instruction::I32Const(5),
+        Instruction::Call(test_index("inc")),
+        Instruction::Call(test_index("debug"))
     )];
-    assert_eq!(executor.execute_line(call_sub).unwrap().message(), "[2]");
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "[5, 1]\n[7, 6]\n[7, 6]"
+    );
+}
 
-    let call_sub = test_line![(), (
-        Instruction::Drop,
-        Instruction::I32Const(0),
-        Instruction::Call(test_index("fn"))
+#[test]
+fn test_debug_func_error() {
+    let mut executor = Executor::new();
+    let line = test_line![(), (
+        Instruction::I32Const(5),
+        Instruction::Call(test_index("debug")),
+        Instruction::I32Add
     )];
-    assert_eq!(executor.execute_line(call_sub).unwrap().message(), "[5]");
+    assert!(executor.execute_line(line).is_err());
+
+    let line = test_line![(), (Instruction::I32Const(6))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[6]");
 }
 
 #[test]
-fn test_func_nested_too_many() {
+fn test_debug_func_user_defined() {
+    let mut executor = Executor::new();
+    let func = test_func!("debug", (), (ValType::I32), (Instruction::I32Const(3)));
+    executor.execute_line(func).unwrap();
+
+    let line = test_line![(), (Instruction::Call(test_index("debug")))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[3]");
+}
+
+#[test]
+fn test_func_ids_resolved_lookups() {
+    let mut executor = Executor::new();
+    executor
+        .eval(
+            "(func $count (param $n i32) (result i32) (local $acc i32) \
+              (loop $next \
+                (local.set $acc (i32.add (local.get $acc) (i32.const 1))) \
+                (br_if $next (i32.lt_s (local.get $acc) (local.get $n)))) \
+              (local.get $acc))",
+        )
+        .unwrap();
+
+    // Only the line itself looks up ids, as many times however often the
+    // loop in the body uses `$n`, `$acc` and `$next`.
+    let mut lookups = Vec::new();
+    for n in [1, 1000] {
+        let before = dict::lookups();
+        let line = format!("(call $count (i32.const {})) (drop)", n);
+        executor.eval(&line).unwrap();
+        lookups.push(dict::lookups() - before);
+    }
+    assert_eq!(lookups[0], lookups[1]);
+}
+
+#[test]
+fn test_func_ids_resolved() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32));
     let func = test_func!(
-        "fn",
-        (),
+        "double",
+        (test_local_id!("num", ValType::I32)),
         (ValType::I32),
         (
-            Instruction::I32Const(1),
-            test_block!(
-                block_type,
-                (
-                    Instruction::I32Const(2),
-                    Instruction::Return,
-                    Instruction::I32Const(3)
-                )
-            )
+            Instruction::LocalGet(test_index("num")),
+            Instruction::LocalGet(test_index("num")),
+            Instruction::I32Add
+        )
+    );
+    executor.execute_line(func).unwrap();
+    let func = test_func!(
+        "quad",
+        (test_local_id!("num", ValType::I32)),
+        (ValType::I32),
+        (
+            Instruction::LocalGet(test_index("num")),
+            Instruction::Call(test_index("double")),
+            Instruction::Call(test_index("double"))
         )
     );
     executor.execute_line(func).unwrap();
 
-    let call_func = test_line![(), (Instruction::Call(test_index("fn")))];
-    assert!(executor.execute_line(call_func).is_err());
+    let quad = executor.funcs.get(&test_index("quad")).unwrap();
+    assert_eq!(
+        quad.line_expression.expr.instrs,
+        vec![
+            Instruction::LocalGet(Index::Num(0)),
+            Instruction::Call(Index::Num(0)),
+            Instruction::Call(Index::Num(0))
+        ]
+    );
+
+    let line = test_line![(), (
+        Instruction::I32Const(3),
+        Instruction::Call(test_index("quad"))
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[12]");
 }
 
 #[test]
-fn test_block() {
+fn test_func_multiple_results_order() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let func = test_func!(
+        "pair",
+        (),
+        (ValType::I32, ValType::I64),
+        (Instruction::I32Const(7), Instruction::I64Const(8))
+    );
+    executor.execute_line(func).unwrap();
+
+    let line = test_line![(), (Instruction::Call(test_index("pair")))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[7, 8]");
+
+    let func_stack = executor.call_stack.get_func_stack().unwrap();
+    assert_eq!(func_stack.pop().unwrap(), Value::I64(8));
+    assert_eq!(func_stack.pop().unwrap(), Value::I32(7));
+}
+
+#[test]
+fn test_assert_trap() {
+    let mut executor = Executor::new();
+    let line = test_line![(), (Instruction::I32Const(1))];
+    executor.execute_line(line).unwrap();
+
+    let line = Line::AssertTrap(
+        Expression {
+            instrs: vec![
+                Instruction::I32Const(1),
+                Instruction::I32Const(0),
+                Instruction::I32DivS,
+            ],
+        },
+        Some(String::from("divide by zero")),
+    );
+    assert_eq!(
+        executor.execute_line(line).unwrap().message(),
+        "trap: integer divide by zero, while executing instruction 3 (i32.div_s)"
+    );
+
+    let line = test_line![(), ()];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1]");
+}
+
+#[test]
+fn test_assert_trap_no_trap() {
+    let mut executor = Executor::new();
+    let line = Line::AssertTrap(
+        Expression {
+            instrs: vec![Instruction::I32Const(1)],
+        },
+        None,
+    );
+    assert!(executor.execute_line(line).is_err());
+
+    let line = test_line![(), ()];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[]");
+}
+
+#[test]
+fn test_assert_trap_message_mismatch() {
+    let mut executor = Executor::new();
+    let line = Line::AssertTrap(
+        Expression {
+            instrs: vec![
+                Instruction::I32Const(1),
+                Instruction::I32Const(0),
+                Instruction::I32DivS,
+            ],
+        },
+        Some(String::from("integer overflow")),
+    );
+    assert!(executor.execute_line(line).is_err());
+}
+
+#[test]
+fn test_max_locals() {
+    let mut executor = Executor::new();
+    executor.set_max_locals(2);
+
+    let line = test_line![(test_local!(ValType::I32), test_local!(ValType::I32)), ()];
+    executor.execute_line(line).unwrap();
+
+    let line = test_line![(test_local!(ValType::I32)), ()];
+    let err = executor.execute_line(line).err().unwrap();
+    assert_eq!(err.to_string(), "too many locals (limit 2)");
+}
+
+#[test]
+fn test_max_locals_func() {
+    let mut executor = Executor::new();
+    executor.set_max_locals(1);
+
+    let func = test_func!(
+        "f",
+        (test_local!(ValType::I32), test_local!(ValType::I32)),
+        (),
+        ()
+    );
+    executor.execute_line(func).unwrap();
+
     let line = test_line![(), (
         Instruction::I32Const(1),
         Instruction::I32Const(2),
-        test_block!(block_type, (Instruction::I32Const(3), Instruction::I32Add)),
-        Instruction::I32Const(4)
+        Instruction::Call(test_index("f"))
     )];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[1, 5, 4]");
+    let err = executor.execute_line(line).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "too many locals (limit 1), while executing instruction 3 (call)"
+    );
 }
 
 #[test]
-fn test_nested_block() {
+fn test_mixed_width_add_rollback() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32, ValType::I32));
-    let block_type_inner = test_block_type!((), (ValType::I32));
+    let line = test_line![(), (Instruction::I32Const(5))];
+    executor.execute_line(line).unwrap();
+
     let line = test_line![(), (
         Instruction::I32Const(1),
-        test_block!(
-            block_type,
-            (
-                Instruction::I32Const(2),
-                test_block!(block_type_inner, (Instruction::I32Const(3)))
-            )
-        ),
-        Instruction::I32Const(4)
+        Instruction::I64Const(2),
+        Instruction::I32Add
     )];
+    assert!(executor.execute_line(line).is_err());
     assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "[1, 2, 3, 4]"
+        executor
+            .call_stack
+            .get_func_stack()
+            .unwrap()
+            .to_soft_string(ValueFormat::new())
+            .unwrap(),
+        "[5]"
     );
 }
 
 #[test]
-fn test_block_branch() {
+fn test_func_recursive() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32));
-    let line = test_line![(), (
-        Instruction::I32Const(1),
-        test_block!(
-            block_type,
-            (
-                Instruction::I32Const(2),
-                Instruction::Br(Index::Num(0)),
-                Instruction::I32Const(3)
+    let func = test_func!("leaf", (), (), ());
+    executor.execute_line(func).unwrap();
+    let func = test_func!("caller", (), (), (Instruction::Call(test_index("leaf"))));
+    executor.execute_line(func).unwrap();
+    let func = test_func!("self", (), (), (Instruction::Call(test_index("self"))));
+    executor.execute_line(func).unwrap();
+    let func = test_func!("indirect", (), (), (Instruction::Call(test_index("self"))));
+    executor.execute_line(func).unwrap();
+    let func = test_func!("unknown", (), (), (Instruction::Call(test_index("later"))));
+    executor.execute_line(func).unwrap();
+
+    let recursive = |executor: &Executor, id: &str| {
+        executor.funcs.get(&test_index(id)).unwrap().recursive
+    };
+    assert!(!recursive(&executor, "leaf"));
+    assert!(!recursive(&executor, "caller"));
+    assert!(recursive(&executor, "self"));
+    assert!(recursive(&executor, "indirect"));
+    assert!(recursive(&executor, "unknown"));
+}
+
+#[test]
+fn test_func_recursive_call() {
+    let mut executor = Executor::new();
+    let func = test_func!(
+        "sum",
+        (test_local!(ValType::I32)),
+        (ValType::I32),
+        (
+            Instruction::LocalGet(Index::Num(0)),
+            test_if!(
+                test_block_type!((), (ValType::I32)),
+                (
+                    Instruction::LocalGet(Index::Num(0)),
+                    Instruction::LocalGet(Index::Num(0)),
+                    Instruction::I32Const(1),
+                    Instruction::I32Sub,
+                    Instruction::Call(test_index("sum")),
+                    Instruction::I32Add
+                ),
+                (Instruction::I32Const(0))
             )
-        ),
-        Instruction::I32Const(4)
+        )
+    );
+    executor.execute_line(func).unwrap();
+
+    let line = test_line![(), (
+        Instruction::I32Const(4),
+        Instruction::Call(test_index("sum"))
     )];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[1, 2, 4]");
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[10]");
 }
 
 #[test]
-fn test_nested_branch_inner_block() {
+fn test_func_body_kept_after_call() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32, ValType::I32, ValType::I32));
-    let block_type_inner = test_block_type!((), (ValType::I32));
+    let func = test_func!(
+        "div",
+        (test_local!(ValType::I32)),
+        (ValType::I32),
+        (
+            Instruction::I32Const(12),
+            Instruction::LocalGet(Index::Num(0)),
+            Instruction::I32DivS
+        )
+    );
+    executor.execute_line(func).unwrap();
+
+    let line = test_line![(), (
+        Instruction::I32Const(0),
+        Instruction::Call(test_index("div"))
+    )];
+    assert!(executor.execute_line(line).is_err());
+
+    let line = test_line![(), (
+        Instruction::I32Const(3),
+        Instruction::Call(test_index("div")),
+        Instruction::Call(test_index("div"))
+    )];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[3]");
+}
+
+#[test]
+fn test_block_branch_shadowed_id() {
+    let mut executor = Executor::new();
+    let mut block_type = test_block_type!((), (ValType::I32));
+    block_type.label = Some("l".to_string());
+    let mut block_type_inner = test_block_type!();
+    block_type_inner.label = Some("l".to_string());
     let line = test_line![(), (
-        Instruction::I32Const(1),
         test_block!(
             block_type,
             (
-                Instruction::I32Const(2),
                 test_block!(
                     block_type_inner,
                     (
-                        Instruction::I32Const(3),
-                        Instruction::Br(Index::Num(0)),
-                        Instruction::I32Const(4)
+                        Instruction::Br(Index::Id("l".to_string())),
+                        Instruction::I32Const(2)
                     )
                 ),
-                Instruction::I32Const(5)
+                Instruction::I32Const(1)
             )
-        ),
-        Instruction::I32Const(6)
+        )
     )];
-    assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "[1, 2, 3, 5, 6]"
-    );
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1]");
 }
 
 #[test]
-fn test_nested_branch_outer_block() {
+fn test_block_branch_outer_id() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32, ValType::I32));
-    let block_type_inner = test_block_type!((), (ValType::I32));
+    let mut block_type = test_block_type!((), (ValType::I32));
+    block_type.label = Some("outer".to_string());
+    let mut block_type_inner = test_block_type!();
+    block_type_inner.label = Some("inner".to_string());
     let line = test_line![(), (
-        Instruction::I32Const(1),
         test_block!(
             block_type,
             (
-                Instruction::I32Const(2),
                 test_block!(
                     block_type_inner,
                     (
-                        Instruction::I32Const(4),
-                        Instruction::Br(Index::Num(1)),
-                        Instruction::I32Const(5)
+                        Instruction::I32Const(2),
+                        Instruction::Br(Index::Id("outer".to_string()))
                     )
                 ),
-                Instruction::I32Const(6)
+                Instruction::I32Const(1)
             )
-        ),
-        Instruction::I32Const(7)
+        )
     )];
-    assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "[1, 2, 4, 7]"
-    );
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[2]");
 }
 
 #[test]
-fn test_branch_too_many() {
+fn test_loop_branch_shadowed_by_block_id() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32));
+    let mut loop_type = test_block_type!();
+    loop_type.label = Some("l".to_string());
+    let mut block_type = test_block_type!();
+    block_type.label = Some("l".to_string());
     let line = test_line![(), (
-        Instruction::I32Const(1),
-        test_block!(
-            block_type,
-            (
-                Instruction::I32Const(2),
-                Instruction::I32Const(3),
-                Instruction::Br(Index::Num(0)),
-                Instruction::I32Const(4)
-            )
+        test_loop!(
+            loop_type,
+            (test_block!(
+                block_type,
+                (Instruction::Br(Index::Id("l".to_string())))
+            ))
         ),
-        Instruction::I32Const(7)
+        Instruction::I32Const(1)
     )];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[1, 3, 7]");
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1]");
 }
 
 #[test]
-fn test_branch_nested_too_many_error() {
+fn test_if_branch_shadowed_id() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32));
-    let block_type_inner = test_block_type!((), (ValType::I32));
+    let mut block_type = test_block_type!((), (ValType::I32));
+    block_type.label = Some("l".to_string());
+    let mut if_type = test_block_type!();
+    if_type.label = Some("l".to_string());
     let line = test_line![(), (
-        Instruction::I32Const(1),
         test_block!(
             block_type,
             (
-                Instruction::I32Const(2),
-                test_block!(
-                    block_type_inner,
-                    (
-                        Instruction::I32Const(3),
-                        Instruction::Br(Index::Num(1)),
-                        Instruction::I32Const(4)
-                    )
-                )
+                Instruction::I32Const(1),
+                test_if!(
+                    if_type,
+                    (Instruction::Br(Index::Id("l".to_string()))),
+                    ()
+                ),
+                Instruction::I32Const(3)
             )
         )
     )];
-    assert!(executor.execute_line(line).is_err());
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[3]");
 }
 
 #[test]
-fn test_branch_not_deep_enough_error() {
+fn test_branch_id_from_unlabeled_block() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32));
-    let block_type_inner = test_block_type!((), (ValType::I32));
+    let line = "(block $outer (result i32) \
+        (block (block (i32.const 2) (br $outer)) (i32.const 3) (drop)) \
+        (i32.const 1))";
+    assert_eq!(executor.eval(line).unwrap(), "[2]");
+
+    let line = "(block $outer (result i32) \
+        (block (br_if $outer (i32.const 4) (i32.const 1)) (drop)) \
+        (i32.const 1))";
+    assert_eq!(executor.eval(line).unwrap(), "[2, 4]");
+
+    let line = "(block $a (result i32) (block $b (result i32) \
+        (block (br_table $b $a (i32.const 5) (i32.const 1)))) (i32.const 6) (i32.add))";
+    assert_eq!(executor.eval(line).unwrap(), "[2, 4, 5]");
+}
+
+#[test]
+fn test_loop_branch_id_from_unlabeled_block() {
+    let mut executor = Executor::new();
+    let line = "(local i32) (loop $again \
+        (block (local.set 0 (i32.add (local.get 0) (i32.const 1))) \
+          (br_if $again (i32.lt_s (local.get 0) (i32.const 3))))) \
+        (local.get 0)";
+    assert_eq!(executor.eval(line).unwrap(), "local ;0;\n[3]");
+}
 
+#[test]
+fn test_branch_unknown_id_leaks_error() {
+    let mut executor = Executor::new();
+    executor.eval("(i32.const 1)").unwrap();
+    let line = "(block $outer (block (i32.const 2) (br $nowhere)))";
+    let err = executor.eval(line).err().unwrap();
+    assert!(err.to_string().contains("br leaking out"));
+    assert_eq!(executor.eval("(nop)").unwrap(), "[1]");
+
+    let func = "(func $f (block $outer (block (br $nowhere))))";
+    let err = executor.eval(func).err().unwrap();
+    assert!(err.to_string().contains("unknown label $nowhere"));
+    assert_eq!(executor.eval("(nop)").unwrap(), "[1]");
+}
+
+#[test]
+fn test_step_branch_by_id() {
+    let mut executor = Executor::new();
+    let line = "(block $outer (result i32) (block (i32.const 2) (br $outer)) (i32.const 1))";
+    let buf = ParseBuffer::new(line).unwrap();
+    let line = Line::try_from(&parse_line(&buf).unwrap()).unwrap();
+    let mut stepper = executor.step_line(line).unwrap();
+    while !stepper.is_done() {
+        executor.step(&mut stepper).unwrap();
+    }
+    assert_eq!(executor.eval("(nop)").unwrap(), "[2]");
+}
+
+#[test]
+fn test_canonicalize_nan() {
+    let nan = f32::from_bits(0x7fa0_0001);
+    let mut executor = Executor::new();
     let line = test_line![(), (
-        Instruction::I32Const(1),
-        test_block!(
-            block_type,
-            (test_block!(
-                block_type_inner,
-                (
-                    Instruction::I32Const(3),
-                    Instruction::Br(Index::Num(2)),
-                    Instruction::I32Const(4)
-                )
-            ))
-        )
+        Instruction::F32Const(nan),
+        Instruction::F32Const(1.0),
+        Instruction::F32Add
     )];
-    assert!(executor.execute_line(line).is_err());
+    executor.execute_line(line).unwrap();
+    let value: f32 = executor
+        .call_stack
+        .get_func_stack()
+        .unwrap()
+        .pop()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_ne!(value.to_bits(), 0x7fc0_0000);
+
+    let mut executor = Executor::new();
+    executor.set_canonicalize_nan(true);
+    let line = test_line![(), (
+        Instruction::F32Const(nan),
+        Instruction::F32Const(1.0),
+        Instruction::F32Add,
+        Instruction::F64Const(-1.0),
+        Instruction::F64Sqrt
+    )];
+    executor.execute_line(line).unwrap();
+    let func_stack = executor.call_stack.get_func_stack().unwrap();
+    let value: f64 = func_stack.pop().unwrap().try_into().unwrap();
+    assert_eq!(value.to_bits(), 0x7ff8_0000_0000_0000);
+    let value: f32 = func_stack.pop().unwrap().try_into().unwrap();
+    assert_eq!(value.to_bits(), 0x7fc0_0000);
 }
 
 #[test]
-fn test_func_branch() {
+fn test_canonicalize_nan_keeps_sign_ops() {
     let mut executor = Executor::new();
-    let func = test_func!(
-        "fn",
-        (),
-        (ValType::I32),
-        (
-            Instruction::I32Const(1),
-            Instruction::Br(Index::Num(0)),
-            Instruction::I32Const(2)
-        )
-    );
-    executor.execute_line(func).unwrap();
-
-    let call_func = test_line![(), (
-        Instruction::Call(test_index("fn")),
-        Instruction::I32Const(3)
+    executor.set_canonicalize_nan(true);
+    let line = test_line![(), (
+        Instruction::F32Const(f32::from_bits(0x7fa0_0001)),
+        Instruction::F32Abs
     )];
+    executor.execute_line(line).unwrap();
+    let value: f32 = executor
+        .call_stack
+        .get_func_stack()
+        .unwrap()
+        .pop()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(value.to_bits(), 0x7fa0_0001);
+}
+
+#[test]
+fn test_float_nan_results() {
+    // sqrt of a negative and 0/0 give a canonical NaN, of either sign.
+    let mut executor = Executor::new();
+    executor.eval("(f32.sqrt (f32.const -1))").unwrap();
+    executor.eval("(f32.div (f32.const 0) (f32.const 0))").unwrap();
+    executor.eval("(f64.sqrt (f64.const -1))").unwrap();
+    executor.eval("(f64.div (f64.const -0) (f64.const 0))").unwrap();
+
+    let value: f64 = executor.pop_value().unwrap().try_into().unwrap();
+    assert_eq!(value.to_bits() & !(1 << 63), 0x7ff8_0000_0000_0000);
+    let value: f64 = executor.pop_value().unwrap().try_into().unwrap();
+    assert_eq!(value.to_bits() & !(1 << 63), 0x7ff8_0000_0000_0000);
+    let value: f32 = executor.pop_value().unwrap().try_into().unwrap();
+    assert_eq!(value.to_bits() & !(1 << 31), 0x7fc0_0000);
+    let value: f32 = executor.pop_value().unwrap().try_into().unwrap();
+    assert_eq!(value.to_bits() & !(1 << 31), 0x7fc0_0000);
+}
+
+#[test]
+fn test_float_nan_results_canonicalized() {
+    let mut executor = Executor::new();
+    executor.set_canonicalize_nan(true);
+    executor.eval("(f32.sqrt (f32.const -1))").unwrap();
+    executor.eval("(f64.div (f64.const 0) (f64.const 0))").unwrap();
+
+    let value: f64 = executor.pop_value().unwrap().try_into().unwrap();
+    assert_eq!(value.to_bits(), 0x7ff8_0000_0000_0000);
+    let value: f32 = executor.pop_value().unwrap().try_into().unwrap();
+    assert_eq!(value.to_bits(), 0x7fc0_0000);
+}
+
+#[test]
+fn test_float_div_by_zero() {
+    let mut executor = Executor::new();
+    let line = "(f32.div (f32.const 1) (f32.const 0)) (f32.div (f32.const -1) (f32.const 0)) \
+        (f32.div (f32.const 1) (f32.const -0))";
+    assert_eq!(executor.eval(line).unwrap(), "[inf, -inf, -inf]");
+    let line = "(f64.div (f64.const 1) (f64.const 0)) (f64.div (f64.const -1) (f64.const 0))";
+    assert_eq!(executor.eval(line).unwrap(), "[inf, -inf, -inf, inf, -inf]");
+
+    // The sqrt of -0 is -0, not a NaN.
+    let mut executor = Executor::new();
+    assert_eq!(executor.eval("(f32.sqrt (f32.const -0))").unwrap(), "[-0]");
+}
+
+#[test]
+fn test_checked_overflow() {
+    let line = "(i32.add (i32.const 2147483647) (i32.const 1))";
+    let mut executor = Executor::new();
+    assert_eq!(executor.eval(line).unwrap(), "[-2147483648]");
+
+    let mut executor = Executor::new();
+    executor.set_checked(true);
+    let err = executor.eval(line).err().unwrap();
     assert_eq!(
-        executor.execute_line(call_func).unwrap().message(),
-        "[1, 3]"
+        err.to_string(),
+        "integer overflow, while executing instruction 3 (i32.add)"
     );
+    assert_eq!(executor.eval("(i32.add (i32.const 1) (i32.const 2))").unwrap(), "[3]");
 }
 
 #[test]
-fn test_func_branch_not_deep_enough_error() {
+fn test_checked_overflow_sub_mul() {
     let mut executor = Executor::new();
-    let func = test_func!(
-        "fn",
-        (),
-        (ValType::I32),
-        (
-            Instruction::I32Const(1),
-            Instruction::Br(Index::Num(1)),
-            Instruction::I32Const(2)
-        )
+    executor.set_checked(true);
+    let lines = [
+        "(i32.sub (i32.const -2147483648) (i32.const 1))",
+        "(i32.mul (i32.const 65536) (i32.const 65536))",
+        "(i64.add (i64.const 9223372036854775807) (i64.const 1))",
+        "(i64.sub (i64.const -9223372036854775808) (i64.const 1))",
+        "(i64.mul (i64.const -9223372036854775808) (i64.const -1))",
+    ];
+    for line in lines {
+        let err = executor.eval(line).err().unwrap();
+        assert!(err.to_string().starts_with("integer overflow"), "{}", line);
+    }
+    assert_eq!(executor.eval("(i64.mul (i64.const 3) (i64.const -2))").unwrap(), "[-6]");
+}
+
+#[test]
+fn test_signed_div_rem_sign() {
+    // Division truncates toward zero, the remainder takes the sign of the dividend.
+    let cases = [
+        (-7, 2, Instruction::I32DivS, "[-3]"),
+        (-7, 2, Instruction::I32RemS, "[-1]"),
+        (7, -2, Instruction::I32DivS, "[-3]"),
+        (7, -2, Instruction::I32RemS, "[1]"),
+    ];
+    for (a, b, instr, result) in cases {
+        let mut executor = Executor::new();
+        let line = test_line![(), (Instruction::I32Const(a), Instruction::I32Const(b), instr)];
+        assert_eq!(executor.execute_line(line).unwrap().message(), result);
+    }
+
+    let cases = [
+        (-7, 2, Instruction::I64DivS, "[-3]"),
+        (-7, 2, Instruction::I64RemS, "[-1]"),
+        (7, -2, Instruction::I64DivS, "[-3]"),
+        (7, -2, Instruction::I64RemS, "[1]"),
+    ];
+    for (a, b, instr, result) in cases {
+        let mut executor = Executor::new();
+        let line = test_line![(), (Instruction::I64Const(a), Instruction::I64Const(b), instr)];
+        assert_eq!(executor.execute_line(line).unwrap().message(), result);
+    }
+}
+
+fn module_func(line: Line) -> Func {
+    match line {
+        Line::Func(func) => func,
+        _ => panic!("Expected Line::Func"),
+    }
+}
+
+fn module_line(funcs: Vec<Func>) -> Line {
+    Line::Module(Module {
+        funcs,
+        ..Module::default()
+    })
+}
+
+#[test]
+fn test_module() {
+    let mut executor = Executor::new();
+    let funcs = vec![
+        module_func(test_func!("one", (), (ValType::I32), (Instruction::I32Const(1)))),
+        module_func(test_func!("two", (), (ValType::I32), (Instruction::Call(test_index("one"))))),
+    ];
+    let response = executor.execute_line(module_line(funcs)).unwrap();
+    assert_eq!(response.message(), "func ;0; one\nfunc ;1; two");
+
+    let line = test_line![(), (Instruction::Call(test_index("two")))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1]");
+}
+
+#[test]
+fn test_module_rollback() {
+    let mut executor = Executor::new();
+    let funcs = vec![
+        module_func(test_func!("one", (), (ValType::I32), (Instruction::I32Const(1)))),
+        module_func(test_func!("one", (), (ValType::I32), (Instruction::I32Const(2)))),
+    ];
+    let err = executor.execute_line(module_line(funcs)).err().unwrap();
+    assert_eq!(err.to_string(), "duplicate function id $one in module");
+    assert_eq!(executor.funcs.len(), 0);
+}
+
+#[test]
+fn test_module_fields() {
+    let mut executor = Executor::new();
+    executor.eval("(memory.grow (i32.const 1)) (drop)").unwrap();
+    let module = "(module \
+        (func $get (result i32) (global.get $g)) \
+        (elem (i32.const 1) $get) \
+        (data $d (i32.const 0) \"\\2a\") \
+        (table $t 2 funcref) \
+        (global $g (mut i32) (i32.const 7)))";
+    assert_eq!(
+        executor.eval(module).unwrap(),
+        "table ;0; t\nglobal ;0; g\nfunc ;0; get\nelem ;0;\ndata ;0; d"
     );
+
+    let line = "(call_indirect $t (result i32) (i32.const 1)) (i32.load (i32.const 0))";
+    assert_eq!(executor.eval(line).unwrap(), "[7, 42]");
+}
+
+#[test]
+fn test_module_fields_rollback() {
+    let mut executor = Executor::new();
+    let module = "(module \
+        (global $g i32 (i32.const 7)) \
+        (table $t 1 funcref) \
+        (func $f) \
+        (data (i32.const 0) \"a\"))";
+    let err = executor.eval(module).err().unwrap();
+    assert!(err.to_string().contains("out of bounds memory access"));
+    assert!(executor.definitions().is_empty());
+    assert_eq!(executor.eval("(memory.size)").unwrap(), "[0]");
+}
+
+#[test]
+fn test_module_redefine() {
+    let mut executor = Executor::new();
+    let func = test_func!("two", (), (ValType::I32), (Instruction::I32Const(0)));
     executor.execute_line(func).unwrap();
 
-    let call_func = test_line![(), (Instruction::Call(test_index("fn")))];
-    assert!(executor.execute_line(call_func).is_err());
+    let funcs = vec![
+        module_func(test_func!("one", (), (ValType::I32), (Instruction::I32Const(1)))),
+        module_func(test_func!("two", (), (ValType::I32), (Instruction::I32Const(2)))),
+    ];
+    let response = executor.execute_line(module_line(funcs)).unwrap();
+    assert_eq!(response.message(), "func ;1; one\nfunc ;0; two");
+    assert_eq!(executor.funcs.len(), 2);
+
+    let line = test_line![(), (Instruction::Call(test_index("two")))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[2]");
 }
 
 #[test]
-fn test_block_branch_by_id() {
+fn test_rotate_count_modulo_width() {
+    // A count of 34 rotates an i32 the same as 2, and 66 an i64.
+    let cases = [
+        (Instruction::I32Rotl, "[1073872904]"),
+        (Instruction::I32Rotr, "[-2080366592]"),
+    ];
+    for (instr, result) in cases {
+        for count in [2, 34] {
+            let mut executor = Executor::new();
+            let line = test_line![(), (
+                Instruction::I32Const(0x10008002),
+                Instruction::I32Const(count),
+                instr.clone()
+            )];
+            assert_eq!(executor.execute_line(line).unwrap().message(), result);
+        }
+    }
+
+    let cases = [
+        (Instruction::I64Rotl, "[4611686018427518984]"),
+        (Instruction::I64Rotr, "[-8935141660703055872]"),
+    ];
+    for (instr, result) in cases {
+        for count in [2, 66] {
+            let mut executor = Executor::new();
+            let line = test_line![(), (
+                Instruction::I64Const(0x1000000000008002),
+                Instruction::I64Const(count),
+                instr.clone()
+            )];
+            assert_eq!(executor.execute_line(line).unwrap().message(), result);
+        }
+    }
+}
+
+#[test]
+fn test_peek_top() {
+    let mut executor = Executor::new();
+    assert_eq!(executor.peek_top(), None);
+
+    let line = test_line![(), (Instruction::I32Const(1), Instruction::I64Const(2))];
+    executor.execute_line(line).unwrap();
+    assert_eq!(executor.peek_top(), Some(Value::I64(2)));
+    assert_eq!(executor.peek_top(), Some(Value::I64(2)));
+
+    // A failing line leaves the top as it was.
+    let line = test_line![(), (Instruction::Drop, Instruction::F32Const(1.0), Instruction::I32Add)];
+    assert!(executor.execute_line(line).is_err());
+    assert_eq!(executor.peek_top(), Some(Value::I64(2)));
+}
+
+#[test]
+fn test_pop_value() {
     let mut executor = Executor::new();
-    let mut block_type = test_block_type!((), (ValType::I32));
-    block_type.label = Some("block_id".to_string());
-    let line = test_line![(), (
-        Instruction::I32Const(1),
-        test_block!(
-            block_type,
-            (
-                Instruction::I32Const(2),
-                Instruction::Br(Index::Id("block_id".to_string())),
-                Instruction::I32Const(3)
-            )
-        ),
-        Instruction::I32Const(4)
-    )];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[1, 2, 4]");
+    let line = test_line![(), (Instruction::I32Const(1), Instruction::I64Const(2))];
+    executor.execute_line(line).unwrap();
+
+    assert_eq!(executor.pop_value().unwrap(), Value::I64(2));
+    let line = test_line![(), ()];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1]");
+
+    // Popped values stay popped after a failing line.
+    let line = test_line![(), (Instruction::Drop, Instruction::Drop)];
+    assert!(executor.execute_line(line).is_err());
+    assert_eq!(executor.pop_value().unwrap(), Value::I32(1));
+    assert!(executor.pop_value().is_err());
 }
 
 #[test]
-fn test_block_branch_by_unknown_id_error() {
+fn test_clear_stack() {
     let mut executor = Executor::new();
-    let mut block_type = test_block_type!((), (ValType::I32));
-    block_type.label = Some("block_id".to_string());
-    let line = test_line![(), (
+    let line = test_line![(test_local!(ValType::I32)), (
         Instruction::I32Const(1),
-        test_block!(
-            block_type,
-            (
-                Instruction::I32Const(2),
-                Instruction::Br(Index::Id("block_id_uk".to_string())),
-                Instruction::I32Const(3)
-            )
-        ),
-        Instruction::I32Const(4)
+        Instruction::I64Const(2)
     )];
+    executor.execute_line(line).unwrap();
+    executor.clear_stack().unwrap();
+
+    // The stack stays cleared after a failing line.
+    let line = test_line![(), (Instruction::Drop)];
     assert!(executor.execute_line(line).is_err());
+    let line = test_line![(), (Instruction::LocalGet(Index::Num(0)))];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[0]");
+}
+
+#[test]
+fn test_call_graph() {
+    let mut executor = Executor::new();
+    assert_eq!(executor.call_graph(), "");
+
+    let func = test_func!("even", (), (), (Instruction::Call(test_index("odd"))));
+    executor.execute_line(func).unwrap();
+    let func = test_func!("odd", (), (), (
+        Instruction::Call(test_index("even")),
+        Instruction::Call(Index::Num(0)),
+        Instruction::Call(test_index("odd"))
+    ));
+    executor.execute_line(func).unwrap();
+
+    assert_eq!(executor.call_graph(), "$even -> $odd\n$odd -> $even, $odd");
+}
+
+#[test]
+fn test_definitions() {
+    let mut executor = Executor::new();
+    assert!(executor.definitions().is_empty());
+
+    let func = test_func!("one", (), (ValType::I32), (Instruction::I32Const(1)));
+    executor.execute_line(func).unwrap();
+    let mut func = module_func(test_func!("two", (), (), ()));
+    func.id = None;
+    executor.execute_line(Line::Func(func)).unwrap();
+    let global = test_global(None, ValType::I32, false, Instruction::I32Const(0));
+    executor.execute_line(global).unwrap();
+
+    assert_eq!(
+        executor.definitions(),
+        vec![
+            Definition {
+                kind: DefinitionKind::Func,
+                index: 0,
+                id: Some("one".to_string()),
+            },
+            Definition {
+                kind: DefinitionKind::Func,
+                index: 1,
+                id: None,
+            },
+            Definition {
+                kind: DefinitionKind::Global,
+                index: 0,
+                id: None,
+            },
+        ]
+    );
 }
 
 #[test]
-fn test_func_branch_too_many() {
+fn test_definitions_memory_data() {
     let mut executor = Executor::new();
-    let func = test_func!(
-        "fn",
-        (),
-        (ValType::I32),
-        (
-            Instruction::I32Const(0),
-            Instruction::I32Const(1),
-            Instruction::Br(Index::Num(0)),
-            Instruction::I32Const(2)
-        )
-    );
-    executor.execute_line(func).unwrap();
+    executor.eval("(memory $m 0)").unwrap();
+    assert!(executor.definitions().is_empty());
 
-    let call_func = test_line![(), (
-        Instruction::Call(test_index("fn")),
-        Instruction::I32Const(3)
-    )];
+    executor.eval("(memory.grow (i32.const 1)) (drop)").unwrap();
+    executor.eval("(data $d \"ab\")").unwrap();
+    executor.eval("(data (i32.const 0) \"c\")").unwrap();
+    executor.eval("(table 1 funcref)").unwrap();
+    executor.eval("(elem $e func)").unwrap();
+
+    let definitions = executor.definitions();
+    let kinds: Vec<_> = definitions
+        .iter()
+        .map(|def| (def.kind, def.index, def.id.as_deref()))
+        .collect();
     assert_eq!(
-        executor.execute_line(call_func).unwrap().message(),
-        "[1, 3]"
+        kinds,
+        vec![
+            (DefinitionKind::Table, 0, None),
+            (DefinitionKind::Memory, 0, Some("m")),
+            (DefinitionKind::Data, 0, Some("d")),
+            (DefinitionKind::Data, 1, None),
+            (DefinitionKind::Elem, 0, Some("e")),
+        ]
     );
+
+    // A failed line leaves neither a segment nor the memory it grew.
+    let mut executor = Executor::new();
+    let line = "(memory.grow (i32.const 1)) (i32.div_s (i32.const 1) (i32.const 0))";
+    assert!(executor.eval(line).is_err());
+    assert!(executor.eval("(data (i32.const 65536) \"a\")").is_err());
+    assert!(executor.definitions().is_empty());
 }
 
 #[test]
-fn test_func_branch_id_error() {
+fn test_eval_error() {
     let mut executor = Executor::new();
-    let func = test_func!(
-        "fname",
-        (),
-        (ValType::I32),
-        (
-            Instruction::I32Const(0),
-            Instruction::I32Const(1),
-            Instruction::Br(Index::Id("fname".to_string())),
-            Instruction::I32Const(2)
-        )
-    );
-    executor.execute_line(func).unwrap();
+    assert_eq!(executor.eval("(i32.const 5)").unwrap(), "[5]");
+    let err = executor.eval("(i32.const 1.5)").err().unwrap();
+    assert_eq!(err.to_string(), "i32.const expects an integer, found 1.5");
+    assert!(executor.eval("(i32.add").is_err());
+    assert_eq!(executor.eval("(nop)").unwrap(), "[5]");
+}
 
-    let call_func = test_line![(), (Instruction::Call(test_index("fname")))];
-    assert!(executor.execute_line(call_func).is_err());
+fn step_messages(executor: &mut Executor, line: Line) -> Vec<String> {
+    let mut stepper = executor.step_line(line).unwrap();
+    let mut messages = vec![];
+    while !stepper.is_done() {
+        messages.push(executor.step(&mut stepper).unwrap().message());
+    }
+    messages
 }
 
 #[test]
-fn test_if_branch() {
+fn test_step() {
     let mut executor = Executor::new();
-    let block_type = test_block_type!((), (ValType::I32));
     let line = test_line![(), (
         Instruction::I32Const(1),
-        test_if!(
-            block_type,
-            (
-                Instruction::I32Const(2),
-                Instruction::I32Const(3),
-                Instruction::Br(Index::Num(0)),
-                Instruction::I32Const(4)
-            ),
-            (Instruction::I32Const(5))
-        ),
-        Instruction::I32Const(6)
+        Instruction::I32Const(2),
+        Instruction::I32Add
     )];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[3, 6]");
+    let mut stepper = executor.step_line(line).unwrap();
+    assert_eq!(stepper.next_instr(), Some(&Instruction::I32Const(1)));
+    assert_eq!(executor.step(&mut stepper).unwrap().message(), "[1]");
+    assert_eq!(executor.step(&mut stepper).unwrap().message(), "[1, 2]");
+    assert_eq!(stepper.next_instr(), Some(&Instruction::I32Add));
+    assert_eq!(executor.step(&mut stepper).unwrap().message(), "[3]");
+    assert!(stepper.is_done());
+    assert_eq!(stepper.next_instr(), None);
+    assert!(executor.step(&mut stepper).is_err());
+
+    // The line is committed once it has run to its end.
+    let line = test_line![(), (Instruction::Nop)];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[3]");
 }
 
 #[test]
-fn test_loop() {
+fn test_step_block() {
     let mut executor = Executor::new();
-    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let if_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let line = test_line![(test_local!(ValType::I32)), (
-        Instruction::I32Const(10),
-        test_loop!(
-            loop_block_type,
+    let block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let line = test_line![(), (
+        Instruction::I32Const(1),
+        Instruction::I32Const(2),
+        test_block!(block_type, (Instruction::I32Const(3), Instruction::I32Add)),
+        Instruction::I32Const(4)
+    )];
+    assert_eq!(
+        step_messages(&mut executor, line),
+        vec!["[1]", "[1, 2]", "[2]", "[2, 3]", "[1, 5]", "[1, 5, 4]"]
+    );
+}
+
+#[test]
+fn test_step_block_br_out() {
+    let mut executor = Executor::new();
+    let outer_block_type = test_block_type!((), (ValType::I32));
+    let inner_block_type = test_block_type!((), ());
+    let line = test_line![(), (
+        test_block!(
+            outer_block_type,
             (
-                Instruction::I32Const(1),
-                Instruction::I32Sub,
-                Instruction::LocalTee(Index::Num(0)),
-                Instruction::LocalGet(Index::Num(0)),
-                test_if!(if_block_type, (Instruction::Br(Index::Num(1))), ())
+                test_block!(
+                    inner_block_type,
+                    (Instruction::I32Const(2), Instruction::Br(Index::Num(1)))
+                ),
+                Instruction::I32Const(3)
             )
-        )
+        ),
+        Instruction::I32Const(4)
     )];
     assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "local ;0;\n[0]"
+        step_messages(&mut executor, line),
+        vec!["[]", "[]", "[2]", "[2]", "[2, 4]"]
     );
 }
 
 #[test]
-fn test_loop_by_id() {
+fn test_step_loop() {
     let mut executor = Executor::new();
-    let mut loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    loop_block_type.label = Some("lname".to_string());
-    let if_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
+    let loop_block_type = test_block_type!();
     let line = test_line![(test_local!(ValType::I32)), (
-        Instruction::I32Const(10),
         test_loop!(
             loop_block_type,
             (
+                Instruction::LocalGet(Index::Num(0)),
                 Instruction::I32Const(1),
-                Instruction::I32Sub,
+                Instruction::I32Add,
                 Instruction::LocalTee(Index::Num(0)),
-                Instruction::LocalGet(Index::Num(0)),
-                test_if!(
-                    if_block_type,
-                    (Instruction::Br(Index::Id("lname".to_string()))),
-                    ()
-                )
+                Instruction::I32Const(2),
+                Instruction::I32LtS,
+                Instruction::BrIf(Index::Num(0))
             )
-        )
+        ),
+        Instruction::LocalGet(Index::Num(0))
     )];
-    assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "local ;0;\n[0]"
-    );
-}
-
-#[test]
-fn test_loop_not_enough_inputs_error() {
-    let mut executor = Executor::new();
-    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let line = test_line![(), (test_loop!(
-        loop_block_type,
-        (Instruction::I32Const(1), Instruction::I32Sub)
-    ))];
-    assert!(executor.execute_line(line).is_err(),);
-}
-
-#[test]
-fn test_loop_execution_error() {
-    let mut executor = Executor::new();
-    let loop_block_type = test_block_type!((), (ValType::I32));
-    let line = test_line![(), (test_loop!(loop_block_type, (Instruction::I32Add)))];
-    assert!(executor.execute_line(line).is_err(),);
+    let messages = step_messages(&mut executor, line);
+    assert_eq!(messages.len(), 16);
+    assert_eq!(messages[7], "[]");
+    assert_eq!(messages[8], "[1]");
+    assert_eq!(messages[15], "[2]");
 }
 
 #[test]
-fn test_loop_output_type_error() {
+fn test_step_error_rollback() {
     let mut executor = Executor::new();
-    let loop_block_type = test_block_type!((), (ValType::I32));
-    let line = test_line![(), (test_loop!(loop_block_type, (Instruction::I64Const(2))))];
-    assert!(executor.execute_line(line).is_err(),);
-}
+    let line = test_line![(), (Instruction::I32Const(1))];
+    executor.execute_line(line).unwrap();
 
-#[test]
-fn test_loop_branch_out() {
-    let mut executor = Executor::new();
-    let outer_block_type = test_block_type!((), (ValType::I32));
-    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let if_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let line = test_line![(test_local!(ValType::I32)), (test_block!(
-        outer_block_type,
-        (
-            Instruction::I32Const(10),
-            test_loop!(
-                loop_block_type,
-                (
-                    Instruction::I32Const(1),
-                    Instruction::I32Sub,
-                    Instruction::LocalTee(Index::Num(0)),
-                    Instruction::LocalGet(Index::Num(0)),
-                    test_if!(if_block_type, (Instruction::Br(Index::Num(2))), ())
-                )
-            )
-        )
-    ))];
+    let line = test_line![(), (
+        Instruction::I32Const(2),
+        Instruction::I32Const(0),
+        Instruction::I32DivS
+    )];
+    let mut stepper = executor.step_line(line).unwrap();
+    executor.step(&mut stepper).unwrap();
+    executor.step(&mut stepper).unwrap();
+    let err = executor.step(&mut stepper).err().unwrap();
     assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "local ;0;\n[9]"
+        err.to_string(),
+        "integer divide by zero, while executing instruction 3 (i32.div_s)"
     );
+    assert!(stepper.is_done());
+
+    let line = test_line![(), (Instruction::Nop)];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[1]");
 }
 
 #[test]
-fn test_loop_branch_out_by_id() {
+fn test_step_stop_line() {
     let mut executor = Executor::new();
-    let mut outer_block_type = test_block_type!((), (ValType::I32));
-    outer_block_type.label = Some("outer".to_string());
-    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let if_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let line = test_line![(test_local!(ValType::I32)), (test_block!(
-        outer_block_type,
-        (
-            Instruction::I32Const(10),
-            test_loop!(
-                loop_block_type,
-                (
-                    Instruction::I32Const(1),
-                    Instruction::I32Sub,
-                    Instruction::LocalTee(Index::Num(0)),
-                    Instruction::LocalGet(Index::Num(0)),
-                    test_if!(
-                        if_block_type,
-                        (Instruction::Br(Index::Id("outer".to_string()))),
-                        ()
-                    )
-                )
-            )
-        )
-    ))];
-    assert_eq!(
-        executor.execute_line(line).unwrap().message(),
-        "local ;0;\n[9]"
-    );
+    let line = test_line![(), (Instruction::I32Const(1), Instruction::I32Const(2))];
+    let mut stepper = executor.step_line(line).unwrap();
+    executor.step(&mut stepper).unwrap();
+    executor.stop_line(stepper);
+
+    let line = test_line![(), (Instruction::Nop)];
+    assert_eq!(executor.execute_line(line).unwrap().message(), "[]");
 }
 
 #[test]
-fn test_loop_func_return() {
+fn test_step_func_error() {
     let mut executor = Executor::new();
-    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let if_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let test_loop = test_loop!(
-        loop_block_type,
-        (
-            Instruction::I32Const(1),
-            Instruction::I32Sub,
-            Instruction::LocalTee(Index::Num(0)),
-            Instruction::LocalGet(Index::Num(0)),
-            test_if!(if_block_type, (Instruction::Return), ())
-        )
-    );
-
-    let func = test_func!(
-        "fname",
-        (test_local!(ValType::I32)),
-        (ValType::I32),
-        (Instruction::LocalGet(Index::Num(0)), test_loop)
-    );
-    let response = executor.execute_line(func).unwrap();
-    assert_eq!(response.message(), "func ;0; fname");
+    let func = test_func!("f", (), (), ());
+    assert!(executor.step_line(func).is_err());
+}
 
-    let line = test_line![(), (
-        Instruction::I32Const(10),
-        Instruction::Call(test_index("fname"))
-    )];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[9]");
+/// Parses and executes a line, returning the bits of the floats it leaves
+/// on the stack.
+fn float_bits(executor: &mut Executor, src: &str) -> Vec<u64> {
+    let buf = ParseBuffer::new(src).unwrap();
+    let line = Line::try_from(&parse_line(&buf).unwrap()).unwrap();
+    let response = executor.execute_line(line).unwrap();
+    let values = response.stack_snapshot().unwrap();
+    values
+        .iter()
+        .map(|value| match value {
+            Value::F32(n) => n.to_bits() as u64,
+            Value::F64(n) => n.to_bits(),
+            _ => panic!("Expected a float"),
+        })
+        .collect()
 }
 
 #[test]
-fn test_loop_too_may_returns_error() {
+fn test_f32_const_special_literals() {
     let mut executor = Executor::new();
-    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let if_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let test_loop = test_loop!(
-        loop_block_type,
-        (
-            Instruction::I32Const(2),
-            Instruction::I32Const(1),
-            Instruction::I32Sub,
-            Instruction::LocalTee(Index::Num(0)),
-            Instruction::LocalGet(Index::Num(0)),
-            test_if!(
-                if_block_type,
-                (Instruction::I32Const(5), Instruction::Br(Index::Num(1))),
-                ()
-            )
-        )
+    let src = "(f32.const inf) (f32.const -inf) (f32.const nan) (f32.const nan:0x400000) \
+        (f32.const nan:0x1) (f32.const -nan:0x200000)";
+    assert_eq!(
+        float_bits(&mut executor, src),
+        vec![0x7f80_0000, 0xff80_0000, 0x7fc0_0000, 0x7fc0_0000, 0x7f80_0001, 0xffa0_0000]
     );
-    let line = test_line![(test_local!(ValType::I32)), (
-        Instruction::I32Const(10),
-        test_loop
-    )];
-    assert!(executor.execute_line(line).is_err());
 }
 
 #[test]
-fn test_loop_as_normal_block() {
+fn test_f64_const_special_literals() {
     let mut executor = Executor::new();
-    let loop_block_type = test_block_type!((test_local!(ValType::I32)), (ValType::I32));
-    let test_loop = test_loop!(
-        loop_block_type,
-        (Instruction::I32Const(1), Instruction::I32Sub)
+    let src = "(f64.const inf) (f64.const -inf) (f64.const nan) (f64.const nan:0x1) \
+        (f64.const -0)";
+    assert_eq!(
+        float_bits(&mut executor, src),
+        vec![
+            0x7ff0_0000_0000_0000,
+            0xfff0_0000_0000_0000,
+            0x7ff8_0000_0000_0000,
+            0x7ff0_0000_0000_0001,
+            0x8000_0000_0000_0000
+        ]
     );
-    let line = test_line![(), (Instruction::I32Const(10), test_loop)];
-    assert_eq!(executor.execute_line(line).unwrap().message(), "[9]");
 }
@@ -0,0 +1,120 @@
+use crate::{
+    elements::Elements,
+    model::{Index, ValType},
+    value::Value,
+};
+use anyhow::{anyhow, Result};
+
+#[derive(Clone)]
+struct Global {
+    id: Option<String>,
+    value: Value,
+    mutable: bool,
+}
+
+#[derive(Clone)]
+pub struct Globals {
+    elements: Elements<Global>,
+}
+
+impl Globals {
+    pub fn new() -> Globals {
+        Globals {
+            elements: Elements::new(),
+        }
+    }
+
+    pub fn grow(&mut self, id: Option<String>, value: Value, mutable: bool) -> Result<usize> {
+        let global = Global {
+            id: id.clone(),
+            value,
+            mutable,
+        };
+        self.elements.grow(id, global)
+    }
+
+    pub fn set(&mut self, index: &Index, value: Value) -> Result<()> {
+        let global = self.elements.get(index)?;
+        if !global.mutable {
+            return Err(anyhow!("global is immutable"));
+        }
+        value.is_same_type(&global.value.val_type())?;
+        let global = Global {
+            value,
+            ..global.clone()
+        };
+        self.elements.set(index, global)
+    }
+
+    pub fn get(&self, index: &Index) -> Result<&Value> {
+        Ok(&self.elements.get(index)?.value)
+    }
+
+    /// The value type of the global, and whether it is mutable.
+    pub fn ty(&self, index: &Index) -> Result<(ValType, bool)> {
+        let global = self.elements.get(index)?;
+        Ok((global.value.val_type(), global.mutable))
+    }
+
+    pub fn id(&self, index: &Index) -> Result<Option<String>> {
+        Ok(self.elements.get(index)?.id.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn commit(&mut self) {
+        self.elements.commit();
+    }
+
+    pub fn rollback(&mut self) {
+        self.elements.rollback();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::globals::Globals;
+    use crate::model::Index;
+    use crate::test_utils::test_index;
+
+    #[test]
+    fn test_grow_get_set() {
+        let mut globals = Globals::new();
+        let id = Some(String::from("g"));
+        globals.grow(id, 0.into(), true).unwrap();
+        globals.set(&Index::Num(0), 1.into()).unwrap();
+
+        assert_eq!(globals.get(&test_index("g")).unwrap().clone(), 1.into());
+        assert!(globals.get(&Index::Num(1)).is_err());
+    }
+
+    #[test]
+    fn test_set_immutable_error() {
+        let mut globals = Globals::new();
+        globals.grow(None, 0.into(), false).unwrap();
+        assert!(globals.set(&Index::Num(0), 1.into()).is_err());
+        assert_eq!(globals.get(&Index::Num(0)).unwrap().clone(), 0.into());
+    }
+
+    #[test]
+    fn test_set_wrong_type() {
+        let mut globals = Globals::new();
+        globals.grow(None, 0.into(), true).unwrap();
+        let err = globals.set(&Index::Num(0), 1i64.into()).unwrap_err();
+        assert_eq!(err.to_string(), "type mismatch: expected i32, found i64");
+    }
+
+    #[test]
+    fn test_commit_rollback() {
+        let mut globals = Globals::new();
+        globals.grow(None, 0.into(), true).unwrap();
+        globals.commit();
+        globals.set(&Index::Num(0), 1.into()).unwrap();
+        globals.grow(None, 0.into(), true).unwrap();
+        globals.rollback();
+        assert_eq!(globals.get(&Index::Num(0)).unwrap().clone(), 0.into());
+        assert_eq!(globals.len(), 1);
+    }
+}
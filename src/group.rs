@@ -1,6 +1,19 @@
 #![allow(unused)]
 use crate::model::{Expression, Instruction};
 use anyhow::Result;
+use std::fmt::{self, Display};
+
+/// A `block`, `loop` or `if` which is missing its `end`.
+#[derive(Debug)]
+pub struct UnterminatedBlock;
+
+impl Display for UnterminatedBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Expected End")
+    }
+}
+
+impl std::error::Error for UnterminatedBlock {}
 
 #[derive(PartialEq, Debug)]
 enum ExprEnd {
@@ -23,8 +36,7 @@ pub fn group_expr(mut instrs: Vec<Instruction>) -> Result<Expression> {
 
 fn expr(instrs: &mut Vec<Instruction>) -> Result<(Expression, ExprEnd)> {
     let mut new_instrs = Vec::new();
-    while !instrs.is_empty() {
-        let instr = instrs.pop().unwrap();
+    while let Some(instr) = instrs.pop() {
         new_instrs.push(match instr {
             Instruction::If(block_type, if_expr, else_expr) => {
                 let (if_ex, else_ex) = expr_if(instrs)?;
@@ -52,7 +64,7 @@ fn expr_if(instrs: &mut Vec<Instruction>) -> Result<(Expression, Expression)> {
         ExprEnd::Else => {
             let (else_group, end) = expr(instrs)?;
             if end != ExprEnd::End {
-                return Err(anyhow::anyhow!("Expected End"));
+                return Err(UnterminatedBlock.into());
             }
             Ok((if_group, else_group))
         }
@@ -66,7 +78,7 @@ fn expr_if(instrs: &mut Vec<Instruction>) -> Result<(Expression, Expression)> {
 fn expr_block(instrs: &mut Vec<Instruction>) -> Result<Expression> {
     let (block_group, end) = expr(instrs)?;
     if end != ExprEnd::End {
-        return Err(anyhow::anyhow!("Expected End"));
+        return Err(UnterminatedBlock.into());
     }
     Ok(block_group)
 }
@@ -153,7 +165,7 @@ mod tests {
         assert_eq!(if_expr.instrs[0], Instruction::I32Const(2));
         assert_eq!(if_expr.instrs[1], Instruction::I32Const(3));
 
-        assert!(else_expr.instrs.len() == 0);
+        assert!(else_expr.instrs.is_empty());
     }
 
     #[test]
@@ -1,25 +1,55 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::ops::BitAnd;
 use std::ops::BitOr;
-use std::ops::{BitXor, Shl};
+use std::ops::BitXor;
 
 use crate::call_stack::FuncStack;
 use crate::model::BlockType;
 use crate::model::Expression;
-use crate::model::{Index, Instruction};
+use crate::model::{Index, Instruction, ValType};
+use crate::ops::CheckedOps;
+use crate::ops::ConvertOps;
+use crate::ops::DemoteOps;
+use crate::ops::Extend32Ops;
+use crate::ops::ExtendOps;
 use crate::ops::FloatOps;
 use crate::ops::IntOps;
 use crate::ops::NumOps;
+use crate::ops::PromoteOps;
+use crate::ops::TruncOps;
+use crate::ops::WrapOps;
 use crate::response::Control;
 use crate::response::Response;
+use crate::value::Value;
 
 pub struct Handler<'a> {
     stack: &'a mut FuncStack,
+    canonicalize_nan: bool,
+    checked: bool,
+    // The instruction being handled, to name in errors.
+    mnemonic: &'static str,
 }
 
 impl<'a> Handler<'a> {
     pub fn new(state: &'a mut FuncStack) -> Self {
-        Handler { stack: state }
+        Handler {
+            stack: state,
+            canonicalize_nan: false,
+            checked: false,
+            mnemonic: "",
+        }
+    }
+
+    /// Replaces NaN results of float arithmetic with the canonical NaN,
+    /// so the payload does not depend on the platform.
+    pub fn set_canonicalize_nan(&mut self, canonicalize_nan: bool) {
+        self.canonicalize_nan = canonicalize_nan;
+    }
+
+    /// Makes integer add, sub and mul error on overflow, instead of
+    /// wrapping around.
+    pub fn set_checked(&mut self, checked: bool) {
+        self.checked = checked;
     }
 
     fn drop(&mut self) -> Result<Response> {
@@ -27,6 +57,15 @@ impl<'a> Handler<'a> {
         Ok(Response::new())
     }
 
+    fn select(&mut self) -> Result<Response> {
+        let condition = self.pop_condition()?;
+        let b = self.stack.pop()?;
+        let a = self.stack.pop()?;
+        a.is_same(&b)?;
+        self.stack.push(if condition { a } else { b })?;
+        Ok(Response::new())
+    }
+
     fn local_get(&mut self, index: &Index) -> Result<Response> {
         let value = self.stack.locals.get(index)?;
         self.stack.push(value.clone())?;
@@ -53,18 +92,34 @@ impl<'a> Handler<'a> {
         Ok(Response::new())
     }
 
+    fn ref_null(&mut self) -> Result<Response> {
+        self.stack.push(Value::default_func_ref())?;
+        Ok(Response::new())
+    }
+
+    fn ref_is_null(&mut self) -> Result<Response> {
+        let value = self.stack.pop()?;
+        value.is_same_type(&ValType::FuncRef)?;
+        let is_null = value == Value::default_func_ref();
+        self.stack.push(Value::from_bool(is_null))?;
+        Ok(Response::new())
+    }
+
     fn call_func(&mut self, index: Index) -> Result<Response> {
         Ok(Response::new_ctrl(Control::ExecFunc(index)))
     }
 
+    fn return_call(&mut self, index: Index) -> Result<Response> {
+        Ok(Response::new_ctrl(Control::ReturnCall(index)))
+    }
+
     fn if_instr(
         &mut self,
         block_type: BlockType,
         if_block: Option<Expression>,
         else_block: Option<Expression>,
     ) -> Result<Response> {
-        let value = self.stack.pop()?;
-        if value.is_true() {
+        if self.pop_condition()? {
             Ok(Response::new_ctrl(Control::ExecBlock(
                 block_type,
                 if_block.unwrap(),
@@ -84,10 +139,36 @@ impl<'a> Handler<'a> {
         )))
     }
 
+    /// Pops the condition of an `if`, `br_if` or `select`.
+    fn pop_condition(&mut self) -> Result<bool> {
+        let value = self.stack.pop()?;
+        value.is_true().map_err(|_| {
+            anyhow!(
+                "{} expected i32 but found {}",
+                self.mnemonic,
+                value.val_type()
+            )
+        })
+    }
+
     fn branch(&mut self, index: Index) -> Result<Response> {
         Ok(Response::new_ctrl(Control::Branch(index)))
     }
 
+    fn br_if(&mut self, index: Index) -> Result<Response> {
+        if self.pop_condition()? {
+            self.branch(index)
+        } else {
+            Ok(Response::new())
+        }
+    }
+
+    fn br_table(&mut self, targets: Vec<Index>, default: Index) -> Result<Response> {
+        let n = self.i32_pop()? as u32 as usize;
+        let index = targets.into_iter().nth(n).unwrap_or(default);
+        self.branch(index)
+    }
+
     fn handle_loop(
         &mut self,
         block_type: BlockType,
@@ -100,9 +181,11 @@ impl<'a> Handler<'a> {
     }
 
     pub fn handle(&mut self, instr: Instruction) -> Result<Response> {
+        self.mnemonic = instr.mnemonic();
         match instr {
             Instruction::I32Const(value) => self.i32_const(value),
             Instruction::Drop => self.drop(),
+            Instruction::Select => self.select(),
             Instruction::I32Clz => self.i32_clz(),
             Instruction::I32Ctz => self.i32_ctz(),
             Instruction::I32Popcnt => self.i32_popcnt(),
@@ -204,37 +287,96 @@ impl<'a> Handler<'a> {
             Instruction::F64Gt => self.f64_gt(),
             Instruction::F64Le => self.f64_le(),
             Instruction::F64Ge => self.f64_ge(),
+            Instruction::I32WrapI64 => self.i32_wrap_i64(),
+            Instruction::I64ExtendI32S => self.i64_extend_i32_s(),
+            Instruction::I64ExtendI32U => self.i64_extend_i32_u(),
+            Instruction::I32TruncF32S => self.i32_trunc_f32_s(),
+            Instruction::I32TruncF32U => self.i32_trunc_f32_u(),
+            Instruction::I32TruncF64S => self.i32_trunc_f64_s(),
+            Instruction::I32TruncF64U => self.i32_trunc_f64_u(),
+            Instruction::I64TruncF32S => self.i64_trunc_f32_s(),
+            Instruction::I64TruncF32U => self.i64_trunc_f32_u(),
+            Instruction::I64TruncF64S => self.i64_trunc_f64_s(),
+            Instruction::I64TruncF64U => self.i64_trunc_f64_u(),
+            Instruction::I32TruncSatF32S => self.i32_trunc_sat_f32_s(),
+            Instruction::I32TruncSatF32U => self.i32_trunc_sat_f32_u(),
+            Instruction::I32TruncSatF64S => self.i32_trunc_sat_f64_s(),
+            Instruction::I32TruncSatF64U => self.i32_trunc_sat_f64_u(),
+            Instruction::I64TruncSatF32S => self.i64_trunc_sat_f32_s(),
+            Instruction::I64TruncSatF32U => self.i64_trunc_sat_f32_u(),
+            Instruction::I64TruncSatF64S => self.i64_trunc_sat_f64_s(),
+            Instruction::I64TruncSatF64U => self.i64_trunc_sat_f64_u(),
+            Instruction::F32ConvertI32S => self.f32_convert_i32_s(),
+            Instruction::F32ConvertI32U => self.f32_convert_i32_u(),
+            Instruction::F32ConvertI64S => self.f32_convert_i64_s(),
+            Instruction::F32ConvertI64U => self.f32_convert_i64_u(),
+            Instruction::F64ConvertI32S => self.f64_convert_i32_s(),
+            Instruction::F64ConvertI32U => self.f64_convert_i32_u(),
+            Instruction::F64ConvertI64S => self.f64_convert_i64_s(),
+            Instruction::F64ConvertI64U => self.f64_convert_i64_u(),
+            Instruction::F32DemoteF64 => self.f32_demote_f64(),
+            Instruction::F64PromoteF32 => self.f64_promote_f32(),
+            Instruction::I32Extend8S => self.i32_extend8_s(),
+            Instruction::I32Extend16S => self.i32_extend16_s(),
+            Instruction::I64Extend8S => self.i64_extend8_s(),
+            Instruction::I64Extend16S => self.i64_extend16_s(),
+            Instruction::I64Extend32S => self.i64_extend32_s(),
             Instruction::LocalGet(index) => self.local_get(&index),
             Instruction::LocalSet(index) => self.local_set(&index),
             Instruction::LocalTee(index) => self.local_tee(&index),
             Instruction::Return => self.return_instr(),
             Instruction::Nop => self.nop(),
             Instruction::Call(index) => self.call_func(index),
+            Instruction::ReturnCall(index) => self.return_call(index),
+            // The functions of a table are looked up by the executor.
+            Instruction::CallIndirect(..) | Instruction::ReturnCallIndirect(..) => unreachable!(),
             Instruction::If(bt, ib, eb) => self.if_instr(bt, ib, eb),
             Instruction::Else => unreachable!(),
             Instruction::End => unreachable!(),
+            // Globals and memory are executor state, out of reach of the handler.
+            Instruction::GlobalGet(_) | Instruction::GlobalSet(_) => unreachable!(),
+            Instruction::I32Load(_) | Instruction::I32Store(_) => unreachable!(),
+            Instruction::MemorySize | Instruction::MemoryGrow => unreachable!(),
+            Instruction::MemoryInit(_) | Instruction::DataDrop(_) => unreachable!(),
+            Instruction::TableInit(..) | Instruction::ElemDrop(_) => unreachable!(),
+            // As are tables, and the functions a reference can be made to.
+            Instruction::TableGet(_) | Instruction::TableSet(_) => unreachable!(),
+            Instruction::TableSize(_) | Instruction::TableGrow(_) => unreachable!(),
+            Instruction::RefFunc(_) => unreachable!(),
+            Instruction::RefNull => self.ref_null(),
+            Instruction::RefIsNull => self.ref_is_null(),
             Instruction::Block(bt, b) => self.block(bt, b),
             Instruction::Br(index) => self.branch(index),
+            Instruction::BrIf(index) => self.br_if(index),
+            Instruction::BrTable(targets, default) => self.br_table(targets, default),
             Instruction::Loop(bt, b) => self.handle_loop(bt, b),
         }
     }
 }
 
 macro_rules! pop {
-    ($fname:ident, $ty:ty) => {
+    ($fname:ident, $ty:ty, $val_type:expr) => {
         impl<'a> Handler<'a> {
             fn $fname(&mut self) -> Result<$ty> {
-                let val: $ty = self.stack.pop()?.try_into()?;
-                Ok(val)
+                let value = self.stack.pop()?;
+                if value.val_type() != $val_type {
+                    return Err(anyhow!(
+                        "{} expected {} but found {}",
+                        self.mnemonic,
+                        $val_type,
+                        value.val_type()
+                    ));
+                }
+                value.try_into()
             }
         }
     };
 }
 
-pop!(i32_pop, i32);
-pop!(i64_pop, i64);
-pop!(f32_pop, f32);
-pop!(f64_pop, f64);
+pop!(i32_pop, i32, ValType::I32);
+pop!(i64_pop, i64, ValType::I64);
+pop!(f32_pop, f32, ValType::F32);
+pop!(f64_pop, f64, ValType::F64);
 
 macro_rules! constant {
     ($fname:ident, $ty:ty) => {
@@ -265,9 +407,62 @@ macro_rules! impl_binary_op {
     };
 }
 
-impl_binary_op!(i32_add, i32_pop, add);
-impl_binary_op!(i32_sub, i32_pop, sub);
-impl_binary_op!(i32_mul, i32_pop, mul);
+// Comparisons, which give an i32 of 1 or 0 whatever the operand type.
+macro_rules! impl_compare_op {
+    ($fname:ident, $pop:ident, $op:ident) => {
+        impl<'a> Handler<'a> {
+            fn $fname(&mut self) -> Result<Response> {
+                let a = self.$pop()?;
+                let b = self.$pop()?;
+                self.stack.push(Value::from_bool(b.$op(a)))?;
+                Ok(Response::new())
+            }
+        }
+    };
+}
+
+// Integer arithmetic, which errors on overflow when checked.
+macro_rules! impl_int_arith_op {
+    ($fname:ident, $pop:ident, $op:ident, $checked_op:ident) => {
+        impl<'a> Handler<'a> {
+            fn $fname(&mut self) -> Result<Response> {
+                let a = self.$pop()?;
+                let b = self.$pop()?;
+                let result = if self.checked {
+                    b.$checked_op(a)?
+                } else {
+                    b.$op(a)
+                };
+                self.stack.push(result.into())?;
+                Ok(Response::new())
+            }
+        }
+    };
+}
+
+// Float arithmetic, whose NaN results may be canonicalized. The op is
+// given by path, as `f32` has inherent methods like `min` which would
+// take precedence over the ones from `FloatOps`.
+macro_rules! impl_float_binary_op {
+    ($fname:ident, $pop:ident, $op:path) => {
+        impl<'a> Handler<'a> {
+            fn $fname(&mut self) -> Result<Response> {
+                let a = self.$pop()?;
+                let b = self.$pop()?;
+                let mut result = $op(b, a);
+                if self.canonicalize_nan {
+                    result = result.canonicalize_nan();
+                }
+                self.stack.push(result.into())?;
+                Ok(Response::new())
+            }
+        }
+    };
+}
+
+impl_int_arith_op!(i32_add, i32_pop, add, add_checked);
+impl_int_arith_op!(i32_sub, i32_pop, sub, sub_checked);
+impl_int_arith_op!(i32_mul, i32_pop, mul, mul_checked);
 impl_binary_op!(i32_and, i32_pop, bitand);
 impl_binary_op!(i32_or, i32_pop, bitor);
 impl_binary_op!(i32_xor, i32_pop, bitxor);
@@ -276,20 +471,20 @@ impl_binary_op!(i32_shr_s, i32_pop, shr_s);
 impl_binary_op!(i32_shr_u, i32_pop, shr_u);
 impl_binary_op!(i32_rotl, i32_pop, rotl);
 impl_binary_op!(i32_rotr, i32_pop, rotr);
-impl_binary_op!(i32_eq, i32_pop, eq);
-impl_binary_op!(i32_ne, i32_pop, ne);
-impl_binary_op!(i32_lt_s, i32_pop, lt_s);
-impl_binary_op!(i32_lt_u, i32_pop, lt_u);
-impl_binary_op!(i32_gt_s, i32_pop, gt_s);
-impl_binary_op!(i32_gt_u, i32_pop, gt_u);
-impl_binary_op!(i32_le_s, i32_pop, le_s);
-impl_binary_op!(i32_le_u, i32_pop, le_u);
-impl_binary_op!(i32_ge_s, i32_pop, ge_s);
-impl_binary_op!(i32_ge_u, i32_pop, ge_u);
-
-impl_binary_op!(i64_add, i64_pop, add);
-impl_binary_op!(i64_sub, i64_pop, sub);
-impl_binary_op!(i64_mul, i64_pop, mul);
+impl_compare_op!(i32_eq, i32_pop, eq);
+impl_compare_op!(i32_ne, i32_pop, ne);
+impl_compare_op!(i32_lt_s, i32_pop, lt_s);
+impl_compare_op!(i32_lt_u, i32_pop, lt_u);
+impl_compare_op!(i32_gt_s, i32_pop, gt_s);
+impl_compare_op!(i32_gt_u, i32_pop, gt_u);
+impl_compare_op!(i32_le_s, i32_pop, le_s);
+impl_compare_op!(i32_le_u, i32_pop, le_u);
+impl_compare_op!(i32_ge_s, i32_pop, ge_s);
+impl_compare_op!(i32_ge_u, i32_pop, ge_u);
+
+impl_int_arith_op!(i64_add, i64_pop, add, add_checked);
+impl_int_arith_op!(i64_sub, i64_pop, sub, sub_checked);
+impl_int_arith_op!(i64_mul, i64_pop, mul, mul_checked);
 impl_binary_op!(i64_and, i64_pop, bitand);
 impl_binary_op!(i64_or, i64_pop, bitor);
 impl_binary_op!(i64_xor, i64_pop, bitxor);
@@ -298,44 +493,44 @@ impl_binary_op!(i64_shr_s, i64_pop, shr_s);
 impl_binary_op!(i64_shr_u, i64_pop, shr_u);
 impl_binary_op!(i64_rotl, i64_pop, rotl);
 impl_binary_op!(i64_rotr, i64_pop, rotr);
-impl_binary_op!(i64_eq, i64_pop, eq);
-impl_binary_op!(i64_ne, i64_pop, ne);
-impl_binary_op!(i64_lt_s, i64_pop, lt_s);
-impl_binary_op!(i64_lt_u, i64_pop, lt_u);
-impl_binary_op!(i64_gt_s, i64_pop, gt_s);
-impl_binary_op!(i64_gt_u, i64_pop, gt_u);
-impl_binary_op!(i64_le_s, i64_pop, le_s);
-impl_binary_op!(i64_le_u, i64_pop, le_u);
-impl_binary_op!(i64_ge_s, i64_pop, ge_s);
-impl_binary_op!(i64_ge_u, i64_pop, ge_u);
-
-impl_binary_op!(f32_add, f32_pop, add);
-impl_binary_op!(f32_sub, f32_pop, sub);
-impl_binary_op!(f32_mul, f32_pop, mul);
-impl_binary_op!(f32_div, f32_pop, div);
-impl_binary_op!(f32_min, f32_pop, min);
-impl_binary_op!(f32_max, f32_pop, max);
+impl_compare_op!(i64_eq, i64_pop, eq);
+impl_compare_op!(i64_ne, i64_pop, ne);
+impl_compare_op!(i64_lt_s, i64_pop, lt_s);
+impl_compare_op!(i64_lt_u, i64_pop, lt_u);
+impl_compare_op!(i64_gt_s, i64_pop, gt_s);
+impl_compare_op!(i64_gt_u, i64_pop, gt_u);
+impl_compare_op!(i64_le_s, i64_pop, le_s);
+impl_compare_op!(i64_le_u, i64_pop, le_u);
+impl_compare_op!(i64_ge_s, i64_pop, ge_s);
+impl_compare_op!(i64_ge_u, i64_pop, ge_u);
+
+impl_float_binary_op!(f32_add, f32_pop, NumOps::add);
+impl_float_binary_op!(f32_sub, f32_pop, NumOps::sub);
+impl_float_binary_op!(f32_mul, f32_pop, NumOps::mul);
+impl_float_binary_op!(f32_div, f32_pop, FloatOps::div);
+impl_float_binary_op!(f32_min, f32_pop, FloatOps::min);
+impl_float_binary_op!(f32_max, f32_pop, FloatOps::max);
 impl_binary_op!(f32_copysign, f32_pop, copysign);
-impl_binary_op!(f32_eq, f32_pop, eq);
-impl_binary_op!(f32_ne, f32_pop, ne);
-impl_binary_op!(f32_lt, f32_pop, lt);
-impl_binary_op!(f32_gt, f32_pop, gt);
-impl_binary_op!(f32_le, f32_pop, le);
-impl_binary_op!(f32_ge, f32_pop, ge);
-
-impl_binary_op!(f64_add, f64_pop, add);
-impl_binary_op!(f64_sub, f64_pop, sub);
-impl_binary_op!(f64_mul, f64_pop, mul);
-impl_binary_op!(f64_min, f64_pop, min);
-impl_binary_op!(f64_div, f64_pop, div);
-impl_binary_op!(f64_max, f64_pop, max);
+impl_compare_op!(f32_eq, f32_pop, eq);
+impl_compare_op!(f32_ne, f32_pop, ne);
+impl_compare_op!(f32_lt, f32_pop, lt);
+impl_compare_op!(f32_gt, f32_pop, gt);
+impl_compare_op!(f32_le, f32_pop, le);
+impl_compare_op!(f32_ge, f32_pop, ge);
+
+impl_float_binary_op!(f64_add, f64_pop, NumOps::add);
+impl_float_binary_op!(f64_sub, f64_pop, NumOps::sub);
+impl_float_binary_op!(f64_mul, f64_pop, NumOps::mul);
+impl_float_binary_op!(f64_min, f64_pop, FloatOps::min);
+impl_float_binary_op!(f64_div, f64_pop, FloatOps::div);
+impl_float_binary_op!(f64_max, f64_pop, FloatOps::max);
 impl_binary_op!(f64_copysign, f64_pop, copysign);
-impl_binary_op!(f64_eq, f64_pop, eq);
-impl_binary_op!(f64_ne, f64_pop, ne);
-impl_binary_op!(f64_lt, f64_pop, lt);
-impl_binary_op!(f64_gt, f64_pop, gt);
-impl_binary_op!(f64_le, f64_pop, le);
-impl_binary_op!(f64_ge, f64_pop, ge);
+impl_compare_op!(f64_eq, f64_pop, eq);
+impl_compare_op!(f64_ne, f64_pop, ne);
+impl_compare_op!(f64_lt, f64_pop, lt);
+impl_compare_op!(f64_gt, f64_pop, gt);
+impl_compare_op!(f64_le, f64_pop, le);
+impl_compare_op!(f64_ge, f64_pop, ge);
 
 macro_rules! impl_binary_res_op {
     ($fname:ident, $popper:ident, $op:ident) => {
@@ -372,31 +567,111 @@ macro_rules! impl_unary_op {
     };
 }
 
+macro_rules! impl_test_op {
+    ($fname:ident, $popper:ident, $op:ident) => {
+        impl<'a> Handler<'a> {
+            fn $fname(&mut self) -> Result<Response> {
+                let a = self.$popper()?;
+                self.stack.push(Value::from_bool(a.$op()))?;
+                Ok(Response::new())
+            }
+        }
+    };
+}
+
+macro_rules! impl_unary_res_op {
+    ($fname:ident, $popper:ident, $op:ident) => {
+        impl<'a> Handler<'a> {
+            fn $fname(&mut self) -> Result<Response> {
+                let a = self.$popper()?;
+                self.stack.push(a.$op()?.into())?;
+                Ok(Response::new())
+            }
+        }
+    };
+}
+
+macro_rules! impl_float_unary_op {
+    ($fname:ident, $popper:ident, $op:ident) => {
+        impl<'a> Handler<'a> {
+            fn $fname(&mut self) -> Result<Response> {
+                let a = self.$popper()?;
+                let mut result = a.$op();
+                if self.canonicalize_nan {
+                    result = result.canonicalize_nan();
+                }
+                self.stack.push(result.into())?;
+                Ok(Response::new())
+            }
+        }
+    };
+}
+
 impl_unary_op!(i32_clz, i32_pop, clz);
 impl_unary_op!(i32_ctz, i32_pop, ctz);
 impl_unary_op!(i32_popcnt, i32_pop, popcnt);
-impl_unary_op!(i32_eqz, i32_pop, eqz);
+impl_test_op!(i32_eqz, i32_pop, eqz);
 
 impl_unary_op!(i64_clz, i64_pop, clz);
 impl_unary_op!(i64_ctz, i64_pop, ctz);
 impl_unary_op!(i64_popcnt, i64_pop, popcnt);
-impl_unary_op!(i64_eqz, i64_pop, eqz);
+impl_test_op!(i64_eqz, i64_pop, eqz);
 
 impl_unary_op!(f32_abs, f32_pop, abs);
 impl_unary_op!(f32_neg, f32_pop, neg);
-impl_unary_op!(f32_ceil, f32_pop, ceil);
-impl_unary_op!(f32_floor, f32_pop, floor);
-impl_unary_op!(f32_trunc, f32_pop, trunc);
-impl_unary_op!(f32_nearest, f32_pop, round);
-impl_unary_op!(f32_sqrt, f32_pop, sqrt);
+impl_float_unary_op!(f32_ceil, f32_pop, ceil);
+impl_float_unary_op!(f32_floor, f32_pop, floor);
+impl_float_unary_op!(f32_trunc, f32_pop, trunc);
+impl_float_unary_op!(f32_nearest, f32_pop, nearest);
+impl_float_unary_op!(f32_sqrt, f32_pop, sqrt);
 
 impl_unary_op!(f64_abs, f64_pop, abs);
 impl_unary_op!(f64_neg, f64_pop, neg);
-impl_unary_op!(f64_ceil, f64_pop, ceil);
-impl_unary_op!(f64_floor, f64_pop, floor);
-impl_unary_op!(f64_trunc, f64_pop, trunc);
-impl_unary_op!(f64_nearest, f64_pop, round);
-impl_unary_op!(f64_sqrt, f64_pop, sqrt);
+impl_float_unary_op!(f64_ceil, f64_pop, ceil);
+impl_float_unary_op!(f64_floor, f64_pop, floor);
+impl_float_unary_op!(f64_trunc, f64_pop, trunc);
+impl_float_unary_op!(f64_nearest, f64_pop, nearest);
+impl_float_unary_op!(f64_sqrt, f64_pop, sqrt);
+
+impl_unary_op!(i32_wrap_i64, i64_pop, wrap);
+impl_unary_op!(i64_extend_i32_s, i32_pop, extend_s);
+impl_unary_op!(i64_extend_i32_u, i32_pop, extend_u);
+
+impl_unary_res_op!(i32_trunc_f32_s, f32_pop, trunc_i32_s);
+impl_unary_res_op!(i32_trunc_f32_u, f32_pop, trunc_i32_u);
+impl_unary_res_op!(i32_trunc_f64_s, f64_pop, trunc_i32_s);
+impl_unary_res_op!(i32_trunc_f64_u, f64_pop, trunc_i32_u);
+impl_unary_res_op!(i64_trunc_f32_s, f32_pop, trunc_i64_s);
+impl_unary_res_op!(i64_trunc_f32_u, f32_pop, trunc_i64_u);
+impl_unary_res_op!(i64_trunc_f64_s, f64_pop, trunc_i64_s);
+impl_unary_res_op!(i64_trunc_f64_u, f64_pop, trunc_i64_u);
+
+impl_unary_op!(i32_trunc_sat_f32_s, f32_pop, trunc_sat_i32_s);
+impl_unary_op!(i32_trunc_sat_f32_u, f32_pop, trunc_sat_i32_u);
+impl_unary_op!(i32_trunc_sat_f64_s, f64_pop, trunc_sat_i32_s);
+impl_unary_op!(i32_trunc_sat_f64_u, f64_pop, trunc_sat_i32_u);
+impl_unary_op!(i64_trunc_sat_f32_s, f32_pop, trunc_sat_i64_s);
+impl_unary_op!(i64_trunc_sat_f32_u, f32_pop, trunc_sat_i64_u);
+impl_unary_op!(i64_trunc_sat_f64_s, f64_pop, trunc_sat_i64_s);
+impl_unary_op!(i64_trunc_sat_f64_u, f64_pop, trunc_sat_i64_u);
+
+impl_unary_op!(f32_convert_i32_s, i32_pop, convert_f32_s);
+impl_unary_op!(f32_convert_i32_u, i32_pop, convert_f32_u);
+impl_unary_op!(f32_convert_i64_s, i64_pop, convert_f32_s);
+impl_unary_op!(f32_convert_i64_u, i64_pop, convert_f32_u);
+impl_unary_op!(f64_convert_i32_s, i32_pop, convert_f64_s);
+impl_unary_op!(f64_convert_i32_u, i32_pop, convert_f64_u);
+impl_unary_op!(f64_convert_i64_s, i64_pop, convert_f64_s);
+impl_unary_op!(f64_convert_i64_u, i64_pop, convert_f64_u);
+
+impl_float_unary_op!(f32_demote_f64, f64_pop, demote);
+impl_float_unary_op!(f64_promote_f32, f32_pop, promote);
+
+impl_unary_op!(i32_extend8_s, i32_pop, extend8_s);
+impl_unary_op!(i32_extend16_s, i32_pop, extend16_s);
+impl_unary_op!(i64_extend8_s, i64_pop, extend8_s);
+impl_unary_op!(i64_extend16_s, i64_pop, extend16_s);
+impl_unary_op!(i64_extend32_s, i64_pop, extend32_s);
 
 #[cfg(test)]
 #[path = "./handler_test.rs"]
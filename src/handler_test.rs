@@ -43,6 +43,73 @@ fn test_drop_error() {
     assert!(exec_instr_handler(Instruction::Drop, &mut stack).is_err());
 }
 
+#[test]
+fn test_select() {
+    let mut stack = FuncStack::new();
+    stack.push(1.5f64.into()).unwrap();
+    stack.push(2.5f64.into()).unwrap();
+    stack.push(1.into()).unwrap();
+    exec_instr_handler(Instruction::Select, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), 1.5f64.into());
+    assert!(stack.pop().is_err());
+}
+
+#[test]
+fn test_select_false() {
+    let mut stack = FuncStack::new();
+    stack.push(1.into()).unwrap();
+    stack.push(2.into()).unwrap();
+    stack.push(0.into()).unwrap();
+    exec_instr_handler(Instruction::Select, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), 2.into());
+    assert!(stack.pop().is_err());
+}
+
+#[test]
+fn test_select_error() {
+    let mut stack = FuncStack::new();
+    stack.push(1.into()).unwrap();
+    stack.push(1.into()).unwrap();
+    assert!(exec_instr_handler(Instruction::Select, &mut stack).is_err());
+}
+
+#[test]
+fn test_select_type_error() {
+    let mut stack = FuncStack::new();
+    stack.push(1.into()).unwrap();
+    stack.push(2i64.into()).unwrap();
+    stack.push(1.into()).unwrap();
+    let err = exec_instr_handler(Instruction::Select, &mut stack).err().unwrap();
+    assert_eq!(err.to_string(), "type mismatch: expected i64, found i32");
+}
+
+#[test]
+fn test_binary_op_type_error() {
+    let mut stack = FuncStack::new();
+    stack.push(1.into()).unwrap();
+    stack.push(2i64.into()).unwrap();
+    let err = exec_instr_handler(Instruction::I32Add, &mut stack).err().unwrap();
+    assert_eq!(err.to_string(), "i32.add expected i32 but found i64");
+
+    stack.push(1.into()).unwrap();
+    let err = exec_instr_handler(Instruction::F64Div, &mut stack).err().unwrap();
+    assert_eq!(err.to_string(), "f64.div expected f64 but found i32");
+}
+
+#[test]
+fn test_unary_op_type_error() {
+    let mut stack = FuncStack::new();
+    stack.push(1.5f32.into()).unwrap();
+    let err = exec_instr_handler(Instruction::I64Eqz, &mut stack).err().unwrap();
+    assert_eq!(err.to_string(), "i64.eqz expected i64 but found f32");
+
+    stack.push(1i64.into()).unwrap();
+    let err = exec_instr_handler(Instruction::BrIf(Index::Num(0)), &mut stack)
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "br_if expected i32 but found i64");
+}
+
 #[test]
 fn test_i32_clz() {
     let mut stack = FuncStack::new();
@@ -639,11 +706,11 @@ fn test_i64_eqz() {
     let mut stack = FuncStack::new();
     stack.push(0i64.into()).unwrap();
     exec_instr_handler(Instruction::I64Eqz, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64Eqz, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 }
 
 #[test]
@@ -652,12 +719,12 @@ fn test_i64_eq() {
     stack.push(1i64.into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64Eq, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 
     stack.push(1i64.into()).unwrap();
     stack.push((-1i64).into()).unwrap();
     exec_instr_handler(Instruction::I64Eq, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 }
 
 #[test]
@@ -666,12 +733,12 @@ fn test_i64_ne() {
     stack.push(1i64.into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64Ne, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 
     stack.push(1i64.into()).unwrap();
     stack.push((-1i64).into()).unwrap();
     exec_instr_handler(Instruction::I64Ne, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 }
 
 #[test]
@@ -680,17 +747,17 @@ fn test_i64_lt_s() {
     stack.push(1i64.into()).unwrap();
     stack.push(2i64.into()).unwrap();
     exec_instr_handler(Instruction::I64LtS, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 
     stack.push(2i64.into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64LtS, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 
     stack.push((-1i64).into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64LtS, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 }
 
 #[test]
@@ -699,17 +766,17 @@ fn test_i64_lt_u() {
     stack.push(1i64.into()).unwrap();
     stack.push(2i64.into()).unwrap();
     exec_instr_handler(Instruction::I64LtU, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 
     stack.push(2i64.into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64LtU, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 
     stack.push((-1i64).into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64LtU, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 }
 
 #[test]
@@ -718,17 +785,17 @@ fn test_i64_gt_s() {
     stack.push(1i64.into()).unwrap();
     stack.push(2i64.into()).unwrap();
     exec_instr_handler(Instruction::I64GtS, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 
     stack.push(2i64.into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64GtS, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 
     stack.push((-1i64).into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64GtS, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 }
 
 #[test]
@@ -737,17 +804,17 @@ fn test_i64_gt_u() {
     stack.push(1i64.into()).unwrap();
     stack.push(2i64.into()).unwrap();
     exec_instr_handler(Instruction::I64GtU, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 
     stack.push(2i64.into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64GtU, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 
     stack.push((-1i64).into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64GtU, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 }
 
 #[test]
@@ -756,17 +823,17 @@ fn test_i64_le_s() {
     stack.push(1i64.into()).unwrap();
     stack.push(2i64.into()).unwrap();
     exec_instr_handler(Instruction::I64LeS, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 
     stack.push(2i64.into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64LeS, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 
     stack.push((-1i64).into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64LeS, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 }
 
 #[test]
@@ -775,17 +842,17 @@ fn test_i64_le_u() {
     stack.push(1i64.into()).unwrap();
     stack.push(2i64.into()).unwrap();
     exec_instr_handler(Instruction::I64LeU, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 
     stack.push(2i64.into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64LeU, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 
     stack.push((-1i64).into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64LeU, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 }
 
 #[test]
@@ -794,17 +861,17 @@ fn test_i64_ge_s() {
     stack.push(1i64.into()).unwrap();
     stack.push(2i64.into()).unwrap();
     exec_instr_handler(Instruction::I64GeS, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 
     stack.push(2i64.into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64GeS, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 
     stack.push((-1i64).into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64GeS, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 }
 
 #[test]
@@ -813,20 +880,39 @@ fn test_i64_ge_u() {
     stack.push(1i64.into()).unwrap();
     stack.push(2i64.into()).unwrap();
     exec_instr_handler(Instruction::I64GeU, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 0i64.into());
+    assert_eq!(stack.pop().unwrap(), 0i32.into());
 
     stack.push(2i64.into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64GeU, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 
     stack.push((-1i64).into()).unwrap();
     stack.push(1i64.into()).unwrap();
     exec_instr_handler(Instruction::I64GeU, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), 1i64.into());
+    assert_eq!(stack.pop().unwrap(), 1i32.into());
 }
 
 #[test]
+fn test_comparison_result_type() {
+    let mut stack = FuncStack::new();
+    stack.push(1i64.into()).unwrap();
+    stack.push(1i64.into()).unwrap();
+    exec_instr_handler(Instruction::I64Eq, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap().val_type(), ValType::I32);
+
+    stack.push(0i64.into()).unwrap();
+    exec_instr_handler(Instruction::I64Eqz, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap().val_type(), ValType::I32);
+
+    stack.push(1.0f64.into()).unwrap();
+    stack.push(2.0f64.into()).unwrap();
+    exec_instr_handler(Instruction::F64Lt, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap().val_type(), ValType::I32);
+}
+
+#[test]
+#[allow(clippy::approx_constant)]
 fn test_f32_const() {
     let mut stack = FuncStack::new();
     exec_instr_handler(Instruction::F32Const(3.14), &mut stack).unwrap();
@@ -841,6 +927,18 @@ fn test_f32_abs() {
     assert_eq!(stack.pop().unwrap(), 2.5f32.into());
 }
 
+#[test]
+fn test_f32_abs_sign_bit() {
+    let mut stack = FuncStack::new();
+    stack.push(f32::from_bits(0xffa0_0001).into()).unwrap();
+    exec_instr_handler(Instruction::F32Abs, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), f32::from_bits(0x7fa0_0001).into());
+
+    stack.push(f32::NEG_INFINITY.into()).unwrap();
+    exec_instr_handler(Instruction::F32Abs, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), f32::INFINITY.into());
+}
+
 #[test]
 fn test_f32_neg() {
     let mut stack = FuncStack::new();
@@ -849,6 +947,15 @@ fn test_f32_neg() {
     assert_eq!(stack.pop().unwrap(), (-2.5f32).into());
 }
 
+#[test]
+fn test_f32_neg_nan() {
+    let mut stack = FuncStack::new();
+    stack.push(f32::from_bits(0x7fa0_0001).into()).unwrap();
+    exec_instr_handler(Instruction::F32Neg, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), f32::from_bits(0xffa0_0001).into());
+    assert_ne!(Value::from(f32::NAN), f32::from_bits(0x7fa0_0001).into());
+}
+
 #[test]
 fn test_f32_ceil() {
     let mut stack = FuncStack::new();
@@ -865,6 +972,48 @@ fn test_f32_floor() {
     assert_eq!(stack.pop().unwrap(), (2.0f32).into());
 }
 
+#[test]
+fn test_f32_ceil_negative_zero() {
+    let mut stack = FuncStack::new();
+    stack.push((-0.5f32).into()).unwrap();
+    exec_instr_handler(Instruction::F32Ceil, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-0.0f32).into());
+}
+
+#[test]
+fn test_f32_floor_negative_zero() {
+    let mut stack = FuncStack::new();
+    stack.push((-0.0f32).into()).unwrap();
+    exec_instr_handler(Instruction::F32Floor, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-0.0f32).into());
+}
+
+#[test]
+fn test_f32_ceil_floor_infinity() {
+    let mut stack = FuncStack::new();
+    stack.push(f32::INFINITY.into()).unwrap();
+    exec_instr_handler(Instruction::F32Floor, &mut stack).unwrap();
+    exec_instr_handler(Instruction::F32Ceil, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), f32::INFINITY.into());
+
+    stack.push(f32::NEG_INFINITY.into()).unwrap();
+    exec_instr_handler(Instruction::F32Ceil, &mut stack).unwrap();
+    exec_instr_handler(Instruction::F32Floor, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), f32::NEG_INFINITY.into());
+}
+
+#[test]
+fn test_f32_ceil_floor_nan() {
+    let mut stack = FuncStack::new();
+    stack.push(f32::NAN.into()).unwrap();
+    exec_instr_handler(Instruction::F32Ceil, &mut stack).unwrap();
+    exec_instr_handler(Instruction::F32Floor, &mut stack).unwrap();
+    match stack.pop().unwrap() {
+        Value::F32(n) => assert!(n.is_nan()),
+        _ => panic!("Expected f32"),
+    }
+}
+
 #[test]
 fn test_f32_trunc() {
     let mut stack = FuncStack::new();
@@ -878,7 +1027,11 @@ fn test_f32_nearest() {
     let mut stack = FuncStack::new();
     stack.push(2.5f32.into()).unwrap();
     exec_instr_handler(Instruction::F32Nearest, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (3.0f32).into());
+    assert_eq!(stack.pop().unwrap(), (2.0f32).into());
+    // Halves round to the even neighbour, either way.
+    stack.push(3.5f32.into()).unwrap();
+    exec_instr_handler(Instruction::F32Nearest, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (4.0f32).into());
 }
 
 #[test]
@@ -934,6 +1087,16 @@ fn test_f32_min() {
     assert_eq!(stack.pop().unwrap(), 2.0f32.into());
 }
 
+#[test]
+fn test_f32_min_nan() {
+    let mut stack = FuncStack::new();
+    stack.push(2.0f32.into()).unwrap();
+    stack.push(f32::NAN.into()).unwrap();
+    exec_instr_handler(Instruction::F32Min, &mut stack).unwrap();
+    let value: f32 = stack.pop().unwrap().try_into().unwrap();
+    assert!(value.is_nan());
+}
+
 #[test]
 fn test_f32_max() {
     let mut stack = FuncStack::new();
@@ -958,12 +1121,12 @@ fn test_f32_eq() {
     stack.push(1.0f32.into()).unwrap();
     stack.push(1.0f32.into()).unwrap();
     exec_instr_handler(Instruction::F32Eq, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (1.0f32).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(1));
 
     stack.push(1.0f32.into()).unwrap();
     stack.push((-1.0f32).into()).unwrap();
     exec_instr_handler(Instruction::F32Eq, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (0.0f32).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(0));
 }
 
 #[test]
@@ -972,12 +1135,12 @@ fn test_f32_ne() {
     stack.push(1.0f32.into()).unwrap();
     stack.push(1.0f32.into()).unwrap();
     exec_instr_handler(Instruction::F32Ne, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (0.0f32).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(0));
 
     stack.push(1.0f32.into()).unwrap();
     stack.push((-1.0f32).into()).unwrap();
     exec_instr_handler(Instruction::F32Ne, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (1.0f32).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(1));
 }
 
 #[test]
@@ -986,12 +1149,12 @@ fn test_f32_lt() {
     stack.push(1.0f32.into()).unwrap();
     stack.push(2.0f32.into()).unwrap();
     exec_instr_handler(Instruction::F32Lt, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (1.0f32).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(1));
 
     stack.push(2.0f32.into()).unwrap();
     stack.push(1.0f32.into()).unwrap();
     exec_instr_handler(Instruction::F32Lt, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (0.0f32).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(0));
 }
 
 #[test]
@@ -1000,12 +1163,12 @@ fn test_f32_gt() {
     stack.push(1.0f32.into()).unwrap();
     stack.push(2.0f32.into()).unwrap();
     exec_instr_handler(Instruction::F32Gt, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (0.0f32).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(0));
 
     stack.push(2.0f32.into()).unwrap();
     stack.push(1.0f32.into()).unwrap();
     exec_instr_handler(Instruction::F32Gt, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (1.0f32).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(1));
 }
 
 #[test]
@@ -1014,12 +1177,12 @@ fn test_f32_le() {
     stack.push(1.0f32.into()).unwrap();
     stack.push(2.0f32.into()).unwrap();
     exec_instr_handler(Instruction::F32Le, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (1.0f32).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(1));
 
     stack.push(2.0f32.into()).unwrap();
     stack.push(1.0f32.into()).unwrap();
     exec_instr_handler(Instruction::F32Le, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (0.0f32).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(0));
 }
 
 #[test]
@@ -1028,15 +1191,16 @@ fn test_f32_ge() {
     stack.push(1.0f32.into()).unwrap();
     stack.push(2.0f32.into()).unwrap();
     exec_instr_handler(Instruction::F32Ge, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (0.0f32).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(0));
 
     stack.push(2.0f32.into()).unwrap();
     stack.push(1.0f32.into()).unwrap();
     exec_instr_handler(Instruction::F32Ge, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (1.0f32).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(1));
 }
 
 #[test]
+#[allow(clippy::approx_constant)]
 fn test_f64_const() {
     let mut stack = FuncStack::new();
     exec_instr_handler(Instruction::F64Const(3.14), &mut stack).unwrap();
@@ -1051,6 +1215,20 @@ fn test_f64_abs() {
     assert_eq!(stack.pop().unwrap(), 2.5f64.into());
 }
 
+#[test]
+fn test_f64_abs_sign_bit() {
+    let mut stack = FuncStack::new();
+    stack
+        .push(f64::from_bits(0xfff4_0000_0000_0001).into())
+        .unwrap();
+    exec_instr_handler(Instruction::F64Abs, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), f64::from_bits(0x7ff4_0000_0000_0001).into());
+
+    stack.push(f64::NEG_INFINITY.into()).unwrap();
+    exec_instr_handler(Instruction::F64Abs, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), f64::INFINITY.into());
+}
+
 #[test]
 fn test_f64_neg() {
     let mut stack = FuncStack::new();
@@ -1059,6 +1237,15 @@ fn test_f64_neg() {
     assert_eq!(stack.pop().unwrap(), (-2.5f64).into());
 }
 
+#[test]
+fn test_f64_neg_nan() {
+    let mut stack = FuncStack::new();
+    stack.push(f64::from_bits(0x7ff4_0000_0000_0001).into()).unwrap();
+    exec_instr_handler(Instruction::F64Neg, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), f64::from_bits(0xfff4_0000_0000_0001).into());
+    assert_ne!(Value::from(f64::NAN), f64::from_bits(0x7ff4_0000_0000_0001).into());
+}
+
 #[test]
 fn test_f64_ceil() {
     let mut stack = FuncStack::new();
@@ -1075,6 +1262,48 @@ fn test_f64_floor() {
     assert_eq!(stack.pop().unwrap(), (2.0f64).into());
 }
 
+#[test]
+fn test_f64_ceil_negative_zero() {
+    let mut stack = FuncStack::new();
+    stack.push((-0.5f64).into()).unwrap();
+    exec_instr_handler(Instruction::F64Ceil, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-0.0f64).into());
+}
+
+#[test]
+fn test_f64_floor_negative_zero() {
+    let mut stack = FuncStack::new();
+    stack.push((-0.0f64).into()).unwrap();
+    exec_instr_handler(Instruction::F64Floor, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-0.0f64).into());
+}
+
+#[test]
+fn test_f64_ceil_floor_infinity() {
+    let mut stack = FuncStack::new();
+    stack.push(f64::INFINITY.into()).unwrap();
+    exec_instr_handler(Instruction::F64Floor, &mut stack).unwrap();
+    exec_instr_handler(Instruction::F64Ceil, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), f64::INFINITY.into());
+
+    stack.push(f64::NEG_INFINITY.into()).unwrap();
+    exec_instr_handler(Instruction::F64Ceil, &mut stack).unwrap();
+    exec_instr_handler(Instruction::F64Floor, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), f64::NEG_INFINITY.into());
+}
+
+#[test]
+fn test_f64_ceil_floor_nan() {
+    let mut stack = FuncStack::new();
+    stack.push(f64::NAN.into()).unwrap();
+    exec_instr_handler(Instruction::F64Ceil, &mut stack).unwrap();
+    exec_instr_handler(Instruction::F64Floor, &mut stack).unwrap();
+    match stack.pop().unwrap() {
+        Value::F64(n) => assert!(n.is_nan()),
+        _ => panic!("Expected f64"),
+    }
+}
+
 #[test]
 fn test_f64_trunc() {
     let mut stack = FuncStack::new();
@@ -1088,7 +1317,11 @@ fn test_f64_nearest() {
     let mut stack = FuncStack::new();
     stack.push(2.5f64.into()).unwrap();
     exec_instr_handler(Instruction::F64Nearest, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (3.0f64).into());
+    assert_eq!(stack.pop().unwrap(), (2.0f64).into());
+    // Halves round to the even neighbour, either way.
+    stack.push(3.5f64.into()).unwrap();
+    exec_instr_handler(Instruction::F64Nearest, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (4.0f64).into());
 }
 
 #[test]
@@ -1153,6 +1386,16 @@ fn test_f64_max() {
     assert_eq!(stack.pop().unwrap(), 3.0f64.into());
 }
 
+#[test]
+fn test_f64_max_signed_zero() {
+    let mut stack = FuncStack::new();
+    stack.push((-0.0f64).into()).unwrap();
+    stack.push(0.0f64.into()).unwrap();
+    exec_instr_handler(Instruction::F64Max, &mut stack).unwrap();
+    let value: f64 = stack.pop().unwrap().try_into().unwrap();
+    assert!(value.is_sign_positive());
+}
+
 #[test]
 fn test_f64_copysign() {
     let mut stack = FuncStack::new();
@@ -1168,12 +1411,12 @@ fn test_f64_eq() {
     stack.push(1.0f64.into()).unwrap();
     stack.push(1.0f64.into()).unwrap();
     exec_instr_handler(Instruction::F64Eq, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (1.0f64).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(1));
 
     stack.push(1.0f64.into()).unwrap();
     stack.push((-1.0f64).into()).unwrap();
     exec_instr_handler(Instruction::F64Eq, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (0.0f64).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(0));
 }
 
 #[test]
@@ -1182,12 +1425,12 @@ fn test_f64_ne() {
     stack.push(1.0f64.into()).unwrap();
     stack.push(1.0f64.into()).unwrap();
     exec_instr_handler(Instruction::F64Ne, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (0.0f64).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(0));
 
     stack.push(1.0f64.into()).unwrap();
     stack.push((-1.0f64).into()).unwrap();
     exec_instr_handler(Instruction::F64Ne, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (1.0f64).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(1));
 }
 
 #[test]
@@ -1196,12 +1439,12 @@ fn test_f64_lt() {
     stack.push(1.0f64.into()).unwrap();
     stack.push(2.0f64.into()).unwrap();
     exec_instr_handler(Instruction::F64Lt, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (1.0f64).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(1));
 
     stack.push(2.0f64.into()).unwrap();
     stack.push(1.0f64.into()).unwrap();
     exec_instr_handler(Instruction::F64Lt, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (0.0f64).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(0));
 }
 
 #[test]
@@ -1210,12 +1453,12 @@ fn test_f64_gt() {
     stack.push(1.0f64.into()).unwrap();
     stack.push(2.0f64.into()).unwrap();
     exec_instr_handler(Instruction::F64Gt, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (0.0f64).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(0));
 
     stack.push(2.0f64.into()).unwrap();
     stack.push(1.0f64.into()).unwrap();
     exec_instr_handler(Instruction::F64Gt, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (1.0f64).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(1));
 }
 
 #[test]
@@ -1224,12 +1467,12 @@ fn test_f64_le() {
     stack.push(1.0f64.into()).unwrap();
     stack.push(2.0f64.into()).unwrap();
     exec_instr_handler(Instruction::F64Le, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (1.0f64).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(1));
 
     stack.push(2.0f64.into()).unwrap();
     stack.push(1.0f64.into()).unwrap();
     exec_instr_handler(Instruction::F64Le, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (0.0f64).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(0));
 }
 
 #[test]
@@ -1238,12 +1481,287 @@ fn test_f64_ge() {
     stack.push(1.0f64.into()).unwrap();
     stack.push(2.0f64.into()).unwrap();
     exec_instr_handler(Instruction::F64Ge, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (0.0f64).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(0));
 
     stack.push(2.0f64.into()).unwrap();
     stack.push(1.0f64.into()).unwrap();
     exec_instr_handler(Instruction::F64Ge, &mut stack).unwrap();
-    assert_eq!(stack.pop().unwrap(), (1.0f64).into());
+    assert_eq!(stack.pop().unwrap(), Value::I32(1));
+}
+
+#[test]
+fn test_i32_wrap_i64() {
+    let mut stack = FuncStack::new();
+    stack.push(0x1_0000_0005i64.into()).unwrap();
+    exec_instr_handler(Instruction::I32WrapI64, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), 5.into());
+
+    stack.push(0x7fff_ffff_ffff_ffffi64.into()).unwrap();
+    exec_instr_handler(Instruction::I32WrapI64, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-1).into());
+}
+
+#[test]
+fn test_i32_wrap_i64_type_error() {
+    let mut stack = FuncStack::new();
+    stack.push(5.into()).unwrap();
+    assert!(exec_instr_handler(Instruction::I32WrapI64, &mut stack).is_err());
+}
+
+#[test]
+fn test_i64_extend_i32_s() {
+    let mut stack = FuncStack::new();
+    stack.push((-2).into()).unwrap();
+    exec_instr_handler(Instruction::I64ExtendI32S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-2i64).into());
+}
+
+#[test]
+fn test_i32_trunc_f32() {
+    let mut stack = FuncStack::new();
+    stack.push((-2.5f32).into()).unwrap();
+    exec_instr_handler(Instruction::I32TruncF32S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-2).into());
+
+    stack.push(3e9f32.into()).unwrap();
+    exec_instr_handler(Instruction::I32TruncF32U, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (3_000_000_000u32 as i32).into());
+}
+
+#[test]
+fn test_i32_trunc_f64_error() {
+    let mut stack = FuncStack::new();
+    for instr in [Instruction::I32TruncF64S, Instruction::I32TruncF64U] {
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 5e9] {
+            stack.push(value.into()).unwrap();
+            assert!(exec_instr_handler(instr.clone(), &mut stack).is_err());
+        }
+    }
+    stack.push((-1.0f64).into()).unwrap();
+    assert!(exec_instr_handler(Instruction::I32TruncF64U, &mut stack).is_err());
+}
+
+#[test]
+fn test_i64_trunc_f64() {
+    let mut stack = FuncStack::new();
+    stack.push((-2.5f64).into()).unwrap();
+    exec_instr_handler(Instruction::I64TruncF64S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-2i64).into());
+
+    stack.push(1e19f64.into()).unwrap();
+    exec_instr_handler(Instruction::I64TruncF64U, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (10_000_000_000_000_000_000u64 as i64).into());
+}
+
+#[test]
+fn test_i64_trunc_f32_error() {
+    let mut stack = FuncStack::new();
+    for instr in [Instruction::I64TruncF32S, Instruction::I64TruncF32U] {
+        for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY, 2e19] {
+            stack.push(value.into()).unwrap();
+            assert!(exec_instr_handler(instr.clone(), &mut stack).is_err());
+        }
+    }
+    stack.push((-1.0f32).into()).unwrap();
+    assert!(exec_instr_handler(Instruction::I64TruncF32U, &mut stack).is_err());
+}
+
+#[test]
+fn test_i32_trunc_sat_f32() {
+    let mut stack = FuncStack::new();
+    let cases = [
+        (Instruction::I32TruncSatF32S, f32::NAN, 0),
+        (Instruction::I32TruncSatF32S, f32::INFINITY, i32::MAX),
+        (Instruction::I32TruncSatF32S, f32::NEG_INFINITY, i32::MIN),
+        (Instruction::I32TruncSatF32S, 2147483904.0, i32::MAX),
+        (Instruction::I32TruncSatF32S, -2147483904.0, i32::MIN),
+        (Instruction::I32TruncSatF32U, f32::NAN, 0),
+        (Instruction::I32TruncSatF32U, f32::INFINITY, -1),
+        (Instruction::I32TruncSatF32U, f32::NEG_INFINITY, 0),
+        (Instruction::I32TruncSatF32U, 4294967808.0, -1),
+        (Instruction::I32TruncSatF32U, -1.0, 0),
+    ];
+    for (instr, value, result) in cases {
+        stack.push(value.into()).unwrap();
+        exec_instr_handler(instr, &mut stack).unwrap();
+        assert_eq!(stack.pop().unwrap(), result.into());
+    }
+}
+
+#[test]
+fn test_i32_trunc_sat_f64() {
+    let mut stack = FuncStack::new();
+    let cases = [
+        (Instruction::I32TruncSatF64S, -2.5, -2),
+        (Instruction::I32TruncSatF64S, 2147483648.0, i32::MAX),
+        (Instruction::I32TruncSatF64S, -2147483649.0, i32::MIN),
+        (Instruction::I32TruncSatF64U, 4294967296.0, -1),
+        (Instruction::I32TruncSatF64U, -1.0, 0),
+    ];
+    for (instr, value, result) in cases {
+        stack.push(value.into()).unwrap();
+        exec_instr_handler(instr, &mut stack).unwrap();
+        assert_eq!(stack.pop().unwrap(), result.into());
+    }
+}
+
+#[test]
+fn test_i64_trunc_sat() {
+    let mut stack = FuncStack::new();
+    let cases = [
+        (Instruction::I64TruncSatF64S, f64::NAN, 0),
+        (Instruction::I64TruncSatF64S, f64::INFINITY, i64::MAX),
+        (Instruction::I64TruncSatF64S, f64::NEG_INFINITY, i64::MIN),
+        (Instruction::I64TruncSatF64S, 9223372036854777856.0, i64::MAX),
+        (Instruction::I64TruncSatF64U, f64::NAN, 0),
+        (Instruction::I64TruncSatF64U, 18446744073709555712.0, -1),
+        (Instruction::I64TruncSatF64U, -1.0, 0),
+    ];
+    for (instr, value, result) in cases {
+        stack.push(value.into()).unwrap();
+        exec_instr_handler(instr, &mut stack).unwrap();
+        assert_eq!(stack.pop().unwrap(), result.into());
+    }
+
+    stack.push(f32::NEG_INFINITY.into()).unwrap();
+    exec_instr_handler(Instruction::I64TruncSatF32S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), i64::MIN.into());
+
+    stack.push(f32::INFINITY.into()).unwrap();
+    exec_instr_handler(Instruction::I64TruncSatF32U, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-1i64).into());
+}
+
+#[test]
+fn test_f32_convert_i32() {
+    let mut stack = FuncStack::new();
+    stack.push((-1).into()).unwrap();
+    exec_instr_handler(Instruction::F32ConvertI32S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-1.0f32).into());
+
+    stack.push((-1).into()).unwrap();
+    exec_instr_handler(Instruction::F32ConvertI32U, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), 4294967296.0f32.into());
+}
+
+#[test]
+fn test_f32_convert_i64() {
+    let mut stack = FuncStack::new();
+    stack.push((-1i64).into()).unwrap();
+    exec_instr_handler(Instruction::F32ConvertI64S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-1.0f32).into());
+
+    stack.push((-1i64).into()).unwrap();
+    exec_instr_handler(Instruction::F32ConvertI64U, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), 18446744073709551616.0f32.into());
+}
+
+#[test]
+fn test_f64_convert_i32() {
+    let mut stack = FuncStack::new();
+    stack.push((-1).into()).unwrap();
+    exec_instr_handler(Instruction::F64ConvertI32S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-1.0f64).into());
+
+    stack.push((-1).into()).unwrap();
+    exec_instr_handler(Instruction::F64ConvertI32U, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), 4294967295.0f64.into());
+}
+
+#[test]
+fn test_f64_convert_i64() {
+    let mut stack = FuncStack::new();
+    stack.push(i64::MIN.into()).unwrap();
+    exec_instr_handler(Instruction::F64ConvertI64S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-9223372036854775808.0f64).into());
+
+    stack.push(i64::MIN.into()).unwrap();
+    exec_instr_handler(Instruction::F64ConvertI64U, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), 9223372036854775808.0f64.into());
+}
+
+#[test]
+fn test_convert_type_error() {
+    let mut stack = FuncStack::new();
+    stack.push(1i64.into()).unwrap();
+    assert!(exec_instr_handler(Instruction::F32ConvertI32S, &mut stack).is_err());
+}
+
+#[test]
+fn test_f32_demote_f64() {
+    let mut stack = FuncStack::new();
+    stack.push(16777217.0f64.into()).unwrap();
+    exec_instr_handler(Instruction::F32DemoteF64, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), 16777216.0f32.into());
+}
+
+#[test]
+fn test_f64_promote_f32() {
+    let mut stack = FuncStack::new();
+    stack.push(2.5f32.into()).unwrap();
+    exec_instr_handler(Instruction::F64PromoteF32, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), 2.5f64.into());
+}
+
+#[test]
+fn test_demote_promote_nan() {
+    let mut stack = FuncStack::new();
+    stack.push(f64::NAN.into()).unwrap();
+    exec_instr_handler(Instruction::F32DemoteF64, &mut stack).unwrap();
+    exec_instr_handler(Instruction::F64PromoteF32, &mut stack).unwrap();
+    let value: f64 = stack.pop().unwrap().try_into().unwrap();
+    assert!(value.is_nan());
+}
+
+#[test]
+fn test_i32_extend8_s() {
+    let mut stack = FuncStack::new();
+    stack.push(0xff.into()).unwrap();
+    exec_instr_handler(Instruction::I32Extend8S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-1).into());
+
+    stack.push(0x17f.into()).unwrap();
+    exec_instr_handler(Instruction::I32Extend8S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), 0x7f.into());
+}
+
+#[test]
+fn test_i32_extend16_s() {
+    let mut stack = FuncStack::new();
+    stack.push(0x8000.into()).unwrap();
+    exec_instr_handler(Instruction::I32Extend16S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-32768).into());
+}
+
+#[test]
+fn test_i64_extend_s() {
+    let mut stack = FuncStack::new();
+    stack.push(0xffi64.into()).unwrap();
+    exec_instr_handler(Instruction::I64Extend8S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-1i64).into());
+
+    stack.push(0xffffi64.into()).unwrap();
+    exec_instr_handler(Instruction::I64Extend16S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-1i64).into());
+
+    stack.push(0xffff_ffffi64.into()).unwrap();
+    exec_instr_handler(Instruction::I64Extend32S, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), (-1i64).into());
+}
+
+#[test]
+fn test_extend_s_type_error() {
+    let mut stack = FuncStack::new();
+    stack.push(0xff.into()).unwrap();
+    assert!(exec_instr_handler(Instruction::I64Extend8S, &mut stack).is_err());
+}
+
+#[test]
+fn test_i64_extend_i32_u() {
+    let mut stack = FuncStack::new();
+    stack.push((-2).into()).unwrap();
+    exec_instr_handler(Instruction::I64ExtendI32U, &mut stack).unwrap();
+    assert_eq!(stack.pop().unwrap(), 0xffff_fffei64.into());
 }
 
 #[test]
@@ -1478,6 +1996,16 @@ fn test_if_error() {
     assert!(exec_instr_handler(test_if!(test_block_type!()), &mut FuncStack::new()).is_err());
 }
 
+#[test]
+fn test_if_condition_type_error() {
+    let mut stack = FuncStack::new();
+    stack.push(1.0f32.into()).unwrap();
+    let err = exec_instr_handler(test_if!(test_block_type!()), &mut stack)
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "if expected i32 but found f32");
+}
+
 #[test]
 #[should_panic]
 fn test_else() {
@@ -1519,6 +2047,50 @@ fn test_branch() {
     assert_eq!(response.control, Control::Branch(Index::Num(0)));
 }
 
+#[test]
+fn test_br_if() {
+    let mut stack = FuncStack::new();
+    stack.push(2.into()).unwrap();
+    let response = exec_instr_handler(Instruction::BrIf(Index::Num(1)), &mut stack).unwrap();
+    assert_eq!(response.control, Control::Branch(Index::Num(1)));
+    assert!(stack.pop().is_err());
+}
+
+#[test]
+fn test_br_if_false() {
+    let mut stack = FuncStack::new();
+    stack.push(0.into()).unwrap();
+    let response = exec_instr_handler(Instruction::BrIf(Index::Num(1)), &mut stack).unwrap();
+    assert_eq!(response.control, Control::None);
+    assert!(stack.pop().is_err());
+}
+
+#[test]
+fn test_br_table() {
+    let targets = vec![Index::Num(0), Index::Num(1)];
+    for (n, index) in [(0, 0), (1, 1), (2, 2), (-1, 2)] {
+        let mut stack = FuncStack::new();
+        stack.push(n.into()).unwrap();
+        let instr = Instruction::BrTable(targets.clone(), Index::Num(2));
+        let response = exec_instr_handler(instr, &mut stack).unwrap();
+        assert_eq!(response.control, Control::Branch(Index::Num(index)));
+    }
+}
+
+#[test]
+fn test_br_table_error() {
+    let mut stack = FuncStack::new();
+    let instr = Instruction::BrTable(vec![], Index::Num(0));
+    assert!(exec_instr_handler(instr, &mut stack).is_err());
+}
+
+#[test]
+fn test_br_if_type_error() {
+    let mut stack = FuncStack::new();
+    stack.push(1i64.into()).unwrap();
+    assert!(exec_instr_handler(Instruction::BrIf(Index::Num(0)), &mut stack).is_err());
+}
+
 #[test]
 fn test_loop() {
     let block_type = test_block_type!((test_local!(ValType::I64)), (ValType::I32));
@@ -0,0 +1,26 @@
+//! An interpreter for Web Assembly text, run a line at a time, as at the
+//! `wasmrepl` prompt. See `executor::Executor::eval`.
+
+mod call_stack;
+mod dict;
+mod elements;
+mod emit;
+pub mod executor;
+mod globals;
+mod group;
+mod handler;
+mod list;
+mod locals;
+mod memory;
+pub mod model;
+mod ops;
+pub mod parser;
+pub mod response;
+mod stack;
+mod tables;
+mod validate;
+pub mod value;
+pub mod wasm_import;
+
+#[cfg(test)]
+mod test_utils;
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use anyhow::{Error, Result};
 
+#[derive(Clone)]
 pub struct List<T> {
     values: Vec<T>,
     soft_len: usize,
@@ -24,6 +25,10 @@ impl<T> List<T> {
         index
     }
 
+    pub fn len(&self) -> usize {
+        self.values.len() + self.soft_len
+    }
+
     fn has_index(&self, index: usize) -> Result<()> {
         if index >= self.values.len() + self.soft_len {
             Err(Error::msg(format!("Index out of bounds: {}", index)))
@@ -45,6 +50,19 @@ impl<T> List<T> {
         }
     }
 
+    pub fn get_mut(&mut self, index: usize) -> Result<&mut T> {
+        self.has_index(index)?;
+        match self.soft_values.get_mut(&index) {
+            Some(value) => Ok(value),
+            None => Ok(self.values.get_mut(index).unwrap()),
+        }
+    }
+
+    /// The committed values, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.values.iter()
+    }
+
     pub fn commit(&mut self) {
         // O(n) operation, n being the number of
         // inserts since the last commit.
@@ -82,6 +100,40 @@ mod tests {
         assert!(list.get(2).is_err());
     }
 
+    #[test]
+    fn test_list_iter() {
+        let mut list = List::new();
+        list.grow(1);
+        list.commit();
+        list.grow(2);
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&1]);
+    }
+
+    #[test]
+    fn test_list_len() {
+        let mut list = List::new();
+        list.grow(0);
+        list.commit();
+        list.grow(0);
+        assert_eq!(list.len(), 2);
+        list.rollback();
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn test_list_get_mut() {
+        let mut list = List::new();
+        list.grow(0);
+        list.commit();
+        list.grow(0);
+
+        *list.get_mut(0).unwrap() = 1;
+        *list.get_mut(1).unwrap() = 2;
+        assert_eq!(list.get(0).unwrap().clone(), 1);
+        assert_eq!(list.get(1).unwrap().clone(), 2);
+        assert!(list.get_mut(2).is_err());
+    }
+
     #[test]
     fn test_list_get_set_commit() {
         let mut list = List::new();
@@ -1,6 +1,7 @@
 use crate::{elements::Elements, model::Index, value::Value};
 use anyhow::Result;
 
+#[derive(Clone)]
 pub struct Locals {
     elements: Elements<Value>,
 }
@@ -17,7 +18,8 @@ impl Locals {
     }
 
     pub fn set(&mut self, index: &Index, value: Value) -> Result<()> {
-        self.elements.get(index)?.is_same(&value)?;
+        let local = self.elements.get(index)?;
+        value.is_same_type(&local.val_type())?;
         self.elements.set(index, value)
     }
 
@@ -25,6 +27,16 @@ impl Locals {
         self.elements.get(index)
     }
 
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// The committed locals with their index and id, in the order of
+    /// their index.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Option<&str>, &Value)> {
+        self.elements.entries()
+    }
+
     pub fn commit(&mut self) {
         self.elements.commit();
     }
@@ -54,7 +66,8 @@ mod tests {
     fn test_set_wrong_type() {
         let mut locals = super::Locals::new();
         locals.grow(None, 0.into()).unwrap();
-        assert!(locals.set(&Index::Num(0), 1i64.into()).is_err());
+        let err = locals.set(&Index::Num(0), 1i64.into()).unwrap_err();
+        assert_eq!(err.to_string(), "type mismatch: expected i32, found i64");
     }
 
     #[test]
@@ -84,6 +97,26 @@ mod tests {
         assert!(locals.set(&test_index("a"), 1i64.into()).is_err());
     }
 
+    #[test]
+    fn test_iter() {
+        let mut locals = super::Locals::new();
+        locals.grow(Some(String::from("a")), 1.into()).unwrap();
+        locals.grow(None, 2i64.into()).unwrap();
+        locals.grow(Some(String::from("b")), 3.0f32.into()).unwrap();
+        locals.commit();
+        locals.grow(None, 4.into()).unwrap();
+
+        let entries: Vec<_> = locals.iter().collect();
+        assert_eq!(
+            entries,
+            vec![
+                (0, Some("a"), &1.into()),
+                (1, None, &2i64.into()),
+                (2, Some("b"), &3.0f32.into()),
+            ]
+        );
+    }
+
     #[test]
     fn test_commit_rollback() {
         let mut locals = super::Locals::new();
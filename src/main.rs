@@ -1,33 +1,48 @@
-mod call_stack;
-mod dict;
-mod elements;
-mod executor;
-mod group;
-mod handler;
-mod list;
-mod locals;
-mod model;
-mod ops;
-mod parser;
-mod response;
-mod stack;
-mod value;
-
-#[cfg(test)]
-mod test_utils;
-
-use executor::Executor;
-use model::Line;
-use parser::parse_line;
 use rustyline::history::FileHistory;
 use rustyline::validate::MatchingBracketValidator;
 use rustyline::{error::ReadlineError, Editor};
 use rustyline::{Cmd, EventHandler, KeyCode, KeyEvent, Modifiers};
 use rustyline_derive::{Completer, Helper, Highlighter, Hinter, Validator};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use wasmrepl::executor::{Definition, DefinitionKind, Executor};
+use wasmrepl::model::{Expression, Global, Index, Instruction, Line, Module, SourceError};
+use wasmrepl::parser::{parse_line, parse_lines, rewrite_binary_literals, Line as WastLine};
+use wasmrepl::value::{DisplayMode, Value};
+use wasmrepl::wasm_import;
+
+const HISTORY_FILE: &str = ".wasmrepl_history";
 
 fn main() -> rustyline::Result<()> {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(2);
+        }
+    };
+
+    let mut repl = Repl::new();
+    repl.executor.set_quiet(options.quiet);
+    if let Some(path) = options.load {
+        match load(&mut repl.executor, &path, options.keep_going) {
+            Ok(output) => println!("{}", output),
+            Err(err) => {
+                println!("Error: {}", err);
+                if !options.keep_going {
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
     let mut rl = new_editor()?;
-    let mut executor = Executor::new();
+    let history = history_path();
+    if let Some(path) = &history {
+        if let Err(err) = load_history(&mut rl, path) {
+            println!("Error: Cannot load history: {}", err);
+        }
+    }
     let mut ctrlc_cnt = 0;
 
     loop {
@@ -36,7 +51,10 @@ fn main() -> rustyline::Result<()> {
             Ok(line) => {
                 ctrlc_cnt = 0;
                 rl.add_history_entry(line.as_str())?;
-                println!("{}", parse_and_execute(&mut executor, line.as_str()));
+                if is_quit(&line) {
+                    break;
+                }
+                println!("{}", repl.eval(line.as_str()));
             }
             Err(ReadlineError::Interrupted) => {
                 ctrlc_cnt += 1;
@@ -54,27 +72,506 @@ fn main() -> rustyline::Result<()> {
             }
         }
     }
+
+    if let Some(path) = &history {
+        if let Err(err) = rl.save_history(path) {
+            println!("Error: Cannot save history: {}", err);
+        }
+    }
     Ok(())
 }
 
-fn parse_and_execute(executor: &mut Executor, line_str: &str) -> String {
-    let buf = wast::parser::ParseBuffer::new(line_str).unwrap();
-    match parse_line(&buf) {
-        Ok(wast_line) => match Line::try_from(&wast_line) {
-            Ok(line) => match executor.execute_line(line) {
-                Ok(response) => response.message(),
-                Err(err) => {
-                    format!("Error: {}", err)
+/// Where the history is kept across sessions, `$WASMREPL_HISTORY` or else
+/// `.wasmrepl_history` in the home directory.
+fn history_path() -> Option<PathBuf> {
+    match std::env::var_os("WASMREPL_HISTORY") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => std::env::var_os("HOME").map(|home| Path::new(&home).join(HISTORY_FILE)),
+    }
+}
+
+/// Loads the history of earlier sessions. There is none on the first run.
+fn load_history(
+    rl: &mut Editor<InputValidator, FileHistory>,
+    path: &Path,
+) -> rustyline::Result<()> {
+    match rl.load_history(path) {
+        Err(ReadlineError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Whether the line asks to leave the REPL, with `:quit` or `:exit`.
+fn is_quit(line: &str) -> bool {
+    matches!(line.trim(), ":quit" | ":exit")
+}
+
+struct Options {
+    /// File to load before the prompt, with `--load <file>` or just `<file>`.
+    load: Option<String>,
+    /// Whether to go on past forms of the file which fail, and to the prompt
+    /// when loading fails, with `--keep-going`.
+    keep_going: bool,
+    /// Whether to leave out the echo of definitions, with `--quiet`.
+    quiet: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> anyhow::Result<Options> {
+    let mut options = Options {
+        load: None,
+        keep_going: false,
+        quiet: false,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--load" => match args.next() {
+                Some(path) => options.load = Some(path),
+                None => return Err(anyhow::anyhow!("Expected --load <file>")),
+            },
+            "--keep-going" => options.keep_going = true,
+            "--quiet" => options.quiet = true,
+            _ if arg.starts_with("--") => return Err(anyhow::anyhow!("Unknown option {}", arg)),
+            _ if options.load.is_none() => options.load = Some(arg),
+            _ => return Err(anyhow::anyhow!("Expected a single file to load")),
+        }
+    }
+    Ok(options)
+}
+
+struct Repl {
+    executor: Executor,
+    aliases: HashMap<String, String>,
+    canon: bool,
+}
+
+impl Repl {
+    fn new() -> Repl {
+        Repl {
+            executor: Executor::new(),
+            aliases: HashMap::new(),
+            canon: false,
+        }
+    }
+
+    fn eval(&mut self, line: &str) -> String {
+        if let Some(output) = self.define_alias(line) {
+            return output;
+        }
+
+        if let Some(output) = self.set_canon(line) {
+            return output;
+        }
+
+        let alias = line.trim().strip_prefix(':');
+        let line = match alias.and_then(|name| self.aliases.get(name)) {
+            Some(expansion) => expansion.clone(),
+            None => line.to_string(),
+        };
+
+        if let Some(output) = handle_command(&mut self.executor, &line) {
+            return output;
+        }
+
+        let output = parse_and_execute(&mut self.executor, &line);
+        if !self.canon {
+            return output;
+        }
+        match canon(&line) {
+            Ok(canon) => format!("{}\n{}", canon, output),
+            Err(_) => output,
+        }
+    }
+
+    /// Turns on or off echoing how each line is grouped, on `:canon on|off`.
+    fn set_canon(&mut self, line: &str) -> Option<String> {
+        let mut args = line.split_whitespace();
+        if args.next()? != ":canon" {
+            return None;
+        }
+
+        Some(match parse_toggle(args.next()) {
+            Ok(on) => {
+                self.canon = on;
+                format!("canon {}", toggle_str(on))
+            }
+            Err(err) => format!("Error: {}", err),
+        })
+    }
+
+    /// Defines an alias on `:alias <name> <text>`, after which `:<name>`
+    /// is evaluated as `<text>`.
+    fn define_alias(&mut self, line: &str) -> Option<String> {
+        let rest = line.trim_start().strip_prefix(":alias")?;
+        if !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+
+        let rest = rest.trim_start();
+        let (name, text) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let text = text.trim();
+        if name.is_empty() || text.is_empty() {
+            return Some(String::from("Error: Expected :alias <name> <text>"));
+        }
+
+        self.aliases.insert(name.to_string(), text.to_string());
+        Some(format!("alias :{}", name))
+    }
+}
+
+/// Handles the REPL commands, which start with a `:`. Returns `None`
+/// if the line is not a command.
+fn handle_command(executor: &mut Executor, line: &str) -> Option<String> {
+    let mut args = line.split_whitespace();
+    let command = args.next()?;
+    if !command.starts_with(':') {
+        return None;
+    }
+
+    Some(match command {
+        ":warn-residue" => match parse_toggle(args.next()) {
+            Ok(on) => {
+                executor.set_warn_residue(on);
+                format!("warn-residue {}", toggle_str(on))
+            }
+            Err(err) => format!("Error: {}", err),
+        },
+        ":quiet" => match parse_toggle(args.next()) {
+            Ok(on) => {
+                executor.set_quiet(on);
+                format!("quiet {}", toggle_str(on))
+            }
+            Err(err) => format!("Error: {}", err),
+        },
+        ":checked" => match parse_toggle(args.next()) {
+            Ok(on) => {
+                executor.set_checked(on);
+                format!("checked {}", toggle_str(on))
+            }
+            Err(err) => format!("Error: {}", err),
+        },
+        ":types" => match parse_toggle(args.next()) {
+            Ok(on) => {
+                executor.set_typed(on);
+                format!("types {}", toggle_str(on))
+            }
+            Err(err) => format!("Error: {}", err),
+        },
+        ":depth" => match args.next().map(str::parse::<usize>) {
+            Some(Ok(depth)) => {
+                executor.set_max_depth(depth);
+                format!("depth {}", depth)
+            }
+            _ => String::from("Error: Expected a number"),
+        },
+        ":global" => match args.next().map(parse_index) {
+            Some(index) => match executor.global(&index) {
+                Ok((value, mutable)) => {
+                    let mutability = if mutable { "mut " } else { "" };
+                    format!("{}: {}{}", value, mutability, value.val_type())
                 }
+                Err(err) => format!("Error: {}", err),
             },
+            None => String::from("Error: Expected a global"),
+        },
+        ":setglobal" => match (args.next().map(parse_index), args.next()) {
+            (Some(index), Some(literal)) => set_global(executor, &index, literal)
+                .unwrap_or_else(|err| format!("Error: {}", err)),
+            _ => String::from("Error: Expected a global and a value"),
+        },
+        ":ast" => {
+            let rest = line.trim_start()[command.len()..].trim();
+            ast(rest).unwrap_or_else(|err| format!("Error: {}", err))
+        }
+        ":graph" => executor.call_graph(),
+        ":funcs" => list_funcs(executor),
+        ":locals" => list_locals(executor),
+        ":stack" => executor.stack_string(),
+        ":hex" => {
+            executor.set_display_mode(DisplayMode::Hex);
+            String::from("hex")
+        }
+        ":dec" => {
+            executor.set_display_mode(DisplayMode::Decimal);
+            String::from("dec")
+        }
+        ":clear" => match executor.clear_stack() {
+            Ok(()) => executor.stack_string(),
+            Err(err) => format!("Error: {}", err),
+        },
+        ":reset" => {
+            executor.reset();
+            String::from("reset")
+        }
+        ":pop" => match executor.pop_value() {
+            Ok(value) => format!("{}: {}", value, value.val_type()),
+            Err(err) => format!("Error: {}", err),
+        },
+        ":peek" => match executor.peek_top() {
+            Some(value) => format!("{}: {}", value, value.val_type()),
+            None => String::from("empty"),
+        },
+        ":step" => {
+            let rest = line.trim_start()[command.len()..].trim();
+            step(executor, rest).unwrap_or_else(|err| format!("Error: {}", err))
+        }
+        ":load" => {
+            let rest = line.trim_start()[command.len()..].trim();
+            load(executor, rest, false).unwrap_or_else(|err| format!("Error: {}", err))
+        }
+        ":save" => {
+            let rest = line.trim_start()[command.len()..].trim();
+            save(executor, rest).unwrap_or_else(|err| format!("Error: {}", err))
+        }
+        ":load-wasm" => {
+            let rest = line.trim_start()[command.len()..].trim();
+            load_wasm(executor, rest).unwrap_or_else(|err| format!("Error: {}", err))
+        }
+        _ => format!("Error: Unknown command {}", command),
+    })
+}
+
+/// Lists the defined functions, one per line, like `func ;0; f (param i32)`.
+fn list_funcs(executor: &Executor) -> String {
+    let funcs = executor.list_funcs().into_iter().map(|(i, id, ty)| {
+        let mut parts = vec![format!("func ;{};", i)];
+        parts.extend(id);
+        let ty = ty.to_string();
+        if !ty.is_empty() {
+            parts.push(ty);
+        }
+        parts.join(" ")
+    });
+    funcs.collect::<Vec<_>>().join("\n")
+}
+
+/// Lists the locals, one per line, like `local ;0; x i32 = 5`.
+fn list_locals(executor: &Executor) -> String {
+    let locals = executor.list_locals().into_iter().map(|(i, id, value)| {
+        let mut parts = vec![format!("local ;{};", i)];
+        parts.extend(id);
+        parts.push(format!("{} = {}", value.val_type(), value));
+        parts.join(" ")
+    });
+    locals.collect::<Vec<_>>().join("\n")
+}
+
+/// Parses a `$id` or an index number.
+fn parse_index(arg: &str) -> Index {
+    match arg.strip_prefix('$') {
+        Some(id) => Index::Id(String::from(id)),
+        None => match arg.parse() {
+            Ok(num) => Index::Num(num),
+            Err(_) => Index::Id(String::from(arg)),
+        },
+    }
+}
+
+/// Sets a global to a literal of its own type, like `5` or `-0x1p3`.
+fn set_global(executor: &mut Executor, index: &Index, literal: &str) -> anyhow::Result<String> {
+    let (value, _) = executor.global(index)?;
+    let line_str = format!("({}.const {})", value.val_type(), literal);
+    let buf = wast::parser::ParseBuffer::new(&line_str)?;
+    let value = match Line::try_from(&parse_line(&buf)?)? {
+        Line::Expression(line) => match line.expr.instrs.as_slice() {
+            [Instruction::I32Const(n)] => Value::from(*n),
+            [Instruction::I64Const(n)] => Value::from(*n),
+            [Instruction::F32Const(n)] => Value::from(*n),
+            [Instruction::F64Const(n)] => Value::from(*n),
+            _ => return Err(anyhow::anyhow!("Expected a value")),
+        },
+        _ => return Err(anyhow::anyhow!("Expected a value")),
+    };
+    executor.set_global(index, value.clone())?;
+    Ok(format!("{}: {}", value, value.val_type()))
+}
+
+/// Shows how a line is grouped into `block`, `loop` and `if`, without
+/// executing it.
+fn ast(line_str: &str) -> anyhow::Result<String> {
+    let exprs = line_exprs(line_str)?;
+    let trees = exprs.iter().map(Expression::to_tree_string);
+    Ok(trees.collect::<Vec<_>>().join("\n"))
+}
+
+/// Renders the instructions of a line back as Wasm text, one per line,
+/// the way they are run.
+fn canon(line_str: &str) -> anyhow::Result<String> {
+    let exprs = line_exprs(line_str)?;
+    let wats = exprs.iter().map(Expression::to_wat);
+    Ok(wats.collect::<Vec<_>>().join("\n"))
+}
+
+/// The instructions of a line, one expression for each function of a
+/// module.
+fn line_exprs(line_str: &str) -> anyhow::Result<Vec<Expression>> {
+    let rewrite = rewrite_binary_literals(line_str);
+    let buf = wast::parser::ParseBuffer::new(&rewrite.src)?;
+    let wast_line = parse_line(&buf).map_err(|err| rewrite.original_error(line_str, err))?;
+    let line = Line::try_from(&wast_line)?;
+    let expr = match line {
+        Line::Expression(line) => line.expr,
+        Line::Func(func) => func.line_expression.expr,
+        Line::Global(global) => global.init,
+        Line::Table(_) | Line::Memory(_) => Expression::default(),
+        Line::Elem(elem) => elem.active.map(|(_, offset)| offset).unwrap_or_default(),
+        Line::Data(data) => data.offset.unwrap_or_default(),
+        Line::AssertTrap(expr, _) => expr,
+        Line::Module(module) => {
+            let funcs = module.funcs.into_iter();
+            let exprs = funcs.map(|func| func.line_expression.expr);
+            return Ok(exprs.collect());
+        }
+    };
+    Ok(vec![expr])
+}
+
+/// Runs a line one instruction at a time, showing the stack of the
+/// innermost block after each.
+fn step(executor: &mut Executor, line_str: &str) -> anyhow::Result<String> {
+    let rewrite = rewrite_binary_literals(line_str);
+    let buf = wast::parser::ParseBuffer::new(&rewrite.src)?;
+    let wast_line = parse_line(&buf).map_err(|err| rewrite.original_error(line_str, err))?;
+    let mut stepper = executor.step_line(Line::try_from(&wast_line)?)?;
+
+    let mut messages = Vec::new();
+    while let Some(instr) = stepper.next_instr() {
+        let mnemonic = instr.mnemonic();
+        let response = executor.step(&mut stepper)?;
+        messages.push(format!("{}: {}", mnemonic, response.message()));
+    }
+    Ok(messages.join("\n"))
+}
+
+/// Runs each top level form of a file, like `(func ...)` definitions, as
+/// a line of its own. A form which fails is reported with the line it
+/// starts at, and stops the load unless `keep_going` is set.
+fn load(executor: &mut Executor, path: &str, keep_going: bool) -> anyhow::Result<String> {
+    let src = std::fs::read_to_string(path)
+        .map_err(|err| anyhow::anyhow!("Cannot read {}: {}", path, err))?;
+    let rewrite = rewrite_binary_literals(&src);
+    let buf = wast::parser::ParseBuffer::new(&rewrite.src)?;
+    let wast_lines = parse_lines(&buf).map_err(|err| rewrite.original_error(&src, err))?;
+
+    let mut messages = Vec::new();
+    for (span, wast_line) in wast_lines.iter() {
+        match execute(executor, wast_line) {
+            Ok(message) => messages.push(message),
             Err(err) => {
-                format!("Error: {}", err)
+                let err = anyhow::anyhow!("line {}: {}", span.linecol_in(&rewrite.src).0 + 1, err);
+                if !keep_going {
+                    return Err(err);
+                }
+                messages.push(format!("Error: {}", err));
             }
-        },
-        Err(err) => {
-            format!("Error: {}", err)
         }
     }
+    Ok(messages.join("\n"))
+}
+
+/// Writes the globals, with the values they have now, and the defined
+/// functions to `path` as text, which `:load` reads back. Calls are written
+/// by the id of the callee where it has one, so that they call the same
+/// functions when loaded after other definitions.
+fn save(executor: &Executor, path: &str) -> anyhow::Result<String> {
+    let mut defs = Vec::new();
+    for def in executor.definitions() {
+        if def.kind == DefinitionKind::Global {
+            defs.push(saved_global(executor, def)?.to_wat());
+        }
+    }
+    let globals = defs.len();
+
+    let ids: Vec<Option<String>> = executor.funcs().map(|func| func.id.clone()).collect();
+    for func in executor.funcs() {
+        let mut func = func.clone();
+        func.name_func_ids(|index| ids.get(index as usize).cloned().flatten());
+        defs.push(func.to_wat());
+    }
+    let funcs = defs.len() - globals;
+
+    let mut src = defs.join("\n\n");
+    src.push('\n');
+    std::fs::write(path, src).map_err(|err| anyhow::anyhow!("Cannot write {}: {}", path, err))?;
+    let saved = match globals {
+        0 => format!("{} funcs", funcs),
+        _ => format!("{} globals and {} funcs", globals, funcs),
+    };
+    Ok(format!("saved {} to {}", saved, path))
+}
+
+/// The definition of a global, initialized to the value it has now.
+fn saved_global(executor: &Executor, def: Definition) -> anyhow::Result<Global> {
+    let (value, mutable) = executor.global(&Index::Num(def.index))?;
+    let ty = value.val_type();
+    let init = match value {
+        Value::I32(n) => Instruction::I32Const(n),
+        Value::I64(n) => Instruction::I64Const(n),
+        Value::F32(n) => Instruction::F32Const(n),
+        Value::F64(n) => Instruction::F64Const(n),
+        Value::FuncRef(_) => return Err(anyhow::anyhow!("Cannot save funcref globals")),
+    };
+    Ok(Global {
+        id: def.id,
+        ty,
+        mutable,
+        init: Expression { instrs: vec![init] },
+    })
+}
+
+/// Adds the functions of a compiled module, and lists the ones which
+/// could not be added, like `skipped func ;1; f: <reason>`.
+fn load_wasm(executor: &mut Executor, path: &str) -> anyhow::Result<String> {
+    let bytes =
+        std::fs::read(path).map_err(|err| anyhow::anyhow!("Cannot read {}: {}", path, err))?;
+    let import = wasm_import::import_wasm(&bytes)?;
+
+    let mut messages = Vec::new();
+    if !import.funcs.is_empty() {
+        let module = Module {
+            funcs: import.funcs,
+            ..Module::default()
+        };
+        messages.push(executor.execute_line(Line::Module(module))?.message());
+    }
+    for skipped in import.skipped {
+        let mut parts = vec![format!("skipped func ;{};", skipped.index)];
+        parts.extend(skipped.id);
+        messages.push(format!("{}: {}", parts.join(" "), skipped.reason));
+    }
+    Ok(messages.join("\n"))
+}
+
+/// Runs a parsed line, from the prompt or from a file.
+fn execute(executor: &mut Executor, wast_line: &WastLine) -> anyhow::Result<String> {
+    let line = Line::try_from(wast_line)?;
+    Ok(executor.execute_line(line)?.message())
+}
+
+fn parse_toggle(arg: Option<&str>) -> anyhow::Result<bool> {
+    match arg {
+        Some("on") => Ok(true),
+        Some("off") => Ok(false),
+        _ => Err(anyhow::anyhow!("Expected on or off")),
+    }
+}
+
+fn toggle_str(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn parse_and_execute(executor: &mut Executor, line_str: &str) -> String {
+    match executor.eval(line_str) {
+        Ok(message) => message,
+        Err(err) => match err.downcast_ref::<SourceError>() {
+            Some(source_err) => format!("Error: {}\n{}", err, source_err.underline()),
+            None => format!("Error: {}", err),
+        },
+    }
 }
 
 fn new_editor() -> rustyline::Result<Editor<InputValidator, FileHistory>> {
@@ -107,6 +604,49 @@ mod tests {
         assert_eq!(parse_and_execute(&mut executor, "(i32.const 1)"), "[42, 1]");
     }
 
+    #[test]
+    fn test_binary_literals() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(&mut executor, "(i32.const 0b1111)");
+        assert_eq!(resp, "[15]");
+        let resp = parse_and_execute(&mut executor, "(i64.const -0b1010_1010) (i32.const 0x10)");
+        assert_eq!(resp, "[15, -170, 16]");
+        let resp = parse_and_execute(&mut executor, "(i32.const 0b102)");
+        assert_eq!(&resp[..7], "Error: ");
+    }
+
+    #[test]
+    fn test_unsupported_instruction_offset() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(&mut executor, "(i32.const 1) (i32.atomic.load)");
+        assert_eq!(
+            resp,
+            "Error: Unsupported instruction: I32AtomicLoad at offset 15\n\
+             (i32.const 1) (i32.atomic.load)\n\
+             \x20              ^^^^^^^^^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn test_binary_literal_error_offset() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(&mut executor, "(i32.const 0b1) (i32.atomic.load)");
+        assert_eq!(
+            resp,
+            "Error: Unsupported instruction: I32AtomicLoad at offset 17\n\
+             (i32.const 0b1) (i32.atomic.load)\n\
+             \x20                ^^^^^^^^^^^^^^^"
+        );
+
+        let resp = parse_and_execute(&mut executor, "(i32.const 0b1) (i32.foo)");
+        assert_eq!(
+            resp,
+            "Error: unknown operator or unexpected token\n     \
+             --> <anon>:1:18\n      |\n    1 | (i32.const 0b1) (i32.foo)\n      \
+             |                  ^"
+        );
+    }
+
     #[test]
     fn test_parse_error() {
         let mut executor = Executor::new();
@@ -114,6 +654,19 @@ mod tests {
         assert_eq!(&resp[..7], "Error: ");
     }
 
+    #[test]
+    fn test_const_literal_error() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(&mut executor, "(i32.const 3.14)");
+        assert_eq!(resp, "Error: i32.const expects an integer, found 3.14");
+        let resp = parse_and_execute(&mut executor, "(i64.const true)");
+        assert_eq!(resp, "Error: i64.const expects an integer, found true");
+        let resp = parse_and_execute(&mut executor, "(f32.const true)");
+        assert_eq!(resp, "Error: f32.const expects a float, found true");
+        let resp = parse_and_execute(&mut executor, "i32.const 1 f64.const");
+        assert_eq!(resp, "Error: f64.const expects a float, found nothing");
+    }
+
     #[test]
     fn test_convert_error() {
         let mut executor = Executor::new();
@@ -139,4 +692,773 @@ mod tests {
         let resp = parse_and_execute(&mut executor, "(call $sq (i32.const 12))");
         assert_eq!(resp, "[144]");
     }
+
+    #[test]
+    fn test_warn_residue_command() {
+        let mut executor = Executor::new();
+        assert_eq!(
+            handle_command(&mut executor, ":warn-residue on").unwrap(),
+            "warn-residue on"
+        );
+        let resp = parse_and_execute(&mut executor, "(i32.const 1) (i32.const 2)");
+        assert_eq!(resp, "[1, 2]\nnote: 2 values now on the stack");
+
+        handle_command(&mut executor, ":warn-residue off").unwrap();
+        let resp = parse_and_execute(&mut executor, "(i32.const 3) (i32.const 4)");
+        assert_eq!(resp, "[1, 2, 3, 4]");
+    }
+
+    #[test]
+    fn test_quiet_command() {
+        let mut executor = Executor::new();
+        let line = "(local $x i32) (local.set $x (i32.const 5)) (local.get $x)";
+        let resp = parse_and_execute(&mut executor, line);
+        assert_eq!(resp, "local ;0; x\n[5]");
+
+        assert_eq!(
+            handle_command(&mut executor, ":quiet on").unwrap(),
+            "quiet on"
+        );
+        let line = "(local $y i64) (local.get $y)";
+        assert_eq!(parse_and_execute(&mut executor, line), "[5, 0]");
+        let resp = parse_and_execute(&mut executor, "(func $f)");
+        assert_eq!(resp, "");
+
+        handle_command(&mut executor, ":quiet off").unwrap();
+        let resp = parse_and_execute(&mut executor, "(func $g)");
+        assert_eq!(resp, "func ;1; g");
+    }
+
+    #[test]
+    fn test_checked_command() {
+        let mut executor = Executor::new();
+        let line = "(i32.add (i32.const 2147483647) (i32.const 1))";
+        assert_eq!(parse_and_execute(&mut executor, line), "[-2147483648]");
+
+        assert_eq!(
+            handle_command(&mut executor, ":checked on").unwrap(),
+            "checked on"
+        );
+        assert_eq!(
+            parse_and_execute(&mut executor, line),
+            "Error: integer overflow, while executing instruction 3 (i32.add)"
+        );
+
+        handle_command(&mut executor, ":checked off").unwrap();
+        let resp = parse_and_execute(&mut executor, line);
+        assert_eq!(resp, "[-2147483648, -2147483648]");
+    }
+
+    #[test]
+    fn test_global_command() {
+        let mut executor = Executor::new();
+        parse_and_execute(&mut executor, "(global $g i32 (i32.const 7))");
+        parse_and_execute(&mut executor, "(global $m (mut f64) (f64.const 1.5))");
+
+        let resp = handle_command(&mut executor, ":global $g").unwrap();
+        assert_eq!(resp, "7: i32");
+        let resp = handle_command(&mut executor, ":global 1").unwrap();
+        assert_eq!(resp, "1.5: mut f64");
+        let resp = handle_command(&mut executor, ":global $nope").unwrap();
+        assert_eq!(resp, "Error: unknown global $nope");
+    }
+
+    #[test]
+    fn test_setglobal_command() {
+        let mut executor = Executor::new();
+        parse_and_execute(&mut executor, "(global $m (mut i32) (i32.const 0))");
+
+        let resp = handle_command(&mut executor, ":setglobal $m 42").unwrap();
+        assert_eq!(resp, "42: i32");
+        let resp = handle_command(&mut executor, ":global $m").unwrap();
+        assert_eq!(resp, "42: mut i32");
+        assert_eq!(parse_and_execute(&mut executor, "(global.get $m)"), "[42]");
+
+        let resp = handle_command(&mut executor, ":setglobal $m 1.5").unwrap();
+        assert!(resp.starts_with("Error: "));
+        let resp = handle_command(&mut executor, ":setglobal $nope 1").unwrap();
+        assert_eq!(resp, "Error: unknown global $nope");
+        let resp = handle_command(&mut executor, ":setglobal $m").unwrap();
+        assert_eq!(resp, "Error: Expected a global and a value");
+        let resp = handle_command(&mut executor, ":global $m").unwrap();
+        assert_eq!(resp, "42: mut i32");
+    }
+
+    #[test]
+    fn test_setglobal_immutable_error() {
+        let mut executor = Executor::new();
+        parse_and_execute(&mut executor, "(global $g i32 (i32.const 7))");
+
+        let resp = handle_command(&mut executor, ":setglobal $g 8").unwrap();
+        assert_eq!(resp, "Error: global is immutable");
+        let resp = handle_command(&mut executor, ":global $g").unwrap();
+        assert_eq!(resp, "7: i32");
+    }
+
+    #[test]
+    fn test_command_errors() {
+        let mut executor = Executor::new();
+        let resp = handle_command(&mut executor, ":warn-residue maybe").unwrap();
+        assert_eq!(&resp[..7], "Error: ");
+        let resp = handle_command(&mut executor, ":nope").unwrap();
+        assert_eq!(&resp[..7], "Error: ");
+        assert!(handle_command(&mut executor, "(i32.const 1)").is_none());
+    }
+
+    #[test]
+    fn test_ast_command() {
+        let mut executor = Executor::new();
+        let resp = handle_command(
+            &mut executor,
+            ":ast (if (i32.const 1) (then (i32.const 2)) (else (i32.const 3) (drop)))",
+        )
+        .unwrap();
+        assert_eq!(
+            resp,
+            "i32.const 1\nif\n  i32.const 2\nelse\n  i32.const 3\n  drop"
+        );
+        assert_eq!(parse_and_execute(&mut executor, "(i32.const 0)"), "[0]");
+    }
+
+    #[test]
+    fn test_alias() {
+        let mut repl = Repl::new();
+        let resp = repl
+            .eval(":alias sq (func $sq (param i32) (result i32) local.get 0 local.get 0 i32.mul)");
+        assert_eq!(resp, "alias :sq");
+        assert_eq!(repl.eval(":sq"), "func ;0; sq");
+        assert_eq!(repl.eval("(call $sq (i32.const 3))"), "[9]");
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join("wasmrepl_test_load.wat");
+        std::fs::write(
+            &path,
+            "(func $one (result i32) (i32.const 1))\n(func $two (result i32) (i32.const 2))\n",
+        )
+        .unwrap();
+
+        let mut executor = Executor::new();
+        let resp = load(&mut executor, path.to_str().unwrap(), false).unwrap();
+        assert_eq!(resp, "func ;0; one\nfunc ;1; two");
+        let resp = parse_and_execute(&mut executor, "(call $one) (call $two)");
+        assert_eq!(resp, "[1, 2]");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_func_and_call() {
+        let path = std::env::temp_dir().join("wasmrepl_test_load_call.wat");
+        let src = ";; doubles\n(func $double (param i32) (result i32)\n  \
+            (i32.mul (local.get 0) (i32.const 2)))\n\n(call $double (i32.const 21))\n";
+        std::fs::write(&path, src).unwrap();
+
+        let mut executor = Executor::new();
+        let resp = load(&mut executor, path.to_str().unwrap(), false).unwrap();
+        assert_eq!(resp, "func ;0; double\n[42]");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_line_error() {
+        let path = std::env::temp_dir().join("wasmrepl_test_load_line_error.wat");
+        let src = "(i32.const 1)\n(call $missing)\n(i32.const 2)\n";
+        std::fs::write(&path, src).unwrap();
+        let path = path.to_str().unwrap();
+
+        let mut executor = Executor::new();
+        let err = load(&mut executor, path, false).err().unwrap();
+        assert!(err.to_string().starts_with("line 2: "));
+        assert_eq!(parse_and_execute(&mut executor, "(nop)"), "[1]");
+
+        let mut executor = Executor::new();
+        let resp = load(&mut executor, path, true).unwrap();
+        let lines: Vec<&str> = resp.lines().collect();
+        assert_eq!(lines[0], "[1]");
+        assert!(lines[1].starts_with("Error: line 2: "));
+        assert_eq!(lines[2], "[1, 2]");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_save() {
+        let path = std::env::temp_dir().join("wasmrepl_test_save.wat");
+        let path = path.to_str().unwrap();
+
+        let mut executor = Executor::new();
+        parse_and_execute(&mut executor, "(func $one (result i32) (i32.const 1))");
+        let src = "(func $two (result i32) (if (result i32) (i32.const 1) \
+            (then (call $one) (i32.const 1) (i32.add)) (else (i32.const 0))))";
+        parse_and_execute(&mut executor, src);
+        let resp = handle_command(&mut executor, &format!(":save {}", path)).unwrap();
+        assert_eq!(resp, format!("saved 2 funcs to {}", path));
+
+        let mut executor = Executor::new();
+        let resp = handle_command(&mut executor, &format!(":load {}", path)).unwrap();
+        assert_eq!(resp, "func ;0; one\nfunc ;1; two");
+        assert_eq!(parse_and_execute(&mut executor, "(call $two)"), "[2]");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_save_globals_and_ids() {
+        let path = std::env::temp_dir().join("wasmrepl_test_save_globals.wat");
+        let path = path.to_str().unwrap();
+
+        let mut executor = Executor::new();
+        parse_and_execute(&mut executor, "(global $g (mut i32) (i32.const 1))");
+        parse_and_execute(&mut executor, "(func $a (result i32) (global.get $g))");
+        parse_and_execute(&mut executor, "(func $b (result i32) (call $a))");
+        parse_and_execute(&mut executor, "(global.set $g (i32.const 7))");
+        let resp = handle_command(&mut executor, &format!(":save {}", path)).unwrap();
+        assert_eq!(resp, format!("saved 1 globals and 2 funcs to {}", path));
+
+        // Loaded after other funcs, $b still calls $a.
+        handle_command(&mut executor, ":reset").unwrap();
+        parse_and_execute(&mut executor, "(func $x (result i32) (i32.const 0))");
+        let resp = handle_command(&mut executor, &format!(":load {}", path)).unwrap();
+        assert_eq!(resp, "global ;0; g\nfunc ;1; a\nfunc ;2; b");
+        assert_eq!(parse_and_execute(&mut executor, "(call $b)"), "[7]");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_save_error() {
+        let mut executor = Executor::new();
+        let resp = handle_command(&mut executor, ":save /nonexistent/defs.wat").unwrap();
+        assert_eq!(&resp[..7], "Error: ");
+    }
+
+    #[test]
+    fn test_load_wasm() {
+        let src = "(module (func (export \"add\") (param i32 i32) (result i32) \
+            local.get 0 local.get 1 i32.add))";
+        let buf = wast::parser::ParseBuffer::new(src).unwrap();
+        let mut wat = wast::parser::parse::<wast::Wat>(&buf).unwrap();
+        let path = std::env::temp_dir().join("wasmrepl_test_load_wasm.wasm");
+        std::fs::write(&path, wat.encode().unwrap()).unwrap();
+
+        let mut executor = Executor::new();
+        let command = format!(":load-wasm {}", path.to_str().unwrap());
+        let resp = handle_command(&mut executor, &command).unwrap();
+        assert_eq!(resp, "func ;0; add");
+        let resp = parse_and_execute(&mut executor, "(call $add (i32.const 1) (i32.const 2))");
+        assert_eq!(resp, "[3]");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_wasm_error() {
+        let mut executor = Executor::new();
+        let resp = handle_command(&mut executor, ":load-wasm /nonexistent/defs.wasm").unwrap();
+        assert_eq!(&resp[..7], "Error: ");
+    }
+
+    #[test]
+    fn test_load_error() {
+        let mut executor = Executor::new();
+        let resp = handle_command(&mut executor, ":load /nonexistent/defs.wat").unwrap();
+        assert_eq!(&resp[..7], "Error: ");
+    }
+
+    #[test]
+    fn test_history() {
+        let path = std::env::temp_dir().join("wasmrepl_test_history");
+        let _ = std::fs::remove_file(&path);
+
+        let mut rl = new_editor().unwrap();
+        load_history(&mut rl, &path).unwrap();
+        assert_eq!(rl.history().iter().count(), 0);
+        rl.add_history_entry("(i32.const 1)").unwrap();
+        rl.add_history_entry(":stack").unwrap();
+        rl.save_history(&path).unwrap();
+
+        let mut rl = new_editor().unwrap();
+        load_history(&mut rl, &path).unwrap();
+        let entries: Vec<&String> = rl.history().iter().collect();
+        assert_eq!(entries, vec!["(i32.const 1)", ":stack"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_is_quit() {
+        assert!(is_quit(":quit"));
+        assert!(is_quit(" :exit "));
+        assert!(!is_quit(":quit now"));
+        assert!(!is_quit("(i32.const 1)"));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = ["--load", "defs.wat", "--keep-going", "--quiet"].map(String::from);
+        let options = parse_args(args.into_iter()).unwrap();
+        assert_eq!(options.load.as_deref(), Some("defs.wat"));
+        assert!(options.keep_going);
+        assert!(options.quiet);
+
+        let options = parse_args(std::iter::empty()).unwrap();
+        assert!(options.load.is_none());
+        assert!(!options.keep_going);
+        assert!(!options.quiet);
+
+        let options = parse_args(["defs.wat".to_string()].into_iter()).unwrap();
+        assert_eq!(options.load.as_deref(), Some("defs.wat"));
+
+        assert!(parse_args(["--load".to_string()].into_iter()).is_err());
+        assert!(parse_args(["--nope".to_string()].into_iter()).is_err());
+        assert!(parse_args(["a.wat", "b.wat"].map(String::from).into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_module() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(
+            &mut executor,
+            "(module\n  (func $one (result i32) (i32.const 1))\n  (func $two (result i32) (i32.const 2)))",
+        );
+        assert_eq!(resp, "func ;0; one\nfunc ;1; two");
+        let resp = parse_and_execute(&mut executor, "(call $one) (call $two)");
+        assert_eq!(resp, "[1, 2]");
+    }
+
+    #[test]
+    fn test_pop_command() {
+        let mut executor = Executor::new();
+        parse_and_execute(&mut executor, "(i32.const 1) (f64.const 2.5)");
+        assert_eq!(handle_command(&mut executor, ":pop").unwrap(), "2.5: f64");
+        assert_eq!(parse_and_execute(&mut executor, "(nop)"), "[1]");
+        assert_eq!(handle_command(&mut executor, ":pop").unwrap(), "1: i32");
+        assert_eq!(
+            handle_command(&mut executor, ":pop").unwrap(),
+            "Error: Stack is empty"
+        );
+    }
+
+    #[test]
+    fn test_peek_command() {
+        let mut executor = Executor::new();
+        assert_eq!(handle_command(&mut executor, ":peek").unwrap(), "empty");
+        parse_and_execute(&mut executor, "(i32.const 1) (f64.const 2.5)");
+        assert_eq!(handle_command(&mut executor, ":peek").unwrap(), "2.5: f64");
+        assert_eq!(parse_and_execute(&mut executor, "(nop)"), "[1, 2.5]");
+    }
+
+    #[test]
+    fn test_stack_command() {
+        let mut executor = Executor::new();
+        assert_eq!(handle_command(&mut executor, ":stack").unwrap(), "[]");
+        parse_and_execute(&mut executor, "(i32.const 1)");
+        parse_and_execute(&mut executor, "(i32.const 2)");
+        parse_and_execute(&mut executor, "(i64.const 3) (i32.const 0) (i32.div_s)");
+        let resp = handle_command(&mut executor, ":stack").unwrap();
+        assert_eq!(resp, "[1: i32, 2: i32]");
+    }
+
+    #[test]
+    fn test_hex_dec_commands() {
+        let mut executor = Executor::new();
+        assert_eq!(handle_command(&mut executor, ":hex").unwrap(), "hex");
+        let resp = parse_and_execute(&mut executor, "(i32.const 0x40000000) (i64.const -1)");
+        assert_eq!(resp, "[0x40000000, 0xffffffffffffffff]");
+        let resp = parse_and_execute(&mut executor, "(f32.const 1.5)");
+        assert_eq!(resp, "[0x40000000, 0xffffffffffffffff, 1.5]");
+        let resp = handle_command(&mut executor, ":stack").unwrap();
+        assert_eq!(resp, "[0x40000000: i32, 0xffffffffffffffff: i64, 1.5: f32]");
+
+        assert_eq!(handle_command(&mut executor, ":dec").unwrap(), "dec");
+        let resp = parse_and_execute(&mut executor, "(nop)");
+        assert_eq!(resp, "[1073741824, -1, 1.5]");
+    }
+
+    #[test]
+    fn test_depth_command() {
+        let mut executor = Executor::new();
+        let func = "(func $down (param i32) (if (local.get 0) (then \
+            (call $down (i32.sub (local.get 0) (i32.const 1))))))";
+        parse_and_execute(&mut executor, func);
+        let resp = parse_and_execute(&mut executor, "(call $down (i32.const 50))");
+        assert_eq!(resp, "[]");
+
+        let resp = handle_command(&mut executor, ":depth 20").unwrap();
+        assert_eq!(resp, "depth 20");
+        let resp = parse_and_execute(&mut executor, "(call $down (i32.const 50))");
+        assert!(resp.contains("Stack overflow"));
+
+        let resp = handle_command(&mut executor, ":depth deep").unwrap();
+        assert_eq!(resp, "Error: Expected a number");
+    }
+
+    #[test]
+    fn test_types_command() {
+        let mut executor = Executor::new();
+        parse_and_execute(&mut executor, "(i32.const 42) (i64.const 1)");
+        assert_eq!(parse_and_execute(&mut executor, "(nop)"), "[42, 1]");
+
+        let resp = handle_command(&mut executor, ":types on").unwrap();
+        assert_eq!(resp, "types on");
+        let resp = parse_and_execute(&mut executor, "(f32.const 1.5)");
+        assert_eq!(resp, "[i32:42, i64:1, f32:1.5]");
+
+        let resp = handle_command(&mut executor, ":types off").unwrap();
+        assert_eq!(resp, "types off");
+        assert_eq!(parse_and_execute(&mut executor, "(nop)"), "[42, 1, 1.5]");
+
+        let resp = handle_command(&mut executor, ":types").unwrap();
+        assert_eq!(resp, "Error: Expected on or off");
+    }
+
+    #[test]
+    fn test_step_command() {
+        let mut executor = Executor::new();
+        let resp = handle_command(&mut executor, ":step (i32.const 1) (i32.const 2) (i32.add)");
+        assert_eq!(
+            resp.unwrap(),
+            "i32.const: [1]\ni32.const: [1, 2]\ni32.add: [3]"
+        );
+        assert_eq!(parse_and_execute(&mut executor, "(nop)"), "[3]");
+
+        let resp = handle_command(&mut executor, ":step (i32.const 0) (i32.div_s)");
+        assert_eq!(&resp.unwrap()[..7], "Error: ");
+        assert_eq!(parse_and_execute(&mut executor, "(nop)"), "[3]");
+    }
+
+    #[test]
+    fn test_clear_command() {
+        let mut executor = Executor::new();
+        parse_and_execute(&mut executor, "(func $f (result i32) (i32.const 2))");
+        parse_and_execute(&mut executor, "(local $l i32) (i32.const 1) (i64.const 2)");
+        assert_eq!(handle_command(&mut executor, ":clear").unwrap(), "[]");
+        assert_eq!(parse_and_execute(&mut executor, "(nop)"), "[]");
+
+        assert_eq!(parse_and_execute(&mut executor, "(call $f)"), "[2]");
+        assert_eq!(parse_and_execute(&mut executor, "(local.get $l)"), "[2, 0]");
+    }
+
+    #[test]
+    fn test_redefine_func() {
+        let mut executor = Executor::new();
+        parse_and_execute(&mut executor, "(func $f (result i32) (i32.const 1))");
+        let resp = parse_and_execute(&mut executor, "(func $f (result i64) (i64.const 2))");
+        assert_eq!(resp, "func ;0; f");
+        assert_eq!(parse_and_execute(&mut executor, "(call $f)"), "[2]");
+        let resp = handle_command(&mut executor, ":funcs").unwrap();
+        assert_eq!(resp, "func ;0; f (result i64)");
+    }
+
+    #[test]
+    fn test_reset_command() {
+        let mut executor = Executor::new();
+        parse_and_execute(&mut executor, "(i32.const 1)");
+        parse_and_execute(&mut executor, "(func $f (result i32) (i32.const 2))");
+        parse_and_execute(&mut executor, "(global $g i32 (i32.const 3))");
+        assert_eq!(handle_command(&mut executor, ":reset").unwrap(), "reset");
+
+        assert_eq!(parse_and_execute(&mut executor, "(nop)"), "[]");
+        let resp = parse_and_execute(&mut executor, "(call $f)");
+        assert_eq!(&resp[..7], "Error: ");
+        let resp = parse_and_execute(&mut executor, "(func $f (result i32) (i32.const 2))");
+        assert_eq!(resp, "func ;0; f");
+        let resp = parse_and_execute(&mut executor, "(global $g i32 (i32.const 3))");
+        assert_eq!(resp, "global ;0; g");
+    }
+
+    #[test]
+    fn test_reset_keeps_settings() {
+        let mut executor = Executor::new();
+        handle_command(&mut executor, ":quiet on").unwrap();
+        handle_command(&mut executor, ":checked on").unwrap();
+        handle_command(&mut executor, ":hex").unwrap();
+        assert_eq!(handle_command(&mut executor, ":reset").unwrap(), "reset");
+
+        assert_eq!(parse_and_execute(&mut executor, "(func $r)"), "");
+        let line = "(i32.add (i32.const 2147483647) (i32.const 1))";
+        let resp = parse_and_execute(&mut executor, line);
+        assert_eq!(&resp[..23], "Error: integer overflow");
+        let resp = parse_and_execute(&mut executor, "(i32.const 255)");
+        assert_eq!(resp, "[0xff]");
+    }
+
+    #[test]
+    fn test_funcs_command() {
+        let mut executor = Executor::new();
+        assert_eq!(handle_command(&mut executor, ":funcs").unwrap(), "");
+
+        let line = "(func $add (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))";
+        parse_and_execute(&mut executor, line);
+        let resp = handle_command(&mut executor, ":funcs").unwrap();
+        assert_eq!(resp, "func ;0; add (param i32 i32) (result i32)");
+
+        parse_and_execute(&mut executor, "(func (nop))");
+        parse_and_execute(&mut executor, "(func (result f64) (f64.const 1))");
+        assert_eq!(
+            handle_command(&mut executor, ":funcs").unwrap(),
+            "func ;0; add (param i32 i32) (result i32)\nfunc ;1;\nfunc ;2; (result f64)"
+        );
+    }
+
+    #[test]
+    fn test_locals_command() {
+        let mut executor = Executor::new();
+        assert_eq!(handle_command(&mut executor, ":locals").unwrap(), "");
+
+        let line = "(local $x i32) (local i64) (local $y f32) (local.set $x (i32.const 5))";
+        parse_and_execute(&mut executor, line);
+        assert_eq!(
+            handle_command(&mut executor, ":locals").unwrap(),
+            "local ;0; x i32 = 5\nlocal ;1; i64 = 0\nlocal ;2; y f32 = 0"
+        );
+
+        // A line which traps leaves the locals as they were.
+        let line = "(local $z i32) (local.set $x (i32.const 6)) \
+            (drop (i32.div_s (i32.const 1) (i32.const 0)))";
+        let resp = parse_and_execute(&mut executor, line);
+        assert!(resp.starts_with("Error: integer divide by zero"));
+        let resp = handle_command(&mut executor, ":locals").unwrap();
+        assert_eq!(
+            resp,
+            "local ;0; x i32 = 5\nlocal ;1; i64 = 0\nlocal ;2; y f32 = 0"
+        );
+    }
+
+    #[test]
+    fn test_graph_command() {
+        let mut executor = Executor::new();
+        parse_and_execute(&mut executor, "(func $b (result i32) (i32.const 1))");
+        parse_and_execute(&mut executor, "(func $a (result i32) (call $b))");
+        assert_eq!(
+            handle_command(&mut executor, ":graph").unwrap(),
+            "$b\n$a -> $b"
+        );
+    }
+
+    #[test]
+    fn test_canon() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.eval(":canon on"), "canon on");
+        assert_eq!(
+            repl.eval("(i32.add (i32.const 1) (i32.const 2))"),
+            "i32.const 1\ni32.const 2\ni32.add\n[3]"
+        );
+        assert_eq!(
+            repl.eval("(block (i32.const 5) (drop))"),
+            "block\n  i32.const 5\n  drop\nend\n[3]"
+        );
+        assert_eq!(&repl.eval("(i32.const 1")[..7], "Error: ");
+
+        assert_eq!(repl.eval(":canon off"), "canon off");
+        assert_eq!(repl.eval("(i32.const 4)"), "[3, 4]");
+        assert_eq!(&repl.eval(":canon maybe")[..7], "Error: ");
+    }
+
+    #[test]
+    fn test_alias_redefine() {
+        let mut repl = Repl::new();
+        repl.eval(":alias one (i32.const 1)");
+        repl.eval(":alias one (i32.const 11)");
+        assert_eq!(repl.eval(":one"), "[11]");
+    }
+
+    #[test]
+    fn test_alias_error() {
+        let mut repl = Repl::new();
+        assert_eq!(&repl.eval(":alias one")[..7], "Error: ");
+        assert_eq!(&repl.eval(":one")[..7], "Error: ");
+    }
+
+    #[test]
+    fn test_assert_trap() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(
+            &mut executor,
+            "(assert_trap (i32.rem_u (i32.const 1) (i32.const 0)) \"divide by zero\")",
+        );
+        assert_eq!(
+            resp,
+            "trap: integer divide by zero, while executing instruction 3 (i32.rem_u)"
+        );
+        let resp = parse_and_execute(&mut executor, "(assert_trap (i32.const 1))");
+        assert_eq!(resp, "Error: Expected a trap");
+    }
+
+    #[test]
+    fn test_folded_comparison() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(&mut executor, "(i32.lt_s (i32.const 1) (i32.const 2))");
+        assert_eq!(resp, "[1]");
+        let resp = parse_and_execute(&mut executor, "(i32.gt_u (i32.const -1) (i32.const 2))");
+        assert_eq!(resp, "[1, 1]");
+        let resp = parse_and_execute(&mut executor, "(i32.ge_s (i32.const -1) (i32.const 2))");
+        assert_eq!(resp, "[1, 1, 0]");
+    }
+
+    #[test]
+    fn test_folded_eqz() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(&mut executor, "(i32.eqz (i32.const 0))");
+        assert_eq!(resp, "[1]");
+        let resp = parse_and_execute(&mut executor, "(i32.eqz (i32.const 7))");
+        assert_eq!(resp, "[1, 0]");
+    }
+
+    #[test]
+    fn test_folded_i64_comparison() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(&mut executor, "(i64.lt_s (i64.const 3) (i64.const 2))");
+        assert_eq!(resp, "[0]");
+        let resp = parse_and_execute(&mut executor, "(i64.eqz (i64.const 0))");
+        assert_eq!(resp, "[0, 1]");
+    }
+
+    #[test]
+    fn test_folded_operand_order() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(&mut executor, "(i32.sub (i32.const 10) (i32.const 3))");
+        assert_eq!(resp, "[7]");
+        let resp = parse_and_execute(&mut executor, "(i64.div_u (i64.const 20) (i64.const 4))");
+        assert_eq!(resp, "[7, 5]");
+        let resp = parse_and_execute(&mut executor, "(f32.div (f32.const 1) (f32.const 4))");
+        assert_eq!(resp, "[7, 5, 0.25]");
+    }
+
+    #[test]
+    fn test_nested_folded_operand_order() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(
+            &mut executor,
+            "(i32.div_s
+                (i32.sub (i32.const 100) (i32.mul (i32.const 4) (i32.const 5)))
+                (i32.sub (i32.const 10) (i32.sub (i32.const 8) (i32.const 2))))",
+        );
+        assert_eq!(resp, "[20]");
+        let resp = parse_and_execute(
+            &mut executor,
+            "(i32.sub (i32.const 1) (i32.rem_s (i32.const -7) (i32.const 2)))",
+        );
+        assert_eq!(resp, "[20, 2]");
+
+        let resp = parse_and_execute(
+            &mut executor,
+            "(func $f (param $a i32) (param $b i32) (result i32)
+                (i32.div_u (i32.sub (local.get $a) (local.get $b)) (i32.const 2)))",
+        );
+        assert_eq!(resp, "func ;0; f");
+        let resp = parse_and_execute(&mut executor, "(call $f (i32.const 9) (i32.const 3))");
+        assert_eq!(resp, "[20, 2, 3]");
+
+        // The divisor is only zero when the operands are in order.
+        let resp = parse_and_execute(
+            &mut executor,
+            "(assert_trap
+                (i32.div_s (i32.const 1) (i32.sub (i32.const 2) (i32.const 2)))
+                \"divide by zero\")",
+        );
+        assert_eq!(&resp[..6], "trap: ");
+    }
+
+    #[test]
+    fn test_memory_grow() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(&mut executor, "(memory.grow (i32.const 1))");
+        assert_eq!(resp, "[0]");
+        let resp = parse_and_execute(&mut executor, "(memory.size)");
+        assert_eq!(resp, "[0, 1]");
+    }
+
+    #[test]
+    fn test_global() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(&mut executor, "(global $g (mut i32) (i32.const 1))");
+        assert_eq!(resp, "global ;0; g");
+        let line = "(global.set $g (i32.const 3)) (global.get $g)";
+        assert_eq!(parse_and_execute(&mut executor, line), "[3]");
+        let resp = parse_and_execute(&mut executor, "(global $h i32 (i32.const 1))");
+        assert_eq!(resp, "global ;1; h");
+        let resp = parse_and_execute(&mut executor, "(global.set $h (i32.const 3))");
+        assert!(resp.starts_with("Error: global is immutable"));
+    }
+
+    #[test]
+    fn test_sequence() {
+        let mut executor = Executor::new();
+        let resp = parse_and_execute(&mut executor, "(i32.const 1)(i32.const 2)(i32.add)");
+        assert_eq!(resp, "[3]");
+        let resp = parse_and_execute(&mut executor, "(i32.const 1) (func $f)");
+        assert!(resp.starts_with("Error: func has to be on a line of its own"));
+        assert_eq!(parse_and_execute(&mut executor, "(nop)"), "[3]");
+    }
+
+    #[test]
+    fn test_call_indirect() {
+        let mut executor = Executor::new();
+        let line = "(func $neg (param i32) (result i32) (i32.sub (i32.const 0) (local.get 0)))";
+        parse_and_execute(&mut executor, line);
+        let resp = parse_and_execute(&mut executor, "(table 2 funcref)");
+        assert_eq!(resp, "table ;0;");
+        let resp = parse_and_execute(&mut executor, "(elem (i32.const 1) $neg)");
+        assert_eq!(resp, "elem ;0;");
+        let line = "(call_indirect (param i32) (result i32) (i32.const 5) (i32.const 1))";
+        assert_eq!(parse_and_execute(&mut executor, line), "[-5]");
+        let line = "(call_indirect (result i32) (i32.const 1))";
+        let resp = parse_and_execute(&mut executor, line);
+        assert!(resp.starts_with("Error: indirect call type mismatch"));
+    }
+
+    #[test]
+    fn test_i32_load_store() {
+        let mut executor = Executor::new();
+        let line = "(memory.grow (i32.const 1)) (drop) \
+            (i32.store offset=4 align=2 (i32.const 0) (i32.const 42))";
+        assert_eq!(parse_and_execute(&mut executor, line), "[]");
+        let resp = parse_and_execute(&mut executor, "(i32.load (i32.const 4))");
+        assert_eq!(resp, "[42]");
+    }
+
+    #[test]
+    fn test_br_table() {
+        let mut executor = Executor::new();
+        let line = "(block $outer (result i32) \
+            (block $inner (result i32) (i32.const 5) (i32.const 1) (br_table $inner $outer $inner)) \
+            (i32.const 2) (i32.add))";
+        let resp = parse_and_execute(&mut executor, line);
+        assert_eq!(resp, "[5]");
+    }
+
+    #[test]
+    fn test_comparisons() {
+        // -1 is the largest value when taken as unsigned.
+        let cases = [
+            ("eq", 2, 2, 1),
+            ("eq", 2, 3, 0),
+            ("ne", 2, 3, 1),
+            ("ne", 2, 2, 0),
+            ("lt_s", -1, 1, 1),
+            ("lt_u", -1, 1, 0),
+            ("gt_s", -1, 1, 0),
+            ("gt_u", -1, 1, 1),
+            ("le_s", -1, 1, 1),
+            ("le_s", 1, 1, 1),
+            ("le_u", -1, 1, 0),
+            ("le_u", 1, 1, 1),
+            ("ge_s", -1, 1, 0),
+            ("ge_s", 1, 1, 1),
+            ("ge_u", -1, 1, 1),
+            ("ge_u", 1, 1, 1),
+        ];
+        for ty in ["i32", "i64"] {
+            for (op, a, b, result) in cases {
+                let mut executor = Executor::new();
+                let line = format!("({ty}.{op} ({ty}.const {a}) ({ty}.const {b}))");
+                let resp = parse_and_execute(&mut executor, &line);
+                assert_eq!(resp, format!("[{}]", result), "{}", line);
+            }
+        }
+    }
 }
@@ -0,0 +1,228 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+/// Size of a page of linear memory, in bytes.
+pub const PAGE_SIZE: usize = 65536;
+
+/// Most pages the memory can grow to. Far below what Wasm allows, since
+/// the whole memory is kept in the REPL process.
+pub const MAX_PAGES: u32 = 256;
+
+/// Linear memory, grown in pages, with commit and rollback.
+#[derive(Clone)]
+pub struct Memory {
+    bytes: Vec<u8>,
+    soft_pages: u32,
+    soft_bytes: HashMap<usize, u8>,
+    max: u32,
+}
+
+impl Memory {
+    pub fn new() -> Memory {
+        Memory {
+            bytes: Vec::new(),
+            soft_pages: 0,
+            soft_bytes: HashMap::new(),
+            max: MAX_PAGES,
+        }
+    }
+
+    /// A memory holding the given bytes, padded with zeros to whole
+    /// pages.
+    pub fn from_bytes(mut bytes: Vec<u8>) -> Result<Memory> {
+        let pages = bytes.len().div_ceil(PAGE_SIZE);
+        if pages > MAX_PAGES as usize {
+            return Err(anyhow!("memory size must be at most {} pages", MAX_PAGES));
+        }
+        bytes.resize(pages * PAGE_SIZE, 0);
+        Ok(Memory {
+            bytes,
+            soft_pages: pages as u32,
+            soft_bytes: HashMap::new(),
+            max: MAX_PAGES,
+        })
+    }
+
+    /// Limits how far the memory can grow, in pages.
+    pub fn set_max(&mut self, max: Option<u32>) {
+        self.max = max.unwrap_or(MAX_PAGES).min(MAX_PAGES);
+    }
+
+    /// The committed bytes.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Current size, in pages.
+    pub fn size(&self) -> u32 {
+        self.soft_pages
+    }
+
+    /// Grows the memory by `delta` pages, returning the previous size.
+    /// Returns `None`, leaving the size as is, if it would go beyond its
+    /// maximum, or `MAX_PAGES`.
+    pub fn grow(&mut self, delta: u32) -> Option<u32> {
+        let pages = self.soft_pages;
+        self.soft_pages = pages
+            .checked_add(delta)
+            .filter(|pages| *pages <= self.max)?;
+        Some(pages)
+    }
+
+    /// Reads `N` bytes starting at `addr`.
+    pub fn load<const N: usize>(&self, addr: u64) -> Result<[u8; N]> {
+        let start = self.check_bounds(addr, N)?;
+        let mut bytes = [0; N];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let addr = start + i;
+            *byte = match self.soft_bytes.get(&addr) {
+                Some(byte) => *byte,
+                // Pages grown since the last commit are not allocated yet.
+                None => self.bytes.get(addr).copied().unwrap_or(0),
+            };
+        }
+        Ok(bytes)
+    }
+
+    /// Writes `bytes` starting at `addr`.
+    pub fn store(&mut self, addr: u64, bytes: &[u8]) -> Result<()> {
+        let start = self.check_bounds(addr, bytes.len())?;
+        for (i, byte) in bytes.iter().enumerate() {
+            self.soft_bytes.insert(start + i, *byte);
+        }
+        Ok(())
+    }
+
+    fn check_bounds(&self, addr: u64, len: usize) -> Result<usize> {
+        let size = self.soft_pages as u64 * PAGE_SIZE as u64;
+        match addr.checked_add(len as u64) {
+            Some(end) if end <= size => Ok(addr as usize),
+            _ => Err(anyhow!("out of bounds memory access")),
+        }
+    }
+
+    pub fn commit(&mut self) {
+        self.bytes.resize(self.soft_pages as usize * PAGE_SIZE, 0);
+        for (addr, byte) in self.soft_bytes.drain() {
+            self.bytes[addr] = byte;
+        }
+    }
+
+    pub fn rollback(&mut self) {
+        self.soft_pages = (self.bytes.len() / PAGE_SIZE) as u32;
+        self.soft_bytes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::{Memory, MAX_PAGES, PAGE_SIZE};
+
+    #[test]
+    fn test_memory_grow() {
+        let mut memory = Memory::new();
+        assert_eq!(memory.size(), 0);
+        assert_eq!(memory.grow(1), Some(0));
+        assert_eq!(memory.grow(2), Some(1));
+        assert_eq!(memory.size(), 3);
+    }
+
+    #[test]
+    fn test_memory_grow_error() {
+        let mut memory = Memory::new();
+        assert_eq!(memory.grow(MAX_PAGES), Some(0));
+        assert_eq!(memory.grow(1), None);
+        assert_eq!(memory.grow(u32::MAX), None);
+        assert_eq!(memory.size(), MAX_PAGES);
+    }
+
+    #[test]
+    fn test_memory_grow_zero() {
+        let mut memory = Memory::new();
+        assert_eq!(memory.grow(0), Some(0));
+        memory.grow(1);
+        memory.commit();
+        assert_eq!(memory.grow(0), Some(1));
+        assert_eq!(memory.size(), 1);
+        memory.commit();
+        assert_eq!(memory.bytes().len(), PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_memory_grow_to_max_pages() {
+        let mut memory = Memory::new();
+        memory.grow(1);
+        assert_eq!(memory.grow(MAX_PAGES - 1), Some(1));
+        assert_eq!(memory.size(), MAX_PAGES);
+        assert_eq!(memory.grow(1), None);
+        assert_eq!(memory.grow(0), Some(MAX_PAGES));
+        assert_eq!(memory.size(), MAX_PAGES);
+    }
+
+    #[test]
+    fn test_memory_grow_max() {
+        let mut memory = Memory::new();
+        memory.set_max(Some(2));
+        assert_eq!(memory.grow(2), Some(0));
+        assert_eq!(memory.grow(1), None);
+    }
+
+    #[test]
+    fn test_memory_from_bytes() {
+        let mut memory = Memory::from_bytes(vec![1, 2, 3]).unwrap();
+        assert_eq!(memory.size(), 1);
+        assert_eq!(memory.load::<4>(0).unwrap(), [1, 2, 3, 0]);
+        memory.store(0, &[4]).unwrap();
+        memory.commit();
+        assert_eq!(&memory.bytes()[..4], [4, 2, 3, 0]);
+        assert_eq!(memory.bytes().len(), PAGE_SIZE);
+
+        assert!(Memory::from_bytes(vec![0; PAGE_SIZE * MAX_PAGES as usize + 1]).is_err());
+    }
+
+    #[test]
+    fn test_memory_rollback() {
+        let mut memory = Memory::new();
+        memory.grow(1);
+        memory.commit();
+        memory.grow(1);
+        assert_eq!(memory.size(), 2);
+        memory.rollback();
+        assert_eq!(memory.size(), 1);
+    }
+
+    #[test]
+    fn test_memory_load_store() {
+        let mut memory = Memory::new();
+        memory.grow(1);
+        assert_eq!(memory.load::<2>(0).unwrap(), [0, 0]);
+        memory.store(1, &[1, 2]).unwrap();
+        assert_eq!(memory.load::<4>(0).unwrap(), [0, 1, 2, 0]);
+
+        memory.commit();
+        assert_eq!(memory.load::<4>(0).unwrap(), [0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn test_memory_load_store_error() {
+        let mut memory = Memory::new();
+        assert!(memory.load::<1>(0).is_err());
+        memory.grow(1);
+        let end = PAGE_SIZE as u64;
+        assert!(memory.store(end - 3, &[0; 4]).is_err());
+        assert!(memory.load::<4>(end - 3).is_err());
+        assert!(memory.load::<4>(u64::MAX).is_err());
+        memory.store(end - 4, &[0; 4]).unwrap();
+    }
+
+    #[test]
+    fn test_memory_store_rollback() {
+        let mut memory = Memory::new();
+        memory.grow(1);
+        memory.store(0, &[1]).unwrap();
+        memory.commit();
+        memory.store(0, &[2]).unwrap();
+        memory.rollback();
+        assert_eq!(memory.load::<1>(0).unwrap(), [1]);
+    }
+}
@@ -5,23 +5,34 @@
 //
 use wast::{
     core::{
-        BlockType as WastBlockType, Expression as WastExpression, Func as WastFunc, FuncKind,
-        FunctionType, Instruction as WastInstruction, Local as WastLocal, TypeUse,
-        ValType as WastValType,
+        BlockType as WastBlockType, Data as WastData, DataKind, Elem as WastElem, ElemKind,
+        ElemPayload, Expression as WastExpression, Func as WastFunc, FuncKind, FunctionType,
+        Global as WastGlobal, GlobalKind, HeapType, Instruction as WastInstruction,
+        Local as WastLocal, MemArg as WastMemArg, Memory as WastMemory, MemoryKind, MemoryType,
+        RefType, Table as WastTable, TableKind, TypeUse, ValType as WastValType,
     },
     token::{Id, Index as WastIndex},
 };
 
 use anyhow::{Error, Result};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
 
 use crate::{
-    group::group_expr,
+    group::{group_expr, UnterminatedBlock},
     parser::{Line as WastLine, LineExpression as WastLineExpression},
 };
 
 pub enum Line {
     Expression(LineExpression),
     Func(Func),
+    Global(Global),
+    Table(Table),
+    Memory(Memory),
+    Elem(Elem),
+    Data(Data),
+    AssertTrap(Expression, Option<String>),
+    Module(Module),
 }
 
 impl TryFrom<&WastLine<'_>> for Line {
@@ -30,6 +41,16 @@ impl TryFrom<&WastLine<'_>> for Line {
         match line {
             WastLine::Expression(line_expr) => Ok(Line::Expression(line_expr.try_into()?)),
             WastLine::Func(func) => Ok(Line::Func(func.try_into()?)),
+            WastLine::Global(global) => Ok(Line::Global(global.try_into()?)),
+            WastLine::Table(table) => Ok(Line::Table(table.try_into()?)),
+            WastLine::Memory(memory) => Ok(Line::Memory(memory.try_into()?)),
+            WastLine::Elem(elem) => Ok(Line::Elem(elem.try_into()?)),
+            WastLine::Data(data) => Ok(Line::Data(data.try_into()?)),
+            WastLine::AssertTrap(assert) => Ok(Line::AssertTrap(
+                (&assert.expr).try_into()?,
+                assert.message.map(String::from),
+            )),
+            WastLine::Module(fields) => Ok(Line::Module(fields.as_slice().try_into()?)),
         }
     }
 }
@@ -39,6 +60,9 @@ pub struct Func {
     pub id: Option<String>,
     pub ty: FuncType,
     pub line_expression: LineExpression,
+    /// Whether a call may re-enter this function, worked out when it is
+    /// defined. Only then its body needs to be cloned for a call.
+    pub recursive: bool,
 }
 
 impl TryFrom<&WastFunc<'_>> for Func {
@@ -59,10 +83,15 @@ impl TryFrom<&WastFunc<'_>> for Func {
                     lcls.push(local.try_into()?);
                 }
 
-                LineExpression {
-                    locals: lcls,
-                    expr: expression.try_into()?,
-                }
+                let expr = Expression::try_from(expression).map_err(|err| {
+                    if err.is::<UnterminatedBlock>() {
+                        unterminated_error(&id)
+                    } else {
+                        err
+                    }
+                })?;
+
+                LineExpression { locals: lcls, expr }
             }
             _ => {
                 return Err(Error::msg("Unsupported function kind"));
@@ -73,10 +102,44 @@ impl TryFrom<&WastFunc<'_>> for Func {
             id,
             ty,
             line_expression,
+            recursive: false,
         })
     }
 }
 
+fn unterminated_error(id: &Option<String>) -> Error {
+    match id {
+        Some(id) => Error::msg(format!("function ${} has an unterminated block", id)),
+        None => Error::msg("function has an unterminated block"),
+    }
+}
+
+impl Func {
+    /// Rewrites local ids, and the function ids `func_index` knows of, into
+    /// numeric indices so that they are not looked up on every execution.
+    pub fn resolve_ids(&mut self, func_index: impl Fn(&str) -> Option<usize>) {
+        let mut local_ids = HashMap::new();
+        let params = self.ty.params.iter();
+        let locals = self.line_expression.locals.iter();
+        for (i, local) in params.chain(locals).enumerate() {
+            if let Some(id) = &local.id {
+                local_ids.insert(id.clone(), i);
+            }
+        }
+
+        self.line_expression
+            .expr
+            .resolve_ids(&|id| local_ids.get(id).copied(), &func_index);
+    }
+
+    /// Rewrites the function indices `func_id` has an id for back into
+    /// that id, so that the function calls the same functions wherever it
+    /// is defined again.
+    pub fn name_func_ids(&mut self, func_id: impl Fn(u32) -> Option<String>) {
+        self.line_expression.expr.name_func_ids(&func_id);
+    }
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub struct FuncType {
     pub params: Vec<Local>,
@@ -115,7 +178,217 @@ impl TryFrom<&TypeUse<'_, FunctionType<'_>>> for FuncType {
     }
 }
 
-#[derive(Clone)]
+/// Renders the type as in the text format, like `(param i32) (result i32)`.
+impl Display for FuncType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut groups = Vec::new();
+        if !self.params.is_empty() {
+            let params: Vec<String> = self.params.iter().map(|p| p.val_type.to_string()).collect();
+            groups.push(format!("(param {})", params.join(" ")));
+        }
+        if !self.results.is_empty() {
+            let results: Vec<String> = self.results.iter().map(|r| r.to_string()).collect();
+            groups.push(format!("(result {})", results.join(" ")));
+        }
+        write!(f, "{}", groups.join(" "))
+    }
+}
+
+/// The definitions of a `(module ...)`, by kind, which are added all
+/// together or not at all.
+#[derive(Default)]
+pub struct Module {
+    pub funcs: Vec<Func>,
+    pub globals: Vec<Global>,
+    pub tables: Vec<Table>,
+    pub elems: Vec<Elem>,
+    pub datas: Vec<Data>,
+}
+
+impl TryFrom<&[WastLine<'_>]> for Module {
+    type Error = Error;
+    fn try_from(fields: &[WastLine]) -> Result<Self> {
+        let mut module = Module::default();
+        for field in fields {
+            match field {
+                WastLine::Func(func) => module.funcs.push(func.try_into()?),
+                WastLine::Global(global) => module.globals.push(global.try_into()?),
+                WastLine::Table(table) => module.tables.push(table.try_into()?),
+                WastLine::Elem(elem) => module.elems.push(elem.try_into()?),
+                WastLine::Data(data) => module.datas.push(data.try_into()?),
+                _ => return Err(Error::msg("Unsupported module field")),
+            }
+        }
+        Ok(module)
+    }
+}
+
+/// A `(global $id (mut i32) (i32.const 0))` definition, whose init
+/// expression is evaluated once, when it is defined.
+pub struct Global {
+    pub id: Option<String>,
+    pub ty: ValType,
+    pub mutable: bool,
+    pub init: Expression,
+}
+
+impl TryFrom<&WastGlobal<'_>> for Global {
+    type Error = Error;
+    fn try_from(global: &WastGlobal) -> Result<Self> {
+        if !global.exports.names.is_empty() {
+            return Err(Error::msg("Unsupported export"));
+        }
+
+        let init = match &global.kind {
+            GlobalKind::Inline(expression) => expression.try_into()?,
+            _ => return Err(Error::msg("Unsupported global kind")),
+        };
+
+        Ok(Global {
+            id: from_id(global.id),
+            ty: (&global.ty.ty).try_into()?,
+            mutable: global.ty.mutable,
+            init,
+        })
+    }
+}
+
+/// A `(table $id 2 funcref)` definition. Only tables of functions are
+/// supported, with their elements set by `elem` lines.
+pub struct Table {
+    pub id: Option<String>,
+    pub min: u32,
+    pub max: Option<u32>,
+}
+
+impl TryFrom<&WastTable<'_>> for Table {
+    type Error = Error;
+    fn try_from(table: &WastTable) -> Result<Self> {
+        if !table.exports.names.is_empty() {
+            return Err(Error::msg("Unsupported export"));
+        }
+
+        match &table.kind {
+            TableKind::Normal {
+                ty,
+                init_expr: None,
+            } if ty.elem == RefType::func() => Ok(Table {
+                id: from_id(table.id),
+                min: ty.limits.min,
+                max: ty.limits.max,
+            }),
+            _ => Err(Error::msg("Unsupported table kind")),
+        }
+    }
+}
+
+/// A `(memory $id 1 2)` definition of the single memory, sized in pages.
+/// It can be exported by name, or imported from a host memory, see
+/// `Executor::define_host_memory`.
+pub struct Memory {
+    pub id: Option<String>,
+    pub import: Option<(String, String)>,
+    pub exports: Vec<String>,
+    pub min: u32,
+    pub max: Option<u32>,
+}
+
+impl TryFrom<&WastMemory<'_>> for Memory {
+    type Error = Error;
+    fn try_from(memory: &WastMemory) -> Result<Self> {
+        let (import, ty) = match &memory.kind {
+            MemoryKind::Normal(ty) => (None, ty),
+            MemoryKind::Import { import, ty } => {
+                let import = (import.module.to_string(), import.field.to_string());
+                (Some(import), ty)
+            }
+            MemoryKind::Inline { .. } => return Err(Error::msg("Unsupported memory kind")),
+        };
+
+        let exports = memory.exports.names.iter().map(|name| name.to_string());
+        match ty {
+            MemoryType::B32 {
+                limits,
+                shared: false,
+            } => Ok(Memory {
+                id: from_id(memory.id),
+                import,
+                exports: exports.collect(),
+                min: limits.min,
+                max: limits.max,
+            }),
+            _ => Err(Error::msg("Unsupported memory type")),
+        }
+    }
+}
+
+/// An `(elem ...)` segment of functions. An active one, like
+/// `(elem (i32.const 0) $f $g)`, puts them into a table from the given
+/// offset when defined. A passive one, like `(elem $e func $f $g)`, is
+/// copied from by `table.init`.
+pub struct Elem {
+    pub id: Option<String>,
+    pub active: Option<(Index, Expression)>,
+    pub funcs: Vec<Index>,
+}
+
+impl TryFrom<&WastElem<'_>> for Elem {
+    type Error = Error;
+    fn try_from(elem: &WastElem) -> Result<Self> {
+        let active = match &elem.kind {
+            ElemKind::Active { table, offset } => Some((table.try_into()?, offset.try_into()?)),
+            ElemKind::Passive => None,
+            ElemKind::Declared => return Err(Error::msg("Unsupported elem kind")),
+        };
+        let funcs = match &elem.payload {
+            ElemPayload::Indices(funcs) => {
+                funcs.iter().map(Index::try_from).collect::<Result<_>>()?
+            }
+            ElemPayload::Exprs { .. } => return Err(Error::msg("Unsupported elem kind")),
+        };
+
+        Ok(Elem {
+            id: from_id(elem.id),
+            active,
+            funcs,
+        })
+    }
+}
+
+/// A `(data ...)` segment of bytes. An active one, with an offset, is
+/// written into memory when defined. A passive one is copied from by
+/// `memory.init`.
+pub struct Data {
+    pub id: Option<String>,
+    pub offset: Option<Expression>,
+    pub bytes: Vec<u8>,
+}
+
+impl TryFrom<&WastData<'_>> for Data {
+    type Error = Error;
+    fn try_from(data: &WastData) -> Result<Self> {
+        let offset = match &data.kind {
+            DataKind::Passive => None,
+            DataKind::Active {
+                memory: WastIndex::Num(0, _),
+                offset,
+            } => Some(offset.try_into()?),
+            DataKind::Active { .. } => return Err(Error::msg("Unsupported memory")),
+        };
+
+        let mut bytes = Vec::new();
+        for val in data.data.iter() {
+            val.push_onto(&mut bytes);
+        }
+        Ok(Data {
+            id: from_id(data.id),
+            offset,
+            bytes,
+        })
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct LineExpression {
     pub locals: Vec<Local>,
     pub expr: Expression,
@@ -155,6 +428,19 @@ pub enum ValType {
     I64,
     F32,
     F64,
+    FuncRef,
+}
+
+impl Display for ValType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::I32 => write!(f, "i32"),
+            Self::I64 => write!(f, "i64"),
+            Self::F32 => write!(f, "f32"),
+            Self::F64 => write!(f, "f64"),
+            Self::FuncRef => write!(f, "funcref"),
+        }
+    }
 }
 
 impl TryFrom<&WastValType<'_>> for ValType {
@@ -165,12 +451,13 @@ impl TryFrom<&WastValType<'_>> for ValType {
             WastValType::I64 => Ok(ValType::I64),
             WastValType::F32 => Ok(ValType::F32),
             WastValType::F64 => Ok(ValType::F64),
+            WastValType::Ref(ty) if *ty == RefType::func() => Ok(ValType::FuncRef),
             _ => Err(Error::msg("Unsupported value type")),
         }
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Default)]
 pub struct Expression {
     pub instrs: Vec<Instruction>,
 }
@@ -187,6 +474,264 @@ impl TryFrom<&WastExpression<'_>> for Expression {
     }
 }
 
+impl Expression {
+    fn resolve_ids(
+        &mut self,
+        local: &dyn Fn(&str) -> Option<usize>,
+        func: &dyn Fn(&str) -> Option<usize>,
+    ) {
+        for instr in self.instrs.iter_mut() {
+            match instr {
+                Instruction::LocalGet(index)
+                | Instruction::LocalSet(index)
+                | Instruction::LocalTee(index) => index.resolve(local),
+                Instruction::Call(index)
+                | Instruction::ReturnCall(index)
+                | Instruction::RefFunc(index) => index.resolve(func),
+                Instruction::If(_, if_block, else_block) => {
+                    for block in [if_block, else_block].into_iter().flatten() {
+                        block.resolve_ids(local, func);
+                    }
+                }
+                Instruction::Block(_, block) | Instruction::Loop(_, block) => {
+                    if let Some(block) = block {
+                        block.resolve_ids(local, func);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn name_func_ids(&mut self, func: &dyn Fn(u32) -> Option<String>) {
+        for instr in self.instrs.iter_mut() {
+            match instr {
+                Instruction::Call(index)
+                | Instruction::ReturnCall(index)
+                | Instruction::RefFunc(index) => index.name(func),
+                Instruction::If(_, if_block, else_block) => {
+                    for block in [if_block, else_block].into_iter().flatten() {
+                        block.name_func_ids(func);
+                    }
+                }
+                Instruction::Block(_, block) | Instruction::Loop(_, block) => {
+                    if let Some(block) = block {
+                        block.name_func_ids(func);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Indices of all the functions called, including from nested blocks.
+    pub fn calls(&self) -> Vec<&Index> {
+        let mut calls = Vec::new();
+        for instr in self.instrs.iter() {
+            match instr {
+                Instruction::Call(index) | Instruction::ReturnCall(index) => calls.push(index),
+                Instruction::If(_, if_block, else_block) => {
+                    for block in [if_block, else_block].into_iter().flatten() {
+                        calls.extend(block.calls());
+                    }
+                }
+                Instruction::Block(_, Some(block)) | Instruction::Loop(_, Some(block)) => {
+                    calls.extend(block.calls());
+                }
+                _ => {}
+            }
+        }
+        calls
+    }
+
+    /// Like `calls`, but to rewrite the indices in place.
+    pub fn calls_mut(&mut self) -> Vec<&mut Index> {
+        let mut calls = Vec::new();
+        for instr in self.instrs.iter_mut() {
+            match instr {
+                Instruction::Call(index) | Instruction::ReturnCall(index) => calls.push(index),
+                Instruction::If(_, if_block, else_block) => {
+                    for block in [if_block, else_block].into_iter().flatten() {
+                        calls.extend(block.calls_mut());
+                    }
+                }
+                Instruction::Block(_, Some(block)) | Instruction::Loop(_, Some(block)) => {
+                    calls.extend(block.calls_mut());
+                }
+                _ => {}
+            }
+        }
+        calls
+    }
+
+    /// Whether there is a `call_indirect` or `return_call_indirect`,
+    /// including in nested blocks.
+    pub fn calls_indirect(&self) -> bool {
+        self.instrs.iter().any(|instr| match instr {
+            Instruction::CallIndirect(..) | Instruction::ReturnCallIndirect(..) => true,
+            Instruction::If(_, if_block, else_block) => [if_block, else_block]
+                .into_iter()
+                .flatten()
+                .any(|block| block.calls_indirect()),
+            Instruction::Block(_, Some(block)) | Instruction::Loop(_, Some(block)) => {
+                block.calls_indirect()
+            }
+            _ => false,
+        })
+    }
+
+    /// Renders the grouped instruction tree, one instruction per line and
+    /// the bodies of `block`, `loop` and `if` indented.
+    pub fn to_tree_string(&self) -> String {
+        let mut lines = Vec::new();
+        self.tree_lines(0, &mut lines);
+        lines.join("\n")
+    }
+
+    fn tree_lines(&self, depth: usize, lines: &mut Vec<String>) {
+        let indent = "  ".repeat(depth);
+        for instr in self.instrs.iter() {
+            match instr {
+                Instruction::If(bt, if_block, else_block) => {
+                    lines.push(format!("{}if{}", indent, label_str(bt)));
+                    if let Some(block) = if_block {
+                        block.tree_lines(depth + 1, lines);
+                    }
+                    lines.push(format!("{}else", indent));
+                    if let Some(block) = else_block {
+                        block.tree_lines(depth + 1, lines);
+                    }
+                }
+                Instruction::Block(bt, block) | Instruction::Loop(bt, block) => {
+                    let name = match instr {
+                        Instruction::Block(..) => "block",
+                        _ => "loop",
+                    };
+                    lines.push(format!("{}{}{}", indent, name, label_str(bt)));
+                    if let Some(block) = block {
+                        block.tree_lines(depth + 1, lines);
+                    }
+                }
+                instr => lines.push(format!("{}{}", indent, instr.to_wat())),
+            }
+        }
+    }
+
+    /// Compares two expressions structurally. With `ignore_labels`, block
+    /// labels are left out, and branches are compared by the depth of the
+    /// block they target rather than by label.
+    pub fn structural_eq(&self, other: &Expression, ignore_labels: bool) -> bool {
+        if ignore_labels {
+            self.eq_ignoring_labels(other, &mut Vec::new(), &mut Vec::new())
+        } else {
+            self == other
+        }
+    }
+
+    fn eq_ignoring_labels(
+        &self,
+        other: &Expression,
+        labels: &mut Vec<Option<String>>,
+        other_labels: &mut Vec<Option<String>>,
+    ) -> bool {
+        if self.instrs.len() != other.instrs.len() {
+            return false;
+        }
+
+        self.instrs
+            .iter()
+            .zip(other.instrs.iter())
+            .all(|instrs| match instrs {
+                (
+                    Instruction::If(bt, if_block, else_block),
+                    Instruction::If(other_bt, other_if, other_else),
+                ) => {
+                    bt.ty == other_bt.ty
+                        && block_eq(bt, if_block, labels, other_bt, other_if, other_labels)
+                        && block_eq(bt, else_block, labels, other_bt, other_else, other_labels)
+                }
+                (Instruction::Block(bt, block), Instruction::Block(other_bt, other_block))
+                | (Instruction::Loop(bt, block), Instruction::Loop(other_bt, other_block)) => {
+                    bt.ty == other_bt.ty
+                        && block_eq(bt, block, labels, other_bt, other_block, other_labels)
+                }
+                (Instruction::Br(index), Instruction::Br(other_index))
+                | (Instruction::BrIf(index), Instruction::BrIf(other_index)) => {
+                    branch_eq(index, labels, other_index, other_labels)
+                }
+                (
+                    Instruction::BrTable(targets, default),
+                    Instruction::BrTable(other_targets, other_default),
+                ) => {
+                    targets.len() == other_targets.len()
+                        && targets
+                            .iter()
+                            .zip(other_targets.iter())
+                            .all(|(index, other)| branch_eq(index, labels, other, other_labels))
+                        && branch_eq(default, labels, other_default, other_labels)
+                }
+                (instr, other_instr) => instr == other_instr,
+            })
+    }
+}
+
+fn label_str(bt: &BlockType) -> String {
+    match bt.label {
+        Some(ref label) => format!(" ${}", label),
+        None => String::new(),
+    }
+}
+
+fn block_eq(
+    bt: &BlockType,
+    block: &Option<Expression>,
+    labels: &mut Vec<Option<String>>,
+    other_bt: &BlockType,
+    other_block: &Option<Expression>,
+    other_labels: &mut Vec<Option<String>>,
+) -> bool {
+    match (block, other_block) {
+        (Some(block), Some(other_block)) => {
+            labels.push(bt.label.clone());
+            other_labels.push(other_bt.label.clone());
+            let eq = block.eq_ignoring_labels(other_block, labels, other_labels);
+            labels.pop();
+            other_labels.pop();
+            eq
+        }
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+fn branch_eq(
+    index: &Index,
+    labels: &[Option<String>],
+    other_index: &Index,
+    other_labels: &[Option<String>],
+) -> bool {
+    match (
+        label_depth(index, labels),
+        label_depth(other_index, other_labels),
+    ) {
+        (Some(depth), Some(other_depth)) => depth == other_depth,
+        (None, None) => index == other_index,
+        _ => false,
+    }
+}
+
+/// Depth of the block a branch targets, if it can be told from `labels`.
+fn label_depth(index: &Index, labels: &[Option<String>]) -> Option<u32> {
+    match index {
+        Index::Num(num) => Some(*num),
+        Index::Id(id) => labels
+            .iter()
+            .rev()
+            .position(|label| label.as_deref() == Some(id))
+            .map(|depth| depth as u32),
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct BlockType {
     pub label: Option<String>,
@@ -219,14 +764,128 @@ impl TryFrom<&WastIndex<'_>> for Index {
     }
 }
 
+impl Index {
+    fn resolve(&mut self, lookup: &dyn Fn(&str) -> Option<usize>) {
+        if let Index::Id(id) = self {
+            if let Some(num) = lookup(id) {
+                *self = Index::Num(num as u32);
+            }
+        }
+    }
+
+    fn name(&mut self, lookup: &dyn Fn(u32) -> Option<String>) {
+        if let Index::Num(num) = self {
+            if let Some(id) = lookup(*num) {
+                *self = Index::Id(id);
+            }
+        }
+    }
+}
+
+/// Static offset and alignment hint of a memory access. The alignment
+/// does not change what the access does.
+#[derive(PartialEq, Debug, Clone)]
+pub struct MemArg {
+    pub offset: u64,
+    pub align: u32,
+}
+
+impl TryFrom<&WastMemArg<'_>> for MemArg {
+    type Error = Error;
+    fn try_from(arg: &WastMemArg) -> Result<Self> {
+        match arg.memory {
+            WastIndex::Num(0, _) => Ok(MemArg {
+                offset: arg.offset,
+                align: arg.align,
+            }),
+            _ => Err(Error::msg("only memory 0 is supported")),
+        }
+    }
+}
+
 fn from_id(id: Option<Id>) -> Option<String> {
     id.map(|id| id.name().to_string())
 }
 
+/// An instruction the REPL can not run, named as `wast` names it, like
+/// `I32AtomicLoad`.
+#[derive(Debug)]
+pub struct UnsupportedInstruction(pub String);
+
+impl Display for UnsupportedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unsupported instruction: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedInstruction {}
+
+/// An error found at a token in the source of a line.
+#[derive(Debug)]
+pub struct SourceError {
+    pub offset: usize,
+    line: String,
+    column: usize,
+    len: usize,
+    source: Error,
+}
+
+impl SourceError {
+    /// The error at the `len` bytes of `src` starting at `offset`.
+    pub fn new(src: &str, offset: usize, len: usize, source: Error) -> SourceError {
+        let start = src[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let end = src[offset..].find('\n').map_or(src.len(), |i| offset + i);
+        SourceError {
+            offset,
+            line: src[start..end].to_string(),
+            column: src[start..offset].chars().count(),
+            len: src[offset..offset + len].chars().count(),
+            source,
+        }
+    }
+
+    /// The line of source with the token underlined, like
+    ///
+    /// ```text
+    /// (i32.const 1) (i32.atomic.load)
+    ///                ^^^^^^^^^^^^^^^
+    /// ```
+    pub fn underline(&self) -> String {
+        format!(
+            "{}\n{}{}",
+            self.line,
+            " ".repeat(self.column),
+            "^".repeat(self.len.max(1))
+        )
+    }
+}
+
+impl Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.source, self.offset)
+    }
+}
+
+impl std::error::Error for SourceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// The variant name of a wast instruction, like `MemoryAtomicNotify`,
+/// without its arguments.
+fn wast_instr_name(instr: &WastInstruction) -> String {
+    let debug = format!("{:?}", instr);
+    let end = debug
+        .find(|c: char| !c.is_alphanumeric())
+        .unwrap_or(debug.len());
+    debug[..end].to_string()
+}
+
 macro_rules! instrs {
     ({
         $(
-           ($name:ident $(($($arg:tt)*))?, $wast:pat $(, ($capt:tt))?)
+           ($name:ident $(($($arg:tt)*))?, $mnemonic:literal, $wast:pat $(, ($capt:tt))?)
         ),*
     }) => {
         #[derive(PartialEq, Debug, Clone)]
@@ -243,130 +902,206 @@ macro_rules! instrs {
                     $(
                         $wast => Ok(Instruction::$name $($capt)?),
                     )*
-                    _ => Err(Error::msg("Unsupported instruction")),
+                    _ => Err(UnsupportedInstruction(wast_instr_name(instruction)).into()),
                 }
             }
         }
 
+        impl Instruction {
+            /// The text format name of the instruction, like `i32.div_s`.
+            pub fn mnemonic(&self) -> &'static str {
+                match self {
+                    $(
+                        Instruction::$name { .. } => $mnemonic,
+                    )*
+                }
+            }
+        }
     };
 }
 
 instrs! {{
-    (Drop, WastInstruction::Drop),
-    (I32Const(i32), WastInstruction::I32Const(i), ((*i))),
-    (I32Clz, WastInstruction::I32Clz),
-    (I32Ctz, WastInstruction::I32Ctz),
-    (I32Popcnt, WastInstruction::I32Popcnt),
-    (I32Add, WastInstruction::I32Add),
-    (I32Sub, WastInstruction::I32Sub),
-    (I32Mul, WastInstruction::I32Mul),
-    (I32DivS, WastInstruction::I32DivS),
-    (I32DivU, WastInstruction::I32DivU),
-    (I32RemS, WastInstruction::I32RemS),
-    (I32RemU, WastInstruction::I32RemU),
-    (I32And, WastInstruction::I32And),
-    (I32Or, WastInstruction::I32Or),
-    (I32Xor, WastInstruction::I32Xor),
-    (I32Shl, WastInstruction::I32Shl),
-    (I32ShrS, WastInstruction::I32ShrS),
-    (I32ShrU, WastInstruction::I32ShrU),
-    (I32Rotl, WastInstruction::I32Rotl),
-    (I32Rotr, WastInstruction::I32Rotr),
-    (I32Eqz, WastInstruction::I32Eqz),
-    (I32Eq, WastInstruction::I32Eq),
-    (I32Ne, WastInstruction::I32Ne),
-    (I32LtS, WastInstruction::I32LtS),
-    (I32LtU, WastInstruction::I32LtU),
-    (I32GtS, WastInstruction::I32GtS),
-    (I32GtU, WastInstruction::I32GtU),
-    (I32LeS, WastInstruction::I32LeS),
-    (I32LeU, WastInstruction::I32LeU),
-    (I32GeS, WastInstruction::I32GeS),
-    (I32GeU, WastInstruction::I32GeU),
-    (I64Const(i64), WastInstruction::I64Const(i), ((*i))),
-    (I64Clz, WastInstruction::I64Clz),
-    (I64Ctz, WastInstruction::I64Ctz),
-    (I64Popcnt, WastInstruction::I64Popcnt),
-    (I64Add, WastInstruction::I64Add),
-    (I64Sub, WastInstruction::I64Sub),
-    (I64Mul, WastInstruction::I64Mul),
-    (I64DivS, WastInstruction::I64DivS),
-    (I64DivU, WastInstruction::I64DivU),
-    (I64RemS, WastInstruction::I64RemS),
-    (I64RemU, WastInstruction::I64RemU),
-    (I64And, WastInstruction::I64And),
-    (I64Or, WastInstruction::I64Or),
-    (I64Xor, WastInstruction::I64Xor),
-    (I64Shl, WastInstruction::I64Shl),
-    (I64ShrS, WastInstruction::I64ShrS),
-    (I64ShrU, WastInstruction::I64ShrU),
-    (I64Rotl, WastInstruction::I64Rotl),
-    (I64Rotr, WastInstruction::I64Rotr),
-    (I64Eqz, WastInstruction::I64Eqz),
-    (I64Eq, WastInstruction::I64Eq),
-    (I64Ne, WastInstruction::I64Ne),
-    (I64LtS, WastInstruction::I64LtS),
-    (I64LtU, WastInstruction::I64LtU),
-    (I64GtS, WastInstruction::I64GtS),
-    (I64GtU, WastInstruction::I64GtU),
-    (I64LeS, WastInstruction::I64LeS),
-    (I64LeU, WastInstruction::I64LeU),
-    (I64GeS, WastInstruction::I64GeS),
-    (I64GeU, WastInstruction::I64GeU),
-    (F32Const(f32), WastInstruction::F32Const(f), ((f32::from_bits(f.bits)))),
-    (F32Abs, WastInstruction::F32Abs),
-    (F32Neg, WastInstruction::F32Neg),
-    (F32Ceil, WastInstruction::F32Ceil),
-    (F32Floor, WastInstruction::F32Floor),
-    (F32Trunc, WastInstruction::F32Trunc),
-    (F32Nearest, WastInstruction::F32Nearest),
-    (F32Sqrt, WastInstruction::F32Sqrt),
-    (F32Add, WastInstruction::F32Add),
-    (F32Sub, WastInstruction::F32Sub),
-    (F32Mul, WastInstruction::F32Mul),
-    (F32Div, WastInstruction::F32Div),
-    (F32Min, WastInstruction::F32Min),
-    (F32Max, WastInstruction::F32Max),
-    (F32Copysign, WastInstruction::F32Copysign),
-    (F32Eq, WastInstruction::F32Eq),
-    (F32Ne, WastInstruction::F32Ne),
-    (F32Lt, WastInstruction::F32Lt),
-    (F32Gt, WastInstruction::F32Gt),
-    (F32Le, WastInstruction::F32Le),
-    (F32Ge, WastInstruction::F32Ge),
-    (F64Const(f64), WastInstruction::F64Const(f), ((f64::from_bits(f.bits)))),
-    (F64Abs, WastInstruction::F64Abs),
-    (F64Neg, WastInstruction::F64Neg),
-    (F64Ceil, WastInstruction::F64Ceil),
-    (F64Floor, WastInstruction::F64Floor),
-    (F64Trunc, WastInstruction::F64Trunc),
-    (F64Nearest, WastInstruction::F64Nearest),
-    (F64Sqrt, WastInstruction::F64Sqrt),
-    (F64Add, WastInstruction::F64Add),
-    (F64Sub, WastInstruction::F64Sub),
-    (F64Mul, WastInstruction::F64Mul),
-    (F64Div, WastInstruction::F64Div),
-    (F64Min, WastInstruction::F64Min),
-    (F64Max, WastInstruction::F64Max),
-    (F64Copysign, WastInstruction::F64Copysign),
-    (F64Eq, WastInstruction::F64Eq),
-    (F64Ne, WastInstruction::F64Ne),
-    (F64Lt, WastInstruction::F64Lt),
-    (F64Gt, WastInstruction::F64Gt),
-    (F64Le, WastInstruction::F64Le),
-    (F64Ge, WastInstruction::F64Ge),
-    (LocalGet(Index), WastInstruction::LocalGet(index), ((index.try_into()?))),
-    (LocalSet(Index), WastInstruction::LocalSet(index), ((index.try_into()?))),
-    (LocalTee(Index), WastInstruction::LocalTee(index), ((index.try_into()?))),
-    (Call(Index), WastInstruction::Call(index), ((index.try_into()?))),
-    (Return, WastInstruction::Return),
-    (Nop, WastInstruction::Nop),
-    (If(BlockType, Option<Expression>, Option<Expression>), WastInstruction::If(ty), ((ty.try_into()?, None, None))),
-    (Else , WastInstruction::Else(_)),
-    (End, WastInstruction::End(_)),
-    (Block(BlockType, Option<Expression>), WastInstruction::Block(ty), ((ty.try_into()?, None))),
-    (Loop(BlockType, Option<Expression>), WastInstruction::Loop(ty), ((ty.try_into()?, None))),
-    (Br(Index), WastInstruction::Br(index), ((index.try_into()?)))
+    (Drop, "drop", WastInstruction::Drop),
+    (Select, "select", WastInstruction::Select(_)),
+    (I32Const(i32), "i32.const", WastInstruction::I32Const(i), ((*i))),
+    (I32Clz, "i32.clz", WastInstruction::I32Clz),
+    (I32Ctz, "i32.ctz", WastInstruction::I32Ctz),
+    (I32Popcnt, "i32.popcnt", WastInstruction::I32Popcnt),
+    (I32Add, "i32.add", WastInstruction::I32Add),
+    (I32Sub, "i32.sub", WastInstruction::I32Sub),
+    (I32Mul, "i32.mul", WastInstruction::I32Mul),
+    (I32DivS, "i32.div_s", WastInstruction::I32DivS),
+    (I32DivU, "i32.div_u", WastInstruction::I32DivU),
+    (I32RemS, "i32.rem_s", WastInstruction::I32RemS),
+    (I32RemU, "i32.rem_u", WastInstruction::I32RemU),
+    (I32And, "i32.and", WastInstruction::I32And),
+    (I32Or, "i32.or", WastInstruction::I32Or),
+    (I32Xor, "i32.xor", WastInstruction::I32Xor),
+    (I32Shl, "i32.shl", WastInstruction::I32Shl),
+    (I32ShrS, "i32.shr_s", WastInstruction::I32ShrS),
+    (I32ShrU, "i32.shr_u", WastInstruction::I32ShrU),
+    (I32Rotl, "i32.rotl", WastInstruction::I32Rotl),
+    (I32Rotr, "i32.rotr", WastInstruction::I32Rotr),
+    (I32Eqz, "i32.eqz", WastInstruction::I32Eqz),
+    (I32Eq, "i32.eq", WastInstruction::I32Eq),
+    (I32Ne, "i32.ne", WastInstruction::I32Ne),
+    (I32LtS, "i32.lt_s", WastInstruction::I32LtS),
+    (I32LtU, "i32.lt_u", WastInstruction::I32LtU),
+    (I32GtS, "i32.gt_s", WastInstruction::I32GtS),
+    (I32GtU, "i32.gt_u", WastInstruction::I32GtU),
+    (I32LeS, "i32.le_s", WastInstruction::I32LeS),
+    (I32LeU, "i32.le_u", WastInstruction::I32LeU),
+    (I32GeS, "i32.ge_s", WastInstruction::I32GeS),
+    (I32GeU, "i32.ge_u", WastInstruction::I32GeU),
+    (I64Const(i64), "i64.const", WastInstruction::I64Const(i), ((*i))),
+    (I64Clz, "i64.clz", WastInstruction::I64Clz),
+    (I64Ctz, "i64.ctz", WastInstruction::I64Ctz),
+    (I64Popcnt, "i64.popcnt", WastInstruction::I64Popcnt),
+    (I64Add, "i64.add", WastInstruction::I64Add),
+    (I64Sub, "i64.sub", WastInstruction::I64Sub),
+    (I64Mul, "i64.mul", WastInstruction::I64Mul),
+    (I64DivS, "i64.div_s", WastInstruction::I64DivS),
+    (I64DivU, "i64.div_u", WastInstruction::I64DivU),
+    (I64RemS, "i64.rem_s", WastInstruction::I64RemS),
+    (I64RemU, "i64.rem_u", WastInstruction::I64RemU),
+    (I64And, "i64.and", WastInstruction::I64And),
+    (I64Or, "i64.or", WastInstruction::I64Or),
+    (I64Xor, "i64.xor", WastInstruction::I64Xor),
+    (I64Shl, "i64.shl", WastInstruction::I64Shl),
+    (I64ShrS, "i64.shr_s", WastInstruction::I64ShrS),
+    (I64ShrU, "i64.shr_u", WastInstruction::I64ShrU),
+    (I64Rotl, "i64.rotl", WastInstruction::I64Rotl),
+    (I64Rotr, "i64.rotr", WastInstruction::I64Rotr),
+    (I64Eqz, "i64.eqz", WastInstruction::I64Eqz),
+    (I64Eq, "i64.eq", WastInstruction::I64Eq),
+    (I64Ne, "i64.ne", WastInstruction::I64Ne),
+    (I64LtS, "i64.lt_s", WastInstruction::I64LtS),
+    (I64LtU, "i64.lt_u", WastInstruction::I64LtU),
+    (I64GtS, "i64.gt_s", WastInstruction::I64GtS),
+    (I64GtU, "i64.gt_u", WastInstruction::I64GtU),
+    (I64LeS, "i64.le_s", WastInstruction::I64LeS),
+    (I64LeU, "i64.le_u", WastInstruction::I64LeU),
+    (I64GeS, "i64.ge_s", WastInstruction::I64GeS),
+    (I64GeU, "i64.ge_u", WastInstruction::I64GeU),
+    (F32Const(f32), "f32.const", WastInstruction::F32Const(f), ((f32::from_bits(f.bits)))),
+    (F32Abs, "f32.abs", WastInstruction::F32Abs),
+    (F32Neg, "f32.neg", WastInstruction::F32Neg),
+    (F32Ceil, "f32.ceil", WastInstruction::F32Ceil),
+    (F32Floor, "f32.floor", WastInstruction::F32Floor),
+    (F32Trunc, "f32.trunc", WastInstruction::F32Trunc),
+    (F32Nearest, "f32.nearest", WastInstruction::F32Nearest),
+    (F32Sqrt, "f32.sqrt", WastInstruction::F32Sqrt),
+    (F32Add, "f32.add", WastInstruction::F32Add),
+    (F32Sub, "f32.sub", WastInstruction::F32Sub),
+    (F32Mul, "f32.mul", WastInstruction::F32Mul),
+    (F32Div, "f32.div", WastInstruction::F32Div),
+    (F32Min, "f32.min", WastInstruction::F32Min),
+    (F32Max, "f32.max", WastInstruction::F32Max),
+    (F32Copysign, "f32.copysign", WastInstruction::F32Copysign),
+    (F32Eq, "f32.eq", WastInstruction::F32Eq),
+    (F32Ne, "f32.ne", WastInstruction::F32Ne),
+    (F32Lt, "f32.lt", WastInstruction::F32Lt),
+    (F32Gt, "f32.gt", WastInstruction::F32Gt),
+    (F32Le, "f32.le", WastInstruction::F32Le),
+    (F32Ge, "f32.ge", WastInstruction::F32Ge),
+    (F64Const(f64), "f64.const", WastInstruction::F64Const(f), ((f64::from_bits(f.bits)))),
+    (F64Abs, "f64.abs", WastInstruction::F64Abs),
+    (F64Neg, "f64.neg", WastInstruction::F64Neg),
+    (F64Ceil, "f64.ceil", WastInstruction::F64Ceil),
+    (F64Floor, "f64.floor", WastInstruction::F64Floor),
+    (F64Trunc, "f64.trunc", WastInstruction::F64Trunc),
+    (F64Nearest, "f64.nearest", WastInstruction::F64Nearest),
+    (F64Sqrt, "f64.sqrt", WastInstruction::F64Sqrt),
+    (F64Add, "f64.add", WastInstruction::F64Add),
+    (F64Sub, "f64.sub", WastInstruction::F64Sub),
+    (F64Mul, "f64.mul", WastInstruction::F64Mul),
+    (F64Div, "f64.div", WastInstruction::F64Div),
+    (F64Min, "f64.min", WastInstruction::F64Min),
+    (F64Max, "f64.max", WastInstruction::F64Max),
+    (F64Copysign, "f64.copysign", WastInstruction::F64Copysign),
+    (F64Eq, "f64.eq", WastInstruction::F64Eq),
+    (F64Ne, "f64.ne", WastInstruction::F64Ne),
+    (F64Lt, "f64.lt", WastInstruction::F64Lt),
+    (F64Gt, "f64.gt", WastInstruction::F64Gt),
+    (F64Le, "f64.le", WastInstruction::F64Le),
+    (F64Ge, "f64.ge", WastInstruction::F64Ge),
+    (I32WrapI64, "i32.wrap_i64", WastInstruction::I32WrapI64),
+    (I64ExtendI32S, "i64.extend_i32_s", WastInstruction::I64ExtendI32S),
+    (I64ExtendI32U, "i64.extend_i32_u", WastInstruction::I64ExtendI32U),
+    (I32TruncF32S, "i32.trunc_f32_s", WastInstruction::I32TruncF32S),
+    (I32TruncF32U, "i32.trunc_f32_u", WastInstruction::I32TruncF32U),
+    (I32TruncF64S, "i32.trunc_f64_s", WastInstruction::I32TruncF64S),
+    (I32TruncF64U, "i32.trunc_f64_u", WastInstruction::I32TruncF64U),
+    (I64TruncF32S, "i64.trunc_f32_s", WastInstruction::I64TruncF32S),
+    (I64TruncF32U, "i64.trunc_f32_u", WastInstruction::I64TruncF32U),
+    (I64TruncF64S, "i64.trunc_f64_s", WastInstruction::I64TruncF64S),
+    (I64TruncF64U, "i64.trunc_f64_u", WastInstruction::I64TruncF64U),
+    (I32TruncSatF32S, "i32.trunc_sat_f32_s", WastInstruction::I32TruncSatF32S),
+    (I32TruncSatF32U, "i32.trunc_sat_f32_u", WastInstruction::I32TruncSatF32U),
+    (I32TruncSatF64S, "i32.trunc_sat_f64_s", WastInstruction::I32TruncSatF64S),
+    (I32TruncSatF64U, "i32.trunc_sat_f64_u", WastInstruction::I32TruncSatF64U),
+    (I64TruncSatF32S, "i64.trunc_sat_f32_s", WastInstruction::I64TruncSatF32S),
+    (I64TruncSatF32U, "i64.trunc_sat_f32_u", WastInstruction::I64TruncSatF32U),
+    (I64TruncSatF64S, "i64.trunc_sat_f64_s", WastInstruction::I64TruncSatF64S),
+    (I64TruncSatF64U, "i64.trunc_sat_f64_u", WastInstruction::I64TruncSatF64U),
+    (F32ConvertI32S, "f32.convert_i32_s", WastInstruction::F32ConvertI32S),
+    (F32ConvertI32U, "f32.convert_i32_u", WastInstruction::F32ConvertI32U),
+    (F32ConvertI64S, "f32.convert_i64_s", WastInstruction::F32ConvertI64S),
+    (F32ConvertI64U, "f32.convert_i64_u", WastInstruction::F32ConvertI64U),
+    (F64ConvertI32S, "f64.convert_i32_s", WastInstruction::F64ConvertI32S),
+    (F64ConvertI32U, "f64.convert_i32_u", WastInstruction::F64ConvertI32U),
+    (F64ConvertI64S, "f64.convert_i64_s", WastInstruction::F64ConvertI64S),
+    (F64ConvertI64U, "f64.convert_i64_u", WastInstruction::F64ConvertI64U),
+    (F32DemoteF64, "f32.demote_f64", WastInstruction::F32DemoteF64),
+    (F64PromoteF32, "f64.promote_f32", WastInstruction::F64PromoteF32),
+    (I32Extend8S, "i32.extend8_s", WastInstruction::I32Extend8S),
+    (I32Extend16S, "i32.extend16_s", WastInstruction::I32Extend16S),
+    (I64Extend8S, "i64.extend8_s", WastInstruction::I64Extend8S),
+    (I64Extend16S, "i64.extend16_s", WastInstruction::I64Extend16S),
+    (I64Extend32S, "i64.extend32_s", WastInstruction::I64Extend32S),
+    (LocalGet(Index), "local.get", WastInstruction::LocalGet(index), ((index.try_into()?))),
+    (LocalSet(Index), "local.set", WastInstruction::LocalSet(index), ((index.try_into()?))),
+    (LocalTee(Index), "local.tee", WastInstruction::LocalTee(index), ((index.try_into()?))),
+    (I32Load(MemArg), "i32.load", WastInstruction::I32Load(arg), ((arg.try_into()?))),
+    (I32Store(MemArg), "i32.store", WastInstruction::I32Store(arg), ((arg.try_into()?))),
+    (GlobalGet(Index), "global.get", WastInstruction::GlobalGet(index), ((index.try_into()?))),
+    (GlobalSet(Index), "global.set", WastInstruction::GlobalSet(index), ((index.try_into()?))),
+    (MemorySize, "memory.size", WastInstruction::MemorySize(_)),
+    (MemoryGrow, "memory.grow", WastInstruction::MemoryGrow(_)),
+    (MemoryInit(Index), "memory.init", WastInstruction::MemoryInit(init), (((&init.data).try_into()?))),
+    (DataDrop(Index), "data.drop", WastInstruction::DataDrop(index), ((index.try_into()?))),
+    (TableInit(Index, Index), "table.init", WastInstruction::TableInit(init), (((&init.table).try_into()?, (&init.elem).try_into()?))),
+    (ElemDrop(Index), "elem.drop", WastInstruction::ElemDrop(index), ((index.try_into()?))),
+    (TableGet(Index), "table.get", WastInstruction::TableGet(arg), (((&arg.dst).try_into()?))),
+    (TableSet(Index), "table.set", WastInstruction::TableSet(arg), (((&arg.dst).try_into()?))),
+    (TableSize(Index), "table.size", WastInstruction::TableSize(arg), (((&arg.dst).try_into()?))),
+    (TableGrow(Index), "table.grow", WastInstruction::TableGrow(arg), (((&arg.dst).try_into()?))),
+    (RefNull, "ref.null", WastInstruction::RefNull(HeapType::Func)),
+    (RefIsNull, "ref.is_null", WastInstruction::RefIsNull),
+    (RefFunc(Index), "ref.func", WastInstruction::RefFunc(index), ((index.try_into()?))),
+    (Call(Index), "call", WastInstruction::Call(index), ((index.try_into()?))),
+    (ReturnCall(Index), "return_call", WastInstruction::ReturnCall(index), ((index.try_into()?))),
+    (CallIndirect(Index, FuncType), "call_indirect", WastInstruction::CallIndirect(call), ((
+        (&call.table).try_into()?,
+        (&call.ty).try_into()?
+    ))),
+    (ReturnCallIndirect(Index, FuncType), "return_call_indirect", WastInstruction::ReturnCallIndirect(call), ((
+        (&call.table).try_into()?,
+        (&call.ty).try_into()?
+    ))),
+    (Return, "return", WastInstruction::Return),
+    (Nop, "nop", WastInstruction::Nop),
+    (If(BlockType, Option<Expression>, Option<Expression>), "if", WastInstruction::If(ty), ((ty.try_into()?, None, None))),
+    (Else, "else", WastInstruction::Else(_)),
+    (End, "end", WastInstruction::End(_)),
+    (Block(BlockType, Option<Expression>), "block", WastInstruction::Block(ty), ((ty.try_into()?, None))),
+    (Loop(BlockType, Option<Expression>), "loop", WastInstruction::Loop(ty), ((ty.try_into()?, None))),
+    (Br(Index), "br", WastInstruction::Br(index), ((index.try_into()?))),
+    (BrIf(Index), "br_if", WastInstruction::BrIf(index), ((index.try_into()?))),
+    (BrTable(Vec<Index>, Index), "br_table", WastInstruction::BrTable(indices), ((
+        indices.labels.iter().map(Index::try_from).collect::<Result<_>>()?,
+        (&indices.default).try_into()?
+    )))
 }}
 
 #[cfg(test)]
@@ -379,13 +1114,13 @@ mod tests {
             ValType,
         },
         parser::{Line as WastLine, LineExpression as WastLineExpression},
-        test_utils::test_index,
+        test_utils::{test_block, test_block_type, test_func_type, test_if, test_index},
     };
     use wast::{
         core::{
             BlockType as WastBlockType, Expression as WastExpression, Func as WastFunc,
             FunctionType, InlineExport, InlineImport, Instruction as WastInstruction,
-            Local as WastLocal, TypeUse, ValType as WastValType,
+            Local as WastLocal, MemArg, TypeUse, ValType as WastValType,
         },
         parser::{self, ParseBuffer},
         token::{Float32, Float64, Id, Index as WastIndex, Span},
@@ -434,6 +1169,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_from_wast_instruction_f32_const() {
         let str_f32 = String::from("3.14");
         let buf_f32 = ParseBuffer::new(&str_f32).unwrap();
@@ -444,6 +1180,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_from_wast_instruction_f64_const() {
         let str_f64 = String::from("3.14");
         let buf_f64 = ParseBuffer::new(&str_f64).unwrap();
@@ -469,6 +1206,23 @@ mod tests {
         assert!(instr.is_err());
     }
 
+    #[test]
+    fn test_from_wast_instruction_error_names_instruction() {
+        let err = Instruction::try_from(&WastInstruction::MemoryAtomicNotify(MemArg {
+            align: 4,
+            offset: 0,
+            memory: WastIndex::Num(0, Span::from_offset(0)),
+        }))
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unsupported instruction: MemoryAtomicNotify"
+        );
+
+        let err = Instruction::try_from(&WastInstruction::RefI31).unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported instruction: RefI31");
+    }
+
     #[test]
     fn test_from_wast_expression() {
         let expr = Expression::try_from(&WastExpression {
@@ -548,6 +1302,117 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_to_tree_string() {
+        let expr = Expression {
+            instrs: vec![
+                Instruction::I32Const(1),
+                test_if!(
+                    test_block_type!(),
+                    (Instruction::I32Const(2)),
+                    (test_block!(
+                        BlockType {
+                            label: Some(String::from("b")),
+                            ty: test_func_type!(),
+                        },
+                        (Instruction::Nop)
+                    ))
+                ),
+            ],
+        };
+        assert_eq!(
+            expr.to_tree_string(),
+            "i32.const 1\nif\n  i32.const 2\nelse\n  block $b\n    nop"
+        );
+    }
+
+    #[test]
+    fn test_structural_eq_ignoring_labels() {
+        let labelled = |label: &str| BlockType {
+            label: Some(String::from(label)),
+            ty: test_func_type!(),
+        };
+        let expr = Expression {
+            instrs: vec![test_block!(
+                labelled("outer"),
+                (test_block!(labelled("inner"), (Instruction::Br(test_index("outer")))))
+            )],
+        };
+        let other = Expression {
+            instrs: vec![test_block!(
+                labelled("a"),
+                (test_block!(test_block_type!(), (Instruction::Br(Index::Num(1)))))
+            )],
+        };
+
+        assert!(expr.structural_eq(&other, true));
+        assert!(!expr.structural_eq(&other, false));
+        assert!(expr.structural_eq(&expr.clone(), false));
+    }
+
+    #[test]
+    fn test_structural_eq_ignoring_labels_branch_depth() {
+        let expr = Expression {
+            instrs: vec![test_block!(
+                BlockType {
+                    label: Some(String::from("outer")),
+                    ty: test_func_type!(),
+                },
+                (test_block!(test_block_type!(), (Instruction::Br(test_index("outer")))))
+            )],
+        };
+        let other = Expression {
+            instrs: vec![test_block!(
+                test_block_type!(),
+                (test_block!(test_block_type!(), (Instruction::Br(Index::Num(0)))))
+            )],
+        };
+
+        assert!(!expr.structural_eq(&other, true));
+    }
+
+    #[test]
+    fn test_func_resolve_ids() {
+        let mut func = Func {
+            id: Some(String::from("f")),
+            ty: FuncType {
+                params: vec![Local {
+                    id: Some(String::from("a")),
+                    val_type: ValType::I32,
+                }],
+                results: vec![],
+            },
+            line_expression: LineExpression {
+                locals: vec![Local {
+                    id: Some(String::from("b")),
+                    val_type: ValType::I32,
+                }],
+                expr: Expression {
+                    instrs: vec![
+                        Instruction::LocalGet(test_index("b")),
+                        test_block!(test_block_type!(), (Instruction::LocalSet(test_index("a")))),
+                        Instruction::LocalGet(test_index("c")),
+                        Instruction::Call(test_index("g")),
+                        Instruction::Call(test_index("h")),
+                    ],
+                },
+            },
+            recursive: false,
+        };
+
+        func.resolve_ids(|id| if id == "g" { Some(3) } else { None });
+        assert_eq!(
+            func.line_expression.expr.instrs,
+            vec![
+                Instruction::LocalGet(Index::Num(1)),
+                test_block!(test_block_type!(), (Instruction::LocalSet(Index::Num(0)))),
+                Instruction::LocalGet(test_index("c")),
+                Instruction::Call(Index::Num(3)),
+                Instruction::Call(test_index("h")),
+            ]
+        );
+    }
+
     #[test]
     fn test_from_wast_import_error() {
         assert!(Func::try_from(&WastFunc {
@@ -594,6 +1459,45 @@ mod tests {
         .is_err());
     }
 
+    #[test]
+    fn test_from_wast_func_unterminated_block_error() {
+        test_id!(fun_id, "$fun1");
+        let result = Func::try_from(&WastFunc {
+            id: Some(fun_id),
+            name: None,
+            exports: InlineExport { names: vec![] },
+            ty: TypeUse {
+                index: None,
+                inline: Some(FunctionType {
+                    params: Box::new([]),
+                    results: Box::new([]),
+                }),
+            },
+            span: Span::from_offset(0),
+            kind: wast::core::FuncKind::Inline {
+                locals: Box::new([]),
+                expression: WastExpression {
+                    instrs: Box::new([
+                        WastInstruction::Block(Box::new(WastBlockType {
+                            label: None,
+                            label_name: None,
+                            ty: TypeUse {
+                                index: None,
+                                inline: None,
+                            },
+                        })),
+                        WastInstruction::I32Const(2),
+                    ]),
+                },
+            },
+        });
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "function $fun1 has an unterminated block"
+        );
+    }
+
     #[test]
     fn test_wast_func_type() {
         test_id!(param_id, "$param1");
@@ -807,4 +1711,12 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_instruction_mnemonic() {
+        assert_eq!(Instruction::I32DivS.mnemonic(), "i32.div_s");
+        assert_eq!(Instruction::F64Copysign.mnemonic(), "f64.copysign");
+        assert_eq!(Instruction::LocalTee(Index::Num(0)).mnemonic(), "local.tee");
+        assert_eq!(Instruction::Br(Index::Num(0)).mnemonic(), "br");
+    }
 }
@@ -31,6 +31,38 @@ macro_rules! impl_integer_num_ops {
 impl_integer_num_ops!(i32);
 impl_integer_num_ops!(i64);
 
+/// Integer arithmetic that errors on overflow, instead of wrapping.
+pub trait CheckedOps: NumOps {
+    fn add_checked(self, rhs: Self) -> Result<Self>
+    where
+        Self: Sized;
+    fn sub_checked(self, rhs: Self) -> Result<Self>
+    where
+        Self: Sized;
+    fn mul_checked(self, rhs: Self) -> Result<Self>
+    where
+        Self: Sized;
+}
+
+macro_rules! impl_checked_ops {
+    ($t:ty) => {
+        impl CheckedOps for $t {
+            fn add_checked(self, rhs: Self) -> Result<Self> {
+                self.checked_add(rhs).ok_or(Error::msg("integer overflow"))
+            }
+            fn sub_checked(self, rhs: Self) -> Result<Self> {
+                self.checked_sub(rhs).ok_or(Error::msg("integer overflow"))
+            }
+            fn mul_checked(self, rhs: Self) -> Result<Self> {
+                self.checked_mul(rhs).ok_or(Error::msg("integer overflow"))
+            }
+        }
+    };
+}
+
+impl_checked_ops!(i32);
+impl_checked_ops!(i64);
+
 macro_rules! impl_float_num_ops {
     ($t:ty) => {
         impl NumOps for $t {
@@ -58,6 +90,12 @@ pub trait IntOps: NumOps {
     where
         Self: Sized;
     fn popcnt(self) -> Self
+    where
+        Self: Sized;
+    fn extend8_s(self) -> Self
+    where
+        Self: Sized;
+    fn extend16_s(self) -> Self
     where
         Self: Sized;
     fn div_s(self, rhs: Self) -> Result<Self>
@@ -70,6 +108,9 @@ pub trait IntOps: NumOps {
     where
         Self: Sized;
     fn rem_u(self, rhs: Self) -> Result<Self>
+    where
+        Self: Sized;
+    fn shl(self, rhs: Self) -> Self
     where
         Self: Sized;
     fn shr_s(self, rhs: Self) -> Self
@@ -84,37 +125,37 @@ pub trait IntOps: NumOps {
     fn rotr(self, rhs: Self) -> Self
     where
         Self: Sized;
-    fn eqz(self) -> Self
+    fn eqz(self) -> bool
     where
         Self: Sized;
-    fn eq(self, rhs: Self) -> Self
+    fn eq(self, rhs: Self) -> bool
     where
         Self: Sized;
-    fn ne(self, rhs: Self) -> Self
+    fn ne(self, rhs: Self) -> bool
     where
         Self: Sized;
-    fn lt_s(self, rhs: Self) -> Self
+    fn lt_s(self, rhs: Self) -> bool
     where
         Self: Sized;
-    fn lt_u(self, rhs: Self) -> Self
+    fn lt_u(self, rhs: Self) -> bool
     where
         Self: Sized;
-    fn gt_s(self, rhs: Self) -> Self
+    fn gt_s(self, rhs: Self) -> bool
     where
         Self: Sized;
-    fn gt_u(self, rhs: Self) -> Self
+    fn gt_u(self, rhs: Self) -> bool
     where
         Self: Sized;
-    fn le_s(self, rhs: Self) -> Self
+    fn le_s(self, rhs: Self) -> bool
     where
         Self: Sized;
-    fn le_u(self, rhs: Self) -> Self
+    fn le_u(self, rhs: Self) -> bool
     where
         Self: Sized;
-    fn ge_s(self, rhs: Self) -> Self
+    fn ge_s(self, rhs: Self) -> bool
     where
         Self: Sized;
-    fn ge_u(self, rhs: Self) -> Self
+    fn ge_u(self, rhs: Self) -> bool
     where
         Self: Sized;
 }
@@ -131,13 +172,19 @@ macro_rules! impl_int_ops {
             fn popcnt(self) -> Self {
                 self.count_ones() as Self
             }
+            fn extend8_s(self) -> Self {
+                self as i8 as Self
+            }
+            fn extend16_s(self) -> Self {
+                self as i16 as Self
+            }
             fn div_s(self, rhs: Self) -> Result<Self> {
                 if rhs == 0 {
-                    Err(Error::msg("Divide by zero"))
+                    Err(Error::msg("integer divide by zero"))
                 } else {
                     let (res, overflow) = self.overflowing_div(rhs);
                     if overflow {
-                        Err(Error::msg("Integer Overflow"))
+                        Err(Error::msg("integer overflow"))
                     } else {
                         Ok(res)
                     }
@@ -147,14 +194,14 @@ macro_rules! impl_int_ops {
                 let a = self as $ut;
                 let b = rhs as $ut;
                 if b == 0 {
-                    Err(Error::msg("Divide by zero"))
+                    Err(Error::msg("integer divide by zero"))
                 } else {
                     Ok(Self::from_ne_bytes((a / b).to_ne_bytes()))
                 }
             }
             fn rem_s(self, rhs: Self) -> Result<Self> {
                 if rhs == 0 {
-                    Err(Error::msg("Divide by zero"))
+                    Err(Error::msg("integer divide by zero"))
                 } else {
                     // This is mathematically not possible but is due to
                     // the implementation artifact we need to use `wrapping_rem`
@@ -165,11 +212,14 @@ macro_rules! impl_int_ops {
                 let a = self as $ut;
                 let b = rhs as $ut;
                 if b == 0 {
-                    Err(Error::msg("Divide by zero"))
+                    Err(Error::msg("integer divide by zero"))
                 } else {
                     Ok(Self::from_ne_bytes((a % b).to_ne_bytes()))
                 }
             }
+            fn shl(self, rhs: Self) -> Self {
+                self.wrapping_shl(rhs as u32)
+            }
             fn shr_s(self, rhs: Self) -> Self {
                 self.wrapping_shr(rhs as u32)
             }
@@ -183,90 +233,46 @@ macro_rules! impl_int_ops {
             fn rotr(self, rhs: Self) -> Self {
                 self.rotate_right(rhs as u32)
             }
-            fn eqz(self) -> Self {
-                if self == 0 {
-                    1
-                } else {
-                    0
-                }
+            fn eqz(self) -> bool {
+                self == 0
             }
-            fn eq(self, rhs: Self) -> Self {
-                if self == rhs {
-                    1
-                } else {
-                    0
-                }
+            fn eq(self, rhs: Self) -> bool {
+                self == rhs
             }
-            fn ne(self, rhs: Self) -> Self {
-                if self == rhs {
-                    0
-                } else {
-                    1
-                }
+            fn ne(self, rhs: Self) -> bool {
+                self != rhs
             }
-            fn lt_s(self, rhs: Self) -> Self {
-                if self < rhs {
-                    1
-                } else {
-                    0
-                }
+            fn lt_s(self, rhs: Self) -> bool {
+                self < rhs
             }
-            fn lt_u(self, rhs: Self) -> Self {
+            fn lt_u(self, rhs: Self) -> bool {
                 let a = self as $ut;
                 let b = rhs as $ut;
-                if a < b {
-                    1
-                } else {
-                    0
-                }
+                a < b
             }
-            fn gt_s(self, rhs: Self) -> Self {
-                if self > rhs {
-                    1
-                } else {
-                    0
-                }
+            fn gt_s(self, rhs: Self) -> bool {
+                self > rhs
             }
-            fn gt_u(self, rhs: Self) -> Self {
+            fn gt_u(self, rhs: Self) -> bool {
                 let a = self as $ut;
                 let b = rhs as $ut;
-                if a > b {
-                    1
-                } else {
-                    0
-                }
+                a > b
             }
-            fn le_s(self, rhs: Self) -> Self {
-                if self <= rhs {
-                    1
-                } else {
-                    0
-                }
+            fn le_s(self, rhs: Self) -> bool {
+                self <= rhs
             }
-            fn le_u(self, rhs: Self) -> Self {
+            fn le_u(self, rhs: Self) -> bool {
                 let a = self as $ut;
                 let b = rhs as $ut;
-                if a <= b {
-                    1
-                } else {
-                    0
-                }
+                a <= b
             }
-            fn ge_s(self, rhs: Self) -> Self {
-                if self >= rhs {
-                    1
-                } else {
-                    0
-                }
+            fn ge_s(self, rhs: Self) -> bool {
+                self >= rhs
             }
-            fn ge_u(self, rhs: Self) -> Self {
+            fn ge_u(self, rhs: Self) -> bool {
                 let a = self as $ut;
                 let b = rhs as $ut;
-                if a >= b {
-                    1
-                } else {
-                    0
-                }
+                a >= b
             }
         }
     };
@@ -275,6 +281,71 @@ macro_rules! impl_int_ops {
 impl_int_ops!(i32, u32);
 impl_int_ops!(i64, u64);
 
+pub trait WrapOps {
+    fn wrap(self) -> i32;
+}
+
+impl WrapOps for i64 {
+    // Keeps the low 32 bits.
+    fn wrap(self) -> i32 {
+        self as i32
+    }
+}
+
+pub trait Extend32Ops {
+    fn extend32_s(self) -> i64;
+}
+
+impl Extend32Ops for i64 {
+    fn extend32_s(self) -> i64 {
+        self as i32 as i64
+    }
+}
+
+pub trait ExtendOps {
+    fn extend_s(self) -> i64;
+    fn extend_u(self) -> i64;
+}
+
+impl ExtendOps for i32 {
+    fn extend_s(self) -> i64 {
+        self as i64
+    }
+    fn extend_u(self) -> i64 {
+        self as u32 as i64
+    }
+}
+
+pub trait ConvertOps {
+    fn convert_f32_s(self) -> f32;
+    fn convert_f32_u(self) -> f32;
+    fn convert_f64_s(self) -> f64;
+    fn convert_f64_u(self) -> f64;
+}
+
+macro_rules! impl_convert_ops {
+    ($t:ty, $ut:ty) => {
+        impl ConvertOps for $t {
+            // `as` rounds to nearest, ties to even, as the spec requires.
+            fn convert_f32_s(self) -> f32 {
+                self as f32
+            }
+            fn convert_f32_u(self) -> f32 {
+                self as $ut as f32
+            }
+            fn convert_f64_s(self) -> f64 {
+                self as f64
+            }
+            fn convert_f64_u(self) -> f64 {
+                self as $ut as f64
+            }
+        }
+    };
+}
+
+impl_convert_ops!(i32, u32);
+impl_convert_ops!(i64, u64);
+
 pub trait FloatOps: NumOps {
     fn neg(self) -> Self
     where
@@ -282,90 +353,211 @@ pub trait FloatOps: NumOps {
     fn div(self, rhs: Self) -> Self
     where
         Self: Sized;
-    fn eq(self, rhs: Self) -> Self
+    fn eq(self, rhs: Self) -> bool
+    where
+        Self: Sized;
+    fn ne(self, rhs: Self) -> bool
+    where
+        Self: Sized;
+    fn lt(self, rhs: Self) -> bool
     where
         Self: Sized;
-    fn ne(self, rhs: Self) -> Self
+    fn gt(self, rhs: Self) -> bool
     where
         Self: Sized;
-    fn lt(self, rhs: Self) -> Self
+    fn le(self, rhs: Self) -> bool
     where
         Self: Sized;
-    fn gt(self, rhs: Self) -> Self
+    fn ge(self, rhs: Self) -> bool
     where
         Self: Sized;
-    fn le(self, rhs: Self) -> Self
+    fn min(self, rhs: Self) -> Self
     where
         Self: Sized;
-    fn ge(self, rhs: Self) -> Self
+    fn max(self, rhs: Self) -> Self
+    where
+        Self: Sized;
+    fn nearest(self) -> Self
+    where
+        Self: Sized;
+    fn canonicalize_nan(self) -> Self
     where
         Self: Sized;
 }
 
 macro_rules! impl_float_ops {
-    ($t:ty) => {
+    ($t:ty, $canonical_nan:expr) => {
         impl FloatOps for $t {
+            // Only flips the sign bit, even for zero and NaN.
             fn neg(self) -> Self {
-                self * -1.0
+                -self
             }
             fn div(self, rhs: Self) -> Self {
                 self / rhs
             }
-            fn eq(self, rhs: Self) -> Self {
-                if self == rhs {
-                    1.0
-                } else {
-                    0.0
-                }
+            fn eq(self, rhs: Self) -> bool {
+                self == rhs
             }
-            fn ne(self, rhs: Self) -> Self {
-                if self == rhs {
-                    0.0
-                } else {
-                    1.0
-                }
+            fn ne(self, rhs: Self) -> bool {
+                self != rhs
+            }
+            fn lt(self, rhs: Self) -> bool {
+                self < rhs
+            }
+            fn gt(self, rhs: Self) -> bool {
+                self > rhs
+            }
+            fn le(self, rhs: Self) -> bool {
+                self <= rhs
             }
-            fn lt(self, rhs: Self) -> Self {
-                if self < rhs {
-                    1.0
+            fn ge(self, rhs: Self) -> bool {
+                self >= rhs
+            }
+            // Unlike the inherent `min`, a NaN operand gives a NaN, and
+            // -0.0 is taken as less than 0.0.
+            fn min(self, rhs: Self) -> Self {
+                if self.is_nan() || rhs.is_nan() {
+                    self + rhs
+                } else if self == rhs {
+                    <$t>::from_bits(self.to_bits() | rhs.to_bits())
+                } else if self < rhs {
+                    self
                 } else {
-                    0.0
+                    rhs
                 }
             }
-            fn gt(self, rhs: Self) -> Self {
-                if self > rhs {
-                    1.0
+            fn max(self, rhs: Self) -> Self {
+                if self.is_nan() || rhs.is_nan() {
+                    self + rhs
+                } else if self == rhs {
+                    <$t>::from_bits(self.to_bits() & rhs.to_bits())
+                } else if self > rhs {
+                    self
                 } else {
-                    0.0
+                    rhs
                 }
             }
-            fn le(self, rhs: Self) -> Self {
-                if self <= rhs {
-                    1.0
+            // Unlike `round`, halves go to the even neighbour. A NaN is
+            // quieted, as by the other arithmetic.
+            fn nearest(self) -> Self {
+                if self.is_nan() {
+                    self + self
                 } else {
-                    0.0
+                    self.round_ties_even()
                 }
             }
-            fn ge(self, rhs: Self) -> Self {
-                if self >= rhs {
-                    1.0
+            fn canonicalize_nan(self) -> Self {
+                if self.is_nan() {
+                    <$t>::from_bits($canonical_nan)
                 } else {
-                    0.0
+                    self
                 }
             }
         }
     };
 }
 
-impl_float_ops!(f32);
-impl_float_ops!(f64);
+// Positive quiet NaN with an all zero payload, as defined by the spec.
+impl_float_ops!(f32, 0x7fc0_0000);
+impl_float_ops!(f64, 0x7ff8_0000_0000_0000);
+
+pub trait DemoteOps {
+    fn demote(self) -> f32;
+}
+
+impl DemoteOps for f64 {
+    // Rounds to nearest, and keeps NaN a NaN.
+    fn demote(self) -> f32 {
+        self as f32
+    }
+}
+
+pub trait PromoteOps {
+    fn promote(self) -> f64;
+}
+
+impl PromoteOps for f32 {
+    fn promote(self) -> f64 {
+        self as f64
+    }
+}
+
+pub trait TruncOps {
+    fn trunc_i32_s(self) -> Result<i32>;
+    fn trunc_i32_u(self) -> Result<i32>;
+    fn trunc_i64_s(self) -> Result<i64>;
+    fn trunc_i64_u(self) -> Result<i64>;
+    fn trunc_sat_i32_s(self) -> i32;
+    fn trunc_sat_i32_u(self) -> i32;
+    fn trunc_sat_i64_s(self) -> i64;
+    fn trunc_sat_i64_u(self) -> i64;
+}
+
+// Truncates towards zero, failing if the result does not fit in
+// `[min, max)`. Every f32 is exactly representable as f64, and so are the
+// bounds, so both widths are checked as f64.
+fn trunc_checked(value: f64, min: f64, max: f64) -> Result<f64> {
+    if value.is_nan() {
+        return Err(Error::msg("invalid conversion to integer"));
+    }
+    let value = value.trunc();
+    if value >= min && value < max {
+        Ok(value)
+    } else {
+        Err(Error::msg("integer overflow"))
+    }
+}
+
+macro_rules! impl_trunc_ops {
+    ($t:ty) => {
+        impl TruncOps for $t {
+            fn trunc_i32_s(self) -> Result<i32> {
+                Ok(trunc_checked(self as f64, -2147483648.0, 2147483648.0)? as i32)
+            }
+            fn trunc_i32_u(self) -> Result<i32> {
+                Ok(trunc_checked(self as f64, 0.0, 4294967296.0)? as u32 as i32)
+            }
+            fn trunc_i64_s(self) -> Result<i64> {
+                let bound = 9223372036854775808.0;
+                Ok(trunc_checked(self as f64, -bound, bound)? as i64)
+            }
+            fn trunc_i64_u(self) -> Result<i64> {
+                Ok(trunc_checked(self as f64, 0.0, 18446744073709551616.0)? as u64 as i64)
+            }
+            // Float to integer `as` casts already saturate, and give 0 for NaN.
+            fn trunc_sat_i32_s(self) -> i32 {
+                self as i32
+            }
+            fn trunc_sat_i32_u(self) -> i32 {
+                self as u32 as i32
+            }
+            fn trunc_sat_i64_s(self) -> i64 {
+                self as i64
+            }
+            fn trunc_sat_i64_u(self) -> i64 {
+                self as u64 as i64
+            }
+        }
+    };
+}
+
+impl_trunc_ops!(f32);
+impl_trunc_ops!(f64);
 
 #[cfg(test)]
 mod tests {
 
+    use crate::ops::CheckedOps;
+    use crate::ops::ConvertOps;
+    use crate::ops::DemoteOps;
+    use crate::ops::Extend32Ops;
+    use crate::ops::ExtendOps;
     use crate::ops::FloatOps;
     use crate::ops::IntOps;
     use crate::ops::NumOps;
+    use crate::ops::PromoteOps;
+    use crate::ops::TruncOps;
+    use crate::ops::WrapOps;
 
     #[test]
     fn test_i32_add() {
@@ -397,6 +589,29 @@ mod tests {
         assert_eq!(i32::MAX.mul(2), -2);
     }
 
+    #[test]
+    fn test_i32_checked() {
+        assert_eq!(1.add_checked(2).unwrap(), 3);
+        assert_eq!(1.sub_checked(2).unwrap(), -1);
+        assert_eq!(4.mul_checked(2).unwrap(), 8);
+    }
+
+    #[test]
+    fn test_i32_checked_overflow() {
+        let err = i32::MAX.add_checked(1).err().unwrap();
+        assert_eq!(err.to_string(), "integer overflow");
+        assert!(i32::MIN.sub_checked(1).is_err());
+        assert!(i32::MAX.mul_checked(2).is_err());
+    }
+
+    #[test]
+    fn test_i64_checked_overflow() {
+        assert!(i64::MAX.add_checked(1).is_err());
+        assert!(i64::MIN.sub_checked(1).is_err());
+        assert!(i64::MIN.mul_checked(-1).is_err());
+        assert_eq!(i64::MAX.sub_checked(1).unwrap(), i64::MAX - 1);
+    }
+
     #[test]
     fn test_i64_add() {
         assert_eq!(1i64.add(2i64), 3i64);
@@ -474,6 +689,16 @@ mod tests {
         assert!(5.rem_u(0).is_err());
     }
 
+    #[test]
+    fn test_i32_shl() {
+        assert_eq!(1i32.shl(2), 4);
+    }
+
+    #[test]
+    fn test_i32_shl_overflow() {
+        assert_eq!(1i32.shl(33), 2);
+    }
+
     #[test]
     fn test_i32_shr_s() {
         assert_eq!(1i32.shr_s(2), 0);
@@ -535,6 +760,11 @@ mod tests {
         assert_eq!(i64::MIN.div_u(2).unwrap(), 0x4000000000000000i64);
     }
 
+    #[test]
+    fn test_i64_shl_overflow() {
+        assert_eq!(1i64.shl(65), 2i64);
+    }
+
     #[test]
     fn test_i64_shr_s() {
         assert_eq!(4i64.shr_s(1), 2i64);
@@ -555,6 +785,104 @@ mod tests {
         assert_eq!(0x10004000000002i64.rotr(37), 0x10008002i64);
     }
 
+    #[test]
+    fn test_i64_wrap() {
+        assert_eq!(5i64.wrap(), 5);
+        assert_eq!(0x1_0000_0005i64.wrap(), 5);
+        assert_eq!(0xffff_ffffi64.wrap(), -1);
+        assert_eq!((-1i64).wrap(), -1);
+    }
+
+    #[test]
+    fn test_extend_s() {
+        assert_eq!(0xffi32.extend8_s(), -1);
+        assert_eq!(0x17fi32.extend8_s(), 127);
+        assert_eq!(0x8000i32.extend16_s(), -32768);
+        assert_eq!(0x7fffi32.extend16_s(), 32767);
+        assert_eq!(0x80i64.extend8_s(), -128);
+        assert_eq!(0x1_ffffi64.extend16_s(), -1);
+        assert_eq!(0x8000_0000i64.extend32_s(), -2147483648);
+        assert_eq!(0x1_7fff_ffffi64.extend32_s(), 2147483647);
+    }
+
+    #[test]
+    fn test_i32_extend() {
+        assert_eq!(5i32.extend_s(), 5);
+        assert_eq!(5i32.extend_u(), 5);
+        assert_eq!((-1i32).extend_s(), -1);
+        assert_eq!((-1i32).extend_u(), 0xffff_ffff);
+    }
+
+    #[test]
+    fn test_trunc_i32() {
+        assert_eq!((-2.7f32).trunc_i32_s().unwrap(), -2);
+        assert_eq!(2147483520.0f32.trunc_i32_s().unwrap(), 2147483520);
+        assert_eq!((-2147483648.9f64).trunc_i32_s().unwrap(), i32::MIN);
+        assert!(2147483648.0f32.trunc_i32_s().is_err());
+        assert!((-2147483649.0f64).trunc_i32_s().is_err());
+
+        assert_eq!((-0.9f32).trunc_i32_u().unwrap(), 0);
+        assert_eq!(4294967295.9f64.trunc_i32_u().unwrap(), -1);
+        assert!((-1.0f64).trunc_i32_u().is_err());
+        assert!(4294967296.0f32.trunc_i32_u().is_err());
+    }
+
+    #[test]
+    fn test_trunc_i64() {
+        assert_eq!((-2.7f64).trunc_i64_s().unwrap(), -2);
+        assert_eq!((-9223372036854775808.0f32).trunc_i64_s().unwrap(), i64::MIN);
+        assert!(9223372036854775808.0f64.trunc_i64_s().is_err());
+
+        assert_eq!(18446744073709549568.0f64.trunc_i64_u().unwrap(), -2048);
+        assert!(18446744073709551616.0f32.trunc_i64_u().is_err());
+        assert!((-1.0f32).trunc_i64_u().is_err());
+    }
+
+    #[test]
+    fn test_trunc_nan_inf() {
+        let err = f32::NAN.trunc_i32_s().err().unwrap();
+        assert_eq!(err.to_string(), "invalid conversion to integer");
+        let err = f64::INFINITY.trunc_i64_u().err().unwrap();
+        assert_eq!(err.to_string(), "integer overflow");
+        assert!(f32::NEG_INFINITY.trunc_i64_s().is_err());
+    }
+
+    #[test]
+    fn test_trunc_sat() {
+        assert_eq!((-2.7f32).trunc_sat_i32_s(), -2);
+        assert_eq!(f32::NAN.trunc_sat_i32_s(), 0);
+        assert_eq!(2147483648.0f64.trunc_sat_i32_s(), i32::MAX);
+        assert_eq!((-2147483649.0f64).trunc_sat_i32_s(), i32::MIN);
+
+        assert_eq!((-1.0f32).trunc_sat_i32_u(), 0);
+        assert_eq!(4294967296.0f64.trunc_sat_i32_u(), -1);
+
+        assert_eq!(f64::INFINITY.trunc_sat_i64_s(), i64::MAX);
+        assert_eq!(f64::NEG_INFINITY.trunc_sat_i64_s(), i64::MIN);
+        assert_eq!(f32::NEG_INFINITY.trunc_sat_i64_u(), 0);
+        assert_eq!(f32::INFINITY.trunc_sat_i64_u(), -1);
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!((-1i32).convert_f32_s(), -1.0);
+        assert_eq!((-1i32).convert_f32_u(), 4294967296.0);
+        assert_eq!((-1i32).convert_f64_u(), 4294967295.0);
+        assert_eq!(16777217i32.convert_f32_s(), 16777216.0);
+        assert_eq!(i64::MIN.convert_f64_s(), -9223372036854775808.0);
+        assert_eq!((-1i64).convert_f64_u(), 18446744073709551616.0);
+        assert_eq!((-1i64).convert_f32_s(), -1.0);
+    }
+
+    #[test]
+    fn test_demote_promote() {
+        assert_eq!(0.1f64.demote(), 0.1f32);
+        assert_eq!(0.1f32.promote(), 0.10000000149011612);
+        assert_eq!(f64::MAX.demote(), f32::INFINITY);
+        assert!(f64::NAN.demote().is_nan());
+        assert!(f32::NAN.promote().is_nan());
+    }
+
     #[test]
     fn test_f32_add() {
         assert_eq!(1.0.add(2.0), 3.0);
@@ -603,243 +931,326 @@ mod tests {
 
     #[test]
     fn test_i32_eqz() {
-        assert_eq!(5i32.eqz(), 0);
-        assert_eq!(0i32.eqz(), 1);
+        assert!(!5i32.eqz());
+        assert!(0i32.eqz());
     }
     #[test]
     fn test_i64_eqz() {
-        assert_eq!(5i64.eqz(), 0);
-        assert_eq!(0i64.eqz(), 1);
+        assert!(!5i64.eqz());
+        assert!(0i64.eqz());
     }
 
     #[test]
     fn test_i32_eq() {
-        assert_eq!(1i32.eq(1), 1);
-        assert_eq!(1i32.eq(2), 0);
+        assert!(1i32.eq(1));
+        assert!(!1i32.eq(2));
     }
     #[test]
     fn test_i64_eq() {
-        assert_eq!(1i64.eq(1), 1);
-        assert_eq!(1i64.eq(2), 0);
+        assert!(1i64.eq(1));
+        assert!(!1i64.eq(2));
     }
 
     #[test]
     fn test_f32_eq() {
-        assert_eq!(1.0f32.eq(1.0), 1.0);
-        assert_eq!(1.0f32.eq(2.0), 0.0);
+        assert!(1.0f32.eq(1.0));
+        assert!(!1.0f32.eq(2.0));
     }
 
     #[test]
     fn test_f64_eq() {
-        assert_eq!(1.0f64.eq(1.0), 1.0);
-        assert_eq!(1.0f64.eq(2.0), 0.0);
+        assert!(1.0f64.eq(1.0));
+        assert!(!1.0f64.eq(2.0));
     }
 
     #[test]
     fn test_i32_ne() {
-        assert_eq!(1i32.ne(1), 0);
-        assert_eq!(1i32.ne(2), 1);
+        assert!(!1i32.ne(1));
+        assert!(1i32.ne(2));
     }
 
     #[test]
     fn test_i64_ne() {
-        assert_eq!(1i64.ne(1), 0);
-        assert_eq!(1i64.ne(2), 1);
+        assert!(!1i64.ne(1));
+        assert!(1i64.ne(2));
     }
 
     #[test]
     fn test_f32_ne() {
-        assert_eq!(1.0f32.ne(1.0), 0.0);
-        assert_eq!(1.0f32.ne(2.0), 1.0);
+        assert!(!1.0f32.ne(1.0));
+        assert!(1.0f32.ne(2.0));
     }
 
     #[test]
     fn test_f64_ne() {
-        assert_eq!(1.0f64.ne(1.0), 0.0);
-        assert_eq!(1.0f64.ne(2.0), 1.0);
+        assert!(!1.0f64.ne(1.0));
+        assert!(1.0f64.ne(2.0));
     }
 
     #[test]
     fn test_i32_lt_s() {
-        assert_eq!(1i32.lt_s(2), 1);
-        assert_eq!(1i32.lt_s(1), 0);
-        assert_eq!(2i32.lt_s(1), 0);
-        assert_eq!((-1i32).lt_s(1), 1);
+        assert!(1i32.lt_s(2));
+        assert!(!1i32.lt_s(1));
+        assert!(!2i32.lt_s(1));
+        assert!((-1i32).lt_s(1));
     }
 
     #[test]
     fn test_i64_lt_s() {
-        assert_eq!(1i64.lt_s(2), 1);
-        assert_eq!(1i64.lt_s(1), 0);
-        assert_eq!(2i64.lt_s(1), 0);
-        assert_eq!((-1i64).lt_s(1), 1);
+        assert!(1i64.lt_s(2));
+        assert!(!1i64.lt_s(1));
+        assert!(!2i64.lt_s(1));
+        assert!((-1i64).lt_s(1));
     }
 
     #[test]
     fn test_i32_lt_u() {
-        assert_eq!(1i32.lt_u(2), 1);
-        assert_eq!(1i32.lt_u(1), 0);
-        assert_eq!(2i32.lt_u(1), 0);
-        assert_eq!((-1i32).lt_u(1), 0);
+        assert!(1i32.lt_u(2));
+        assert!(!1i32.lt_u(1));
+        assert!(!2i32.lt_u(1));
+        assert!(!(-1i32).lt_u(1));
     }
 
     #[test]
     fn test_i64_lt_u() {
-        assert_eq!(1i64.lt_u(2), 1);
-        assert_eq!(1i64.lt_u(1), 0);
-        assert_eq!(2i64.lt_u(1), 0);
-        assert_eq!((-1i64).lt_u(1), 0);
+        assert!(1i64.lt_u(2));
+        assert!(!1i64.lt_u(1));
+        assert!(!2i64.lt_u(1));
+        assert!(!(-1i64).lt_u(1));
     }
 
     #[test]
     fn test_f32_lt() {
-        assert_eq!(1.0f32.lt(2.0), 1.0);
-        assert_eq!(1.0f32.lt(1.0), 0.0);
-        assert_eq!(2.0f32.lt(1.0), 0.0);
-        assert_eq!((-1.0f32).lt(1.0), 1.0);
+        assert!(1.0f32.lt(2.0));
+        assert!(!1.0f32.lt(1.0));
+        assert!(!2.0f32.lt(1.0));
+        assert!((-1.0f32).lt(1.0));
     }
 
     #[test]
     fn test_f64_lt() {
-        assert_eq!(1.0f64.lt(2.0), 1.0);
-        assert_eq!(1.0f64.lt(1.0), 0.0);
-        assert_eq!(2.0f64.lt(1.0), 0.0);
-        assert_eq!((-1.0f64).lt(1.0), 1.0);
+        assert!(1.0f64.lt(2.0));
+        assert!(!1.0f64.lt(1.0));
+        assert!(!2.0f64.lt(1.0));
+        assert!((-1.0f64).lt(1.0));
     }
 
     #[test]
     fn test_i32_gt_s() {
-        assert_eq!(1i32.gt_s(2), 0);
-        assert_eq!(1i32.gt_s(1), 0);
-        assert_eq!(2i32.gt_s(1), 1);
-        assert_eq!((-1i32).gt_s(1), 0);
+        assert!(!1i32.gt_s(2));
+        assert!(!1i32.gt_s(1));
+        assert!(2i32.gt_s(1));
+        assert!(!(-1i32).gt_s(1));
     }
 
     #[test]
     fn test_i64_gt_s() {
-        assert_eq!(1i64.gt_s(2), 0);
-        assert_eq!(1i64.gt_s(1), 0);
-        assert_eq!(2i64.gt_s(1), 1);
-        assert_eq!((-1i64).gt_s(1), 0);
+        assert!(!1i64.gt_s(2));
+        assert!(!1i64.gt_s(1));
+        assert!(2i64.gt_s(1));
+        assert!(!(-1i64).gt_s(1));
     }
 
     #[test]
     fn test_i32_gt_u() {
-        assert_eq!(1i32.gt_u(2), 0);
-        assert_eq!(1i32.gt_u(1), 0);
-        assert_eq!(2i32.gt_u(1), 1);
-        assert_eq!((-1i32).gt_u(1), 1);
+        assert!(!1i32.gt_u(2));
+        assert!(!1i32.gt_u(1));
+        assert!(2i32.gt_u(1));
+        assert!((-1i32).gt_u(1));
     }
 
     #[test]
     fn test_i64_gt_u() {
-        assert_eq!(1i64.gt_u(2), 0);
-        assert_eq!(1i64.gt_u(1), 0);
-        assert_eq!(2i64.gt_u(1), 1);
-        assert_eq!((-1i64).gt_u(1), 1);
+        assert!(!1i64.gt_u(2));
+        assert!(!1i64.gt_u(1));
+        assert!(2i64.gt_u(1));
+        assert!((-1i64).gt_u(1));
     }
 
     #[test]
     fn test_f32_gt() {
-        assert_eq!(1.0f32.gt(2.0), 0.0);
-        assert_eq!(1.0f32.gt(1.0), 0.0);
-        assert_eq!(2.0f32.gt(1.0), 1.0);
-        assert_eq!((-1.0f32).gt(1.0), 0.0);
+        assert!(!1.0f32.gt(2.0));
+        assert!(!1.0f32.gt(1.0));
+        assert!(2.0f32.gt(1.0));
+        assert!(!(-1.0f32).gt(1.0));
     }
 
     #[test]
     fn test_f64_gt() {
-        assert_eq!(1.0f64.gt(2.0), 0.0);
-        assert_eq!(1.0f64.gt(1.0), 0.0);
-        assert_eq!(2.0f64.gt(1.0), 1.0);
-        assert_eq!((-1.0f64).gt(1.0), 0.0);
+        assert!(!1.0f64.gt(2.0));
+        assert!(!1.0f64.gt(1.0));
+        assert!(2.0f64.gt(1.0));
+        assert!(!(-1.0f64).gt(1.0));
     }
 
     #[test]
     fn test_i32_le_s() {
-        assert_eq!(1i32.le_s(2), 1);
-        assert_eq!(1i32.le_s(1), 1);
-        assert_eq!(2i32.le_s(1), 0);
-        assert_eq!((-1i32).le_s(1), 1);
+        assert!(1i32.le_s(2));
+        assert!(1i32.le_s(1));
+        assert!(!2i32.le_s(1));
+        assert!((-1i32).le_s(1));
     }
 
     #[test]
     fn test_i64_le_s() {
-        assert_eq!(1i64.le_s(2), 1);
-        assert_eq!(1i64.le_s(1), 1);
-        assert_eq!(2i64.le_s(1), 0);
-        assert_eq!((-1i64).le_s(1), 1);
+        assert!(1i64.le_s(2));
+        assert!(1i64.le_s(1));
+        assert!(!2i64.le_s(1));
+        assert!((-1i64).le_s(1));
     }
 
     #[test]
     fn test_i32_le_u() {
-        assert_eq!(1i32.le_u(2), 1);
-        assert_eq!(1i32.le_u(1), 1);
-        assert_eq!(2i32.le_u(1), 0);
-        assert_eq!((-1i32).le_u(1), 0);
+        assert!(1i32.le_u(2));
+        assert!(1i32.le_u(1));
+        assert!(!2i32.le_u(1));
+        assert!(!(-1i32).le_u(1));
     }
 
     #[test]
     fn test_i64_le_u() {
-        assert_eq!(1i64.le_u(2), 1);
-        assert_eq!(1i64.le_u(1), 1);
-        assert_eq!(2i64.le_u(1), 0);
-        assert_eq!((-1i64).le_u(1), 0);
+        assert!(1i64.le_u(2));
+        assert!(1i64.le_u(1));
+        assert!(!2i64.le_u(1));
+        assert!(!(-1i64).le_u(1));
     }
 
     #[test]
     fn test_f32_le() {
-        assert_eq!(1.0f32.le(2.0), 1.0);
-        assert_eq!(1.0f32.le(1.0), 1.0);
-        assert_eq!(2.0f32.le(1.0), 0.0);
-        assert_eq!((-1.0f32).le(1.0), 1.0);
+        assert!(1.0f32.le(2.0));
+        assert!(1.0f32.le(1.0));
+        assert!(!2.0f32.le(1.0));
+        assert!((-1.0f32).le(1.0));
     }
 
     #[test]
     fn test_f64_le() {
-        assert_eq!(1.0f64.le(2.0), 1.0);
-        assert_eq!(1.0f64.le(1.0), 1.0);
-        assert_eq!(2.0f64.le(1.0), 0.0);
-        assert_eq!((-1.0f64).le(1.0), 1.0);
+        assert!(1.0f64.le(2.0));
+        assert!(1.0f64.le(1.0));
+        assert!(!2.0f64.le(1.0));
+        assert!((-1.0f64).le(1.0));
     }
 
     #[test]
     fn test_i32_ge_s() {
-        assert_eq!(1i32.ge_s(2), 0);
-        assert_eq!(1i32.ge_s(1), 1);
-        assert_eq!(2i32.ge_s(1), 1);
-        assert_eq!((-1i32).ge_s(1), 0);
+        assert!(!1i32.ge_s(2));
+        assert!(1i32.ge_s(1));
+        assert!(2i32.ge_s(1));
+        assert!(!(-1i32).ge_s(1));
     }
 
     #[test]
     fn test_i64_ge_s() {
-        assert_eq!(1i64.ge_s(2), 0);
-        assert_eq!(1i64.ge_s(1), 1);
-        assert_eq!(2i64.ge_s(1), 1);
-        assert_eq!((-1i64).ge_s(1), 0);
+        assert!(!1i64.ge_s(2));
+        assert!(1i64.ge_s(1));
+        assert!(2i64.ge_s(1));
+        assert!(!(-1i64).ge_s(1));
     }
 
     #[test]
     fn test_i32_ge_u() {
-        assert_eq!(1i32.ge_u(2), 0);
-        assert_eq!(1i32.ge_u(1), 1);
-        assert_eq!(2i32.ge_u(1), 1);
-        assert_eq!((-1i32).ge_u(1), 1);
+        assert!(!1i32.ge_u(2));
+        assert!(1i32.ge_u(1));
+        assert!(2i32.ge_u(1));
+        assert!((-1i32).ge_u(1));
     }
 
     #[test]
     fn test_i64_ge_u() {
-        assert_eq!(1i64.ge_u(2), 0);
-        assert_eq!(1i64.ge_u(1), 1);
-        assert_eq!(2i64.ge_u(1), 1);
-        assert_eq!((-1i64).ge_u(1), 1);
+        assert!(!1i64.ge_u(2));
+        assert!(1i64.ge_u(1));
+        assert!(2i64.ge_u(1));
+        assert!((-1i64).ge_u(1));
     }
 
     #[test]
     fn test_f32_ge() {
-        assert_eq!(1.0f32.ge(2.0), 0.0);
-        assert_eq!(1.0f32.ge(1.0), 1.0);
-        assert_eq!(2.0f32.ge(1.0), 1.0);
-        assert_eq!((-1.0f32).ge(1.0), 0.0);
+        assert!(!1.0f32.ge(2.0));
+        assert!(1.0f32.ge(1.0));
+        assert!(2.0f32.ge(1.0));
+        assert!(!(-1.0f32).ge(1.0));
+    }
+
+    #[test]
+    fn test_f32_neg() {
+        assert_eq!(FloatOps::neg(1.5f32), -1.5);
+        assert_eq!(FloatOps::neg(0.0f32).to_bits(), 0x8000_0000);
+        assert_eq!(FloatOps::neg(-0.0f32).to_bits(), 0);
+        assert_eq!(
+            FloatOps::neg(f32::from_bits(0x7fa0_0001)).to_bits(),
+            0xffa0_0001
+        );
+    }
+
+    #[test]
+    fn test_f64_neg() {
+        assert_eq!(FloatOps::neg(0.0f64).to_bits(), 0x8000_0000_0000_0000);
+        assert_eq!(
+            FloatOps::neg(f64::from_bits(0xfff0_0000_0000_0001)).to_bits(),
+            0x7ff0_0000_0000_0001
+        );
+    }
+
+    #[test]
+    fn test_f32_min_max() {
+        assert_eq!(FloatOps::min(1.0f32, 2.0), 1.0);
+        assert_eq!(FloatOps::max(1.0f32, 2.0), 2.0);
+        assert!(FloatOps::min(f32::NAN, 2.0).is_nan());
+        assert!(FloatOps::min(1.0, f32::NAN).is_nan());
+        assert!(FloatOps::max(f32::NAN, 2.0).is_nan());
+        assert!(FloatOps::max(1.0, f32::NAN).is_nan());
+    }
+
+    #[test]
+    fn test_f32_nearest() {
+        assert_eq!(FloatOps::nearest(0.5f32), 0.0);
+        assert_eq!(FloatOps::nearest(2.5f32), 2.0);
+        assert_eq!(FloatOps::nearest(-3.5f32), -4.0);
+        assert!(FloatOps::nearest(-0.5f32).is_sign_negative());
+        let nan = FloatOps::nearest(f32::from_bits(0x7fa0_0000));
+        assert_eq!(nan.to_bits() & 0x0040_0000, 0x0040_0000);
+    }
+
+    #[test]
+    fn test_f32_min_max_signed_zero() {
+        assert!(FloatOps::min(0.0f32, -0.0).is_sign_negative());
+        assert!(FloatOps::min(-0.0f32, 0.0).is_sign_negative());
+        assert!(FloatOps::max(0.0f32, -0.0).is_sign_positive());
+        assert!(FloatOps::max(-0.0f32, 0.0).is_sign_positive());
+        assert!(FloatOps::max(-0.0f32, -0.0).is_sign_negative());
+    }
+
+    #[test]
+    fn test_f64_min_max() {
+        assert_eq!(FloatOps::min(1.0f64, 2.0), 1.0);
+        assert_eq!(FloatOps::max(1.0f64, 2.0), 2.0);
+        assert!(FloatOps::min(f64::NAN, 2.0).is_nan());
+        assert!(FloatOps::min(1.0, f64::NAN).is_nan());
+        assert!(FloatOps::max(f64::NAN, 2.0).is_nan());
+        assert!(FloatOps::max(1.0, f64::NAN).is_nan());
+    }
+
+    #[test]
+    fn test_f64_min_max_signed_zero() {
+        assert!(FloatOps::min(0.0f64, -0.0).is_sign_negative());
+        assert!(FloatOps::min(-0.0f64, 0.0).is_sign_negative());
+        assert!(FloatOps::max(0.0f64, -0.0).is_sign_positive());
+        assert!(FloatOps::max(-0.0f64, 0.0).is_sign_positive());
+        assert!(FloatOps::min(0.0f64, 0.0).is_sign_positive());
+    }
+
+    #[test]
+    fn test_canonicalize_nan() {
+        assert_eq!(
+            f32::from_bits(0xffa0_0001).canonicalize_nan().to_bits(),
+            0x7fc0_0000
+        );
+        assert_eq!(
+            f64::from_bits(0x7ff0_0000_0000_0001)
+                .canonicalize_nan()
+                .to_bits(),
+            0x7ff8_0000_0000_0000
+        );
+        assert_eq!(1.5f32.canonicalize_nan(), 1.5);
     }
 }
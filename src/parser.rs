@@ -1,20 +1,49 @@
+use wast::core::Data;
+use wast::core::Elem;
 use wast::core::Expression;
 use wast::core::Func;
+use wast::core::Global;
 use wast::core::Local;
 use wast::core::LocalParser;
+use wast::core::Memory;
+use wast::core::Table;
 use wast::kw;
+use wast::parser::Cursor;
 use wast::parser::Parse;
 use wast::parser::ParseBuffer;
 use wast::parser::Parser;
+use wast::parser::Peek;
 use wast::parser::Result;
+use wast::token::Id;
+use wast::token::LParen;
+use wast::token::Span;
 
 use anyhow::Result as AnyhowResult;
+use std::marker::PhantomData;
+
+use crate::model::{SourceError, UnsupportedInstruction};
 
 pub enum Line<'a> {
     Expression(LineExpression<'a>),
     Func(Func<'a>),
+    Global(Global<'a>),
+    Table(Table<'a>),
+    Memory(Memory<'a>),
+    Elem(Elem<'a>),
+    Data(Data<'a>),
+    AssertTrap(AssertTrap<'a>),
+    Module(Vec<Line<'a>>),
+}
+
+/// `(assert_trap <folded instrs> "message")`, where the message is an
+/// optional substring of the expected error.
+pub struct AssertTrap<'a> {
+    pub expr: Expression<'a>,
+    pub message: Option<&'a str>,
 }
 
+wast::custom_keyword!(assert_trap);
+
 pub struct LineExpression<'a> {
     pub locals: Vec<Local<'a>>,
     pub expr: Expression<'a>,
@@ -27,6 +56,41 @@ impl<'a> Parse<'a> for Line<'a> {
             return Ok(Line::Func(func));
         }
 
+        if parser.peek2::<kw::global>()? {
+            let global = parser.parens(|p| p.parse::<Global>())?;
+            return Ok(Line::Global(global));
+        }
+
+        if parser.peek2::<kw::table>()? {
+            let table = parser.parens(|p| p.parse::<Table>())?;
+            return Ok(Line::Table(table));
+        }
+
+        if parser.peek2::<kw::memory>()? {
+            let memory = parser.parens(|p| p.parse::<Memory>())?;
+            return Ok(Line::Memory(memory));
+        }
+
+        if parser.peek2::<kw::elem>()? {
+            let elem = parser.parens(|p| p.parse::<Elem>())?;
+            return Ok(Line::Elem(elem));
+        }
+
+        if parser.peek2::<kw::data>()? {
+            let data = parser.parens(|p| p.parse::<Data>())?;
+            return Ok(Line::Data(data));
+        }
+
+        if parser.peek2::<assert_trap>()? {
+            let assert = parser.parens(|p| p.parse::<AssertTrap>())?;
+            return Ok(Line::AssertTrap(assert));
+        }
+
+        if parser.peek2::<kw::module>()? {
+            let fields = parser.parens(parse_module)?;
+            return Ok(Line::Module(fields));
+        }
+
         let mut locals = Vec::new();
         while parser.peek2::<kw::local>()? {
             parser.parens(|p| {
@@ -35,6 +99,11 @@ impl<'a> Parse<'a> for Line<'a> {
             })?;
         }
 
+        if let Some(definition) = definition_ahead(parser)? {
+            let message = format!("{} has to be on a line of its own", definition);
+            return Err(parser.error(message));
+        }
+
         Ok(Line::Expression(LineExpression {
             locals,
             expr: parser.parse()?,
@@ -42,21 +111,350 @@ impl<'a> Parse<'a> for Line<'a> {
     }
 }
 
+/// Peeks past the instructions of a line, plain or folded, for a `K`
+/// form at the top level, as in `(i32.const 1) (func ...)`.
+struct FormAhead<K>(PhantomData<K>);
+
+impl<K: Peek> Peek for FormAhead<K> {
+    fn peek(mut cursor: Cursor<'_>) -> Result<bool> {
+        let mut depth = 0;
+        loop {
+            if let Some(next) = cursor.lparen()? {
+                if depth == 0 && K::peek(next)? {
+                    return Ok(true);
+                }
+                depth += 1;
+                cursor = next;
+            } else if let Some(next) = cursor.rparen()? {
+                if depth == 0 {
+                    return Ok(false);
+                }
+                depth -= 1;
+                cursor = next;
+            } else if let Some((_, next)) = cursor.keyword()? {
+                cursor = next;
+            } else if let Some((_, next)) = cursor.id()? {
+                cursor = next;
+            } else if let Some((_, next)) = cursor.reserved()? {
+                cursor = next;
+            } else if let Some((_, next)) = cursor.integer()? {
+                cursor = next;
+            } else if let Some((_, next)) = cursor.float()? {
+                cursor = next;
+            } else if let Some((_, next)) = cursor.string()? {
+                cursor = next;
+            } else {
+                return Ok(false);
+            }
+        }
+    }
+
+    fn display() -> &'static str {
+        K::display()
+    }
+}
+
+/// The kind of the first definition left on the line, if any. Definitions
+/// are not instructions, so they can not follow any.
+fn definition_ahead(parser: Parser) -> Result<Option<&'static str>> {
+    let definition = if parser.peek::<FormAhead<kw::func>>()? {
+        Some("func")
+    } else if parser.peek::<FormAhead<kw::global>>()? {
+        Some("global")
+    } else if parser.peek::<FormAhead<kw::table>>()? {
+        Some("table")
+    } else if parser.peek::<FormAhead<kw::memory>>()? {
+        Some("memory")
+    } else if parser.peek::<FormAhead<kw::elem>>()? {
+        Some("elem")
+    } else if parser.peek::<FormAhead<kw::data>>()? {
+        Some("data")
+    } else if parser.peek::<FormAhead<kw::module>>()? {
+        Some("module")
+    } else if parser.peek::<FormAhead<assert_trap>>()? {
+        Some("assert_trap")
+    } else {
+        None
+    };
+    Ok(definition)
+}
+
+/// `(module $id (func ...) ...)`, of which the fields that can be defined
+/// on a line of their own are supported, except for `memory`.
+fn parse_module<'a>(parser: Parser<'a>) -> Result<Vec<Line<'a>>> {
+    parser.parse::<kw::module>()?;
+    parser.parse::<Option<Id>>()?;
+
+    let mut fields = Vec::new();
+    while !parser.is_empty() {
+        if !(parser.peek2::<kw::func>()?
+            || parser.peek2::<kw::global>()?
+            || parser.peek2::<kw::table>()?
+            || parser.peek2::<kw::elem>()?
+            || parser.peek2::<kw::data>()?)
+        {
+            let message = "only func, global, table, elem and data are supported in a module";
+            return Err(parser.error(message));
+        }
+        fields.push(parser.parse::<Line>()?);
+    }
+    Ok(fields)
+}
+
+impl<'a> Parse<'a> for AssertTrap<'a> {
+    fn parse(parser: Parser<'a>) -> Result<Self> {
+        parser.parse::<assert_trap>()?;
+
+        let mut instrs = Vec::new();
+        while parser.peek::<LParen>()? {
+            let expr = Expression::parse_folded_instruction(parser)?;
+            instrs.extend(expr.instrs.into_vec());
+        }
+
+        Ok(AssertTrap {
+            expr: Expression {
+                instrs: instrs.into(),
+            },
+            message: parser.parse()?,
+        })
+    }
+}
+
+/// The top level forms of a file, each of which is run as a line of its
+/// own, with where they start. Expressions have to be folded, as in
+/// `(i32.const 1)`.
+struct Lines<'a> {
+    lines: Vec<(Span, Line<'a>)>,
+}
+
+impl<'a> Parse<'a> for Lines<'a> {
+    fn parse(parser: Parser<'a>) -> Result<Self> {
+        let mut lines = Vec::new();
+        while !parser.is_empty() {
+            let span = parser.cur_span();
+            if parser.peek2::<kw::func>()?
+                || parser.peek2::<kw::global>()?
+                || parser.peek2::<kw::table>()?
+                || parser.peek2::<kw::elem>()?
+                || parser.peek2::<kw::data>()?
+                || parser.peek2::<assert_trap>()?
+                || parser.peek2::<kw::module>()?
+            {
+                lines.push((span, parser.parse::<Line>()?));
+            } else {
+                let line = Line::Expression(LineExpression {
+                    locals: vec![],
+                    expr: Expression::parse_folded_instruction(parser)?,
+                });
+                lines.push((span, line));
+            }
+        }
+        Ok(Lines { lines })
+    }
+}
+
+pub fn parse_lines<'a>(buf: &'a ParseBuffer) -> AnyhowResult<Vec<(Span, Line<'a>)>> {
+    match wast::parser::parse::<Lines>(buf) {
+        Ok(lines) => Ok(lines.lines),
+        Err(err) => Err(err.into()),
+    }
+}
+
 pub fn parse_line<'a>(buf: &'a ParseBuffer) -> AnyhowResult<Line<'a>> {
     match wast::parser::parse::<Line>(buf) {
         Ok(line) => Ok(line),
-        Err(err) => Err(anyhow::anyhow!(err.to_string())),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Rewrites binary integer literals like `0b1010`, which the text format
+/// does not have, to decimal. Strings and comments are left as they are.
+pub fn binary_literals(line_str: &str) -> String {
+    rewrite_binary_literals(line_str).src
+}
+
+/// Source with its binary literals rewritten to decimal, which keeps
+/// where each came from, so that errors can point at the original.
+pub struct Rewrite {
+    pub src: String,
+    // Offset and length of each literal in `src`, and in the original.
+    literals: Vec<((usize, usize), (usize, usize))>,
+}
+
+impl Rewrite {
+    /// The offset in the original source of `offset` in the rewritten one.
+    /// An offset within a rewritten literal is the start of the literal.
+    pub fn original_offset(&self, offset: usize) -> usize {
+        let mut shift = 0isize;
+        for ((start, len), (original_start, original_len)) in self.literals.iter() {
+            if offset < *start {
+                break;
+            }
+            if offset < start + len {
+                return *original_start;
+            }
+            shift += *original_len as isize - *len as isize;
+        }
+        (offset as isize + shift) as usize
+    }
+
+    /// Points a `wast` error at the original source instead of the
+    /// rewritten one. Other errors are left as they are.
+    pub fn original_error(&self, original: &str, err: anyhow::Error) -> anyhow::Error {
+        match err.downcast::<wast::Error>() {
+            Ok(err) => {
+                let offset = self.original_offset(err.span().offset());
+                let mut err = wast::Error::new(Span::from_offset(offset), err.message());
+                err.set_text(original);
+                err.into()
+            }
+            Err(err) => err,
+        }
+    }
+}
+
+/// Rewrites binary literals as `binary_literals` does, keeping where
+/// they came from.
+pub fn rewrite_binary_literals(line_str: &str) -> Rewrite {
+    let mut src = String::with_capacity(line_str.len());
+    let mut literals = Vec::new();
+    let mut last = 0;
+    for (offset, atom) in atoms(line_str) {
+        if let Some(decimal) = binary_to_decimal(atom) {
+            src.push_str(&line_str[last..offset]);
+            literals.push(((src.len(), decimal.len()), (offset, atom.len())));
+            src.push_str(&decimal);
+            last = offset + atom.len();
+        }
+    }
+    src.push_str(&line_str[last..]);
+    Rewrite { src, literals }
+}
+
+/// Points an error which names an unsupported instruction at where the
+/// instruction is in `src`. Conversion from `wast` loses the positions, so
+/// it is the first instruction of that name which is pointed at.
+pub fn locate_error(src: &str, err: anyhow::Error) -> anyhow::Error {
+    let name = match err.downcast_ref::<UnsupportedInstruction>() {
+        Some(UnsupportedInstruction(name)) => name,
+        None => return err,
+    };
+    let found = atoms(src)
+        .into_iter()
+        .find(|(_, atom)| wast_name(atom) == *name);
+    match found {
+        Some((offset, atom)) => SourceError::new(src, offset, atom.len(), err).into(),
+        None => err,
+    }
+}
+
+/// The name `wast` gives to an instruction keyword, like `I32AtomicLoad`
+/// for `i32.atomic.load`.
+fn wast_name(keyword: &str) -> String {
+    keyword
+        .split(['.', '_'])
+        .flat_map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_uppercase())
+                .into_iter()
+                .chain(chars)
+        })
+        .collect()
+}
+
+/// The atoms of `src`, like keywords, ids and numbers, with their offsets.
+/// Parens, strings and comments are skipped.
+fn atoms(src: &str) -> Vec<(usize, &str)> {
+    let mut atoms = Vec::new();
+    let mut offset = 0;
+    while let Some(c) = src[offset..].chars().next() {
+        let rest = &src[offset..];
+        let end = if c == '"' {
+            string_end(rest)
+        } else if rest.starts_with(";;") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if c.is_whitespace() || c == '(' || c == ')' || c == ';' {
+            c.len_utf8()
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "()\";".contains(c))
+                .unwrap_or(rest.len());
+            atoms.push((offset, &rest[..end]));
+            end
+        };
+        offset += end;
+    }
+    atoms
+}
+
+/// The length of the string literal at the start of `s`, quotes included.
+fn string_end(s: &str) -> usize {
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' => return i + 1,
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+fn binary_to_decimal(token: &str) -> Option<String> {
+    let (sign, unsigned) = match token.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", token.strip_prefix('+').unwrap_or(token)),
+    };
+    let digits = unsigned.strip_prefix("0b")?;
+    if digits.starts_with('_') || digits.ends_with('_') {
+        return None;
+    }
+
+    let n = u64::from_str_radix(&digits.replace('_', ""), 2).ok()?;
+    Some(format!("{}{}", sign, n))
+}
+
+/// Rewords the parse error for a `*.const` given a literal of the wrong
+/// kind, like `(i32.const 3.14)` or `(f32.const true)`.
+pub fn const_error(line_str: &str, err: &anyhow::Error) -> Option<String> {
+    let err = err.downcast_ref::<wast::Error>()?;
+    let expected = match err.message().as_str() {
+        "expected a i32" | "expected a i64" => "an integer",
+        "expected a float" => "a float",
+        _ => return None,
+    };
+
+    let offset = err.span().offset();
+    let instr = line_str[..offset].split_whitespace().last()?;
+    let instr = instr.trim_start_matches('(');
+    if !instr.ends_with(".const") {
+        return None;
     }
+
+    let found = line_str[offset..]
+        .split(|c: char| c.is_whitespace() || c == ')')
+        .next()
+        .filter(|found| !found.is_empty())
+        .unwrap_or("nothing");
+    Some(format!("{} expects {}, found {}", instr, expected, found))
 }
 
 #[cfg(test)]
 mod tests {
     use wast::{
-        core::Instruction,
+        core::{ElemPayload, Instruction},
         parser::{parse, ParseBuffer},
     };
 
-    use crate::parser::{parse_line, Line};
+    use crate::model::{SourceError, UnsupportedInstruction};
+    use crate::parser::{
+        binary_literals, locate_error, parse_line, parse_lines, rewrite_binary_literals, wast_name,
+        Line,
+    };
 
     #[test]
     fn test_line_parse_expr() {
@@ -82,7 +480,7 @@ mod tests {
 
         if let Line::Expression(line_expr) = lp {
             assert_eq!(line_expr.locals.len(), 1);
-            let lc = line_expr.locals.get(0).unwrap();
+            let lc = line_expr.locals.first().unwrap();
             assert_eq!(lc.id.unwrap().name(), "num");
         } else {
             panic!("Expected Line::Expression");
@@ -101,6 +499,113 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_line_parse_data() {
+        let buf = ParseBuffer::new("(data $d (i32.const 0) \"ab\")").unwrap();
+        assert!(matches!(parse::<Line>(&buf).unwrap(), Line::Data(_)));
+        let buf = ParseBuffer::new("(data \"ab\")").unwrap();
+        assert!(matches!(parse::<Line>(&buf).unwrap(), Line::Data(_)));
+    }
+
+    #[test]
+    fn test_line_parse_memory() {
+        let buf = ParseBuffer::new("(memory $m (export \"mem\") 1 2)").unwrap();
+        if let Line::Memory(memory) = parse::<Line>(&buf).unwrap() {
+            assert_eq!(memory.id.unwrap().name(), "m");
+            assert_eq!(memory.exports.names, vec!["mem"]);
+        } else {
+            panic!("Expected Line::Memory");
+        }
+
+        let buf = ParseBuffer::new("(memory (import \"env\" \"mem\") 1)").unwrap();
+        assert!(matches!(parse::<Line>(&buf).unwrap(), Line::Memory(_)));
+    }
+
+    #[test]
+    fn test_line_parse_table_elem() {
+        let buf = ParseBuffer::new("(table $t 2 funcref)").unwrap();
+        if let Line::Table(table) = parse::<Line>(&buf).unwrap() {
+            assert_eq!(table.id.unwrap().name(), "t");
+        } else {
+            panic!("Expected Line::Table");
+        }
+
+        let buf = ParseBuffer::new("(elem (i32.const 0) $f $g)").unwrap();
+        if let Line::Elem(elem) = parse::<Line>(&buf).unwrap() {
+            assert!(matches!(elem.payload, ElemPayload::Indices(funcs) if funcs.len() == 2));
+        } else {
+            panic!("Expected Line::Elem");
+        }
+        let buf = ParseBuffer::new("(elem $e func $f $g)").unwrap();
+        assert!(matches!(parse::<Line>(&buf).unwrap(), Line::Elem(_)));
+    }
+
+    #[test]
+    fn test_line_parse_global() {
+        let buf = ParseBuffer::new("(global $g (mut i32) (i32.const 1))").unwrap();
+        let lp = parse::<Line>(&buf).unwrap();
+
+        if let Line::Global(global) = lp {
+            assert_eq!(global.id.unwrap().name(), "g");
+            assert!(global.ty.mutable);
+        } else {
+            panic!("Expected Line::Global");
+        }
+    }
+
+    #[test]
+    fn test_line_parse_assert_trap() {
+        let buf = ParseBuffer::new(
+            "(assert_trap (i32.div_s (i32.const 1) (i32.const 0)) (drop) \"divide by zero\")",
+        )
+        .unwrap();
+        let lp = parse::<Line>(&buf).unwrap();
+
+        if let Line::AssertTrap(assert) = lp {
+            assert_eq!(assert.expr.instrs.len(), 4);
+            assert_eq!(assert.message, Some("divide by zero"));
+        } else {
+            panic!("Expected Line::AssertTrap");
+        }
+    }
+
+    #[test]
+    fn test_line_parse_module() {
+        let buf = ParseBuffer::new("(module $m (func $f (i32.const 1)) (func $g (i32.const 2)))")
+            .unwrap();
+        let lp = parse::<Line>(&buf).unwrap();
+
+        if let Line::Module(fields) = lp {
+            assert_eq!(fields.len(), 2);
+            assert!(matches!(&fields[1], Line::Func(func) if func.id.unwrap().name() == "g"));
+        } else {
+            panic!("Expected Line::Module");
+        }
+    }
+
+    #[test]
+    fn test_line_parse_module_fields() {
+        let src = "(module (func $f) (global $g i32 (i32.const 1)) (table 1 funcref) \
+            (elem (i32.const 0) $f) (data \"ab\"))";
+        let buf = ParseBuffer::new(src).unwrap();
+        if let Line::Module(fields) = parse::<Line>(&buf).unwrap() {
+            assert!(matches!(fields[1], Line::Global(_)));
+            assert!(matches!(fields[2], Line::Table(_)));
+            assert!(matches!(fields[3], Line::Elem(_)));
+            assert!(matches!(fields[4], Line::Data(_)));
+        } else {
+            panic!("Expected Line::Module");
+        }
+    }
+
+    #[test]
+    fn test_line_parse_module_error() {
+        let buf = ParseBuffer::new("(module (func $f) (memory 1))").unwrap();
+        assert!(parse::<Line>(&buf).is_err());
+        let buf = ParseBuffer::new("(module (func $f) (i32.const 1))").unwrap();
+        assert!(parse::<Line>(&buf).is_err());
+    }
+
     #[test]
     fn test_parse_line() {
         let buf = ParseBuffer::new("(i32.const 32)").unwrap();
@@ -113,4 +618,131 @@ mod tests {
         let line = parse_line(&buf);
         assert!(line.is_err());
     }
+
+    #[test]
+    fn test_parse_line_sequence() {
+        let buf = ParseBuffer::new("(i32.const 1)(i32.const 2)(i32.add)").unwrap();
+        if let Line::Expression(line_expr) = parse_line(&buf).unwrap() {
+            let instrs = line_expr.expr.instrs;
+            assert_eq!(instrs.len(), 3);
+            assert!(matches!(instrs[0], Instruction::I32Const(1)));
+            assert!(matches!(instrs[1], Instruction::I32Const(2)));
+            assert!(matches!(instrs[2], Instruction::I32Add));
+        } else {
+            panic!("Expected Line::Expression");
+        }
+    }
+
+    #[test]
+    fn test_parse_line_mixed() {
+        let line = "i32.const 1 (i32.add (i32.const 2) (i32.const 3)) (if (then)) drop";
+        let buf = ParseBuffer::new(line).unwrap();
+        if let Line::Expression(line_expr) = parse_line(&buf).unwrap() {
+            assert_eq!(line_expr.expr.instrs.len(), 7);
+        } else {
+            panic!("Expected Line::Expression");
+        }
+    }
+
+    #[test]
+    fn test_parse_line_incomplete_paren_error() {
+        let buf = ParseBuffer::new("(i32.const 1) (i32.const 2").unwrap();
+        assert!(parse_line(&buf).is_err());
+    }
+
+    #[test]
+    fn test_parse_line_definition_error() {
+        let buf = ParseBuffer::new("(i32.const 1) (func $f (i32.const 2))").unwrap();
+        let err = parse_line(&buf).err().unwrap();
+        assert!(err
+            .to_string()
+            .starts_with("func has to be on a line of its own"));
+    }
+
+    #[test]
+    fn test_parse_line_nested_definition_keyword() {
+        // A `func` keyword inside an instruction is left to the instruction.
+        let buf = ParseBuffer::new("(i32.const 1) (ref.null func)").unwrap();
+        assert!(parse_line(&buf).is_ok());
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let src = ";; definitions\n(func $f (i32.const 1))\n(call $f) (module)\n";
+        let buf = ParseBuffer::new(src).unwrap();
+        let lines = parse_lines(&buf).unwrap();
+
+        assert_eq!(lines.len(), 3);
+        assert!(matches!(lines[0].1, Line::Func(_)));
+        assert!(matches!(lines[1].1, Line::Expression(_)));
+        assert!(matches!(lines[2].1, Line::Module(_)));
+        assert_eq!(lines[0].0.linecol_in(src), (1, 0));
+        assert_eq!(lines[2].0.linecol_in(src), (2, 10));
+    }
+
+    #[test]
+    fn test_parse_lines_error() {
+        let buf = ParseBuffer::new("(func $f (i32.const 1)) i32.const 2").unwrap();
+        assert!(parse_lines(&buf).is_err());
+    }
+
+    #[test]
+    fn test_rewrite_original_offset() {
+        let src = "(i32.const 0b1) (i32.const 0b1010) (i32.foo)";
+        let rewrite = rewrite_binary_literals(src);
+        assert_eq!(rewrite.src, "(i32.const 1) (i32.const 10) (i32.foo)");
+        assert_eq!(rewrite.original_offset(0), 0);
+        assert_eq!(rewrite.original_offset(11), 11);
+        assert_eq!(rewrite.original_offset(15), 17);
+        assert_eq!(rewrite.original_offset(25), 27);
+        assert_eq!(rewrite.original_offset(27), 33);
+        assert_eq!(rewrite.original_offset(30), src.find("i32.foo").unwrap());
+    }
+
+    #[test]
+    fn test_rewrite_original_error() {
+        let src = "(i32.const 0b1) (i32.foo)";
+        let rewrite = rewrite_binary_literals(src);
+        let buf = ParseBuffer::new(&rewrite.src).unwrap();
+        let err = parse_line(&buf).err().unwrap();
+        let err = rewrite.original_error(src, err);
+        let err = err.downcast_ref::<wast::Error>().unwrap();
+        assert_eq!(err.span().offset(), src.find("i32.foo").unwrap());
+        assert!(err.to_string().contains(src));
+    }
+
+    #[test]
+    fn test_binary_literals_rewrite() {
+        assert_eq!(binary_literals("(i32.const 0b11)"), "(i32.const 3)");
+        assert_eq!(binary_literals("i32.const +0b11"), "i32.const 3");
+        assert_eq!(binary_literals("(local $0b1 i32)"), "(local $0b1 i32)");
+        assert_eq!(
+            binary_literals("(assert_trap (nop) \"0b1 \\\" 0b1\") ;; 0b1"),
+            "(assert_trap (nop) \"0b1 \\\" 0b1\") ;; 0b1"
+        );
+        assert_eq!(binary_literals("0b_1 0b1_ 0b 0x0b1"), "0b_1 0b1_ 0b 0x0b1");
+    }
+
+    #[test]
+    fn test_wast_name() {
+        assert_eq!(wast_name("i32.atomic.load"), "I32AtomicLoad");
+        assert_eq!(wast_name("i32.trunc_sat_f32_s"), "I32TruncSatF32S");
+        assert_eq!(wast_name("ref.null"), "RefNull");
+    }
+
+    #[test]
+    fn test_locate_error() {
+        let src = "(i32.const 1) ;; ref.null\n  (ref.null func)";
+        let err = UnsupportedInstruction(String::from("RefNull")).into();
+        let err = locate_error(src, err);
+        assert_eq!(
+            err.to_string(),
+            "Unsupported instruction: RefNull at offset 29"
+        );
+        let underline = err.downcast_ref::<SourceError>().unwrap().underline();
+        assert_eq!(underline, "  (ref.null func)\n   ^^^^^^^^");
+
+        let err = locate_error(src, anyhow::anyhow!("other"));
+        assert_eq!(err.to_string(), "other");
+    }
 }
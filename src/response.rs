@@ -1,9 +1,11 @@
 use crate::model::{BlockType, Expression, Index};
+use crate::value::Value;
 
 pub struct Response {
     pub control: Control,
     pub requires_empty: bool,
     messages: Vec<String>,
+    stack_snapshot: Option<Vec<Value>>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -13,12 +15,22 @@ pub enum Control {
     ExecLoop(BlockType, Expression),
     Branch(Index),
     Return,
+    ReturnCall(Index),
     None,
 }
 
 impl Control {
     fn requires_empty(&self) -> bool {
-        !matches!(self, Control::Return | Control::Branch(_))
+        !matches!(
+            self,
+            Control::Return | Control::Branch(_) | Control::ReturnCall(_)
+        )
+    }
+}
+
+impl Default for Response {
+    fn default() -> Self {
+        Response::new()
     }
 }
 
@@ -28,6 +40,7 @@ impl Response {
             messages: Vec::new(),
             control: Control::None,
             requires_empty: true,
+            stack_snapshot: None,
         }
     }
 
@@ -52,20 +65,32 @@ impl Response {
         self.messages.join("\n")
     }
 
+    /// The committed stack after a line of instructions, bottom first.
+    /// `None` for definitions.
+    pub fn stack_snapshot(&self) -> Option<&[Value]> {
+        self.stack_snapshot.as_deref()
+    }
+
+    pub fn set_stack_snapshot(&mut self, values: Vec<Value>) {
+        self.stack_snapshot = Some(values);
+    }
+
     pub fn new_ctrl(ctrl: Control) -> Response {
         let requires_empty = ctrl.requires_empty();
         Response {
             messages: Vec::new(),
             control: ctrl,
             requires_empty,
+            stack_snapshot: None,
         }
     }
 
-    fn new_message(message: String) -> Response {
+    pub fn new_message(message: String) -> Response {
         Response {
             messages: vec![message],
             control: Control::None,
             requires_empty: true,
+            stack_snapshot: None,
         }
     }
 }
@@ -76,6 +101,7 @@ mod tests {
     use crate::{
         model::Index,
         response::{Control, Response},
+        value::Value,
     };
 
     #[test]
@@ -83,14 +109,14 @@ mod tests {
         let resp = Response::new();
         assert_eq!(resp.message(), "");
         assert_eq!(resp.control, Control::None);
-        assert_eq!(resp.requires_empty, true);
+        assert!(resp.requires_empty);
     }
 
     #[test]
     fn test_new_index() {
         let resp = Response::new_index("local", 0, None);
         assert_eq!(resp.message(), "local ;0;");
-        assert_eq!(resp.requires_empty, true);
+        assert!(resp.requires_empty);
     }
 
     #[test]
@@ -108,7 +134,7 @@ mod tests {
         resp1.extend(resp2);
         assert_eq!(resp1.message(), "local ;0;\nlocal ;1;");
         assert_eq!(resp1.control, Control::Return);
-        assert_eq!(resp1.requires_empty, false)
+        assert!(!resp1.requires_empty)
     }
 
     #[test]
@@ -119,6 +145,14 @@ mod tests {
         assert_eq!(resp.message(), "foo\nbar");
     }
 
+    #[test]
+    fn test_stack_snapshot() {
+        let mut resp = Response::new();
+        assert_eq!(resp.stack_snapshot(), None);
+        resp.set_stack_snapshot(vec![Value::I32(1)]);
+        assert_eq!(resp.stack_snapshot(), Some(&[Value::I32(1)][..]));
+    }
+
     #[test]
     fn test_new_return() {
         let resp = Response::new_ctrl(Control::Return);
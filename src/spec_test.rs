@@ -0,0 +1,299 @@
+//! Runs a subset of the WebAssembly spec test suite through the executor.
+//!
+//! Every `module` in a `.wast` file starts a fresh executor, with its
+//! exported functions defined in order. `assert_return` and `assert_trap`
+//! directives invoke those exports, and `assert_invalid` defines the
+//! functions of its module in an executor of its own. Directives, functions
+//! and values which need features we do not support yet are skipped with a
+//! message.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use wast::core::{ModuleField, ModuleKind, NanPattern, WastArgCore, WastRetCore};
+use wast::parser::{self, ParseBuffer};
+use wast::token::Span;
+use wast::{QuoteWat, Wast, WastArg, WastDirective, WastExecute, WastInvoke, WastRet, Wat};
+
+use crate::executor::Executor;
+use crate::model::{Expression, Func, FuncType, Index, Instruction, Line, LineExpression};
+use crate::value::Value;
+
+struct SpecRunner<'a> {
+    name: &'a str,
+    src: &'a str,
+    executor: Executor,
+    exports: HashMap<String, u32>,
+    failures: Vec<String>,
+}
+
+impl<'a> SpecRunner<'a> {
+    fn new(name: &'a str, src: &'a str) -> SpecRunner<'a> {
+        SpecRunner {
+            name,
+            src,
+            executor: Executor::new(),
+            exports: HashMap::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Vec<String> {
+        let buf = ParseBuffer::new(self.src).unwrap();
+        let mut wast = parser::parse::<Wast>(&buf).unwrap();
+
+        for directive in wast.directives.iter_mut() {
+            match directive {
+                WastDirective::Wat(QuoteWat::Wat(Wat::Module(module))) => match &mut module.kind {
+                    ModuleKind::Text(fields) => self.define_module(fields),
+                    ModuleKind::Binary(_) => self.skip(module.span, "binary module"),
+                },
+                WastDirective::AssertReturn {
+                    span,
+                    exec: WastExecute::Invoke(invoke),
+                    results,
+                } => self.assert_return(*span, invoke, results),
+                WastDirective::AssertTrap {
+                    span,
+                    exec: WastExecute::Invoke(invoke),
+                    message,
+                } => self.assert_trap(*span, invoke, message),
+                WastDirective::AssertInvalid {
+                    span,
+                    module: QuoteWat::Wat(Wat::Module(module)),
+                    message,
+                } => match &module.kind {
+                    ModuleKind::Text(fields) => self.assert_invalid(*span, fields, message),
+                    ModuleKind::Binary(_) => self.skip(module.span, "binary module"),
+                },
+                directive => self.skip(directive.span(), "directive"),
+            }
+        }
+
+        self.failures
+    }
+
+    fn define_module(&mut self, fields: &mut [ModuleField]) {
+        self.executor = Executor::new();
+        self.exports.clear();
+
+        for field in fields.iter_mut() {
+            let ModuleField::Func(func) = field else {
+                continue;
+            };
+
+            let names = std::mem::take(&mut func.exports.names);
+            let index = self.executor.funcs.len();
+            let defined = Func::try_from(&*func)
+                .and_then(|func| self.executor.execute_line(Line::Func(func)));
+            match defined {
+                Ok(_) => {
+                    for name in names {
+                        self.exports.insert(name.to_string(), index as u32);
+                    }
+                }
+                Err(err) => {
+                    // Keep the indices of the functions after this one intact.
+                    self.executor.funcs.grow(None, placeholder_func()).unwrap();
+                    self.executor.funcs.commit();
+                    self.skip(func.span, &format!("func {:?} ({})", names, err));
+                }
+            }
+        }
+    }
+
+    fn assert_return(&mut self, span: Span, invoke: &WastInvoke, results: &[WastRet]) {
+        let values = match self.invoke(span, invoke) {
+            Some(Ok(values)) => values,
+            Some(Err(err)) => return self.fail(span, &format!("unexpected error: {}", err)),
+            None => return,
+        };
+
+        if values.len() != results.len() || !values.iter().zip(results).all(|(v, r)| matches(v, r))
+        {
+            self.fail(span, &format!("unexpected result {:?}", values));
+        }
+    }
+
+    fn assert_trap(&mut self, span: Span, invoke: &WastInvoke, message: &str) {
+        match self.invoke(span, invoke) {
+            Some(Ok(values)) => self.fail(span, &format!("expected trap, got {:?}", values)),
+            Some(Err(err)) if !err.to_string().contains(message) => self.fail(
+                span,
+                &format!("expected trap {:?}, got {:?}", message, err.to_string()),
+            ),
+            _ => {}
+        }
+    }
+
+    fn assert_invalid(&mut self, span: Span, fields: &[ModuleField], message: &str) {
+        let mut executor = Executor::new();
+        for field in fields {
+            let ModuleField::Func(func) = field else {
+                return self.skip(span, "module field");
+            };
+            let defined =
+                Func::try_from(func).and_then(|func| executor.execute_line(Line::Func(func)));
+            match defined {
+                Ok(_) => {}
+                Err(err) if err.to_string().contains(message) => return,
+                Err(err) => {
+                    let err = err.to_string();
+                    let failure = format!("expected invalid {:?}, got {:?}", message, err);
+                    return self.fail(span, &failure);
+                }
+            }
+        }
+        self.fail(span, "expected an invalid module");
+    }
+
+    /// Calls an export with the given arguments, returning the values it
+    /// left on the stack. Returns `None` if the invocation is skipped.
+    fn invoke(&mut self, span: Span, invoke: &WastInvoke) -> Option<Result<Vec<Value>>> {
+        let Some(index) = self.exports.get(invoke.name) else {
+            self.skip(span, &format!("invoke {:?}", invoke.name));
+            return None;
+        };
+
+        let mut instrs = Vec::new();
+        for arg in invoke.args.iter() {
+            match const_instr(arg) {
+                Some(instr) => instrs.push(instr),
+                None => {
+                    self.skip(span, "argument");
+                    return None;
+                }
+            }
+        }
+        instrs.push(Instruction::Call(Index::Num(*index)));
+
+        let snapshot = self.executor.snapshot();
+        let line = Line::Expression(LineExpression {
+            locals: vec![],
+            expr: Expression { instrs },
+        });
+        let result = self.executor.execute_line(line).and_then(|_| {
+            let func_stack = self.executor.call_stack.get_func_stack()?;
+            let mut values = Vec::new();
+            for _ in 0..func_stack.len() {
+                values.insert(0, func_stack.pop()?);
+            }
+            Ok(values)
+        });
+        self.executor.restore(&snapshot);
+        Some(result)
+    }
+
+    fn skip(&self, span: Span, what: &str) {
+        println!("{}: skipping unsupported {}", self.location(span), what);
+    }
+
+    fn fail(&mut self, span: Span, message: &str) {
+        let failure = format!("{}: {}", self.location(span), message);
+        self.failures.push(failure);
+    }
+
+    fn location(&self, span: Span) -> String {
+        let (line, col) = span.linecol_in(self.src);
+        format!("{}:{}:{}", self.name, line + 1, col + 1)
+    }
+}
+
+fn placeholder_func() -> Func {
+    Func {
+        id: None,
+        ty: FuncType {
+            params: vec![],
+            results: vec![],
+        },
+        line_expression: LineExpression {
+            locals: vec![],
+            expr: Expression { instrs: vec![] },
+        },
+        recursive: false,
+    }
+}
+
+fn const_instr(arg: &WastArg) -> Option<Instruction> {
+    match arg {
+        WastArg::Core(WastArgCore::I32(i)) => Some(Instruction::I32Const(*i)),
+        WastArg::Core(WastArgCore::I64(i)) => Some(Instruction::I64Const(*i)),
+        WastArg::Core(WastArgCore::F32(f)) => Some(Instruction::F32Const(f32::from_bits(f.bits))),
+        WastArg::Core(WastArgCore::F64(f)) => Some(Instruction::F64Const(f64::from_bits(f.bits))),
+        _ => None,
+    }
+}
+
+// A canonical NaN has only the top bit of the payload set, and either
+// sign. An arithmetic NaN has that bit set, whatever the rest.
+const F32_SIGN: u32 = 0x8000_0000;
+const F32_QUIET: u32 = 0x0040_0000;
+const F32_CANONICAL_NAN: u32 = 0x7fc0_0000;
+const F64_SIGN: u64 = 0x8000_0000_0000_0000;
+const F64_QUIET: u64 = 0x0008_0000_0000_0000;
+const F64_CANONICAL_NAN: u64 = 0x7ff8_0000_0000_0000;
+
+/// Whether the value is the expected result. NaN results are matched
+/// by their pattern, as the spec leaves the sign unspecified.
+fn matches(value: &Value, ret: &WastRet) -> bool {
+    match (value, ret) {
+        (Value::I32(v), WastRet::Core(WastRetCore::I32(r))) => v == r,
+        (Value::I64(v), WastRet::Core(WastRetCore::I64(r))) => v == r,
+        (Value::F32(v), WastRet::Core(WastRetCore::F32(r))) => match r {
+            NanPattern::Value(r) => v.to_bits() == r.bits,
+            NanPattern::CanonicalNan => v.to_bits() & !F32_SIGN == F32_CANONICAL_NAN,
+            NanPattern::ArithmeticNan => v.is_nan() && v.to_bits() & F32_QUIET != 0,
+        },
+        (Value::F64(v), WastRet::Core(WastRetCore::F64(r))) => match r {
+            NanPattern::Value(r) => v.to_bits() == r.bits,
+            NanPattern::CanonicalNan => v.to_bits() & !F64_SIGN == F64_CANONICAL_NAN,
+            NanPattern::ArithmeticNan => v.is_nan() && v.to_bits() & F64_QUIET != 0,
+        },
+        _ => false,
+    }
+}
+
+fn run_spec(name: &str, src: &str) -> Result<()> {
+    let failures = SpecRunner::new(name, src).run();
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} failures\n{}",
+            failures.len(),
+            failures.join("\n")
+        ))
+    }
+}
+
+#[test]
+fn test_spec_i32() {
+    run_spec("i32.wast", include_str!("../tests/spec/i32.wast")).unwrap();
+}
+
+#[test]
+fn test_spec_i64() {
+    run_spec("i64.wast", include_str!("../tests/spec/i64.wast")).unwrap();
+}
+
+#[test]
+fn test_spec_f32() {
+    run_spec("f32.wast", include_str!("../tests/spec/f32.wast")).unwrap();
+}
+
+#[test]
+fn test_spec_f32_cmp() {
+    run_spec("f32_cmp.wast", include_str!("../tests/spec/f32_cmp.wast")).unwrap();
+}
+
+#[test]
+fn test_spec_failure() {
+    let src = r#"
+        (module (func (export "one") (result i32) (i32.const 1)))
+        (assert_return (invoke "one") (i32.const 2))
+        (assert_trap (invoke "one") "unreachable")
+        (assert_invalid (module (func (i32.const 1) (drop))) "type mismatch")
+    "#;
+    let err = run_spec("inline.wast", src).unwrap_err();
+    assert!(err.to_string().starts_with("3 failures"));
+}
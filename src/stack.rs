@@ -1,8 +1,10 @@
 use anyhow::{Error, Result};
+use std::fmt::{self, Display};
 
-use crate::value::Value;
+use crate::value::{DisplayMode, Value, ValueFormat};
 
 /// Stack with commit and rollback in constant time.
+#[derive(Clone)]
 pub struct Stack {
     values: Vec<Value>,
     shrink_by: usize,
@@ -55,6 +57,10 @@ impl Stack {
         Ok(())
     }
 
+    pub fn len(&self) -> usize {
+        self.values.len() - self.shrink_by + self.soft_values.len()
+    }
+
     pub fn is_empty(&self) -> bool {
         (self.values.len() as i32 - self.shrink_by as i32 + self.soft_values.len() as i32) == 0
     }
@@ -71,34 +77,55 @@ impl Stack {
         self.soft_values.clear();
     }
 
-    // Used only for test
-    #[allow(dead_code)]
-    pub fn to_soft_string(&self) -> Result<String> {
+    /// The committed values, bottom first.
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// The committed values.
+    pub fn format(&self, format: ValueFormat) -> String {
+        let strs: Vec<String> = self.values.iter().map(|v| format.value(v)).collect();
+        format!("[{}]", strs.join(", "))
+    }
+
+    pub fn to_soft_string(&self, format: ValueFormat) -> Result<String> {
         let mut strs = vec![];
 
         let mut i = 0;
         while i < self.values.len() - self.shrink_by {
-            strs.push(self.values[i].to_string());
+            strs.push(format.value(&self.values[i]));
             i += 1;
         }
 
         for value in self.soft_values.iter() {
-            strs.push(value.to_string());
+            strs.push(format.value(value));
         }
 
         Ok(format!("[{}]", strs.join(", ")))
     }
 
-    pub fn to_string(&self) -> String {
-        let strs: Vec<String> = self.values.iter().map(|v| v.to_string()).collect();
+    /// The committed values with their types, like `[1: i32, 2.5: f64]`.
+    pub fn to_typed_string(&self, mode: DisplayMode) -> String {
+        let strs: Vec<String> = self
+            .values
+            .iter()
+            .map(|v| format!("{}: {}", v.format(mode), v.val_type()))
+            .collect();
         format!("[{}]", strs.join(", "))
     }
 }
 
+impl Display for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(ValueFormat::new()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::stack::Stack;
-    use crate::test_utils::test_val_i32;
+    use crate::test_utils::{test_val_i32, test_val_i64};
+    use crate::value::{DisplayMode, ValueFormat};
 
     #[test]
     fn test_stack() {
@@ -231,7 +258,86 @@ mod tests {
         stack.commit();
         stack.push(test_val_i32(3));
         assert_eq!(stack.to_string(), "[1, 2]");
-        assert_eq!(stack.to_soft_string().unwrap(), "[1, 2, 3]");
+        assert_eq!(
+            stack.to_soft_string(ValueFormat::new()).unwrap(),
+            "[1, 2, 3]"
+        );
+    }
+
+    #[test]
+    fn test_stack_to_typed_string() {
+        let mut stack = Stack::new();
+        stack.push(test_val_i32(1));
+        stack.push(2.5f64.into());
+        stack.commit();
+        stack.push(test_val_i32(3));
+        assert_eq!(
+            stack.to_typed_string(DisplayMode::Decimal),
+            "[1: i32, 2.5: f64]"
+        );
+    }
+
+    #[test]
+    fn test_stack_format_hex() {
+        let hex = ValueFormat {
+            mode: DisplayMode::Hex,
+            typed: false,
+        };
+        let mut stack = Stack::new();
+        stack.push(test_val_i32(0x40000000));
+        stack.push(test_val_i64(-1));
+        stack.commit();
+        stack.push(1.5f32.into());
+        assert_eq!(stack.format(hex), "[0x40000000, 0xffffffffffffffff]");
+        assert_eq!(
+            stack.to_soft_string(hex).unwrap(),
+            "[0x40000000, 0xffffffffffffffff, 1.5]"
+        );
+        assert_eq!(stack.to_string(), "[1073741824, -1]");
+    }
+
+    #[test]
+    fn test_stack_format_typed() {
+        let typed = ValueFormat {
+            typed: true,
+            ..ValueFormat::new()
+        };
+        let mut stack = Stack::new();
+        stack.push(test_val_i32(42));
+        stack.push(test_val_i64(1));
+        stack.commit();
+        stack.push(2.5f64.into());
+        assert_eq!(stack.format(typed), "[i32:42, i64:1]");
+        assert_eq!(
+            stack.to_soft_string(typed).unwrap(),
+            "[i32:42, i64:1, f64:2.5]"
+        );
+        assert_eq!(stack.format(ValueFormat::new()), "[42, 1]");
+    }
+
+    #[test]
+    fn test_stack_values() {
+        let mut stack = Stack::new();
+        stack.push(test_val_i32(1));
+        stack.commit();
+        stack.push(test_val_i64(2));
+        assert_eq!(stack.values(), &[test_val_i32(1)]);
+    }
+
+    #[test]
+    fn test_len() {
+        let mut stack = Stack::new();
+        assert_eq!(stack.len(), 0);
+        stack.push(test_val_i32(1));
+        stack.push(test_val_i32(2));
+        stack.commit();
+        stack.push(test_val_i32(3));
+        assert_eq!(stack.len(), 3);
+        stack.pop().unwrap();
+        stack.pop().unwrap();
+        assert_eq!(stack.len(), 1);
+        stack.rollback();
+        assert_eq!(stack.len(), 2);
     }
 
     #[test]
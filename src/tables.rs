@@ -0,0 +1,201 @@
+use crate::{elements::Elements, model::Index};
+use anyhow::{anyhow, Result};
+
+/// Most elements a table can grow to, when it has no maximum of its own.
+/// Far below what Wasm allows, as with memory.
+pub const MAX_ELEMS: u32 = 65536;
+
+/// A table of functions, by their index. Elements not set by an `elem`
+/// line or `table.set` are `None`.
+#[derive(Clone)]
+struct Table {
+    id: Option<String>,
+    funcs: Vec<Option<u32>>,
+    max: Option<u32>,
+}
+
+#[derive(Clone)]
+pub struct Tables {
+    elements: Elements<Table>,
+}
+
+impl Tables {
+    pub fn new() -> Tables {
+        Tables {
+            elements: Elements::new(),
+        }
+    }
+
+    pub fn grow(&mut self, id: Option<String>, size: u32, max: Option<u32>) -> Result<usize> {
+        let table = Table {
+            id: id.clone(),
+            funcs: vec![None; size as usize],
+            max,
+        };
+        self.elements.grow(id, table)
+    }
+
+    /// Sets the elements from `offset` onwards to `funcs`. Nothing is set
+    /// if they do not all fit in the table.
+    pub fn init(&mut self, index: &Index, offset: u32, funcs: &[u32]) -> Result<()> {
+        let mut table = self.elements.get(index)?.clone();
+        let start = offset as usize;
+        let end = start + funcs.len();
+        if end > table.funcs.len() {
+            return Err(anyhow!("out of bounds table access"));
+        }
+        for (elem, func) in table.funcs[start..end].iter_mut().zip(funcs) {
+            *elem = Some(*func);
+        }
+        self.elements.set(index, table)
+    }
+
+    /// The index of the function at element `i` of the table.
+    pub fn get(&self, index: &Index, i: u32) -> Result<u32> {
+        match self.elements.get(index)?.funcs.get(i as usize) {
+            Some(Some(func)) => Ok(*func),
+            Some(None) => Err(anyhow!("uninitialized element {}", i)),
+            None => Err(anyhow!("undefined element {}", i)),
+        }
+    }
+
+    /// The element `i` of the table, `None` for a null reference.
+    pub fn get_elem(&self, index: &Index, i: u32) -> Result<Option<u32>> {
+        match self.elements.get(index)?.funcs.get(i as usize) {
+            Some(func) => Ok(*func),
+            None => Err(anyhow!("out of bounds table access")),
+        }
+    }
+
+    pub fn set_elem(&mut self, index: &Index, i: u32, func: Option<u32>) -> Result<()> {
+        let mut table = self.elements.get(index)?.clone();
+        match table.funcs.get_mut(i as usize) {
+            Some(elem) => *elem = func,
+            None => return Err(anyhow!("out of bounds table access")),
+        }
+        self.elements.set(index, table)
+    }
+
+    /// Current size of the table, in elements.
+    pub fn size(&self, index: &Index) -> Result<u32> {
+        Ok(self.elements.get(index)?.funcs.len() as u32)
+    }
+
+    /// Grows the table by `delta` elements set to `init`, returning the
+    /// previous size. Returns `None`, leaving the size as is, if it would
+    /// go beyond the maximum of the table or `MAX_ELEMS`.
+    pub fn grow_elems(
+        &mut self,
+        index: &Index,
+        delta: u32,
+        init: Option<u32>,
+    ) -> Result<Option<u32>> {
+        let mut table = self.elements.get(index)?.clone();
+        let size = table.funcs.len() as u32;
+        let max = table.max.unwrap_or(MAX_ELEMS).min(MAX_ELEMS);
+        match size.checked_add(delta).filter(|size| *size <= max) {
+            Some(new_size) => {
+                table.funcs.resize(new_size as usize, init);
+                self.elements.set(index, table)?;
+                Ok(Some(size))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn id(&self, index: &Index) -> Result<Option<String>> {
+        Ok(self.elements.get(index)?.id.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn commit(&mut self) {
+        self.elements.commit();
+    }
+
+    pub fn rollback(&mut self) {
+        self.elements.rollback();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::Index;
+    use crate::tables::{Tables, MAX_ELEMS};
+    use crate::test_utils::test_index;
+
+    #[test]
+    fn test_init_get() {
+        let mut tables = Tables::new();
+        tables.grow(Some(String::from("t")), 3, None).unwrap();
+        tables.init(&test_index("t"), 1, &[4, 5]).unwrap();
+
+        assert_eq!(tables.get(&Index::Num(0), 1).unwrap(), 4);
+        assert_eq!(tables.get(&Index::Num(0), 2).unwrap(), 5);
+        assert!(tables.get(&Index::Num(1), 0).is_err());
+    }
+
+    #[test]
+    fn test_get_error() {
+        let mut tables = Tables::new();
+        tables.grow(None, 1, None).unwrap();
+        let err = tables.get(&Index::Num(0), 0).err().unwrap();
+        assert_eq!(err.to_string(), "uninitialized element 0");
+        let err = tables.get(&Index::Num(0), 1).err().unwrap();
+        assert_eq!(err.to_string(), "undefined element 1");
+    }
+
+    #[test]
+    fn test_init_out_of_bounds() {
+        let mut tables = Tables::new();
+        tables.grow(None, 2, None).unwrap();
+        assert!(tables.init(&Index::Num(0), 1, &[4, 5]).is_err());
+        assert!(tables.get(&Index::Num(0), 1).is_err());
+    }
+
+    #[test]
+    fn test_get_set_elem() {
+        let mut tables = Tables::new();
+        tables.grow(None, 2, None).unwrap();
+        tables.set_elem(&Index::Num(0), 1, Some(3)).unwrap();
+        assert_eq!(tables.get_elem(&Index::Num(0), 0).unwrap(), None);
+        assert_eq!(tables.get_elem(&Index::Num(0), 1).unwrap(), Some(3));
+
+        let err = tables.get_elem(&Index::Num(0), 2).err().unwrap();
+        assert_eq!(err.to_string(), "out of bounds table access");
+        assert!(tables.set_elem(&Index::Num(0), 2, None).is_err());
+    }
+
+    #[test]
+    fn test_grow_elems() {
+        let mut tables = Tables::new();
+        tables.grow(None, 1, Some(3)).unwrap();
+        let table = Index::Num(0);
+        assert_eq!(tables.grow_elems(&table, 0, None).unwrap(), Some(1));
+        assert_eq!(tables.grow_elems(&table, 2, Some(7)).unwrap(), Some(1));
+        assert_eq!(tables.size(&table).unwrap(), 3);
+        assert_eq!(tables.get_elem(&table, 2).unwrap(), Some(7));
+        assert_eq!(tables.grow_elems(&table, 1, None).unwrap(), None);
+
+        tables.grow(None, 0, None).unwrap();
+        let table = Index::Num(1);
+        assert_eq!(tables.grow_elems(&table, MAX_ELEMS, None).unwrap(), Some(0));
+        assert_eq!(tables.grow_elems(&table, 1, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_commit_rollback() {
+        let mut tables = Tables::new();
+        tables.grow(None, 1, None).unwrap();
+        tables.commit();
+        tables.init(&Index::Num(0), 0, &[4]).unwrap();
+        tables.grow_elems(&Index::Num(0), 1, None).unwrap();
+        tables.grow(None, 1, None).unwrap();
+        tables.rollback();
+        assert_eq!(tables.size(&Index::Num(0)).unwrap(), 1);
+        assert!(tables.get(&Index::Num(0), 0).is_err());
+        assert_eq!(tables.len(), 1);
+    }
+}
@@ -115,3 +115,13 @@ pub(crate) use test_if;
 pub(crate) use test_local;
 pub(crate) use test_local_id;
 pub(crate) use test_loop;
+
+/// A binary module with `(func (export "add") (param i32 i32) (result i32)
+/// local.get 0 local.get 1 i32.add)`.
+pub const ADD_WASM: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f, // types
+    0x03, 0x02, 0x01, 0x00, // funcs
+    0x07, 0x07, 0x01, 0x03, b'a', b'd', b'd', 0x00, 0x00, // exports
+    0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b, // code
+];
@@ -0,0 +1,618 @@
+// Type checks a function body against its declared type when it is
+// defined, so that mistakes show up then rather than on the first call.
+// Only the types of the operands are followed, much like the validation
+// algorithm of the spec.
+//
+use anyhow::{anyhow, Result};
+
+use crate::model::{BlockType, Expression, Func, FuncType, Index, Instruction, Local, ValType};
+
+/// The type of an operand, or `None` when it cannot be known, as after an
+/// unconditional branch or a call to a function not defined yet.
+type Operand = Option<ValType>;
+
+/// A `block`, `loop` or `if` being checked, or the body of the function
+/// itself at the bottom.
+struct Frame {
+    label: Option<String>,
+    /// What a branch to the frame takes, the params of a loop and the
+    /// results otherwise.
+    label_types: Vec<ValType>,
+    results: Vec<ValType>,
+    height: usize,
+    unreachable: bool,
+}
+
+/// Looks up what a function body refers to outside of itself. An index
+/// not found is taken as defined later, and anything goes for it.
+pub struct Context<'a> {
+    pub func_type: &'a dyn Fn(&Index) -> Option<FuncType>,
+    pub global_type: &'a dyn Fn(&Index) -> Option<(ValType, bool)>,
+}
+
+struct Validator<'a> {
+    context: &'a Context<'a>,
+    locals: Vec<Local>,
+    operands: Vec<Operand>,
+    frames: Vec<Frame>,
+}
+
+/// Checks that the body of `func` leaves values of its result types, and
+/// that every instruction in it gets operands of the right types.
+pub fn validate_func(func: &Func, context: &Context) -> Result<()> {
+    let mut locals = func.ty.params.clone();
+    locals.extend(func.line_expression.locals.iter().cloned());
+
+    let mut validator = Validator {
+        context,
+        locals,
+        operands: Vec::new(),
+        frames: Vec::new(),
+    };
+    let results = func.ty.results.clone();
+    validator.push_frame(None, results.clone(), results);
+    validator.expr(&func.line_expression.expr)?;
+    validator
+        .pop_frame()
+        .map_err(|err| anyhow!("{} at the end of func", err))
+}
+
+impl Validator<'_> {
+    fn expr(&mut self, expr: &Expression) -> Result<()> {
+        for instr in expr.instrs.iter() {
+            match instr {
+                // Errors from the body are about the instructions in it.
+                Instruction::Block(..) | Instruction::Loop(..) | Instruction::If(..) => {
+                    self.instr(instr)?
+                }
+                _ => self
+                    .instr(instr)
+                    .map_err(|err| anyhow!("{} in {}", err, instr.mnemonic()))?,
+            }
+        }
+        Ok(())
+    }
+
+    fn instr(&mut self, instr: &Instruction) -> Result<()> {
+        if let Some((params, result)) = numeric_type(instr) {
+            self.pop_types(params)?;
+            self.operands.push(Some(result));
+            return Ok(());
+        }
+
+        match instr {
+            Instruction::Nop => {}
+            Instruction::Drop => {
+                self.pop()?;
+            }
+            Instruction::Select => {
+                self.pop_type(&ValType::I32)?;
+                let first = self.pop()?;
+                let second = self.pop()?;
+                if let (Some(first), Some(second)) = (&first, &second) {
+                    if first != second {
+                        return Err(anyhow!("type mismatch: {} and {}", second, first));
+                    }
+                }
+                self.operands.push(first.or(second));
+            }
+            Instruction::LocalGet(index) => {
+                let ty = self.local_type(index)?;
+                self.operands.push(Some(ty));
+            }
+            Instruction::LocalSet(index) => {
+                let ty = self.local_type(index)?;
+                self.pop_type(&ty)?;
+            }
+            Instruction::LocalTee(index) => {
+                let ty = self.local_type(index)?;
+                self.pop_type(&ty)?;
+                self.operands.push(Some(ty));
+            }
+            Instruction::GlobalGet(index) => {
+                let ty = (self.context.global_type)(index).map(|(ty, _)| ty);
+                self.operands.push(ty);
+            }
+            Instruction::GlobalSet(index) => match (self.context.global_type)(index) {
+                Some((_, false)) => return Err(anyhow!("global is immutable")),
+                Some((ty, true)) => self.pop_type(&ty)?,
+                None => {
+                    self.pop()?;
+                }
+            },
+            Instruction::I32Load(_) => {
+                self.pop_type(&ValType::I32)?;
+                self.operands.push(Some(ValType::I32));
+            }
+            Instruction::I32Store(_) => self.pop_types(&[ValType::I32, ValType::I32])?,
+            Instruction::MemorySize => self.operands.push(Some(ValType::I32)),
+            Instruction::MemoryInit(_) => {
+                self.pop_types(&[ValType::I32, ValType::I32, ValType::I32])?
+            }
+            Instruction::DataDrop(_) | Instruction::ElemDrop(_) => {}
+            Instruction::TableInit(..) => {
+                self.pop_types(&[ValType::I32, ValType::I32, ValType::I32])?
+            }
+            Instruction::TableGet(_) => {
+                self.pop_type(&ValType::I32)?;
+                self.operands.push(Some(ValType::FuncRef));
+            }
+            Instruction::TableSet(_) => self.pop_types(&[ValType::I32, ValType::FuncRef])?,
+            Instruction::TableSize(_) => self.operands.push(Some(ValType::I32)),
+            Instruction::TableGrow(_) => {
+                self.pop_types(&[ValType::FuncRef, ValType::I32])?;
+                self.operands.push(Some(ValType::I32));
+            }
+            Instruction::RefNull | Instruction::RefFunc(_) => {
+                self.operands.push(Some(ValType::FuncRef))
+            }
+            Instruction::RefIsNull => {
+                self.pop_type(&ValType::FuncRef)?;
+                self.operands.push(Some(ValType::I32));
+            }
+            Instruction::MemoryGrow => {
+                self.pop_type(&ValType::I32)?;
+                self.operands.push(Some(ValType::I32));
+            }
+            Instruction::Call(index) => match (self.context.func_type)(index) {
+                Some(ty) => {
+                    self.pop_types(&local_types(&ty.params))?;
+                    self.push_types(&ty.results);
+                }
+                None => self.set_unreachable(),
+            },
+            Instruction::ReturnCall(index) => {
+                if let Some(ty) = (self.context.func_type)(index) {
+                    self.pop_types(&local_types(&ty.params))?;
+                    if ty.results != self.frames[0].results {
+                        return Err(anyhow!("type mismatch: results of the callee"));
+                    }
+                }
+                self.set_unreachable();
+            }
+            Instruction::CallIndirect(_, ty) => {
+                self.pop_type(&ValType::I32)?;
+                self.pop_types(&local_types(&ty.params))?;
+                self.push_types(&ty.results);
+            }
+            Instruction::ReturnCallIndirect(_, ty) => {
+                self.pop_type(&ValType::I32)?;
+                self.pop_types(&local_types(&ty.params))?;
+                if ty.results != self.frames[0].results {
+                    return Err(anyhow!("type mismatch: results of the callee"));
+                }
+                self.set_unreachable();
+            }
+            Instruction::Return => {
+                let results = self.frames[0].results.clone();
+                self.pop_types(&results)?;
+                self.set_unreachable();
+            }
+            Instruction::Block(bt, body) => {
+                self.pop_types(&local_types(&bt.ty.params))
+                    .map_err(|err| anyhow!("{} in {}", err, instr.mnemonic()))?;
+                self.block(instr, bt, body.as_ref())?;
+                self.push_types(&bt.ty.results);
+            }
+            Instruction::Loop(bt, body) => {
+                self.pop_types(&local_types(&bt.ty.params))
+                    .map_err(|err| anyhow!("{} in {}", err, instr.mnemonic()))?;
+                self.block(instr, bt, body.as_ref())?;
+                self.push_types(&bt.ty.results);
+            }
+            Instruction::If(bt, then_body, else_body) => {
+                self.pop_type(&ValType::I32)
+                    .and_then(|_| self.pop_types(&local_types(&bt.ty.params)))
+                    .map_err(|err| anyhow!("{} in {}", err, instr.mnemonic()))?;
+                // Without an else, the params are passed on as the results.
+                self.block(instr, bt, then_body.as_ref())?;
+                self.block(instr, bt, else_body.as_ref())?;
+                self.push_types(&bt.ty.results);
+            }
+            Instruction::Br(index) => {
+                let label_types = self.label_types(index)?;
+                self.pop_types(&label_types)?;
+                self.set_unreachable();
+            }
+            Instruction::BrIf(index) => {
+                self.pop_type(&ValType::I32)?;
+                let label_types = self.label_types(index)?;
+                self.pop_types(&label_types)?;
+                self.push_types(&label_types);
+            }
+            Instruction::BrTable(indices, default) => {
+                self.pop_type(&ValType::I32)?;
+                let label_types = self.label_types(default)?;
+                for index in indices.iter() {
+                    if self.label_types(index)?.len() != label_types.len() {
+                        return Err(anyhow!("type mismatch: labels of different arity"));
+                    }
+                }
+                self.pop_types(&label_types)?;
+                self.set_unreachable();
+            }
+            // Grouped into the body of the block they close.
+            Instruction::Else | Instruction::End => {}
+            _ => return Err(anyhow!("unsupported instruction")),
+        }
+        Ok(())
+    }
+
+    /// Checks a body of `instr`, which starts with the params of the block
+    /// type on the stack and has to end with its results.
+    fn block(
+        &mut self,
+        instr: &Instruction,
+        bt: &BlockType,
+        body: Option<&Expression>,
+    ) -> Result<()> {
+        let params = local_types(&bt.ty.params);
+        let label_types = match instr {
+            Instruction::Loop(..) => params.clone(),
+            _ => bt.ty.results.clone(),
+        };
+        self.push_frame(bt.label.clone(), label_types, bt.ty.results.clone());
+        self.push_types(&params);
+        if let Some(body) = body {
+            self.expr(body)?;
+        }
+        self.pop_frame()
+            .map_err(|err| anyhow!("{} at the end of {}", err, instr.mnemonic()))
+    }
+
+    fn local_type(&self, index: &Index) -> Result<ValType> {
+        let local = match index {
+            Index::Num(num) => self.locals.get(*num as usize),
+            Index::Id(id) => self
+                .locals
+                .iter()
+                .find(|local| local.id.as_deref() == Some(id.as_str())),
+        };
+        local
+            .map(|local| local.val_type.clone())
+            .ok_or_else(|| anyhow!("unknown local {}", index_name(index)))
+    }
+
+    /// Branches by a number count outwards from the innermost frame,
+    /// those by an id go to the innermost frame with that label.
+    fn label_types(&self, index: &Index) -> Result<Vec<ValType>> {
+        let frame = match index {
+            Index::Num(num) => self.frames.iter().rev().nth(*num as usize),
+            Index::Id(id) => self
+                .frames
+                .iter()
+                .rev()
+                .find(|frame| frame.label.as_deref() == Some(id.as_str())),
+        };
+        frame
+            .map(|frame| frame.label_types.clone())
+            .ok_or_else(|| anyhow!("unknown label {}", index_name(index)))
+    }
+
+    fn push_frame(
+        &mut self,
+        label: Option<String>,
+        label_types: Vec<ValType>,
+        results: Vec<ValType>,
+    ) {
+        self.frames.push(Frame {
+            label,
+            label_types,
+            results,
+            height: self.operands.len(),
+            unreachable: false,
+        });
+    }
+
+    fn pop_frame(&mut self) -> Result<()> {
+        let results = self.frames.last().unwrap().results.clone();
+        self.pop_types(&results)?;
+        let frame = self.frames.pop().unwrap();
+        if self.operands.len() != frame.height {
+            return Err(anyhow!("type mismatch: too many values"));
+        }
+        Ok(())
+    }
+
+    fn set_unreachable(&mut self) {
+        let frame = self.frames.last_mut().unwrap();
+        self.operands.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    fn push_types(&mut self, types: &[ValType]) {
+        self.operands.extend(types.iter().cloned().map(Some));
+    }
+
+    fn pop(&mut self) -> Result<Operand> {
+        let frame = self.frames.last().unwrap();
+        if self.operands.len() > frame.height {
+            Ok(self.operands.pop().unwrap())
+        } else if frame.unreachable {
+            Ok(None)
+        } else {
+            Err(anyhow!("type mismatch: expected a value, found none"))
+        }
+    }
+
+    fn pop_type(&mut self, ty: &ValType) -> Result<()> {
+        match self.pop() {
+            Ok(Some(operand)) if operand != *ty => {
+                Err(anyhow!("type mismatch: expected {}, found {}", ty, operand))
+            }
+            Ok(_) => Ok(()),
+            Err(_) => Err(anyhow!("type mismatch: expected {}, found none", ty)),
+        }
+    }
+
+    fn pop_types(&mut self, types: &[ValType]) -> Result<()> {
+        for ty in types.iter().rev() {
+            self.pop_type(ty)?;
+        }
+        Ok(())
+    }
+}
+
+fn local_types(locals: &[Local]) -> Vec<ValType> {
+    locals.iter().map(|local| local.val_type.clone()).collect()
+}
+
+fn index_name(index: &Index) -> String {
+    match index {
+        Index::Id(id) => format!("${}", id),
+        Index::Num(num) => num.to_string(),
+    }
+}
+
+/// The params and result of the instructions working on values alone.
+fn numeric_type(instr: &Instruction) -> Option<(&'static [ValType], ValType)> {
+    use ValType::{F32, F64, I32, I64};
+
+    let ty: (&'static [ValType], ValType) = match instr {
+        Instruction::I32Const(_) => (&[], I32),
+        Instruction::I64Const(_) => (&[], I64),
+        Instruction::F32Const(_) => (&[], F32),
+        Instruction::F64Const(_) => (&[], F64),
+
+        Instruction::I32Clz
+        | Instruction::I32Ctz
+        | Instruction::I32Popcnt
+        | Instruction::I32Eqz
+        | Instruction::I32Extend8S
+        | Instruction::I32Extend16S => (&[I32], I32),
+        Instruction::I32Add
+        | Instruction::I32Sub
+        | Instruction::I32Mul
+        | Instruction::I32DivS
+        | Instruction::I32DivU
+        | Instruction::I32RemS
+        | Instruction::I32RemU
+        | Instruction::I32And
+        | Instruction::I32Or
+        | Instruction::I32Xor
+        | Instruction::I32Shl
+        | Instruction::I32ShrS
+        | Instruction::I32ShrU
+        | Instruction::I32Rotl
+        | Instruction::I32Rotr
+        | Instruction::I32Eq
+        | Instruction::I32Ne
+        | Instruction::I32LtS
+        | Instruction::I32LtU
+        | Instruction::I32GtS
+        | Instruction::I32GtU
+        | Instruction::I32LeS
+        | Instruction::I32LeU
+        | Instruction::I32GeS
+        | Instruction::I32GeU => (&[I32, I32], I32),
+
+        Instruction::I64Clz
+        | Instruction::I64Ctz
+        | Instruction::I64Popcnt
+        | Instruction::I64Extend8S
+        | Instruction::I64Extend16S
+        | Instruction::I64Extend32S => (&[I64], I64),
+        Instruction::I64Eqz => (&[I64], I32),
+        Instruction::I64Add
+        | Instruction::I64Sub
+        | Instruction::I64Mul
+        | Instruction::I64DivS
+        | Instruction::I64DivU
+        | Instruction::I64RemS
+        | Instruction::I64RemU
+        | Instruction::I64And
+        | Instruction::I64Or
+        | Instruction::I64Xor
+        | Instruction::I64Shl
+        | Instruction::I64ShrS
+        | Instruction::I64ShrU
+        | Instruction::I64Rotl
+        | Instruction::I64Rotr => (&[I64, I64], I64),
+        Instruction::I64Eq
+        | Instruction::I64Ne
+        | Instruction::I64LtS
+        | Instruction::I64LtU
+        | Instruction::I64GtS
+        | Instruction::I64GtU
+        | Instruction::I64LeS
+        | Instruction::I64LeU
+        | Instruction::I64GeS
+        | Instruction::I64GeU => (&[I64, I64], I32),
+
+        Instruction::F32Abs
+        | Instruction::F32Neg
+        | Instruction::F32Ceil
+        | Instruction::F32Floor
+        | Instruction::F32Trunc
+        | Instruction::F32Nearest
+        | Instruction::F32Sqrt => (&[F32], F32),
+        Instruction::F32Add
+        | Instruction::F32Sub
+        | Instruction::F32Mul
+        | Instruction::F32Div
+        | Instruction::F32Min
+        | Instruction::F32Max
+        | Instruction::F32Copysign => (&[F32, F32], F32),
+        Instruction::F32Eq
+        | Instruction::F32Ne
+        | Instruction::F32Lt
+        | Instruction::F32Gt
+        | Instruction::F32Le
+        | Instruction::F32Ge => (&[F32, F32], I32),
+
+        Instruction::F64Abs
+        | Instruction::F64Neg
+        | Instruction::F64Ceil
+        | Instruction::F64Floor
+        | Instruction::F64Trunc
+        | Instruction::F64Nearest
+        | Instruction::F64Sqrt => (&[F64], F64),
+        Instruction::F64Add
+        | Instruction::F64Sub
+        | Instruction::F64Mul
+        | Instruction::F64Div
+        | Instruction::F64Min
+        | Instruction::F64Max
+        | Instruction::F64Copysign => (&[F64, F64], F64),
+        Instruction::F64Eq
+        | Instruction::F64Ne
+        | Instruction::F64Lt
+        | Instruction::F64Gt
+        | Instruction::F64Le
+        | Instruction::F64Ge => (&[F64, F64], I32),
+
+        Instruction::I32WrapI64 => (&[I64], I32),
+        Instruction::I64ExtendI32S | Instruction::I64ExtendI32U => (&[I32], I64),
+        Instruction::I32TruncF32S
+        | Instruction::I32TruncF32U
+        | Instruction::I32TruncSatF32S
+        | Instruction::I32TruncSatF32U => (&[F32], I32),
+        Instruction::I32TruncF64S
+        | Instruction::I32TruncF64U
+        | Instruction::I32TruncSatF64S
+        | Instruction::I32TruncSatF64U => (&[F64], I32),
+        Instruction::I64TruncF32S
+        | Instruction::I64TruncF32U
+        | Instruction::I64TruncSatF32S
+        | Instruction::I64TruncSatF32U => (&[F32], I64),
+        Instruction::I64TruncF64S
+        | Instruction::I64TruncF64U
+        | Instruction::I64TruncSatF64S
+        | Instruction::I64TruncSatF64U => (&[F64], I64),
+        Instruction::F32ConvertI32S | Instruction::F32ConvertI32U => (&[I32], F32),
+        Instruction::F32ConvertI64S | Instruction::F32ConvertI64U => (&[I64], F32),
+        Instruction::F64ConvertI32S | Instruction::F64ConvertI32U => (&[I32], F64),
+        Instruction::F64ConvertI64S | Instruction::F64ConvertI64U => (&[I64], F64),
+        Instruction::F32DemoteF64 => (&[F64], F32),
+        Instruction::F64PromoteF32 => (&[F32], F64),
+        _ => return None,
+    };
+    Some(ty)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::executor::Executor;
+
+    fn define(executor: &mut Executor, func: &str) -> Result<(), String> {
+        executor
+            .eval(func)
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn test_valid_funcs() {
+        let mut executor = Executor::new();
+        let funcs = [
+            "(func (param i32) (result i64) (i64.extend_i32_s (local.get 0)))",
+            "(func (result i32) (block (result i32) (br 0 (i32.const 1)) (drop) (i32.const 2)))",
+            "(func (param i32) (result i32) (if (result i32) (local.get 0) \
+                (then (i32.const 1)) (else (return (i32.const 2)))))",
+            "(func (param i32) (loop $l (br_if $l (local.tee 0 (i32.sub (local.get 0) \
+                (i32.const 1))))))",
+            "(func (result f32) (select (f32.const 1) (f32.const 2) (i32.const 0)))",
+            "(func (param i32) (block (block (br_table 0 1 (local.get 0)))))",
+            "(func (param i32) (local funcref) (local.set 1 (table.get (local.get 0))) \
+                (drop (table.grow (local.get 1) (table.size))))",
+            // Whatever a function not defined yet takes and returns.
+            "(func $early (result i64) (call $later (i32.const 1)))",
+        ];
+        for func in funcs {
+            assert_eq!(define(&mut executor, func), Ok(()), "{}", func);
+        }
+    }
+
+    #[test]
+    fn test_invalid_funcs() {
+        let mut executor = Executor::new();
+        executor.eval("(global $g i32 (i32.const 0))").unwrap();
+        executor
+            .eval("(func $f (param i64) (result i64) (local.get 0))")
+            .unwrap();
+        let funcs = [
+            (
+                "(func (result i32) (i32.add (i32.const 1) (i64.const 2)))",
+                "type mismatch: expected i32, found i64 in i32.add",
+            ),
+            (
+                "(func (result i32) (i32.const 1) (i32.const 2))",
+                "type mismatch: too many values at the end of func",
+            ),
+            (
+                "(func (result i32) (block (i32.const 1)))",
+                "type mismatch: too many values at the end of block",
+            ),
+            (
+                "(func (result i32) (if (result i32) (i32.const 1) (then (i32.const 2))))",
+                "type mismatch: expected i32, found none at the end of if",
+            ),
+            (
+                "(func (drop (call $f (i32.const 1))))",
+                "type mismatch: expected i64, found i32 in call",
+            ),
+            (
+                "(func (param i32) (local.set 1 (i32.const 1)))",
+                "unknown local 1 in local.set",
+            ),
+            (
+                "(func (global.set $g (i32.const 1)))",
+                "global is immutable in global.set",
+            ),
+            ("(func (block $b (br $c)))", "unknown label $c in br"),
+            (
+                "(func (table.set (i32.const 0) (i32.const 1)))",
+                "type mismatch: expected funcref, found i32 in table.set",
+            ),
+            (
+                "(func (result i64) (return_call_indirect (result i32) (i32.const 0)))",
+                "type mismatch: results of the callee in return_call_indirect",
+            ),
+            (
+                "(func (result i64) (select (i32.const 1) (i64.const 2) (i32.const 0)))",
+                "type mismatch: i32 and i64 in select",
+            ),
+        ];
+        for (func, message) in funcs {
+            assert_eq!(define(&mut executor, func), Err(String::from(message)));
+        }
+    }
+
+    #[test]
+    fn test_invalid_func_not_added() {
+        let mut executor = Executor::new();
+        let err = executor
+            .eval("(func $f (result i32) (i64.const 1))")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("expected i32, found i64"));
+        assert!(executor.eval("(call $f)").is_err());
+
+        executor
+            .eval("(func $f (result i32) (i32.const 1))")
+            .unwrap();
+        let err = executor.eval("(func $f (result i32))").err().unwrap();
+        assert!(err.to_string().contains("found none"));
+        assert_eq!(executor.eval("(call $f)").unwrap(), "[1]");
+    }
+}
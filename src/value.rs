@@ -1,14 +1,111 @@
 use anyhow::{Error, Result};
 use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
 
 use crate::model::ValType;
 
-#[derive(PartialEq, Debug)]
+/// How values are shown on the stack.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum DisplayMode {
+    Decimal,
+    /// Integers as hexadecimal, negative ones in two's complement as in
+    /// `0xffffffff`. Floats are still shown in decimal.
+    Hex,
+}
+
+/// How values are shown on the stack.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ValueFormat {
+    pub mode: DisplayMode,
+    /// Prefixes each value with its type, as in `i32:42`.
+    pub typed: bool,
+}
+
+impl Default for ValueFormat {
+    fn default() -> Self {
+        ValueFormat::new()
+    }
+}
+
+impl ValueFormat {
+    pub fn new() -> ValueFormat {
+        ValueFormat {
+            mode: DisplayMode::Decimal,
+            typed: false,
+        }
+    }
+
+    pub fn value(&self, value: &Value) -> String {
+        let s = value.format(self.mode);
+        if self.typed {
+            format!("{}:{}", value.val_type(), s)
+        } else {
+            s
+        }
+    }
+}
+
+#[derive(Debug)]
 pub enum Value {
     I32(i32),
     I64(i64),
     F32(f32),
     F64(f64),
+    /// A reference to the function at an index, or a null reference.
+    FuncRef(Option<u32>),
+}
+
+// Values are equal when they have the same type and bits. Unlike float
+// comparison, `-0.0` and `0.0` are different values and a NaN is equal to
+// itself, which keeps `Eq` and `Hash` consistent for use as map keys.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::I32(a), Self::I32(b)) => a == b,
+            (Self::I64(a), Self::I64(b)) => a == b,
+            (Self::F32(a), Self::F32(b)) => a.to_bits() == b.to_bits(),
+            (Self::F64(a), Self::F64(b)) => a.to_bits() == b.to_bits(),
+            (Self::FuncRef(a), Self::FuncRef(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Self::I32(n) => n.hash(state),
+            Self::I64(n) => n.hash(state),
+            Self::F32(n) => n.to_bits().hash(state),
+            Self::F64(n) => n.to_bits().hash(state),
+            Self::FuncRef(func) => func.hash(state),
+        }
+    }
+}
+
+macro_rules! float_literal {
+    // With `$short_nan`, the canonical NaN is written as plain `nan`.
+    ($n:expr, $mantissa_bits:expr, $short_nan:expr) => {{
+        let n = $n;
+        let sign = if n.is_sign_negative() { "-" } else { "" };
+        if n.is_nan() {
+            let payload = n.to_bits() & ((1 << $mantissa_bits) - 1);
+            if $short_nan && payload == 1 << ($mantissa_bits - 1) {
+                format!("{}nan", sign)
+            } else {
+                format!("{}nan:0x{:x}", sign, payload)
+            }
+        } else if n.is_infinite() {
+            format!("{}inf", sign)
+        } else {
+            // Display already gives the shortest decimal that parses back
+            // to the same bits, including the sign of zero.
+            format!("{}", n)
+        }
+    }};
 }
 
 impl Display for Value {
@@ -16,8 +113,9 @@ impl Display for Value {
         match self {
             Self::I32(n) => write!(f, "{}", n),
             Self::I64(n) => write!(f, "{}", n),
-            Self::F32(n) => write!(f, "{}", n),
-            Self::F64(n) => write!(f, "{}", n),
+            Self::F32(n) => write!(f, "{}", float_literal!(n, 23, true)),
+            Self::F64(n) => write!(f, "{}", float_literal!(n, 52, true)),
+            Self::FuncRef(_) => write!(f, "{}", self.to_literal()),
         }
     }
 }
@@ -29,6 +127,7 @@ impl Clone for Value {
             Self::I64(n) => Self::I64(*n),
             Self::F32(n) => Self::F32(*n),
             Self::F64(n) => Self::F64(*n),
+            Self::FuncRef(func) => Self::FuncRef(*func),
         }
     }
 }
@@ -45,7 +144,7 @@ macro_rules! map_num_types {
             fn try_into(self) -> Result<$type> {
                 match self {
                     $e(n) => Ok(n),
-                    _ => Err(Error::msg("Type mismatch")),
+                    _ => Err(Error::msg("type mismatch")),
                 }
             }
         }
@@ -58,6 +157,27 @@ map_num_types!(f32, Value::F32);
 map_num_types!(f64, Value::F64);
 
 impl Value {
+    /// The value as a `.wat` literal, which parses back to the very same
+    /// bits, NaN payloads included.
+    pub fn to_literal(&self) -> String {
+        match self {
+            Self::I32(n) => n.to_string(),
+            Self::I64(n) => n.to_string(),
+            Self::F32(n) => float_literal!(n, 23, false),
+            Self::F64(n) => float_literal!(n, 52, false),
+            Self::FuncRef(Some(func)) => format!("ref.func {}", func),
+            Self::FuncRef(None) => String::from("ref.null func"),
+        }
+    }
+
+    pub fn format(&self, mode: DisplayMode) -> String {
+        match (self, mode) {
+            (Self::I32(n), DisplayMode::Hex) => format!("{:#x}", n),
+            (Self::I64(n), DisplayMode::Hex) => format!("{:#x}", n),
+            _ => self.to_string(),
+        }
+    }
+
     pub fn default_i32() -> Value {
         Self::I32(0)
     }
@@ -74,33 +194,46 @@ impl Value {
         Self::F64(0.0)
     }
 
-    pub fn is_same(&self, other: &Self) -> Result<()> {
-        match (self, other) {
-            (Self::I32(_), Self::I32(_)) => Ok(()),
-            (Self::I64(_), Self::I64(_)) => Ok(()),
-            (Self::F32(_), Self::F32(_)) => Ok(()),
-            (Self::F64(_), Self::F64(_)) => Ok(()),
-            _ => Err(Error::msg("Type mismatch")),
+    pub fn default_func_ref() -> Value {
+        Self::FuncRef(None)
+    }
+
+    pub fn val_type(&self) -> ValType {
+        match self {
+            Self::I32(_) => ValType::I32,
+            Self::I64(_) => ValType::I64,
+            Self::F32(_) => ValType::F32,
+            Self::F64(_) => ValType::F64,
+            Self::FuncRef(_) => ValType::FuncRef,
         }
     }
 
+    pub fn is_same(&self, other: &Self) -> Result<()> {
+        self.is_same_type(&other.val_type())
+    }
+
+    /// Errors naming both types, as in `type mismatch: expected i32, found f32`.
     pub fn is_same_type(&self, ty: &ValType) -> Result<()> {
-        match (self, ty) {
-            (Self::I32(_), ValType::I32) => Ok(()),
-            (Self::I64(_), ValType::I64) => Ok(()),
-            (Self::F32(_), ValType::F32) => Ok(()),
-            (Self::F64(_), ValType::F64) => Ok(()),
-            _ => Err(Error::msg("Type mismatch")),
+        let val_type = self.val_type();
+        if &val_type == ty {
+            Ok(())
+        } else {
+            Err(Error::msg(format!(
+                "type mismatch: expected {}, found {}",
+                ty, val_type
+            )))
         }
     }
 
-    pub fn is_true(&self) -> bool {
-        match self {
-            Self::I32(n) => *n != 0,
-            Self::I64(n) => *n != 0,
-            Self::F32(n) => *n != 0.0,
-            Self::F64(n) => *n != 0.0,
-        }
+    /// The i32 a comparison or test gives, 1 for true and 0 for false.
+    pub fn from_bool(b: bool) -> Value {
+        Self::I32(b as i32)
+    }
+
+    /// Reads the value as a condition, which only an i32 can be.
+    pub fn is_true(&self) -> Result<bool> {
+        self.is_same_type(&ValType::I32)?;
+        Ok(*self != Self::I32(0))
     }
 }
 
@@ -108,18 +241,131 @@ impl Value {
 mod tests {
     use crate::model::ValType;
     use crate::test_utils::{test_val_f32, test_val_f64, test_val_i32, test_val_i64};
-    use crate::value::Value;
+    use crate::value::{DisplayMode, Value, ValueFormat};
     use anyhow::Result;
+    use std::collections::HashMap;
+    use wast::core::{Expression, Instruction};
+    use wast::parser::{self, ParseBuffer};
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_value_display() {
         assert_eq!(test_val_i32(1).to_string(), "1");
         assert_eq!(test_val_i64(2).to_string(), "2");
         assert_eq!(test_val_f32(3.14).to_string(), "3.14");
         assert_eq!(test_val_f64(3.14f64).to_string(), "3.14");
+        assert_eq!(Value::FuncRef(Some(2)).to_string(), "ref.func 2");
+        assert_eq!(Value::FuncRef(None).to_string(), "ref.null func");
+    }
+
+    #[test]
+    fn test_value_display_special_floats() {
+        assert_eq!(test_val_f32(f32::INFINITY).to_string(), "inf");
+        assert_eq!(test_val_f32(f32::NEG_INFINITY).to_string(), "-inf");
+        assert_eq!(test_val_f64(f64::INFINITY).to_string(), "inf");
+        assert_eq!(test_val_f64(f64::NEG_INFINITY).to_string(), "-inf");
+        assert_eq!(test_val_f32(f32::NAN).to_string(), "nan");
+        assert_eq!(test_val_f64(-f64::NAN).to_string(), "-nan");
+        let nan = f32::from_bits(0x7fa0_0001);
+        assert_eq!(test_val_f32(nan).to_string(), "nan:0x200001");
+        let nan = f64::from_bits(0xfff0_0000_0000_0001);
+        assert_eq!(test_val_f64(nan).to_string(), "-nan:0x1");
+        assert_eq!(test_val_f32(-0.0).to_string(), "-0");
+        assert_eq!(test_val_f64(-0.0).to_string(), "-0");
+    }
+
+    #[test]
+    fn test_to_literal() {
+        assert_eq!(test_val_i32(-1).to_literal(), "-1");
+        assert_eq!(test_val_f32(0.1).to_literal(), "0.1");
+        assert_eq!(test_val_f32(-0.0).to_literal(), "-0");
+        assert_eq!(test_val_f64(f64::NEG_INFINITY).to_literal(), "-inf");
+        assert_eq!(test_val_f32(f32::NAN).to_literal(), "nan:0x400000");
     }
 
     #[test]
+    fn test_to_literal_round_trip() {
+        let f32s = [
+            0.1,
+            2.71,
+            1e38,
+            -0.0,
+            f32::MAX,
+            f32::MIN_POSITIVE,
+            f32::from_bits(1),
+            f32::from_bits(0x007fffff),
+            f32::INFINITY,
+            f32::NAN,
+            f32::from_bits(0xffa00001),
+        ];
+        for n in f32s {
+            let instr = parse_const(&format!("f32.const {}", test_val_f32(n).to_literal()));
+            match instr {
+                Instruction::F32Const(f) => assert_eq!(f.bits, n.to_bits()),
+                _ => panic!("Expected f32.const"),
+            }
+        }
+
+        let f64s = [
+            0.1,
+            1e308,
+            -0.0,
+            f64::MIN_POSITIVE,
+            f64::from_bits(1),
+            f64::NEG_INFINITY,
+            f64::NAN,
+            f64::from_bits(0x7ff0000000000001),
+        ];
+        for n in f64s {
+            let instr = parse_const(&format!("f64.const {}", test_val_f64(n).to_literal()));
+            match instr {
+                Instruction::F64Const(f) => assert_eq!(f.bits, n.to_bits()),
+                _ => panic!("Expected f64.const"),
+            }
+        }
+    }
+
+    fn parse_const(src: &str) -> Instruction<'static> {
+        let buf = ParseBuffer::new(src).unwrap();
+        let expr = parser::parse::<Expression>(&buf).unwrap();
+        match expr.instrs[0] {
+            Instruction::F32Const(f) => Instruction::F32Const(f),
+            Instruction::F64Const(f) => Instruction::F64Const(f),
+            _ => panic!("Expected a float const"),
+        }
+    }
+
+    #[test]
+    fn test_bitwise_eq() {
+        assert_eq!(test_val_f32(f32::NAN), test_val_f32(f32::NAN));
+        assert_eq!(test_val_f64(f64::NAN), test_val_f64(f64::NAN));
+        let payload = f32::from_bits(0x7fa0_0000);
+        assert_ne!(test_val_f32(f32::NAN), test_val_f32(payload));
+        assert_ne!(test_val_f32(f32::NAN), test_val_f32(-f32::NAN));
+        assert_ne!(test_val_f32(0.0), test_val_f32(-0.0));
+        assert_ne!(test_val_f64(0.0), test_val_f64(-0.0));
+        assert_ne!(test_val_i32(1), test_val_i64(1));
+    }
+
+    #[test]
+    fn test_hash_map_keys() {
+        let mut map = HashMap::new();
+        map.insert(test_val_i32(1), "i32");
+        map.insert(test_val_i64(1), "i64");
+        map.insert(test_val_f32(0.0), "zero");
+        map.insert(test_val_f32(-0.0), "negative zero");
+        map.insert(test_val_f32(f32::NAN), "nan");
+
+        assert_eq!(map.len(), 5);
+        assert_eq!(map[&test_val_i32(1)], "i32");
+        assert_eq!(map[&test_val_i64(1)], "i64");
+        assert_eq!(map[&test_val_f32(0.0)], "zero");
+        assert_eq!(map[&test_val_f32(-0.0)], "negative zero");
+        assert_eq!(map[&test_val_f32(f32::NAN)], "nan");
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
     fn test_from_num() {
         assert_eq!(Value::from(1), test_val_i32(1));
         assert_eq!(Value::from(2i64), test_val_i64(2));
@@ -146,6 +392,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_from_num_type() {
         let v: Value = Value::from(1i32);
         assert_eq!(v, test_val_i32(1));
@@ -159,11 +406,11 @@ mod tests {
 
     #[test]
     fn test_into_num_type() {
-        let i: Value = test_val_i32(1).try_into().unwrap();
+        let i: Value = 1.into();
         assert_eq!(i, Value::I32(1));
-        let i: Value = test_val_i64(1).try_into().unwrap();
+        let i: Value = 1i64.into();
         assert_eq!(i, Value::I64(1));
-        let i: Value = test_val_f32(1.0).try_into().unwrap();
+        let i: Value = 1.0f32.into();
         assert_eq!(i, Value::F32(1.0));
     }
 
@@ -197,31 +444,93 @@ mod tests {
         assert!(test_val_i64(1).is_same_type(&ValType::F64).is_err());
     }
 
+    #[test]
+    fn test_is_same_type_error_message() {
+        let err = test_val_f32(1.0).is_same_type(&ValType::I32).unwrap_err();
+        assert_eq!(err.to_string(), "type mismatch: expected i32, found f32");
+        let err = test_val_i64(1).is_same(&test_val_i32(2)).unwrap_err();
+        assert_eq!(err.to_string(), "type mismatch: expected i32, found i64");
+    }
+
+    #[test]
+    fn test_val_type() {
+        assert_eq!(test_val_i32(1).val_type(), ValType::I32);
+        assert_eq!(test_val_i64(1).val_type(), ValType::I64);
+        assert_eq!(test_val_f32(1.0).val_type(), ValType::F32);
+        assert_eq!(test_val_f64(1.0).val_type(), ValType::F64);
+    }
+
+    #[test]
+    fn test_from_bool() {
+        assert_eq!(Value::from_bool(true), test_val_i32(1));
+        assert_eq!(Value::from_bool(false), test_val_i32(0));
+    }
+
     #[test]
     fn test_is_true_i32() {
-        assert!(test_val_i32(1).is_true());
-        assert!(test_val_i32(-1).is_true());
-        assert!(!test_val_i32(0).is_true());
+        assert!(test_val_i32(1).is_true().unwrap());
+        assert!(test_val_i32(-1).is_true().unwrap());
+        assert!(!test_val_i32(0).is_true().unwrap());
     }
 
     #[test]
     fn test_is_true_i64() {
-        assert!(test_val_i64(1).is_true());
-        assert!(test_val_i64(-1).is_true());
-        assert!(!test_val_i64(0).is_true());
+        let err = test_val_i64(1).is_true().err().unwrap();
+        assert_eq!(err.to_string(), "type mismatch: expected i32, found i64");
+        assert!(test_val_i64(0).is_true().is_err());
     }
 
     #[test]
     fn test_is_true_f32() {
-        assert!(test_val_f32(1.0).is_true());
-        assert!(test_val_f32(-1.0).is_true());
-        assert!(!test_val_f32(0.0).is_true());
+        let err = test_val_f32(1.0).is_true().err().unwrap();
+        assert_eq!(err.to_string(), "type mismatch: expected i32, found f32");
+        assert!(test_val_f32(0.0).is_true().is_err());
     }
 
     #[test]
     fn test_is_true_f64() {
-        assert!(test_val_f64(1.0).is_true());
-        assert!(test_val_f64(-1.0).is_true());
-        assert!(!test_val_f64(0.0).is_true());
+        let err = test_val_f64(1.0).is_true().err().unwrap();
+        assert_eq!(err.to_string(), "type mismatch: expected i32, found f64");
+        assert!(test_val_f64(0.0).is_true().is_err());
+    }
+
+    #[test]
+    fn test_format_hex() {
+        assert_eq!(
+            test_val_i32(0x40000000).format(DisplayMode::Hex),
+            "0x40000000"
+        );
+        assert_eq!(test_val_i32(-1).format(DisplayMode::Hex), "0xffffffff");
+        assert_eq!(test_val_i64(255).format(DisplayMode::Hex), "0xff");
+        assert_eq!(
+            test_val_i64(-2).format(DisplayMode::Hex),
+            "0xfffffffffffffffe"
+        );
+        assert_eq!(test_val_f32(1.5).format(DisplayMode::Hex), "1.5");
+        assert_eq!(test_val_f64(-0.5).format(DisplayMode::Hex), "-0.5");
+    }
+
+    #[test]
+    fn test_format_decimal() {
+        assert_eq!(test_val_i32(-1).format(DisplayMode::Decimal), "-1");
+        assert_eq!(test_val_i64(255).format(DisplayMode::Decimal), "255");
+    }
+
+    #[test]
+    fn test_value_format_typed() {
+        let format = ValueFormat {
+            typed: true,
+            ..ValueFormat::new()
+        };
+        assert_eq!(format.value(&test_val_i32(42)), "i32:42");
+        assert_eq!(format.value(&test_val_i64(1)), "i64:1");
+        assert_eq!(format.value(&test_val_f32(1.5)), "f32:1.5");
+        assert_eq!(ValueFormat::new().value(&test_val_i64(1)), "1");
+
+        let format = ValueFormat {
+            mode: DisplayMode::Hex,
+            typed: true,
+        };
+        assert_eq!(format.value(&test_val_i32(255)), "i32:0xff");
     }
 }
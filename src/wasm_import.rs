@@ -0,0 +1,206 @@
+// A compiled module is printed back to the text format and parsed with
+// wast, so that its functions go through the same conversion to the model
+// as the ones typed at the REPL.
+//
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use wast::{
+    core::{ExportKind, ItemKind, ModuleField, ModuleKind},
+    parser::{self, ParseBuffer},
+    token::Index as WastIndex,
+    Wat,
+};
+
+use crate::model::{Func, Index};
+
+/// The functions lifted from a compiled module, and the ones left out.
+pub struct WasmImport {
+    pub funcs: Vec<Func>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// A function of the module that could not be lifted, with the reason.
+pub struct Skipped {
+    pub index: u32,
+    pub id: Option<String>,
+    pub reason: String,
+}
+
+/// Lifts the functions of a `.wasm` binary. Calls between them are made
+/// by id, as they land at different indices in the REPL. So a function is
+/// left out if it calls one that has no name, or that is left out itself.
+pub fn import_wasm(bytes: &[u8]) -> Result<WasmImport> {
+    let text = wasmprinter::print_bytes(bytes)?;
+    let buf = ParseBuffer::new(&text)?;
+    let mut wat = parser::parse::<Wat>(&buf)?;
+    let fields = match &mut wat {
+        Wat::Module(module) => match &mut module.kind {
+            ModuleKind::Text(fields) => fields,
+            ModuleKind::Binary(_) => return Err(anyhow!("Expected a module in text")),
+        },
+        Wat::Component(_) => return Err(anyhow!("Components are not supported")),
+    };
+
+    let mut exports = HashMap::new();
+    for field in fields.iter() {
+        if let ModuleField::Export(export) = field {
+            if let (ExportKind::Func, WastIndex::Num(num, _)) = (export.kind, export.item) {
+                exports.entry(num).or_insert(export.name);
+            }
+        }
+    }
+
+    // Imported functions come first in the index space.
+    let mut ids = Vec::new();
+    let mut lifted = Vec::new();
+    for field in fields.iter_mut() {
+        let index = ids.len() as u32;
+        let export = exports.get(&index).map(|name| name.to_string());
+        match field {
+            ModuleField::Import(import) if matches!(import.item.kind, ItemKind::Func(_)) => {
+                let id = import.item.id.map(|id| id.name().to_string()).or(export);
+                let reason = format!("imported from {}.{}", import.module, import.field);
+                ids.push(id);
+                lifted.push((index, Err(anyhow!(reason))));
+            }
+            ModuleField::Func(func) => {
+                let id = func.id.map(|id| id.name().to_string()).or(export);
+                // The printer refers to the type section, and repeats
+                // the type inline as well.
+                func.ty.index = None;
+                let func = Func::try_from(&*func).map(|func| Func {
+                    id: id.clone(),
+                    ..func
+                });
+                ids.push(id);
+                lifted.push((index, func));
+            }
+            _ => {}
+        }
+    }
+
+    while let Some(pos) = lifted.iter().position(|(_, func)| match func {
+        Ok(func) => call_error(func, &ids, &lifted).is_some(),
+        Err(_) => false,
+    }) {
+        let (index, func) = &lifted[pos];
+        let err = call_error(func.as_ref().unwrap(), &ids, &lifted).unwrap();
+        lifted[pos] = (*index, Err(err));
+    }
+
+    let mut import = WasmImport {
+        funcs: Vec::new(),
+        skipped: Vec::new(),
+    };
+    for (index, func) in lifted {
+        match func {
+            Ok(mut func) => {
+                for call in func.line_expression.expr.calls_mut() {
+                    if let Index::Num(num) = call {
+                        *call = Index::Id(ids[*num as usize].clone().unwrap());
+                    }
+                }
+                import.funcs.push(func);
+            }
+            Err(err) => import.skipped.push(Skipped {
+                index,
+                id: ids[index as usize].clone(),
+                reason: err.to_string(),
+            }),
+        }
+    }
+    Ok(import)
+}
+
+/// Why `func` cannot be lifted because of a function it calls, if so.
+fn call_error(
+    func: &Func,
+    ids: &[Option<String>],
+    lifted: &[(u32, Result<Func>)],
+) -> Option<anyhow::Error> {
+    func.line_expression
+        .expr
+        .calls()
+        .into_iter()
+        .find_map(|call| {
+            let num = match call {
+                Index::Num(num) => *num as usize,
+                Index::Id(_) => return None,
+            };
+            match (ids.get(num), lifted.get(num)) {
+                (Some(Some(_)), Some((_, Ok(_)))) => None,
+                (Some(None), Some((_, Ok(_)))) => {
+                    Some(anyhow!("calls func {}, which has no name", num))
+                }
+                _ => Some(anyhow!("calls func {}, which is not loaded", num)),
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::Index;
+    use crate::test_utils::ADD_WASM;
+    use crate::wasm_import::import_wasm;
+
+    #[test]
+    fn test_import_add() {
+        let import = import_wasm(ADD_WASM).unwrap();
+        assert!(import.skipped.is_empty());
+        assert_eq!(import.funcs.len(), 1);
+        let func = &import.funcs[0];
+        assert_eq!(func.id, Some(String::from("add")));
+        assert_eq!(func.ty.to_string(), "(param i32 i32) (result i32)");
+    }
+
+    #[test]
+    fn test_import_skipped() {
+        // (import "env" "log" (func))
+        // (func (export "trap") unreachable)
+        // (func (export "run") call 1)
+        // (func (export "two") (result i32) i32.const 2)
+        // (func (export "call_two") (result i32) call 3)
+        let wasm = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x08, 0x02, 0x60, 0x00, 0x00, 0x60, 0x00, 0x01, 0x7f, // types
+            0x02, 0x0b, 0x01, 0x03, b'e', b'n', b'v', 0x03, b'l', b'o', b'g', 0x00,
+            0x00, // imports
+            0x03, 0x05, 0x04, 0x00, 0x00, 0x01, 0x01, // funcs
+            0x07, 0x1f, 0x04, // exports
+            0x04, b't', b'r', b'a', b'p', 0x00, 0x01, //
+            0x03, b'r', b'u', b'n', 0x00, 0x02, //
+            0x03, b't', b'w', b'o', 0x00, 0x03, //
+            0x08, b'c', b'a', b'l', b'l', b'_', b't', b'w', b'o', 0x00, 0x04, //
+            0x0a, 0x14, 0x04, // code
+            0x03, 0x00, 0x00, 0x0b, //
+            0x04, 0x00, 0x10, 0x01, 0x0b, //
+            0x04, 0x00, 0x41, 0x02, 0x0b, //
+            0x04, 0x00, 0x10, 0x03, 0x0b,
+        ];
+        let import = import_wasm(wasm).unwrap();
+
+        let ids: Vec<_> = import.funcs.iter().map(|f| f.id.clone().unwrap()).collect();
+        assert_eq!(ids, vec!["two", "call_two"]);
+        let calls = import.funcs[1].line_expression.expr.calls();
+        assert_eq!(calls, vec![&Index::Id(String::from("two"))]);
+
+        let skipped: Vec<_> = import
+            .skipped
+            .iter()
+            .map(|s| (s.index, s.id.as_deref(), s.reason.as_str()))
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                (0, None, "imported from env.log"),
+                (1, Some("trap"), "Unsupported instruction: Unreachable"),
+                (2, Some("run"), "calls func 1, which is not loaded"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_error() {
+        assert!(import_wasm(&[0x00, 0x61, 0x73, 0x6d]).is_err());
+    }
+}